@@ -0,0 +1,16 @@
+use human_panic::{handle_dump, print_msg, Metadata, PanicStyle};
+
+fn main() {
+    // Only a plain hook is set up front; the human-panic report and its metadata are put
+    // together on the first panic, so a `kv get` that doesn't panic doesn't pay for them.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if PanicStyle::default() == PanicStyle::Debug {
+            return default_hook(info);
+        }
+        let meta = Metadata::new(env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"));
+        let file_path = handle_dump(&meta, info);
+        let _ = print_msg(file_path, &meta);
+    }));
+    kv::cli::main()
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use std::io;
+
+/// Why a request to kv serve failed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The server URL isn't `http://<host>:<port>`.
+    Url(String),
+    /// Connecting to the server, or talking to it, failed.
+    Io(io::Error),
+    /// The server didn't answer within the client's timeout.
+    Timeout,
+    /// The server's answer isn't HTTP as kv serve speaks it.
+    Protocol(String),
+    /// 401: the token is missing or wrong.
+    Unauthorized,
+    /// 403: the key is secret, or the token's `serve.policies` don't cover it. The server's
+    /// message says which.
+    Forbidden(String),
+    /// Any other error status, with the server's message.
+    Status(u16, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Url(url) => write!(f, "{:?} is not a kv serve URL like http://127.0.0.1:8080", url),
+            Error::Io(e) => write!(f, "Cannot talk to kv serve, error {}", e),
+            Error::Timeout => f.write_str("kv serve didn't answer in time"),
+            Error::Protocol(what) => write!(f, "kv serve sent {}", what),
+            Error::Unauthorized => f.write_str("kv serve needs a valid token"),
+            Error::Forbidden(message) => f.write_str(message),
+            Error::Status(status, message) => write!(f, "kv serve answered {}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
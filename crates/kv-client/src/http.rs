@@ -0,0 +1,116 @@
+use std::time::Instant;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::Error;
+
+/// Answers larger than this are refused. kv serve limits values to 1MiB, but a listing can
+/// hold many of them.
+const MAX_BODY: usize = 64 << 20;
+
+/// One connection to kv serve, kept open between requests.
+pub struct Connection {
+    stream: BufReader<TcpStream>,
+    /// When the last answer came, to drop connections the server has likely closed.
+    pub used: Instant,
+}
+
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Whether the server keeps the connection open for another request.
+    pub keep_alive: bool,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &value[..])
+    }
+
+    /// The body as text, e.g. the server's message with an error status.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).trim_end().to_owned()
+    }
+}
+
+impl Connection {
+    pub async fn open(addr: &str) -> Result<Connection, Error> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream: BufReader::new(stream),
+            used: Instant::now(),
+        })
+    }
+
+    /// Send `request`, a whole HTTP request, and read the answer.
+    pub async fn send(&mut self, request: &[u8]) -> Result<Response, Error> {
+        self.stream.get_mut().write_all(request).await?;
+        let response = self.read_response().await?;
+        self.used = Instant::now();
+        Ok(response)
+    }
+
+    async fn read_response(&mut self) -> Result<Response, Error> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let status = line
+            .strip_prefix("HTTP/1.")
+            .and_then(|rest| rest.split_whitespace().nth(1))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| Error::Protocol(format!("a bad status line {:?}", line.trim_end())))?;
+        let mut headers = vec![];
+        loop {
+            line.clear();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(Error::Protocol("an answer cut off in its headers".to_owned()));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| Error::Protocol(format!("a bad header {:?}", header)))?;
+            headers.push((name.to_owned(), value.trim().to_owned()));
+        }
+        let mut response = Response {
+            status,
+            headers,
+            body: vec![],
+            keep_alive: false,
+        };
+        response.keep_alive = response
+            .header("connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("keep-alive"));
+        let length = match response.header("content-length") {
+            Some(length) => length
+                .parse()
+                .map_err(|_| Error::Protocol(format!("a bad Content-Length {:?}", length)))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(Error::Protocol(format!("an answer of {} bytes", length)));
+        }
+        response.body = vec![0; length];
+        self.stream.read_exact(&mut response.body).await?;
+        Ok(response)
+    }
+}
+
+/// Escape everything but letters, digits and `-._~` as `%XX`, for keys in URLs.
+pub fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,248 @@
+//! An async client for `kv serve`, so Rust services can share a kv store over HTTP.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), kv_client::Error> {
+//! let client = kv_client::Client::builder("http://127.0.0.1:8080")
+//!     .token("s3cret")
+//!     .build()?;
+//! client.set("greeting", "hello").await?;
+//! assert_eq!(client.get("greeting").await?.as_deref(), Some("hello"));
+//! client.del("greeting").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A `Client` is cheap to clone and keeps connections open between requests, up to
+//! `max_idle` of them, for the clones to share. It needs a tokio runtime.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod error;
+mod http;
+
+pub use error::Error;
+use http::{percent_encode, Connection, Response};
+
+/// How long an idle connection is reused. kv serve closes connections left idle for 10s.
+const KEEP_FOR: Duration = Duration::from_secs(5);
+
+/// Keys asked for per request while listing.
+const PAGE: usize = 1000;
+
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// `host:port`, also sent as the Host header.
+    addr: String,
+    token: Option<String>,
+    max_idle: usize,
+    timeout: Duration,
+    idle: Mutex<Vec<Connection>>,
+}
+
+pub struct Builder {
+    url: String,
+    token: Option<String>,
+    max_idle: usize,
+    timeout: Duration,
+}
+
+impl Client {
+    /// A client for the kv serve at `url`, e.g. `http://127.0.0.1:8080`, without a token.
+    pub fn new(url: &str) -> Result<Client, Error> {
+        Client::builder(url).build()
+    }
+
+    /// Configure a client, e.g. `Client::builder(url).token(t).max_idle(4).build()`.
+    pub fn builder(url: &str) -> Builder {
+        Builder {
+            url: url.to_owned(),
+            token: None,
+            max_idle: 8,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// The value of `key`, None if it isn't set. A secret key is `Error::Forbidden`.
+    pub async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        let response = self.request("GET", &key_path(key), b"").await?;
+        match response.status {
+            404 => Ok(None),
+            _ => {
+                let response = ok(response)?;
+                let value = String::from_utf8(response.body)
+                    .map_err(|_| Error::Protocol("a value that isn't UTF-8".to_owned()))?;
+                Ok(Some(value))
+            }
+        }
+    }
+
+    /// Set `key` to `value`. Its set hooks run on the server.
+    pub async fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        ok(self.request("PUT", &key_path(key), value.as_bytes()).await?)?;
+        Ok(())
+    }
+
+    /// Delete `key`, returning whether it existed.
+    pub async fn del(&self, key: &str) -> Result<bool, Error> {
+        let response = self.request("DELETE", &key_path(key), b"").await?;
+        match response.status {
+            404 => Ok(false),
+            _ => ok(response).map(|_| true),
+        }
+    }
+
+    /// Every key starting with `prefix` that the token can read, and its value. Secret
+    /// values are hidden the way `kv list` hides them.
+    pub async fn list(&self, prefix: &str) -> Result<BTreeMap<String, String>, Error> {
+        let mut keys = BTreeMap::new();
+        let mut path = format!("/keys?prefix={}&limit={}", percent_encode(prefix), PAGE);
+        loop {
+            let response = ok(self.request("GET", &path, b"").await?)?;
+            let next = response.header("link").and_then(next_page);
+            keys.extend(json::<BTreeMap<String, String>>(&response)?);
+            match next {
+                Some(next) => path = next,
+                None => return Ok(keys),
+            }
+        }
+    }
+
+    /// Set all of `kvs` in one write of the store, or none of them if the token can't
+    /// change one.
+    pub async fn set_many(&self, kvs: &BTreeMap<String, String>) -> Result<(), Error> {
+        let body = serde_json::to_vec(kvs).unwrap();
+        ok(self.request("PUT", "/keys", &body).await?)?;
+        Ok(())
+    }
+
+    /// Delete all of `keys` in one write of the store, or none of them if the token can't
+    /// change one. Returns the keys that existed.
+    pub async fn del_many(&self, keys: &[&str]) -> Result<Vec<String>, Error> {
+        let body = serde_json::to_vec(keys).unwrap();
+        json(&ok(self.request("DELETE", "/keys", &body).await?)?)
+    }
+
+    /// Connections kept open for the next requests.
+    pub fn idle_connections(&self) -> usize {
+        self.inner.idle.lock().unwrap().len()
+    }
+
+    async fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<Response, Error> {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            self.inner.addr,
+            body.len()
+        );
+        if let Some(token) = &self.inner.token {
+            request += &format!("Authorization: Bearer {}\r\n", token);
+        }
+        request += "\r\n";
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body);
+        let timeout = self.inner.timeout;
+        tokio::time::timeout(timeout, self.send(&request))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    async fn send(&self, request: &[u8]) -> Result<Response, Error> {
+        // The server may have closed a kept connection since, so one that fails is dropped
+        // and the request sent again on a new one. Every request kv serve takes is
+        // idempotent, so one that did arrive can be repeated.
+        while let Some(mut connection) = self.take_idle() {
+            if let Ok(response) = connection.send(request).await {
+                self.put_back(connection, &response);
+                return Ok(response);
+            }
+        }
+        let mut connection = Connection::open(&self.inner.addr).await?;
+        let response = connection.send(request).await?;
+        self.put_back(connection, &response);
+        Ok(response)
+    }
+
+    fn take_idle(&self) -> Option<Connection> {
+        let mut idle = self.inner.idle.lock().unwrap();
+        idle.retain(|connection| connection.used.elapsed() < KEEP_FOR);
+        idle.pop()
+    }
+
+    fn put_back(&self, connection: Connection, response: &Response) {
+        let mut idle = self.inner.idle.lock().unwrap();
+        if response.keep_alive && idle.len() < self.inner.max_idle {
+            idle.push(connection);
+        }
+    }
+}
+
+impl Builder {
+    /// The token kv serve printed at startup, or its `--token` or one from
+    /// `serve.policies`.
+    pub fn token(mut self, token: impl Into<String>) -> Builder {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Connections kept open between requests, 8 if not set. 0 opens one per request.
+    pub fn max_idle(mut self, max_idle: usize) -> Builder {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// How long a request may take, connecting included, 30s if not set.
+    pub fn timeout(mut self, timeout: Duration) -> Builder {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let addr = self
+            .url
+            .strip_prefix("http://")
+            .map(|rest| rest.trim_end_matches('/'))
+            .filter(|addr| !addr.is_empty() && !addr.contains(['/', '?', '#', ' ']))
+            .ok_or_else(|| Error::Url(self.url.clone()))?;
+        Ok(Client {
+            inner: Arc::new(Inner {
+                addr: addr.to_owned(),
+                token: self.token,
+                max_idle: self.max_idle,
+                timeout: self.timeout,
+                idle: Mutex::new(vec![]),
+            }),
+        })
+    }
+}
+
+fn key_path(key: &str) -> String {
+    format!("/keys/{}", percent_encode(key))
+}
+
+/// `response` if its status is a success, its typed error if not.
+fn ok(response: Response) -> Result<Response, Error> {
+    match response.status {
+        200..=299 => Ok(response),
+        401 => Err(Error::Unauthorized),
+        403 => Err(Error::Forbidden(response.text())),
+        status => Err(Error::Status(status, response.text())),
+    }
+}
+
+fn json<T: serde::de::DeserializeOwned>(response: &Response) -> Result<T, Error> {
+    serde_json::from_slice(&response.body).map_err(|e| Error::Protocol(format!("bad JSON, {}", e)))
+}
+
+/// The path in a `Link: <path>; rel="next"` header.
+fn next_page(link: &str) -> Option<String> {
+    let (target, params) = link.split_once(';')?;
+    params.contains("rel=\"next\"").then_some(())?;
+    Some(target.trim().strip_prefix('<')?.strip_suffix('>')?.to_owned())
+}
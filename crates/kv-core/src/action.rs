@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(feature = "net")]
+use std::time::Duration;
+
+use crate::{
+    hook_command, hook_process, record, run_command, sandbox, script, wait_hook, Hook, OpType,
+};
+
+/// A hook firing: which hook, its cmd's text, and the key and op that set it off.
+pub struct HookEvent<'a> {
+    pub hook: &'a Hook,
+    /// The text stored under the hook's cmd: a shell command, URL or message, depending on
+    /// the action.
+    pub cmd: &'a str,
+    pub key: &'a str,
+    pub op: &'a OpType,
+    /// The key's value, for get and set. None for del and secret keys.
+    pub value: Option<&'a str>,
+}
+
+/// What a hook does when it fires. The built-in actions are `shell`, `webhook`, `notify`
+/// and `wasm`, plus `lua` and `rhai` for shell hooks whose cmd is a script; code embedding kv can
+/// `register` its own.
+pub trait HookAction: Send + Sync {
+    fn run(&self, event: &HookEvent) -> Result<(), String>;
+}
+
+/// The default: run the cmd with the user's shell and wait for it. The key that
+/// fired it (which a wildcard hook needs) is in `KV_KEY`, with `KV_OP` and `KV_VALUE`.
+struct Shell;
+
+impl HookAction for Shell {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let mut command = hook_command(event.cmd, event.hook)?;
+        command.env("KV_KEY", event.key).env("KV_OP", event.op.to_string());
+        if let Some(value) = event.value {
+            command.env("KV_VALUE", value);
+        }
+        if record::is_active() {
+            let (argv, env) = record::command_parts(&command);
+            record::hook(event.hook, event.key, event.op, argv, env);
+            Ok(())
+        } else {
+            run_command(&event.hook.cmd_name, command)
+        }
+    }
+}
+
+/// Run the cmd as a Lua script, with the key, op and value as its `...`.
+struct Lua;
+
+impl HookAction for Lua {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let op = event.op.to_string();
+        let mut args = vec![event.key, &op[..]];
+        args.extend(event.value);
+        let command = hook_process(script::lua_argv(&args)?, event.hook)?;
+        if record::is_active() {
+            let (argv, env) = record::command_parts(&command);
+            record::hook(event.hook, event.key, event.op, argv, env);
+            return Ok(());
+        }
+        let child = script::spawn_with_source(command, &script::lua_source(event.cmd))
+            .map_err(|e| format!("cannot run lua, {}", e))?;
+        wait_hook(child)
+    }
+}
+
+/// Run the cmd as a Rhai script in the embedded engine, with `key`, `op` and `value` in
+/// scope. A script that throws fails the hook.
+struct Rhai;
+
+impl HookAction for Rhai {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        if record::is_active() {
+            let argv = vec!["rhai".to_owned(), event.cmd.to_owned()];
+            record::hook(event.hook, event.key, event.op, argv, BTreeMap::new());
+            return Ok(());
+        }
+        let op = event.op.to_string();
+        let (printed, _) = script::run_rhai(event.cmd, Some(event.key), Some(&op), event.value)?;
+        print!("{}", printed);
+        Ok(())
+    }
+}
+
+/// POST the event as JSON to the URL in the cmd.
+struct Webhook;
+
+impl HookAction for Webhook {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let url = event.cmd.trim();
+        if record::is_active() {
+            let argv = vec!["POST".to_owned(), url.to_owned()];
+            record::hook(event.hook, event.key, event.op, argv, BTreeMap::new());
+            return Ok(());
+        }
+        let body = serde_json::json!({
+            "hook": event.hook.name,
+            "key": event.key,
+            "op": event.op.to_string(),
+            "value": event.value,
+        });
+        post(url, body)
+    }
+}
+
+#[cfg(feature = "net")]
+fn post(url: &str, body: serde_json::Value) -> Result<(), String> {
+    ureq::post(url)
+        .timeout(Duration::from_secs(5))
+        .send_json(body)
+        .map(|_| ())
+        .map_err(|e| format!("POST to {} failed, {}", url, e))
+}
+
+#[cfg(not(feature = "net"))]
+fn post(_url: &str, _body: serde_json::Value) -> Result<(), String> {
+    Err(crate::features::missing("net"))
+}
+
+/// A desktop notification showing the cmd, with `{key}`, `{op}` and `{value}` filled in.
+struct Notify;
+
+impl HookAction for Notify {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let message = event
+            .cmd
+            .replace("{key}", event.key)
+            .replace("{op}", &event.op.to_string())
+            .replace("{value}", event.value.unwrap_or(""));
+        let title = format!("kv: {}", event.hook.name);
+        let argv = notify_argv(&title, &message)?;
+        if record::is_active() {
+            record::hook(event.hook, event.key, event.op, argv, BTreeMap::new());
+            return Ok(());
+        }
+        Command::new(&argv[0])
+            .args(&argv[1..])
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("cannot run {}, {}", argv[0], e))
+    }
+}
+
+/// Call `on_event(key, op, value)` in the wasm module at the path in the cmd, in an
+/// embedded wasmtime. See the `wasm` module for the ABI; the module gets nothing but the
+/// event: no files, no network.
+struct Wasm;
+
+impl HookAction for Wasm {
+    fn run(&self, event: &HookEvent) -> Result<(), String> {
+        let module = event.cmd.trim();
+        if record::is_active() {
+            let argv = vec![module.to_owned(), "on_event".to_owned()];
+            record::hook(event.hook, event.key, event.op, argv, BTreeMap::new());
+            return Ok(());
+        }
+        run_wasm(std::path::Path::new(module), event)
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn run_wasm(module: &std::path::Path, event: &HookEvent) -> Result<(), String> {
+    crate::wasm::run(module, event)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn run_wasm(_module: &std::path::Path, _event: &HookEvent) -> Result<(), String> {
+    Err(crate::features::missing("wasm"))
+}
+
+/// The command showing a notification: notify-send, or osascript on macOS.
+pub(crate) fn notify_argv(title: &str, message: &str) -> Result<Vec<String>, String> {
+    if sandbox::find_program("notify-send").is_some() {
+        return Ok(vec![
+            "notify-send".to_owned(),
+            title.to_owned(),
+            message.to_owned(),
+        ]);
+    }
+    if sandbox::find_program("osascript").is_some() {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let script = format!(
+            "display notification {} with title {}",
+            quote(message),
+            quote(title)
+        );
+        return Ok(vec!["osascript".to_owned(), "-e".to_owned(), script]);
+    }
+    Err("notifications need notify-send or osascript".to_owned())
+}
+
+/// The actions `kv cmd add-hook --action` offers.
+pub const BUILT_IN: &[&str] = &["shell", "webhook", "notify", "wasm"];
+
+type Registry = Mutex<BTreeMap<String, Arc<dyn HookAction>>>;
+
+fn registry() -> &'static Registry {
+    static ACTIONS: OnceLock<Registry> = OnceLock::new();
+    ACTIONS.get_or_init(|| {
+        let mut actions: BTreeMap<String, Arc<dyn HookAction>> = BTreeMap::new();
+        actions.insert("shell".to_owned(), Arc::new(Shell));
+        actions.insert("webhook".to_owned(), Arc::new(Webhook));
+        actions.insert("notify".to_owned(), Arc::new(Notify));
+        actions.insert("wasm".to_owned(), Arc::new(Wasm));
+        actions.insert("lua".to_owned(), Arc::new(Lua));
+        actions.insert("rhai".to_owned(), Arc::new(Rhai));
+        Mutex::new(actions)
+    })
+}
+
+/// Make `action` available to hooks whose action is `name`, replacing any action by that name.
+pub fn register(name: &str, action: impl HookAction + 'static) {
+    registry()
+        .lock()
+        .unwrap()
+        .insert(name.to_owned(), Arc::new(action));
+}
+
+pub(crate) fn get(name: &str) -> Option<Arc<dyn HookAction>> {
+    registry().lock().unwrap().get(name).cloned()
+}
@@ -0,0 +1,134 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::{print_err, KV};
+
+/// `--region` and `--profile`, passed through to the aws CLI.
+pub struct Options<'a> {
+    pub region: Option<&'a str>,
+    pub profile: Option<&'a str>,
+}
+
+/// Run the aws CLI and parse its JSON output. `input` is passed as `--cli-input-json` on
+/// stdin, which keeps secret values out of the process list.
+fn aws(opts: &Options, args: &[&str], input: Option<serde_json::Value>) -> serde_json::Value {
+    let mut command = Command::new("aws");
+    command.args(args).args(["--output", "json"]);
+    if let Some(region) = opts.region {
+        command.args(["--region", region]);
+    }
+    if let Some(profile) = opts.profile {
+        command.args(["--profile", profile]);
+    }
+    if input.is_some() {
+        command.args(["--cli-input-json", "file:///dev/stdin"]);
+    }
+    let child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            let err_msg = format!("Error! Cannot run the aws CLI, error {}", e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let mut stdin = child.stdin.take().unwrap();
+    if let Some(input) = input {
+        let _ = stdin.write_all(input.to_string().as_bytes());
+    }
+    drop(stdin);
+    match child.wait_with_output() {
+        Ok(output) if output.status.success() => {
+            serde_json::from_slice(&output.stdout).unwrap_or_default()
+        }
+        _ => {
+            let err_msg = format!("Error! aws {} failed", args[..2].join(" "));
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Every parameter under `path`, decrypted, keyed by its name relative to `path`.
+pub fn ssm_pull(opts: &Options, path: &str) -> KV {
+    let json = aws(
+        opts,
+        &[
+            "ssm",
+            "get-parameters-by-path",
+            "--path",
+            path,
+            "--recursive",
+            "--with-decryption",
+        ],
+        None,
+    );
+    let base = format!("{}/", path.trim_end_matches('/'));
+    json["Parameters"]
+        .as_array()
+        .map(|params| {
+            params
+                .iter()
+                .filter_map(|p| {
+                    let name = p["Name"].as_str()?;
+                    let key = name.strip_prefix(&base).unwrap_or(name);
+                    Some((key.to_owned(), p["Value"].as_str()?.to_owned()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Create or overwrite a parameter `<path>/<key>` for each key.
+pub fn ssm_push(opts: &Options, path: &str, kvs: &KV, secure: bool) {
+    let base = path.trim_end_matches('/');
+    let kind = if secure { "SecureString" } else { "String" };
+    for (key, val) in kvs {
+        let input = serde_json::json!({
+            "Name": format!("{}/{}", base, key),
+            "Value": val,
+            "Type": kind,
+            "Overwrite": true,
+        });
+        aws(opts, &["ssm", "put-parameter"], Some(input));
+    }
+}
+
+/// The keys of a Secrets Manager secret holding a JSON object, like the ones the AWS console
+/// creates for key/value secrets.
+pub fn secret_pull(opts: &Options, id: &str) -> KV {
+    let json = aws(
+        opts,
+        &["secretsmanager", "get-secret-value", "--secret-id", id],
+        None,
+    );
+    let secret: serde_json::Value =
+        serde_json::from_str(json["SecretString"].as_str().unwrap_or_default()).unwrap_or_default();
+    match secret.as_object() {
+        Some(map) => map
+            .iter()
+            .map(|(key, val)| match val {
+                serde_json::Value::String(s) => (key.clone(), s.clone()),
+                val => (key.clone(), val.to_string()),
+            })
+            .collect(),
+        None => {
+            let err_msg = format!(
+                "Error! Secret {} is not a JSON object of keys to values",
+                id
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Store `kvs` as a JSON object in a new version of secret `id`.
+pub fn secret_push(opts: &Options, id: &str, kvs: &KV) {
+    let input = serde_json::json!({
+        "SecretId": id,
+        "SecretString": serde_json::to_string(kvs).unwrap(),
+    });
+    aws(opts, &["secretsmanager", "put-secret-value"], Some(input));
+}
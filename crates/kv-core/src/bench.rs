@@ -0,0 +1,92 @@
+use std::process::Stdio;
+use std::time::Instant;
+
+use crate::{
+    config, get_key, get_main_store, get_value, glob, hook_command, options, print_aligned, time, Backend,
+    KVStore,
+};
+
+/// Run each hook's command to completion, timing it, and flag hooks over `hooks.warn-after`.
+///
+/// Hook output is discarded so the report stays readable.
+pub fn hooks(kvstore: &KVStore, key: Option<&str>) {
+    let budget = config::get().get_duration("hooks.warn-after");
+    let mut lines =
+        vec!["Hook Name\t--\tCmd Name\t--\tTrigger\t--\tKey\t--\tTime\t--\tStatus".to_owned()];
+    let mut slow = vec![];
+    for hook in kvstore
+        .hooks
+        .iter()
+        .filter(|hook| key.is_none_or(|k| glob::matches(&hook.key, k)))
+    {
+        let (took, status) = match get_key(&hook.cmd_name, &kvstore.cmds) {
+            Some(_) if hook.action() != "shell" => {
+                ("-".to_owned(), format!("skipped, {} hook", hook.action()))
+            }
+            Some(_) if kvstore.cmd_lang(&hook.cmd_name) != "shell" => (
+                "-".to_owned(),
+                format!("skipped, {} cmd", kvstore.cmd_lang(&hook.cmd_name)),
+            ),
+            Some(cmd) => {
+                let start = Instant::now();
+                let status = hook_command(&cmd, hook).and_then(|mut command| {
+                    command
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status()
+                        .map_err(|e| e.to_string())
+                });
+                let took = start.elapsed();
+                if took > budget {
+                    slow.push((&hook.name, took));
+                }
+                let status = match status {
+                    Ok(s) if s.success() => "ok".to_owned(),
+                    Ok(s) => match s.code() {
+                        Some(code) => format!("exit {}", code),
+                        None => "killed".to_owned(),
+                    },
+                    Err(e) => format!("failed: {}", e),
+                };
+                (time::format_duration(took), status)
+            }
+            None => ("-".to_owned(), "no such cmd".to_owned()),
+        };
+        lines.push(format!(
+            "{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}",
+            hook.name, hook.cmd_name, hook.run_on, hook.key, took, status
+        ));
+    }
+    print_aligned(lines);
+    for (name, took) in slow {
+        println!(
+            "Warning! Hook {} took {}, over the hooks.warn-after budget of {}.",
+            name,
+            time::format_duration(took),
+            time::format_duration(budget)
+        );
+    }
+}
+
+/// Look `key` up `runs` times the way `kv get` does, reading the store file each time, and
+/// report how long a lookup takes. Sharded stores only parse the shard holding the key.
+pub fn get(key: &str, runs: u32) {
+    let start = Instant::now();
+    let mut found = false;
+    for _ in 0..runs {
+        found = get_value(key).is_some();
+    }
+    let took = start.elapsed() / runs;
+    let store = match (options::get().backend(), get_main_store().shards) {
+        (Backend::Sqlite, _) => "SQLite".to_owned(),
+        (_, Some(shards)) => format!("sharded, {} shards", shards),
+        (_, None) => "single file".to_owned(),
+    };
+    print_aligned(vec![
+        format!("Key\t--\t{}", key),
+        format!("Found\t--\t{}", if found { "yes" } else { "no" }),
+        format!("Store\t--\t{}", store),
+        format!("Runs\t--\t{}", runs),
+        format!("Per get\t--\t{}us", took.as_micros()),
+    ]);
+}
@@ -0,0 +1,162 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{
+    context, get_key, get_store, index, is_secret, on_change, options, set_key, time, touch_meta,
+    with_store_lock, write_file, OpType,
+};
+
+#[derive(Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    from: Option<User>,
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: i64,
+    username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+const HELP: &str = "get <key>\nset <key> <value>";
+
+/// A Telegram bot answering `get` and `set` messages from the users in `allow`, by numeric
+/// user id. Everyone else is ignored.
+pub struct Telegram {
+    /// `<api>/bot<token>`, the base of every Bot API method URL.
+    base: String,
+    allow: Vec<i64>,
+}
+
+impl Telegram {
+    pub fn new(api: &str, token: &str, allow: Vec<i64>) -> Telegram {
+        Telegram {
+            base: format!("{}/bot{}", api.trim_end_matches('/'), token),
+            allow,
+        }
+    }
+
+    fn allowed(&self, user: &User) -> bool {
+        // Not usernames: they can be changed, and a freed one taken by someone else.
+        self.allow.contains(&user.id)
+    }
+
+    /// Wait up to 30s for messages after `offset`.
+    fn updates(&self, offset: i64) -> Result<Vec<Update>, String> {
+        ureq::get(&format!("{}/getUpdates", self.base))
+            .query("offset", &offset.to_string())
+            .query("timeout", "30")
+            .timeout(Duration::from_secs(40))
+            .call()
+            .map_err(|e| e.to_string())
+            .and_then(|res| res.into_json::<Updates>().map_err(|e| e.to_string()))
+            .map(|updates| updates.result)
+    }
+
+    fn send(&self, chat: i64, text: &str) -> Result<(), String> {
+        ureq::post(&format!("{}/sendMessage", self.base))
+            .timeout(Duration::from_secs(10))
+            .send_json(serde_json::json!({ "chat_id": chat, "text": text }))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Answer messages until interrupted. Network errors are retried every 5s.
+    pub fn run(&self) {
+        let mut offset = 0;
+        loop {
+            let updates = match self.updates(offset) {
+                Ok(updates) => updates,
+                Err(e) => {
+                    // The token is part of the URL, so it must not end up in the message.
+                    eprintln!(
+                        "Warning! Cannot reach Telegram, error {}. Retrying in 5s",
+                        e.replace(&self.base, "<api>")
+                    );
+                    std::thread::sleep(Duration::from_secs(5));
+                    continue;
+                }
+            };
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(message) = update.message else {
+                    continue;
+                };
+                let (Some(user), Some(text)) = (&message.from, &message.text) else {
+                    continue;
+                };
+                if !self.allowed(user) {
+                    eprintln!(
+                        "{}\tignored a message from user {} ({}), who is not allowed",
+                        time::format_timestamp(time::now()),
+                        user.id,
+                        user.username.as_deref().unwrap_or("-")
+                    );
+                    continue;
+                }
+                let reply = answer(text);
+                if let Err(e) = self.send(message.chat.id, &reply) {
+                    eprintln!(
+                        "Warning! Cannot answer on Telegram, error {}",
+                        e.replace(&self.base, "<api>")
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The reply to message `text`. Commands may start with a slash, like Telegram's own.
+fn answer(text: &str) -> String {
+    let text = text.trim();
+    let mut words = text.trim_start_matches('/').splitn(3, char::is_whitespace);
+    match (words.next(), words.next(), words.next()) {
+        (Some("get"), Some(key), None) => get(&context::key(key)),
+        (Some("set"), Some(key), Some(value)) => set(&context::key(key), value.trim_start()),
+        _ => HELP.to_owned(),
+    }
+}
+
+/// The raw value of `key`. Secrets and password manager references are never resolved,
+/// since the answer goes through Telegram.
+fn get(key: &str) -> String {
+    let kvstore = get_store();
+    match get_key(key, &kvstore.kvs) {
+        Some(_) if is_secret(key, &kvstore.meta) => format!("{} is secret", key),
+        Some(value) => value,
+        None => format!("{} does not exist", key),
+    }
+}
+
+fn set(key: &str, value: &str) -> String {
+    if options::get().read_only() {
+        return "The store is read-only".to_owned();
+    }
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        let old = get_key(key, &kvstore.kvs);
+        set_key(key, value, &mut kvstore.kvs);
+        touch_meta(key, "bot:telegram", &mut kvstore.meta);
+        write_file(&kvstore);
+        index::update(&kvstore, key, old.as_deref(), Some(value));
+    });
+    on_change(key, OpType::Set, Some(value));
+    format!("Set {}", key)
+}
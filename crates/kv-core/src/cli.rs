@@ -0,0 +1,3239 @@
+use std::env;
+
+use clap::{self, value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use super::*;
+
+/// Full name of the subcommand being run, e.g. `cmd run`.
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = vec![];
+    let mut m = matches;
+    while let (name, Some(sub)) = m.subcommand() {
+        names.push(name);
+        m = sub;
+    }
+    names.join(" ")
+}
+
+/// How `kv -o json` shows a key: its value (null if unset) and metadata. The key is shown
+/// without the namespace.
+fn key_json(kvstore: &KVStore, key: &str, value: Option<&str>) -> serde_json::Value {
+    let shown = key.strip_prefix(&context::prefix()[..]).unwrap_or(key);
+    let mut object = columns::json_row(columns::NAMES, kvstore, key, shown, "");
+    object["value"] = serde_json::json!(value);
+    object
+}
+
+fn print_json(value: &serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// What `kv del` prints: the old value, or with `json` the key and its old value (null if
+/// it didn't exist).
+fn print_deleted(key: &str, old: Option<&str>, json: bool) {
+    if json {
+        let shown = key.strip_prefix(&context::prefix()[..]).unwrap_or(key);
+        print_json(&serde_json::json!({ "key": shown, "value": old }));
+    } else {
+        print_res(old.map(str::to_owned));
+    }
+}
+
+/// `kv get <key>`: print the value, resolving references unless `raw`, and run its get hooks.
+/// With `json`, print it with its metadata, see `key_json`.
+fn run_get(key: &str, raw: bool, qr: bool, json: bool) {
+    let key = &context::key(key)[..];
+    let kvstore = get_main_store();
+    let value = get_value_in(key, &kvstore);
+    let value = if raw { value } else { value.map(refs::resolve) };
+    match value {
+        Some(value) if qr => qr::print(&value),
+        None if qr => {
+            let err_msg = format!("Error! Key {} does not exist!", key);
+            print_err(&err_msg[..]);
+        }
+        value if json => print_json(&key_json(&kvstore, key, value.as_deref())),
+        value => print_res(value),
+    }
+    // Most stores have no hooks, and loading the store again to find that out would cost
+    // a prompt calling kv get as much as the lookup itself.
+    if !kvstore.hooks.is_empty() {
+        run_hooks(key, &OpType::Get);
+    }
+}
+
+/// Warn that secret `key`'s value was given on the command line, unless `secrets.warn-argv`
+/// is off.
+fn warn_secret_argv(key: &str, secret: bool, kvstore: &KVStore) {
+    if (secret || is_secret(key, &kvstore.meta)) && config::get().get_bool("secrets.warn-argv") {
+        eprintln!(
+            "Warning! {} is secret, but its value was passed on the command line, where ps and shell history can see it. Next time try\n kv set --prompt {}",
+            key, key
+        );
+    }
+}
+
+fn warn_machine_override(key: &str, kvstore: &KVStore) {
+    if kvstore.machine.contains_key(key) {
+        eprintln!(
+            "Warning! {} is overridden on this machine, so it still reads the override here. To drop the override try\n kv del --local-machine {}",
+            key, key
+        );
+    }
+}
+
+/// The `--ttl` of `kv set`.
+fn set_ttl(set: &ArgMatches) -> Option<std::time::Duration> {
+    set.value_of("ttl")
+        .map(|ttl| time::parse_duration(ttl).unwrap_or_else(|e| print_err(&format!("Error! {}", e))))
+}
+
+/// Set `key` in `kvstore` the way `kv set` does, returning the old value. The caller writes
+/// the store.
+fn set_from_cli(
+    kvstore: &mut KVStore,
+    key: &str,
+    value: &str,
+    secret: bool,
+    ttl: Option<std::time::Duration>,
+) -> Option<String> {
+    let old = get_key(key, &kvstore.kvs);
+    set_key(key, value, &mut kvstore.kvs);
+    touch_meta(key, "cli", &mut kvstore.meta);
+    let meta = kvstore.meta.get_mut(key).unwrap();
+    if secret {
+        meta.secret = true;
+    }
+    meta.expires = ttl.map(|ttl| time::now().saturating_add(ttl.as_secs()));
+    old
+}
+
+/// `kv set k1=v1 k2=v2 ...`: all the keys are set in one read and write of the store, then
+/// hooks run for each key in the order given.
+fn set_many(set: &ArgMatches, kvstore: &KVStore, pairs: &[&str]) {
+    let mut parsed = vec![];
+    for pair in pairs {
+        let (key, value) = pair.split_once('=').unwrap();
+        if key.is_empty() {
+            let err_msg = format!("Error! {} has no key before the =. Nothing was changed.", pair);
+            print_err(&err_msg[..]);
+        }
+        let key = context::key(key);
+        let value = transformed(kvstore, &key, value);
+        parsed.push((key, value));
+    }
+    let secret = set.is_present("secret");
+    for (key, _) in &parsed {
+        warn_secret_argv(key, secret, kvstore);
+    }
+    if set.is_present("local-machine") {
+        let mut layer = machine::load();
+        for (key, value) in &parsed {
+            layer.insert(key.clone(), value.clone());
+        }
+        machine::save(&layer);
+        for (key, value) in &parsed {
+            on_change(key, OpType::Set, Some(value));
+        }
+        return;
+    }
+    let ttl = set_ttl(set);
+    for (key, _) in &parsed {
+        warn_machine_override(key, kvstore);
+    }
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        let olds: Vec<Option<String>> = parsed
+            .iter()
+            .map(|(key, value)| set_from_cli(&mut kvstore, key, value, secret, ttl))
+            .collect();
+        write_file(&kvstore);
+        for ((key, value), old) in parsed.iter().zip(olds) {
+            index::update(&kvstore, key, old.as_deref(), Some(value));
+        }
+    });
+    for (key, value) in &parsed {
+        on_change(key, OpType::Set, Some(value));
+    }
+}
+
+/// `value` as the transforms on `key` leave it, exiting if one rejects it.
+fn transformed(kvstore: &KVStore, key: &str, value: &str) -> String {
+    transform::apply(kvstore, key, value).unwrap_or_else(|e| {
+        let err_msg = format!("Error! Cannot set {}, {}. Nothing was changed.", key, e);
+        print_err(&err_msg[..]);
+    })
+}
+
+/// `kv get` with several keys: one value per line, or with `json` one object of keys and
+/// values (null if unset). The store is read once for all of them.
+fn run_get_many(keys: &[&str], raw: bool, json: bool) {
+    let kvstore = get_main_store();
+    let keys: Vec<String> = keys.iter().map(|&key| context::key(key)).collect();
+    let values: Vec<Option<String>> = keys
+        .iter()
+        .map(|key| {
+            let value = get_value_in(key, &kvstore);
+            if raw {
+                value
+            } else {
+                value.map(refs::resolve)
+            }
+        })
+        .collect();
+    if json {
+        let prefix = context::prefix();
+        let object: serde_json::Map<_, _> = keys
+            .iter()
+            .zip(&values)
+            .map(|(key, value)| {
+                let shown = key.strip_prefix(&prefix[..]).unwrap_or(key);
+                (shown.to_owned(), serde_json::json!(value))
+            })
+            .collect();
+        print_json(&serde_json::Value::Object(object));
+    } else {
+        values.into_iter().for_each(print_res);
+    }
+    if !kvstore.hooks.is_empty() {
+        for key in &keys {
+            run_hooks(key, &OpType::Get);
+        }
+    }
+}
+
+/// Set the store options from the context, `--no-lock` and `$SHELL`, and warn about sync
+/// conflicts. `subcommand` is the one being run.
+fn open_store(subcommand: Option<&str>, no_lock: bool, detach: bool) {
+    let mut builder = KVStore::builder()
+        .read_only(stateless::is_active())
+        .autolock(!no_lock)
+        .detach_hooks(detach);
+    // Managing contexts must keep working when KV_CONTEXT names one that doesn't exist.
+    if subcommand != Some("context") && subcommand != Some("profile") {
+        if let Some(store) = context::store() {
+            builder = builder.path(store);
+        }
+    }
+    if let Ok(shell) = env::var("SHELL") {
+        builder = builder.shell(shell);
+    }
+    if config::try_get().is_ok_and(|config| config.value("persist.backend") == "sqlite") {
+        builder = builder.backend(Backend::Sqlite);
+    }
+    builder.apply().unwrap_or_else(|e| fail(e));
+    if subcommand != Some("sync") {
+        sync::warn_conflicts();
+    }
+}
+
+/// `kv secret set|get|del`.
+#[cfg(feature = "crypto")]
+fn run_secret(matches: &ArgMatches) {
+    if let Some(m_set) = matches.subcommand_matches("set") {
+        let key = &context::key(m_set.value_of("key").unwrap())[..];
+        let value = match m_set.value_of("val") {
+            Some(value) => value.to_owned(),
+            None => match prompt::hidden(&format!("Value for {}: ", key)) {
+                Some(value) => value,
+                None => print_err("Error! No value entered. Nothing was changed."),
+            },
+        };
+        let sealed = secret::seal(key, &value);
+        with_store_lock(|| {
+            let mut kvstore = get_store();
+            let old = get_key(key, &kvstore.kvs);
+            set_key(key, &sealed, &mut kvstore.kvs);
+            touch_meta(key, "secret", &mut kvstore.meta);
+            kvstore.meta.get_mut(key).unwrap().secret = true;
+            write_file(&kvstore);
+            index::update(&kvstore, key, old.as_deref(), Some(&sealed));
+        });
+        on_change(key, OpType::Set, Some(&sealed));
+    }
+    if let Some(m_get) = matches.subcommand_matches("get") {
+        let key = &context::key(m_get.value_of("key").unwrap())[..];
+        match get_value(key) {
+            Some(value) if secret::is_encrypted(&value) => println!("{}", secret::reveal(key, &value)),
+            Some(_) => {
+                let err_msg = format!("Error! {} is not encrypted. To read it try\n kv get {}", key, key);
+                print_err(&err_msg[..]);
+            }
+            None => {
+                let err_msg = format!("Error! Key {} does not exist!", key);
+                print_err(&err_msg[..]);
+            }
+        }
+    }
+    if let Some(m_del) = matches.subcommand_matches("del") {
+        let key = &context::key(m_del.value_of("key").unwrap())[..];
+        let old = with_store_lock(|| {
+            let mut kvstore = get_store();
+            let old = del_key(key, &mut kvstore.kvs);
+            if old.is_some() {
+                kvstore.meta.remove(key);
+                write_file(&kvstore);
+                index::update(&kvstore, key, old.as_deref(), None);
+            }
+            old
+        });
+        if old.is_some() {
+            on_change(key, OpType::Del, None);
+        }
+    }
+}
+
+/// `value` of `key` as `kv keys --show` prints it: decrypted if `kv secret set` stored it.
+#[cfg_attr(not(feature = "crypto"), allow(unused_variables))]
+fn plain_value<'a>(key: &str, value: &'a str) -> std::borrow::Cow<'a, str> {
+    #[cfg(feature = "crypto")]
+    if secret::is_encrypted(value) {
+        return std::borrow::Cow::Owned(secret::reveal(key, value));
+    }
+    std::borrow::Cow::Borrowed(value)
+}
+
+fn run(matches: ArgMatches) {
+    if let Some(name) = matches.subcommand_name() {
+        features::check(name);
+    }
+    let json_output = matches.value_of("output") == Some("json");
+    #[cfg(feature = "crypto")]
+    if let Some(m_totp) = matches.subcommand_matches("totp") {
+        let key = &context::key(m_totp.value_of("key").unwrap())[..];
+        let seed = match get_value(key) {
+            Some(seed) => refs::resolve(seed),
+            None => {
+                let err_msg = format!("Error! Key {} does not exist!", key);
+                print_err(&err_msg[..]);
+            }
+        };
+        let totp = match totp::Totp::parse(&seed) {
+            Ok(totp) => totp,
+            Err(e) => {
+                let err_msg = format!("Error! Cannot use {} for TOTP, {}", key, e);
+                print_err(&err_msg[..]);
+            }
+        };
+        let (code, left) = totp.code(time::now());
+        if m_totp.is_present("copy") {
+            if let Err(e) = clipboard::copy(&code) {
+                print_err(&format!("Error! Cannot copy the code, {}", e));
+            }
+            eprintln!("Copied, valid for {}s", left);
+        } else {
+            println!("{}", code);
+        }
+        return;
+    }
+    #[cfg(feature = "crypto")]
+    if let Some(m_secret) = matches.subcommand_matches("secret") {
+        run_secret(m_secret);
+        return;
+    }
+    if let Some(m_context) = matches.subcommand_matches("context") {
+        if let Some(m_create) = m_context.subcommand_matches("create") {
+            context::create(
+                m_create.value_of("name").unwrap(),
+                context::Context {
+                    store: m_create.value_of("store").map(PathBuf::from),
+                    namespace: m_create.value_of("namespace").map(|ns| ns.trim_end_matches('/').to_owned()),
+                    format: m_create.value_of("format").map(|f| f.to_owned()),
+                },
+            );
+        } else if let Some(m_use) = m_context.subcommand_matches("use") {
+            context::use_context(m_use.value_of("name"));
+        } else if let Some(m_delete) = m_context.subcommand_matches("delete") {
+            context::delete(m_delete.value_of("name").unwrap());
+        } else {
+            context::show();
+        }
+        return;
+    }
+    if let Some(m_profile) = matches.subcommand_matches("profile") {
+        if let Some(m_create) = m_profile.subcommand_matches("create") {
+            context::create_profile(m_create.value_of("name").unwrap());
+        } else if let Some(m_use) = m_profile.subcommand_matches("use") {
+            context::use_context(m_use.value_of("name"));
+        } else if let Some(m_delete) = m_profile.subcommand_matches("delete") {
+            context::delete(m_delete.value_of("name").unwrap());
+        } else {
+            context::show();
+        }
+        return;
+    }
+    if let Some(get) = matches.subcommand_matches("get") {
+        let keys: Vec<&str> = get.values_of("key").unwrap().collect();
+        if keys.len() > 1 && get.is_present("qr") {
+            print_err("Error! --qr shows one key at a time.");
+        }
+        if let Some(stores) = get.values_of("stores") {
+            // Keys are looked up in each store's own namespace, so the active one isn't added.
+            let sources: Vec<_> = stores.map(federation::source).collect();
+            let lookup = |key: &str| {
+                let value = sources.iter().find_map(|source| source.get(key).map(|v| v.to_owned()));
+                if get.is_present("raw") {
+                    value
+                } else {
+                    value.map(refs::resolve)
+                }
+            };
+            if let [key] = keys[..] {
+                match lookup(key) {
+                    Some(value) if get.is_present("qr") => qr::print(&value),
+                    None if get.is_present("qr") => {
+                        let err_msg = format!("Error! Key {} does not exist in any of the stores!", key);
+                        print_err(&err_msg[..]);
+                    }
+                    value if json_output => print_json(&serde_json::json!({ "key": key, "value": value })),
+                    value => print_res(value),
+                }
+            } else if json_output {
+                let values: serde_json::Map<_, _> =
+                    keys.iter().map(|&key| (key.to_owned(), serde_json::json!(lookup(key)))).collect();
+                print_json(&serde_json::Value::Object(values));
+            } else {
+                keys.iter().for_each(|&key| print_res(lookup(key)));
+            }
+            return;
+        }
+        match keys[..] {
+            [key] => run_get(key, get.is_present("raw"), get.is_present("qr"), json_output),
+            _ => run_get_many(&keys, get.is_present("raw"), json_output),
+        }
+        return;
+    }
+    if let Some(m_init) = matches.subcommand_matches("shell-init") {
+        let name = m_init.value_of("name").unwrap();
+        let script = shell_init::script(m_init.value_of("shell").unwrap(), name)
+            .unwrap_or_else(|e| print_err(&format!("Error! {}", e)));
+        print!("{}", script);
+        return;
+    }
+    if matches.subcommand_matches("cd-keys").is_some() {
+        let kvstore = get_store();
+        let namespace = context::prefix();
+        for key in shell_init::dir_keys(&kvstore.kvs) {
+            if let Some(key) = key.strip_prefix(&namespace[..]) {
+                println!("{}", key);
+            }
+        }
+        return;
+    }
+    if let Some(m_watch) = matches.subcommand_matches("watch") {
+        let interval = time::parse_duration(m_watch.value_of("interval").unwrap())
+            .unwrap_or_else(|e| print_err(&format!("Error! {}", e)));
+        watch::run(
+            &context::key(m_watch.value_of("key").unwrap()),
+            interval,
+            m_watch.value_of("exec"),
+            m_watch.is_present("initial"),
+        );
+        return;
+    }
+    if let Some(m_open) = matches.subcommand_matches("open") {
+        let key = &context::key(m_open.value_of("key").unwrap())[..];
+        let value = match get_value(key) {
+            Some(value) => refs::resolve(value),
+            None => {
+                let err_msg = format!("Error! Key {} does not exist!", key);
+                print_err(&err_msg[..]);
+            }
+        };
+        if let Err(e) = opener::open(&value, m_open.value_of("with")) {
+            print_err(&format!("Error! Cannot open {}, {}", key, e));
+        }
+        run_hooks(key, &OpType::Get);
+        return;
+    }
+    if let Some(m_simulate) = matches.subcommand_matches("simulate") {
+        let (op, m_op) = match m_simulate.subcommand() {
+            ("set", Some(m_op)) => (OpType::Set, m_op),
+            ("del", Some(m_op)) => (OpType::Del, m_op),
+            (_, m_op) => (OpType::Get, m_op.unwrap()),
+        };
+        let key = &context::key(m_op.value_of("key").unwrap())[..];
+        simulate::run(key, op, m_op.value_of("val"));
+        return;
+    }
+    let mut kvstore = get_store();
+    if let Some(m_mktemp) = matches.subcommand_matches("mktemp") {
+        let prefix = &context::key(m_mktemp.value_of("prefix").unwrap())[..];
+        let value = m_mktemp.value_of("val").unwrap_or("");
+        let key = with_store_lock(|| {
+            let mut kvstore = get_store();
+            let key = unused_key(prefix, &kvstore.kvs);
+            set_key(&key, value, &mut kvstore.kvs);
+            touch_meta(&key, "mktemp", &mut kvstore.meta);
+            write_file(&kvstore);
+            index::update(&kvstore, &key, None, Some(value));
+            key
+        });
+        println!("{}", key.strip_prefix(&context::prefix()[..]).unwrap_or(&key));
+        on_change(&key, OpType::Set, Some(value));
+    }
+    if let Some(set) = matches.subcommand_matches("set") {
+        let first = set.value_of("key").unwrap();
+        let vals: Vec<&str> = set.values_of("val").map(|vals| vals.collect()).unwrap_or_default();
+        let from_file = set.value_of("from-file");
+        if set.is_present("many") {
+            let pairs: Vec<&str> = std::iter::once(first).chain(vals).collect();
+            if let Some(pair) = pairs.iter().find(|pair| !pair.contains('=')) {
+                let err_msg = format!(
+                    "Error! {} is not a <key>=<val> pair. Nothing was changed. To set several keys at once try\n kv set --many k1=v1 k2=v2",
+                    pair
+                );
+                print_err(&err_msg[..]);
+            }
+            set_many(set, &kvstore, &pairs);
+            return;
+        }
+        let key = &context::key(first)[..];
+        if vals.len() > 1 {
+            let err_msg = format!(
+                "Error! kv set takes one value for {}. To set several keys at once try\n kv set --many k1=v1 k2=v2",
+                key
+            );
+            print_err(&err_msg[..]);
+        }
+        let read;
+        let value = match (vals.first(), from_file) {
+            (Some(&"-"), _) => {
+                read = match std::io::read_to_string(std::io::stdin()) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let err_msg = format!("Error! Cannot read the value for {} from stdin, error {}", key, e);
+                        print_err(&err_msg[..]);
+                    }
+                };
+                &read[..]
+            }
+            (Some(&value), _) => {
+                warn_secret_argv(key, set.is_present("secret"), &kvstore);
+                value
+            }
+            (None, Some(path)) => {
+                read = match std::fs::read_to_string(path) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+                        print_err(&err_msg[..]);
+                    }
+                };
+                &read[..]
+            }
+            (None, None) if set.is_present("prompt") => {
+                read = match prompt::hidden(&format!("Value for {}: ", key)) {
+                    Some(value) => value,
+                    None => print_err("Error! No value entered. Nothing was changed."),
+                };
+                &read[..]
+            }
+            (None, None) => {
+                let err_msg = format!("Error! No value for {}. To type it at a prompt try\n kv set --prompt {}", key, key);
+                print_err(&err_msg[..]);
+            }
+        };
+        let value = &transformed(&kvstore, key, value)[..];
+        // Overrides have no metadata, so clap refuses --secret and --ttl with this.
+        if set.is_present("local-machine") {
+            let mut layer = machine::load();
+            layer.insert(key.to_owned(), value.to_owned());
+            machine::save(&layer);
+            on_change(key, OpType::Set, Some(value));
+            return;
+        }
+        let ttl = set_ttl(set);
+        warn_machine_override(key, &kvstore);
+        with_store_lock(|| {
+            let mut kvstore = get_store();
+            let old = set_from_cli(&mut kvstore, key, value, set.is_present("secret"), ttl);
+            write_file(&kvstore);
+            index::update(&kvstore, key, old.as_deref(), Some(value));
+        });
+        on_change(key, OpType::Set, Some(value));
+    }
+    if let Some(del) = matches.subcommand_matches("del") {
+        let key = &context::key(del.value_of("key").unwrap())[..];
+        if del.is_present("local-machine") {
+            let mut layer = machine::load();
+            let value = layer.remove(key);
+            machine::save(&layer);
+            print_deleted(key, value.as_deref(), json_output);
+            on_change(key, OpType::Del, None);
+        } else if del.is_present("glob") {
+            let doomed = with_store_lock(|| {
+                let mut kvstore = get_store();
+                let doomed: Vec<String> = kvstore
+                    .kvs
+                    .keys()
+                    .filter(|k| glob::matches(key, k))
+                    .cloned()
+                    .collect();
+                if !doomed.is_empty() && !del.is_present("no-snapshot") {
+                    snapshot::before(&kvstore, "del");
+                }
+                for k in &doomed {
+                    del_key(k, &mut kvstore.kvs);
+                    kvstore.meta.remove(k);
+                }
+                write_file(&kvstore);
+                index::refresh(&kvstore);
+                doomed
+            });
+            if json_output {
+                print_json(&serde_json::json!(doomed));
+            } else {
+                doomed.iter().for_each(|k| println!("{}", k));
+            }
+            for k in &doomed {
+                on_change(k, OpType::Del, None);
+            }
+        } else {
+            let value = with_store_lock(|| {
+                let mut kvstore = get_store();
+                let value = del_key(key, &mut kvstore.kvs);
+                kvstore.meta.remove(key);
+                write_file(&kvstore);
+                index::update(&kvstore, key, value.as_deref(), None);
+                value
+            });
+            print_deleted(key, value.as_deref(), json_output);
+            on_change(key, OpType::Del, None);
+        }
+    }
+    if let Some(m_rename) = matches.subcommand_matches("rename-prefix") {
+        let from = &context::key(m_rename.value_of("from").unwrap())[..];
+        let to = &context::key(m_rename.value_of("to").unwrap())[..];
+        let (renames, kvstore) = with_store_lock(|| {
+            let mut kvstore = get_store();
+            let renames: Vec<(String, String)> = kvstore
+                .kvs
+                .keys()
+                .filter(|k| k.starts_with(from))
+                .map(|k| (k.clone(), format!("{}{}", to, &k[from.len()..])))
+                .collect();
+            // Keys that aren't renamed themselves would be overwritten.
+            let collisions: Vec<&str> = renames
+                .iter()
+                .map(|(_, new)| &new[..])
+                .filter(|new| kvstore.kvs.contains_key(*new) && !new.starts_with(from))
+                .collect();
+            if !collisions.is_empty() && !m_rename.is_present("force") {
+                let shown = context::prefix();
+                let err_msg = format!(
+                    "Error! Renaming would overwrite {}. To overwrite them try\n kv rename-prefix --force {} {}",
+                    collisions
+                        .iter()
+                        .map(|key| key.strip_prefix(&shown[..]).unwrap_or(key))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    m_rename.value_of("from").unwrap(),
+                    m_rename.value_of("to").unwrap()
+                );
+                print_err(&err_msg[..]);
+            }
+            if !renames.is_empty() && !m_rename.is_present("no-snapshot") {
+                snapshot::before(&kvstore, "rename-prefix");
+            }
+            // Take everything out before putting anything back, in case old and new names overlap.
+            let moved: Vec<(String, String, Option<Meta>)> = renames
+                .iter()
+                .map(|(old, new)| {
+                    let val = del_key(old, &mut kvstore.kvs).unwrap();
+                    (new.clone(), val, kvstore.meta.remove(old))
+                })
+                .collect();
+            for (new, val, meta) in moved {
+                set_key(&new, &val, &mut kvstore.kvs);
+                if let Some(meta) = meta {
+                    kvstore.meta.insert(new, meta);
+                }
+            }
+            write_file(&kvstore);
+            index::refresh(&kvstore);
+            (renames, kvstore)
+        });
+        let namespace = context::prefix();
+        for (old, new) in &renames {
+            let shown = |key: &str| key.strip_prefix(&namespace[..]).unwrap_or(key).to_owned();
+            println!("{} -> {}", shown(old), shown(new));
+            on_change(old, OpType::Del, None);
+            on_change(new, OpType::Set, kvstore.kvs.get(new).map(|v| &v[..]));
+        }
+    }
+    if let Some(m_lock) = matches.subcommand_matches("lock") {
+        let duration_arg = |m: &ArgMatches, name: &str| {
+            time::parse_duration(m.value_of(name).unwrap())
+                .unwrap_or_else(|e| print_err(&format!("Error! {}", e)))
+        };
+        if let Some(m_acquire) = m_lock.subcommand_matches("acquire") {
+            let token = lease::acquire(
+                &context::key(m_acquire.value_of("name").unwrap()),
+                duration_arg(m_acquire, "ttl"),
+                m_acquire.value_of("token"),
+                duration_arg(m_acquire, "wait"),
+            );
+            println!("{}", token);
+        }
+        if let Some(m_renew) = m_lock.subcommand_matches("renew") {
+            lease::renew(
+                &context::key(m_renew.value_of("name").unwrap()),
+                m_renew.value_of("token").unwrap(),
+                duration_arg(m_renew, "ttl"),
+            );
+        }
+        if let Some(m_release) = m_lock.subcommand_matches("release") {
+            lease::release(
+                &context::key(m_release.value_of("name").unwrap()),
+                m_release.value_of("token"),
+            );
+        }
+        if m_lock.subcommand_matches("list").is_some() {
+            print_aligned(lease::list());
+        }
+    }
+    if let Some(m_queue) = matches.subcommand_matches("queue") {
+        if let Some(m_push) = m_queue.subcommand_matches("push") {
+            let key = &context::key(m_push.value_of("key").unwrap())[..];
+            let vals: Vec<&str> = m_push.values_of("val").unwrap().collect();
+            let value = queue::push(key, &vals);
+            on_change(key, OpType::Set, Some(&value));
+        }
+        if let Some(m_pop) = m_queue.subcommand_matches("pop") {
+            let key = &context::key(m_pop.value_of("key").unwrap())[..];
+            let wait = time::parse_duration(m_pop.value_of("wait").unwrap())
+                .unwrap_or_else(|e| print_err(&format!("Error! {}", e)));
+            match queue::pop(key, wait) {
+                Some((item, value)) => {
+                    println!("{}", item);
+                    on_change(key, OpType::Set, Some(&value));
+                }
+                None => queue::exit_empty(key),
+            }
+        }
+        if let Some(m_len) = m_queue.subcommand_matches("len") {
+            println!("{}", queue::len(&context::key(m_len.value_of("key").unwrap())));
+        }
+    }
+    if let Some(m_import) = matches.subcommand_matches("import") {
+        let (source, incoming) = match m_import.value_of("from-k8s") {
+            Some(res) => {
+                let resource = k8s::parse_resource(res);
+                let incoming = k8s::read(&resource, m_import.value_of("namespace"));
+                (format!("import:k8s:{}", res), incoming)
+            }
+            None => {
+                let file = m_import.value_of("file").unwrap();
+                let format = match m_import.value_of("format") {
+                    Some(format) => format,
+                    None if m_import.is_present("csv") || file.to_lowercase().ends_with(".csv") => "csv",
+                    None if dotenv::is_env_file(file) => "env",
+                    None => "json",
+                };
+                let incoming = match format {
+                    "csv" => {
+                        let mapping = m_import.value_of("map").map(|spec| {
+                            csv::parse_map(spec).unwrap_or_else(|e| print_err(&format!("Error! {}", e)))
+                        });
+                        csv::read_file(file, mapping.as_ref())
+                    }
+                    "env" => dotenv::read_file(file),
+                    _ => import::read_file(file),
+                };
+                (format!("import:{}", file), incoming)
+            }
+        };
+        let incoming = match m_import.value_of("prefix") {
+            Some(prefix) => incoming
+                .into_iter()
+                .map(|(key, val)| (format!("{}{}", prefix, key), val))
+                .collect(),
+            None => incoming,
+        };
+        // With --secret every key left with the imported value is flagged, even one that
+        // already had it.
+        let secret = if m_import.is_present("secret") {
+            incoming.clone()
+        } else {
+            KV::new()
+        };
+        let (changed, removed) = if m_import.is_present("replace") {
+            if !m_import.is_present("no-snapshot") {
+                snapshot::before(&kvstore, "import-replace");
+            }
+            import::replace(&mut kvstore, incoming)
+        } else {
+            let strategy = if m_import.is_present("interactive") {
+                import::Strategy::Interactive
+            } else {
+                match m_import.value_of("strategy") {
+                    Some("keep") => import::Strategy::Keep,
+                    Some("replace") => import::Strategy::Replace,
+                    _ => import::Strategy::Fail,
+                }
+            };
+            (import::merge(&mut kvstore, incoming, strategy), vec![])
+        };
+        let mut changed = changed;
+        for (key, val) in &secret {
+            if kvstore.kvs.get(key) == Some(val) {
+                touch_meta(key, &source, &mut kvstore.meta);
+                kvstore.meta.get_mut(key).unwrap().secret = true;
+                if !changed.contains(key) {
+                    changed.push(key.clone());
+                }
+            }
+        }
+        save_imported(&mut kvstore, &changed, &removed, &source);
+        println!("Imported {} keys, removed {} keys", changed.len(), removed.len());
+    }
+    #[cfg(feature = "net")]
+    if let Some(m_bot) = matches.subcommand_matches("bot") {
+        let allow = m_bot
+            .values_of("allow")
+            .unwrap()
+            .map(|a| {
+                a.parse().unwrap_or_else(|_| {
+                    print_err(&format!(
+                        "Error! {:?} is not a Telegram user id. Usernames can be changed and taken over, so only numeric ids are allowed; the bot logs the id of everyone who messages it",
+                        a
+                    ))
+                })
+            })
+            .collect();
+        let token = m_bot.value_of("telegram-token").unwrap();
+        bot::Telegram::new(m_bot.value_of("telegram-api").unwrap(), token, allow).run();
+    }
+    #[cfg(feature = "net")]
+    if let Some(m_serve) = matches.subcommand_matches("serve") {
+        let port = value_t!(m_serve, "port", u16).unwrap_or_else(|e| e.exit());
+        let addr = format!("{}:{}", m_serve.value_of("bind").unwrap(), port);
+        let hosts = m_serve
+            .values_of("host")
+            .map(|hosts| hosts.map(|h| h.to_owned()).collect())
+            .unwrap_or_default();
+        let idle_timeout = m_serve.value_of("idle-timeout").map(|timeout| {
+            time::parse_duration(timeout).unwrap_or_else(|e| print_err(&format!("Error! {}", e)))
+        });
+        serve::run(
+            &addr,
+            m_serve.value_of("token"),
+            m_serve.is_present("insecure"),
+            hosts,
+            idle_timeout,
+        );
+    }
+    #[cfg(feature = "net")]
+    if let Some(m_daemon) = matches.subcommand_matches("daemon") {
+        if let Some(m_install) = m_daemon.subcommand_matches("install-service") {
+            let serve_args: Vec<&str> = m_install.values_of("serve-args").map(|args| args.collect()).unwrap_or_default();
+            service::install(
+                m_install.value_of("name").unwrap(),
+                matches.value_of("store"),
+                &serve_args,
+                m_install.is_present("no-start"),
+            );
+        }
+        if let Some(m_uninstall) = m_daemon.subcommand_matches("uninstall-service") {
+            service::uninstall(m_uninstall.value_of("name").unwrap(), m_uninstall.is_present("no-stop"));
+        }
+    }
+    #[cfg(feature = "net")]
+    if let Some(m_digest) = matches.subcommand_matches("digest") {
+        let every = if m_digest.is_present("daily") {
+            Some(std::time::Duration::from_secs(24 * 60 * 60))
+        } else {
+            m_digest
+                .value_of("every")
+                .map(|every| time::parse_duration(every).unwrap_or_else(|e| print_err(&format!("Error! {}", e))))
+        };
+        digest::send(m_digest.value_of("to").unwrap(), every, m_digest.is_present("print"));
+    }
+    if let Some(m_sync) = matches.subcommand_matches("sync") {
+        if let Some(m_merge) = m_sync.subcommand_matches("merge") {
+            let file = m_merge.value_of("file").unwrap();
+            let incoming = import::read_file(file);
+            let changed = import::merge(&mut kvstore, incoming, import::Strategy::Interactive);
+            save_imported(&mut kvstore, &changed, &[], &format!("import:{}", file));
+            if let Err(e) = std::fs::remove_file(file) {
+                let err_msg = format!("Error! Merged {} keys, but cannot remove {}, error {}", changed.len(), file, e);
+                print_err(&err_msg[..]);
+            }
+            println!("Merged {} keys and removed {}", changed.len(), file);
+        } else {
+            sync::status();
+        }
+    }
+    if let Some(m_ask) = matches.subcommand_matches("ask") {
+        let key = &context::key(m_ask.value_of("key").unwrap())[..];
+        let ask = |msg: &str| match prompt::hidden(msg) {
+            Some(value) if !value.is_empty() => value,
+            _ => print_err("Error! No value entered. Nothing was changed."),
+        };
+        let msg = match m_ask.value_of("prompt") {
+            Some(msg) => format!("{} ", msg),
+            None => format!("Value for {}: ", key),
+        };
+        let value = ask(&msg);
+        if m_ask.is_present("confirm") && ask("Again to confirm: ") != value {
+            print_err("Error! The values don't match. Nothing was changed.");
+        }
+        with_store_lock(|| {
+            let mut kvstore = get_store();
+            let old = get_key(key, &kvstore.kvs);
+            set_key(key, &value, &mut kvstore.kvs);
+            touch_meta(key, "ask", &mut kvstore.meta);
+            if !m_ask.is_present("not-secret") {
+                kvstore.meta.get_mut(key).unwrap().secret = true;
+            }
+            write_file(&kvstore);
+            index::update(&kvstore, key, old.as_deref(), Some(&value));
+        });
+        on_change(key, OpType::Set, Some(&value));
+    }
+    #[cfg(all(feature = "net", feature = "crypto"))]
+    if let Some(m_share) = matches.subcommand_matches("share") {
+        if let Some(link) = m_share.value_of("receive") {
+            let (shared_key, value) = share::receive(link);
+            if m_share.is_present("print") {
+                println!("{}", value);
+            } else {
+                let shown = m_share.value_of("as").unwrap_or(&shared_key);
+                let key = &context::key(shown)[..];
+                with_store_lock(|| {
+                    let mut kvstore = get_store();
+                    let old = get_key(key, &kvstore.kvs);
+                    set_key(key, &value, &mut kvstore.kvs);
+                    touch_meta(key, "share", &mut kvstore.meta);
+                    kvstore.meta.get_mut(key).unwrap().secret = true;
+                    write_file(&kvstore);
+                    index::update(&kvstore, key, old.as_deref(), Some(&value));
+                });
+                println!("Received {}", shown);
+                on_change(key, OpType::Set, Some(&value));
+            }
+        } else {
+            // The link carries the key without the namespace, for the receiver's own.
+            let shown = m_share.value_of("key").unwrap();
+            let key = &context::key(shown)[..];
+            let value = match get_key(key, &kvstore.kvs) {
+                Some(value) => value,
+                None => {
+                    let err_msg = format!("Error! Key {} does not exist!", key);
+                    print_err(&err_msg[..]);
+                }
+            };
+            let expires = time::parse_duration(m_share.value_of("expires").unwrap())
+                .unwrap_or_else(|e| print_err(&format!("Error! {}", e)));
+            share::share(shown, &value, expires, m_share.value_of("to-file"));
+        }
+    }
+    if let Some(m_require) = matches.subcommand_matches("require") {
+        let specs: Vec<&str> = m_require.values_of("keys").unwrap().collect();
+        let filled = require::require(&mut kvstore, &specs, m_require.is_present("interactive"));
+        if !filled.is_empty() {
+            save_imported(&mut kvstore, &filled, &[], "require");
+        }
+    }
+    if let Some(m_direnv) = matches.subcommand_matches("direnv") {
+        if m_direnv.is_present("stdlib") {
+            print!("{}", direnv::stdlib());
+        } else {
+            print!("{}", direnv::envrc(m_direnv.value_of("prefix").unwrap_or("")));
+        }
+    }
+    if let Some(m_inject) = matches.subcommand_matches("inject") {
+        let path = m_inject.value_of("template").unwrap();
+        let template = match std::fs::read_to_string(path) {
+            Ok(template) => template,
+            Err(e) => {
+                let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+                print_err(&err_msg[..]);
+            }
+        };
+        let rendered = inject::render(&template, |key| {
+            let key = context::key(key);
+            get_value_in(&key, &kvstore).map(|value| refs::resolve(plain_value(&key, &value).into_owned()))
+        });
+        let rendered = match rendered {
+            Ok(rendered) => rendered,
+            Err(missing) => {
+                let err_msg = format!(
+                    "Error! {} refers to keys that don't exist:\n {}\nNothing was written.",
+                    path,
+                    missing.join("\n ")
+                );
+                print_err(&err_msg[..]);
+            }
+        };
+        if m_inject.is_present("stdout") {
+            print!("{}", rendered);
+        } else {
+            let to = m_inject.value_of("to").unwrap_or(path);
+            if to == path && rendered == template {
+                eprintln!("Warning! {} has no {{{{kv:key}}}} markers, so it was left as it is.", path);
+            } else if let Err(e) = std::fs::write(to, &rendered) {
+                let err_msg = format!("Error! Cannot write {}, error {}", to, e);
+                print_err(&err_msg[..]);
+            }
+        }
+        if m_inject.is_present("watch") {
+            let keys: Vec<String> = inject::keys(&template).iter().map(|key| context::key(key)).collect();
+            inject::watch(
+                &inject::absolute(Path::new(path)),
+                &inject::absolute(Path::new(m_inject.value_of("to").unwrap())),
+                &keys,
+            );
+        }
+    }
+    if let Some(m_tmux) = matches.subcommand_matches("tmux-refresh") {
+        let prefix = m_tmux.value_of("prefix").unwrap();
+        tmux::refresh(
+            &export::strip_prefix(&kvstore.kvs, prefix),
+            prefix,
+            m_tmux.value_of("target"),
+            m_tmux.is_present("panes"),
+        );
+    }
+    if let Some(m_cloud) = matches.subcommand_matches("cloud") {
+        let (name, m) = m_cloud.subcommand();
+        let m = m.unwrap();
+        let opts = aws::Options {
+            region: m.value_of("region"),
+            profile: m.value_of("profile"),
+        };
+        let (source, remote) = match (m.value_of("aws-ssm"), m.value_of("aws-secret")) {
+            (Some(path), _) => (format!("aws-ssm:{}", path), path),
+            (_, Some(id)) => (format!("aws-secret:{}", id), id),
+            _ => print_err("Error! Say where to sync with, e.g.\n kv cloud pull --aws-ssm /myapp/"),
+        };
+        let prefix = match m.value_of("prefix") {
+            Some(prefix) => prefix.to_owned(),
+            None => format!("{}/", remote.trim_matches('/')),
+        };
+        if name == "pull" {
+            let incoming = if m.is_present("aws-ssm") {
+                aws::ssm_pull(&opts, remote)
+            } else {
+                aws::secret_pull(&opts, remote)
+            };
+            let incoming = incoming
+                .into_iter()
+                .map(|(key, val)| (format!("{}{}", prefix, key), val))
+                .collect();
+            let changed = import::merge(&mut kvstore, incoming, import::Strategy::Replace);
+            save_imported(&mut kvstore, &changed, &[], &source);
+            println!("Pulled {} changed keys into {}", changed.len(), prefix);
+        } else {
+            let kvs = export::strip_prefix(&kvstore.kvs, &prefix);
+            if kvs.is_empty() {
+                let err_msg = format!("Error! There are no keys under {} to push", prefix);
+                print_err(&err_msg[..]);
+            }
+            if m.is_present("aws-ssm") {
+                aws::ssm_push(&opts, remote, &kvs, m.is_present("secure"));
+            } else {
+                aws::secret_push(&opts, remote, &kvs);
+            }
+            println!("Pushed {} keys under {} to {}", kvs.len(), prefix, remote);
+        }
+    }
+    #[cfg(feature = "net")]
+    if let Some(m_vault) = matches.subcommand_matches("vault") {
+        let (name, m) = m_vault.subcommand();
+        let m = m.unwrap();
+        let path = m.value_of("path").unwrap();
+        let prefix = match m.value_of("prefix") {
+            Some(prefix) => prefix.to_owned(),
+            None => format!("{}/", path.trim_matches('/')),
+        };
+        if name == "pull" {
+            let incoming = vault::pull(path)
+                .into_iter()
+                .map(|(key, val)| (format!("{}{}", prefix, key), val))
+                .collect();
+            let changed = import::merge(&mut kvstore, incoming, import::Strategy::Replace);
+            save_imported(&mut kvstore, &changed, &[], &format!("vault:{}", path));
+            println!("Pulled {} changed keys into {}", changed.len(), prefix);
+        } else {
+            let kvs = export::strip_prefix(&kvstore.kvs, &prefix);
+            if kvs.is_empty() {
+                let err_msg = format!("Error! There are no keys under {} to push", prefix);
+                print_err(&err_msg[..]);
+            }
+            vault::push(path, &kvs);
+            println!("Pushed {} keys under {} to {}", kvs.len(), prefix, path);
+        }
+    }
+    if let Some(m_test) = matches.subcommand_matches("test") {
+        harness::run(m_test.value_of("script").unwrap(), &kvstore);
+    }
+    if let Some(m_graph) = matches.subcommand_matches("graph") {
+        match m_graph.value_of("format") {
+            Some("mermaid") => print!("{}", graph::mermaid(&kvstore)),
+            _ => print!("{}", graph::dot(&kvstore)),
+        }
+    }
+    if let Some(m_transform) = matches.subcommand_matches("transform") {
+        if let Some(m_add) = m_transform.subcommand_matches("add") {
+            transform::add(
+                &context::key(m_add.value_of("key").unwrap()),
+                m_add.value_of("cmd-name").unwrap(),
+            );
+        }
+        if let Some(m_del) = m_transform.subcommand_matches("del") {
+            transform::del(&context::key(m_del.value_of("key").unwrap()));
+        }
+        if m_transform.subcommand_matches("list").is_some() {
+            print_aligned(transform::list(&kvstore));
+        }
+    }
+    if let Some(m_computed) = matches.subcommand_matches("computed") {
+        if let Some(m_add) = m_computed.subcommand_matches("add") {
+            computed::add(
+                &context::key(m_add.value_of("key").unwrap()),
+                computed::Computed {
+                    from: m_add.values_of("from").unwrap().map(context::key).collect(),
+                    cmd: m_add.value_of("cmd").unwrap().to_owned(),
+                },
+            );
+        }
+        if let Some(m_del) = m_computed.subcommand_matches("del") {
+            computed::del(&context::key(m_del.value_of("key").unwrap()));
+        }
+        if let Some(m_refresh) = m_computed.subcommand_matches("refresh") {
+            computed::refresh(&context::key(m_refresh.value_of("key").unwrap()));
+        }
+        if m_computed.subcommand_matches("list").is_some() {
+            print_aligned(computed::list(&kvstore));
+        }
+    }
+    if let Some(m_poll) = matches.subcommand_matches("poll") {
+        if let Some(m_add) = m_poll.subcommand_matches("add") {
+            poll::add(
+                &context::key(m_add.value_of("key").unwrap()),
+                poll::Poll {
+                    every: m_add.value_of("every").unwrap().to_owned(),
+                    cmd: m_add.value_of("cmd").unwrap().to_owned(),
+                },
+            );
+        }
+        if let Some(m_del) = m_poll.subcommand_matches("del") {
+            poll::del(&context::key(m_del.value_of("key").unwrap()));
+        }
+        if m_poll.subcommand_matches("list").is_some() {
+            print_aligned(poll::list(&kvstore));
+        }
+        if let Some(m_run) = m_poll.subcommand_matches("run") {
+            poll::run(m_run.is_present("once"));
+        }
+    }
+    if let Some(m_export) = matches.subcommand_matches("export") {
+        let namespace = context::prefix();
+        let prefix = m_export.value_of("prefix").unwrap_or(&namespace);
+        let format = m_export.value_of("format").or(context::format()).unwrap_or("json");
+        let kvs = export::strip_prefix(&kvstore.kvs, prefix);
+        if let Some(res) = m_export.value_of("to-k8s") {
+            let resource = k8s::parse_resource(res);
+            k8s::write(&resource, m_export.value_of("namespace"), &kvs);
+        } else if let Some(dir) = m_export.value_of("split-per-key") {
+            match export::split_per_key(&kvs, Path::new(dir)) {
+                Ok(count) => eprintln!("Wrote {} files to {}", count, dir),
+                Err(e) => {
+                    let err_msg = format!("Error! Cannot write to {}, error {}", dir, e);
+                    print_err(&err_msg[..]);
+                }
+            }
+        } else if format == "shell" {
+            match export::shell(&kvs) {
+                Ok(lines) => print!("{}", lines),
+                Err(e) => print_err(&format!("Error! Cannot export, {}", e)),
+            }
+        } else if format == "env" {
+            match export::dotenv(&kvs) {
+                Ok(env) => print!("{}", env),
+                Err(e) => print_err(&format!("Error! Cannot export, {}", e)),
+            }
+        } else if format == "csv" {
+            print!("{}", csv::write(&kvs));
+        } else if format == "compose-env" {
+            let mut header = vec![
+                format!("Generated by kv export at {} UTC", time::format_timestamp(time::now())),
+                format!(
+                    "from {}",
+                    stateless::source()
+                        .map(|s| s.to_owned())
+                        .unwrap_or_else(|| get_file_location().to_string_lossy().into_owned())
+                ),
+            ];
+            if !prefix.is_empty() {
+                header.push(format!("keys under {}, without the prefix", prefix));
+            }
+            match export::compose_env(&kvs, &header) {
+                Ok(env) => print!("{}", env),
+                Err(e) => print_err(&format!("Error! Cannot export, {}", e)),
+            }
+        } else {
+            println!("{}", export::json(&kvs));
+        }
+    }
+    if let Some(m_publish) = matches.subcommand_matches("publish") {
+        let namespace = context::prefix();
+        let prefix = m_publish.value_of("prefix").unwrap_or(&namespace);
+        let exclude: Vec<String> = m_publish
+            .values_of("exclude")
+            .map_or(vec![], |globs| globs.map(context::key).collect());
+        let kvs = match export::public(&kvstore, m_publish.is_present("exclude-secrets"), &exclude) {
+            Ok(kvs) => kvs,
+            Err(secrets) => {
+                let err_msg = format!(
+                    "Error! {} would be published but {} secret. Nothing was written. To leave secret keys out try\n kv publish --exclude-secrets",
+                    secrets.join(", "),
+                    if secrets.len() == 1 { "is" } else { "are" }
+                );
+                print_err(&err_msg[..]);
+            }
+        };
+        let kvs = export::strip_prefix(&kvs, prefix);
+        let json = export::json(&kvs);
+        match m_publish.value_of("to") {
+            Some(path) => {
+                if let Err(e) = std::fs::write(path, format!("{}\n", json)) {
+                    let err_msg = format!("Error! Cannot write {}, error {}", path, e);
+                    print_err(&err_msg[..]);
+                }
+                eprintln!("Published {} keys to {}", kvs.len(), path);
+            }
+            None => println!("{}", json),
+        }
+    }
+    if let Some(m_snapshot) = matches.subcommand_matches("snapshot") {
+        if let Some(m_create) = m_snapshot.subcommand_matches("create") {
+            let name = snapshot::create(&kvstore, m_create.value_of("label").unwrap_or("manual"));
+            println!("{}", name);
+        }
+        if m_snapshot.subcommand_matches("list").is_some() {
+            print_aligned(snapshot::list());
+        }
+        if let Some(m_restore) = m_snapshot.subcommand_matches("restore") {
+            snapshot::restore(m_restore.value_of("name").unwrap(), !m_restore.is_present("no-snapshot"));
+        }
+        if let Some(m_del) = m_snapshot.subcommand_matches("del") {
+            snapshot::delete(m_del.value_of("name").unwrap());
+        }
+    }
+    if let Some(m_meta) = matches.subcommand_matches("meta") {
+        let key = &context::key(m_meta.value_of("key").unwrap())[..];
+        if !kvstore.kvs.contains_key(key) {
+            let err_msg = format!("Error! Key {} does not exist!", key);
+            print_err(&err_msg[..]);
+        }
+        let lines = match kvstore.meta.get(key) {
+            Some(meta) => vec![
+                format!("Created\t--\t{}", time::format_timestamp(meta.created)),
+                format!("Modified\t--\t{}", time::format_timestamp(meta.modified)),
+                format!("Source\t--\t{}", meta.source.as_deref().unwrap_or("unknown")),
+                format!("Secret\t--\t{}", if meta.secret { "yes" } else { "no" }),
+                format!("Expires\t--\t{}", meta.expires.map_or("never".to_owned(), time::format_timestamp)),
+            ],
+            None => vec!["No metadata, the key was set before kv recorded any.".to_owned()],
+        };
+        print_aligned(lines);
+    }
+    if let Some(search) = matches.subcommand_matches("search") {
+        if search.is_present("all-stores") {
+            let mut lines = vec!["Store\t--\tKey\t--\tValue".to_owned()];
+            for source in federation::all() {
+                lines.extend(source.search(search.value_of("query").unwrap(), search.is_present("values")));
+            }
+            print_aligned(lines);
+            return;
+        }
+        let query = search.value_of("query").unwrap().to_lowercase();
+        let mut found: Vec<scan::Entry> = kvstore
+            .scan("")
+            .filter(|e| e.key.to_lowercase().contains(&query))
+            .collect();
+        if search.is_present("values") {
+            let value_matches = |e: &scan::Entry| {
+                !e.key.to_lowercase().contains(&query)
+                    && !e.meta.is_some_and(|m| m.secret)
+                    && e.value.to_lowercase().contains(&query)
+            };
+            match index::load().and_then(|index| index.candidates(&query)) {
+                Some(candidates) => found.extend(
+                    candidates
+                        .iter()
+                        .filter_map(|key| kvstore.entry(key))
+                        .filter(value_matches),
+                ),
+                None => found.extend(kvstore.scan("").filter(value_matches)),
+            }
+        }
+        let mut start = vec!["Key\t--\tValue".to_owned()];
+        let mut to_print = found
+            .iter()
+            .map(|e| format!("{}\t--\t{}", e.key, shown_value(e.key, e.value, &kvstore.meta)))
+            .collect::<Vec<String>>();
+        start.append(&mut to_print);
+        print_aligned(start);
+    }
+    if let Some(m_grep) = matches.subcommand_matches("grep") {
+        let pattern = m_grep.value_of("pattern").unwrap();
+        let pattern = if m_grep.is_present("ignore-case") {
+            format!("(?i){}", pattern)
+        } else {
+            pattern.to_owned()
+        };
+        let re = match regex::Regex::new(&pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                let err_msg = format!("Error! Invalid regex:\n{}", e);
+                print_err(&err_msg[..]);
+            }
+        };
+        let num_arg = |name: &str| {
+            if m_grep.is_present(name) {
+                Some(value_t!(m_grep, name, usize).unwrap_or_else(|e| e.exit()))
+            } else {
+                None
+            }
+        };
+        let context = num_arg("context").unwrap_or(0);
+        let opts = grep::GrepOptions {
+            before: num_arg("before").unwrap_or(context),
+            after: num_arg("after").unwrap_or(context),
+            color: match m_grep.value_of("color") {
+                Some("always") => true,
+                Some("never") => false,
+                _ => std::io::stdout().is_terminal(),
+            },
+        };
+        let searchable: KV = kvstore
+            .kvs
+            .iter()
+            .filter(|(key, _)| !is_secret(key, &kvstore.meta))
+            .map(|(key, val)| (key.clone(), val.clone()))
+            .collect();
+        for line in grep::grep(&searchable, &re, &opts) {
+            println!("{}", line);
+        }
+    }
+    if let Some(m_events) = matches.subcommand_matches("events") {
+        let since = m_events
+            .value_of("since")
+            .map(|s| time::parse_time_spec(s).unwrap_or_else(|e| print_err(&format!("Error! {}", e))));
+        events::show(
+            m_events.value_of("format") == Some("jsonl"),
+            m_events.is_present("follow"),
+            since,
+            m_events.value_of("key").map(context::key).as_deref(),
+        );
+    }
+    if matches.subcommand_matches("report").is_some() {
+        usage::report();
+    }
+    if matches.subcommand_matches("verify-config").is_some() {
+        verify::verify_config();
+    }
+    #[cfg(all(feature = "net", feature = "crypto"))]
+    if let Some(m_update) = matches.subcommand_matches("self-update") {
+        update::self_update(m_update.is_present("check"));
+    }
+    if let Some(m_bench) = matches.subcommand_matches("bench") {
+        if let Some(m_hooks) = m_bench.subcommand_matches("hooks") {
+            bench::hooks(&kvstore, m_hooks.value_of("key").map(context::key).as_deref());
+        }
+        if let Some(m_get) = m_bench.subcommand_matches("get") {
+            let runs = value_t!(m_get, "runs", u32).unwrap_or_else(|e| e.exit());
+            bench::get(&context::key(m_get.value_of("key").unwrap()), runs.max(1));
+        }
+    }
+    if let Some(m_config) = matches.subcommand_matches("config") {
+        if let Some(m_get) = m_config.subcommand_matches("get") {
+            println!("{}", config::get().value(m_get.value_of("name").unwrap()));
+        }
+        if let Some(m_set) = m_config.subcommand_matches("set") {
+            config::set(
+                m_set.value_of("name").unwrap(),
+                m_set.value_of("value").unwrap(),
+            );
+        }
+        if let Some(m_unset) = m_config.subcommand_matches("unset") {
+            config::unset(m_unset.value_of("name").unwrap());
+        }
+        if m_config.subcommand_matches("list").is_some() {
+            print_aligned(config::list());
+        }
+    }
+    if matches.subcommand_matches("fmt").is_some() {
+        with_store_lock(|| write_store(&get_store(), true));
+    }
+    if let Some(m_shard) = matches.subcommand_matches("shard") {
+        if let Some(m_enable) = m_shard.subcommand_matches("enable") {
+            let shards = value_t!(m_enable, "shards", u32).unwrap_or_else(|e| e.exit());
+            if shards == 0 {
+                print_err("Error! Need at least one shard!");
+            }
+            if options::get().backend() == Backend::Sqlite {
+                print_err("Error! An SQLite store only reads and writes the keys it needs, it can't be sharded.");
+            }
+            with_store_lock(|| {
+                let mut kvstore = get_store();
+                shard::reshard(&mut kvstore, shards).unwrap_or_else(|e| fail(e));
+            });
+        }
+        if m_shard.subcommand_matches("disable").is_some() {
+            with_store_lock(|| {
+                let mut kvstore = get_store();
+                kvstore.shards = None;
+                write_file(&kvstore);
+                shard::remove_all();
+            });
+        }
+    }
+    if let Some(m_index) = matches.subcommand_matches("index") {
+        if m_index.subcommand_matches("build").is_some() {
+            with_store_lock(|| index::build(&get_store()));
+        }
+        if m_index.subcommand_matches("drop").is_some() {
+            index::delete();
+        }
+    }
+    if let Some(m_show) = matches.subcommand_matches("hooks").and_then(|m| m.subcommand_matches("show")) {
+        let name = m_show.value_of("hook-name").unwrap();
+        match kvstore.hooks.iter().find(|hook| hook.name == name) {
+            Some(hook) => print_aligned(hook_details(hook, &kvstore)),
+            None => {
+                let err_msg = format!("Error! Hook {} does not exist!", name);
+                print_err(&err_msg[..]);
+            }
+        }
+        return;
+    }
+    if let Some(m_describe) = matches.subcommand_matches("hooks").and_then(|m| m.subcommand_matches("describe")) {
+        let name = m_describe.value_of("hook-name").unwrap();
+        with_store_lock(|| {
+            let mut kvstore = get_store();
+            match kvstore.hooks.iter_mut().find(|hook| hook.name == name) {
+                Some(hook) => hook.description = Some(m_describe.value_of("description").unwrap().to_owned()),
+                None => {
+                    let err_msg = format!("Error! Hook {} does not exist!", name);
+                    print_err(&err_msg[..]);
+                }
+            }
+            write_file(&kvstore);
+        });
+        return;
+    }
+    #[cfg(feature = "crypto")]
+    if let Some(m_scan) = matches.subcommand_matches("scan") {
+        let (secrets, skipped) = leaks::Secrets::from_store(&kvstore);
+        if !skipped.is_empty() {
+            eprintln!(
+                "Warning! Not looking for {}: encrypted with kv secret, or too short to tell apart from chance",
+                skipped.join(", ")
+            );
+        }
+        let paths: Vec<&str> = m_scan.values_of("paths").unwrap().collect();
+        let found = if secrets.is_empty() { vec![] } else { leaks::scan(&secrets, &paths) };
+        if json_output {
+            let found: Vec<_> = found
+                .iter()
+                .map(|leak| serde_json::json!({ "path": leak.path, "line": leak.line, "key": leak.key }))
+                .collect();
+            print_json(&serde_json::json!(found));
+        } else {
+            for leak in &found {
+                println!("{}:{}: the value of secret key {}", leak.path.display(), leak.line, leak.key);
+            }
+        }
+        if !found.is_empty() {
+            if !json_output {
+                println!("Error! Found {} secret values. Remove them before committing.", found.len());
+            }
+            usage::finish();
+            std::process::exit(1);
+        }
+        return;
+    }
+    if matches.subcommand_matches("lint").is_some() {
+        let findings = lint::check(&kvstore);
+        if json_output {
+            print_json(&serde_json::json!(findings));
+        } else if findings.is_empty() {
+            println!("No problems found");
+        } else {
+            let mut lines = vec!["Rule\t--\tKey or Hook\t--\tProblem".to_owned()];
+            lines.extend(findings.iter().map(|f| format!("{}\t--\t{}\t--\t{}", f.rule, f.subject, f.message)));
+            print_aligned(lines);
+        }
+        if !findings.is_empty() {
+            usage::finish();
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(m_diff) = matches.subcommand_matches("env").and_then(|m| m.subcommand_matches("diff")) {
+        let namespace = context::prefix();
+        let prefix = m_diff.value_of("prefix").unwrap_or(&namespace);
+        let drift = drift::diff(&kvstore.kvs, prefix);
+        if json_output {
+            print_json(&serde_json::json!(drift));
+        } else if drift.is_empty() {
+            println!("The environment matches the store");
+        } else {
+            let mut lines = vec!["Variable\t--\tKey\t--\tDrift".to_owned()];
+            lines.extend(drift.iter().map(|d| {
+                let key = d.key.strip_prefix(&namespace[..]).unwrap_or(&d.key);
+                format!("{}\t--\t{}\t--\t{}", d.variable, key, d.drift)
+            }));
+            print_aligned(lines);
+        }
+        if !drift.is_empty() {
+            usage::finish();
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(m_for) = matches.subcommand_matches("hooks").and_then(|m| m.subcommand_matches("for-key")) {
+        let key = context::key(m_for.value_of("key").unwrap());
+        let mut lines = vec!["Hook Name\t--\tCmd Name\t--\tTrigger\t--\tKey\t--\tOptions".to_owned()];
+        lines.extend(kvstore.hooks.iter().filter(|hook| glob::matches(&hook.key, &key)).map(hook_row));
+        if lines.len() == 1 {
+            println!("No hooks fire on {}", key);
+        } else {
+            print_aligned(lines);
+        }
+        return;
+    }
+    let listing = match matches.subcommand_matches("list") {
+        Some(m_list) => Some((m_list, m_list.value_of("to-list"))),
+        None => ["keys", "cmds", "hooks"]
+            .iter()
+            .find_map(|&subject| matches.subcommand_matches(subject).map(|m| (m, Some(subject)))),
+    };
+    if let Some((to_list, subject)) = listing {
+        let time_arg = |name: &str| {
+            to_list
+                .value_of(name)
+                .map(|s| time::parse_time_spec(s).unwrap_or_else(|e| print_err(&format!("Error! {}", e))))
+        };
+        let modified_since = time_arg("modified-since");
+        let modified_before = time_arg("modified-before");
+        let created_since = time_arg("created-since");
+        let created_before = time_arg("created-before");
+        let time_filtered = modified_since.is_some()
+            || modified_before.is_some()
+            || created_since.is_some()
+            || created_before.is_some();
+        // Keys written before metadata existed have no times, so never match a time filter.
+        let keep_key = |key: &str, kvstore: &KVStore| {
+            if !time_filtered {
+                return true;
+            }
+            match kvstore.meta.get(key) {
+                Some(m) => {
+                    modified_since.is_none_or(|t| m.modified >= t)
+                        && modified_before.is_none_or(|t| m.modified < t)
+                        && created_since.is_none_or(|t| m.created >= t)
+                        && created_before.is_none_or(|t| m.created < t)
+                }
+                None => false,
+            }
+        };
+        let paged = to_list.is_present("limit") || to_list.is_present("after");
+        let order = sort::Order::parse(to_list.value_of("sort").unwrap_or("lexical"));
+        let sorted = order != sort::Order::Lexical;
+        let show = to_list.is_present("show");
+        let cols = match to_list.value_of("columns") {
+            Some(spec) => Some(columns::parse(spec).unwrap_or_else(|e| print_err(&format!("Error! {}", e)))),
+            None if to_list.is_present("long") => Some(columns::LONG.to_vec()),
+            None => None,
+        };
+        let subject = match subject {
+            None if time_filtered || paged || sorted || cols.is_some() || show => Some("keys"),
+            Some("cmds") | Some("hooks") if paged => {
+                print_err("Error! --limit and --after only apply to keys!")
+            }
+            Some("cmds") | Some("hooks") if time_filtered => {
+                print_err("Error! Time filters only apply to keys!")
+            }
+            Some("cmds") | Some("hooks") if sorted => print_err("Error! --sort only applies to keys!"),
+            Some("cmds") | Some("hooks") if show => print_err("Error! --show only applies to keys!"),
+            Some("cmds") | Some("hooks") if cols.is_some() => {
+                print_err("Error! --columns only applies to keys!")
+            }
+            subject => subject,
+        };
+        // --after continues from a key in the store's own order, which any other order breaks.
+        if paged && sorted {
+            print_err("Error! --limit and --after only work with lexical order!");
+        }
+        let prefix = to_list.value_of("prefix").unwrap_or("");
+        let format = if json_output { "json" } else { to_list.value_of("format").unwrap() };
+        if subject.is_none() && format != "table" && !json_output {
+            print_err("Error! --format only applies when listing one of keys, cmds or hooks!");
+        }
+        // kv -o json shows keys with all their metadata.
+        let cols = cols.or_else(|| json_output.then(|| columns::NAMES.to_vec()));
+        let kvstore = get_store();
+
+        // Each listing prints itself, or returns its JSON so that kv -o json list can put
+        // all three in one object.
+        let print_cmds = |kvstore: &KVStore| {
+            let cmds = kvstore.cmds.iter().filter(|(name, _)| name.starts_with(prefix));
+            match format {
+                "json" => return Some(serde_json::json!(cmds.collect::<BTreeMap<_, _>>())),
+                "names" => cmds.for_each(|(name, _)| println!("{}", name)),
+                _ => {
+                    let mut start = vec!["Key\t--\tValue".to_owned()];
+                    let mut to_print = cmds
+                        .map(|(key, val)| match kvstore.cmd_lang(key) {
+                            "shell" => format!("{}\t--\t{}", key, val),
+                            lang => format!("{} ({})\t--\t{}", key, lang, val),
+                        })
+                        .collect::<Vec<String>>();
+                    start.append(&mut to_print);
+                    print_aligned(start);
+                }
+            }
+            None
+        };
+        
+        let namespace = context::prefix();
+        let scanned = format!("{}{}", namespace, prefix);
+        let limit = to_list.value_of("limit").map(|_| value_t!(to_list, "limit", usize).unwrap_or_else(|e| e.exit()));
+        let after = to_list.value_of("after").map(|key| scan::Cursor::after(&context::key(key)));
+        let print_keys = |kvstore: &KVStore| {
+            let mut rows = vec![];
+            let mut entries = kvstore
+                .scan_from(&scanned, after.as_ref())
+                .filter(|e| keep_key(e.key, kvstore))
+                .peekable();
+            let mut last = None;
+            for e in entries.by_ref().take(limit.unwrap_or(usize::MAX)) {
+                let value = if show {
+                    plain_value(e.key, e.value)
+                } else {
+                    shown_value(e.key, e.value, &kvstore.meta).into()
+                };
+                rows.push((&e.key[namespace.len()..], value));
+                last = Some(e.cursor());
+            }
+            if sorted {
+                order.sort(&mut rows);
+            }
+            let mut json = None;
+            if let Some(cols) = &cols {
+                let full = |key: &str| format!("{}{}", namespace, key);
+                match format {
+                    "json" => {
+                        let objects: Vec<_> = rows
+                            .iter()
+                            .map(|(key, value)| columns::json_row(cols, kvstore, &full(key), key, value))
+                            .collect();
+                        json = Some(serde_json::Value::Array(objects));
+                    }
+                    "names" => print_err("Error! --columns only applies to --format table or json!"),
+                    _ => {
+                        let mut start = vec![columns::header(cols)];
+                        start.extend(rows.iter().map(|(key, value)| columns::row(cols, kvstore, &full(key), key, value)));
+                        print_aligned(start);
+                    }
+                }
+            } else {
+                match format {
+                    "json" => println!("{}", sort::json_object(&rows)),
+                    "names" => rows.iter().for_each(|(key, _)| println!("{}", key)),
+                    _ => {
+                        let mut start = vec!["Key\t--\tValue".to_owned()];
+                        start.extend(rows.iter().map(|(key, value)| format!("{}\t--\t{}", key, value)));
+                        print_aligned(start);
+                    }
+                }
+            }
+            if let (Some(_), Some(last)) = (entries.peek(), last) {
+                eprintln!(
+                    "More keys follow. To see them try\n kv keys --limit {} --after '{}'",
+                    limit.unwrap(),
+                    &last.key()[namespace.len()..]
+                );
+            }
+            json
+        };
+
+        let print_hooks = |kvstore: &KVStore| {
+            let hooks = kvstore.hooks.iter().filter(|hook| hook.name.starts_with(prefix));
+            match format {
+                "json" => return Some(serde_json::json!(hooks.collect::<Vec<_>>())),
+                "names" => {
+                    hooks.for_each(|hook| println!("{}", hook.name));
+                    return None;
+                }
+                _ => {}
+            }
+            let mut start = vec!["Hook Name\t--\tCmd Name\t--\tTrigger\t--\tKey\t--\tOptions".to_owned()];
+            let mut to_print = hooks.map(hook_row).collect::<Vec<String>>();
+            start.append(&mut to_print);
+            print_aligned(start);
+            None
+        };
+        let print = |json: Option<serde_json::Value>| {
+            if let Some(json) = json {
+                print_json(&json);
+            }
+        };
+        match subject {
+            Some("cmds") => print(print_cmds(&kvstore)),
+            Some("keys") => print(print_keys(&kvstore)),
+            Some("hooks") => print(print_hooks(&kvstore)),
+            None if json_output => print(Some(serde_json::json!({
+                "keys": print_keys(&kvstore),
+                "cmds": print_cmds(&kvstore),
+                "hooks": print_hooks(&kvstore),
+            }))),
+            None => {
+                print_keys(&kvstore);
+                println!("-------------------");
+                print_cmds(&kvstore);
+                println!("-------------------");
+                print_hooks(&kvstore);
+            }
+            _ => print_err("Error! Unknown subject to list!"),
+        }
+    }
+
+    if let Some(cmd) = matches.subcommand_matches("cmd") {
+        if let Some(m_run) = cmd.subcommand_matches("run") {
+            let cmd_name = m_run.value_of("cmd-name").unwrap();
+            let cmd_value = get_key(cmd_name, &kvstore.cmds);
+            match cmd_value {
+                Some(v) => run_command_recorded(
+                    cmd_name,
+                    &v,
+                    kvstore.cmd_lang(cmd_name),
+                    m_run.value_of("capture").map(context::key).as_deref(),
+                ),
+                None => println!("Error! Command {} does not exist!", cmd_name),
+            }
+        }
+
+        if let Some(m_history) = cmd.subcommand_matches("history") {
+            let count = value_t!(m_history, "count", usize).unwrap_or_else(|e| e.exit());
+            print_aligned(history::list(count));
+        }
+
+        if let Some(m_rerun) = cmd.subcommand_matches("rerun") {
+            let id = value_t!(m_rerun, "id", u64).unwrap_or_else(|e| e.exit());
+            let entry = history::find(id);
+            run_command_recorded(&entry.name, &entry.cmd, entry.lang.as_deref().unwrap_or("shell"), None);
+        }
+
+        if let Some(m_add) = cmd.subcommand_matches("add") {
+            let cmd_name = m_add.value_of("cmd-name").unwrap();
+            let cmd_value = m_add.value_of("cmd-value").unwrap();
+            let lang = m_add.value_of("lang").unwrap();
+            if lang == "shell" && config::get().get_bool("cmds.safety-check") && !m_add.is_present("allow-dangerous") {
+                let reasons = safety::review(cmd_value);
+                if !reasons.is_empty() {
+                    let err_msg = format!(
+                        "Error! Command {} looks dangerous, it:\n {}\nStored commands run unreviewed from hooks. If you are sure, try\n kv cmd add --allow-dangerous {} '{}'",
+                        cmd_name,
+                        reasons.join("\n "),
+                        cmd_name,
+                        cmd_value
+                    );
+                    print_err(&err_msg[..]);
+                }
+            }
+            if lang == "rhai" {
+                if let Err(e) = script::check_rhai(cmd_value) {
+                    let err_msg = format!("Error! Cannot add {}, {}", cmd_name, e);
+                    print_err(&err_msg[..]);
+                }
+            }
+            with_store_lock(|| {
+                let mut kvstore = get_store();
+                set_key(cmd_name, cmd_value, &mut kvstore.cmds);
+                if lang == "shell" {
+                    kvstore.cmd_langs.remove(cmd_name);
+                } else {
+                    kvstore.cmd_langs.insert(cmd_name.to_owned(), lang.to_owned());
+                }
+                write_file(&kvstore);
+            });
+            events::record("cmd", &OpType::Set, cmd_name, Some(cmd_value));
+        }
+
+        if let Some(m_del_hook) = cmd.subcommand_matches("del-hook") {
+            let hook_name = m_del_hook.value_of("hook-name").unwrap();
+            rm_hook(hook_name);
+        }
+
+        if let Some(m_add_hook) = cmd.subcommand_matches("add-hook") {
+            let hook_name = m_add_hook.value_of("hook-name").unwrap();
+            let cmd_name = m_add_hook.value_of("cmd-name").unwrap();
+            let trigger_op = value_t!(m_add_hook, "trigger", OpType).unwrap();
+            let key = context::key(m_add_hook.value_of("key").unwrap());
+            add_hook(Hook {
+                name: hook_name.to_owned(),
+                cmd_name: cmd_name.to_owned(),
+                run_on: trigger_op,
+                key,
+                sandbox: m_add_hook.is_present("sandbox"),
+                run_as: m_add_hook.value_of("run-as").map(|u| u.to_owned()),
+                elevate_with: m_add_hook.value_of("elevate-with").map(|t| t.to_owned()),
+                action: m_add_hook.value_of("action").filter(|&a| a != "shell").map(|a| a.to_owned()),
+                description: m_add_hook.value_of("description").map(|d| d.to_owned()),
+            })
+        }
+    }
+}
+
+/// Arguments shared by `kv cloud pull` and `kv cloud push`.
+fn cloud_subcommand(name: &str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .arg(Arg::with_name("aws-ssm")
+             .long("aws-ssm")
+             .takes_value(true)
+             .value_name("PATH")
+             .required_unless("aws-secret")
+             .conflicts_with("aws-secret")
+             .help("SSM Parameter Store path, e.g. /myapp/"))
+        .arg(Arg::with_name("aws-secret")
+             .long("aws-secret")
+             .takes_value(true)
+             .value_name("SECRET-ID")
+             .help("Secrets Manager secret holding a JSON object of keys to values"))
+        .arg(Arg::with_name("prefix").long("prefix").takes_value(true).help("Prefix of the local keys"))
+        .arg(Arg::with_name("region").long("region").takes_value(true).help("AWS region"))
+        .arg(Arg::with_name("profile").long("profile").takes_value(true).help("AWS CLI profile"))
+}
+
+/// Commands whose output goes through the pager, see `pager::start`.
+const PAGED: &[&str] = &["list", "keys", "cmds", "hooks", "search", "cmd history"];
+
+/// `kv <name>` listing keys, cmds or hooks, with the filters they share.
+fn list_subcommand(name: &str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .arg(Arg::with_name("prefix").long("prefix").takes_value(true)
+             .help("Only keys, cmds or hooks whose name starts with <prefix>"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .takes_value(true)
+             .possible_values(&["table", "json", "names"])
+             .default_value("table")
+             .help("names prints one name per line, for scripts. Secret values are hidden in every format"))
+}
+
+/// Arguments of `kv keys` and `kv list` that only apply to keys.
+fn key_filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("modified-since").long("modified-since").takes_value(true).value_name("TIME")
+            .help("Only keys modified at or after TIME (a date like 2024-01-01, or a duration ago like 2d)"),
+        Arg::with_name("modified-before").long("modified-before").takes_value(true).value_name("TIME")
+            .help("Only keys last modified before TIME"),
+        Arg::with_name("created-since").long("created-since").takes_value(true).value_name("TIME")
+            .help("Only keys created at or after TIME"),
+        Arg::with_name("created-before").long("created-before").takes_value(true).value_name("TIME")
+            .help("Only keys created before TIME"),
+        Arg::with_name("limit").long("limit").takes_value(true).value_name("N")
+            .help("Show at most N keys"),
+        Arg::with_name("after").long("after").takes_value(true).value_name("KEY")
+            .help("Start after KEY, e.g. where the last --limit listing stopped"),
+        Arg::with_name("sort").long("sort").takes_value(true).possible_values(sort::NAMES)
+            .help("natural orders item2 before item10, numeric orders by value. lexical (by key) if not set"),
+        Arg::with_name("columns").long("columns").takes_value(true).value_name("COLUMNS")
+            .help("Comma separated columns to show, out of key,value,created,modified,source,secret,expires,hooks. key,value if not set"),
+        Arg::with_name("long").long("long").short("l").conflicts_with("columns")
+            .help("Show when keys were modified, what set them and which hooks fire on them. Same as --columns key,value,modified,source,hooks"),
+        Arg::with_name("show").long("show")
+            .help("Show secret values instead of ********, decrypting the ones kv secret set stored"),
+    ]
+}
+
+/// Fooar
+pub fn main() {
+    CLI.store(true, Ordering::Relaxed);
+    // clap prints the version and exits as soon as it sees --version, so this goes first.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--features") && args.iter().any(|a| a == "--version" || a == "-V") {
+        features::print_version();
+        return;
+    }
+    // Building the whole App takes most of a `kv get`, which shell prompts run on every
+    // line, so a plain `kv get <key>` skips it.
+    if let [cmd, key] = &args[..] {
+        if cmd == "get" && !key.starts_with('-') {
+            open_store(Some("get"), false, false);
+            usage::start("get");
+            run_get(key, false, false, false);
+            usage::finish();
+            exit_for_hooks();
+            return;
+        }
+    }
+    let matches = App::new("kv")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Elijah Samson(elijahobara357@gmail.com)")
+        .about("Simple key, value storage with hooks.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .about("Key-Value Storage with bash command hooks. Add hooks to run commands on variable update.")
+        .arg(Arg::with_name("store-from-env")
+             .long("store-from-env")
+             .takes_value(true)
+             .value_name("VAR")
+             .conflicts_with("store-from-file")
+             .help("Read the store from the JSON in environment variable VAR instead of the store file, and never write"))
+        .arg(Arg::with_name("store-from-file")
+             .long("store-from-file")
+             .takes_value(true)
+             .value_name("PATH")
+             .help("Read the store from PATH (e.g. a read-only mount) instead of the store file, and never write"))
+        .arg(Arg::with_name("store")
+             .long("store")
+             .takes_value(true)
+             .value_name("NAME")
+             .help("Use profile or context NAME for this call, over KV_CONTEXT and kv profile use"))
+        .arg(Arg::with_name("output")
+             .short("o")
+             .long("output")
+             .takes_value(true)
+             .possible_values(&["text", "json"])
+             .default_value("text")
+             .help("json prints get, del and listings as JSON for scripts, keys with their metadata, e.g. kv -o json list"))
+        .arg(Arg::with_name("no-pager")
+             .long("no-pager")
+             .help("Don't page long listings through $KV_PAGER or $PAGER"))
+        .arg(Arg::with_name("detach")
+             .long("detach")
+             .help("Start hooks without waiting for them, so their failures don't make kv fail"))
+        .arg(Arg::with_name("no-lock")
+             .long("no-lock")
+             .help("Don't lock the store while changing it, e.g. when the calling script already runs kv one at a time"))
+        .arg(Arg::with_name("record-hooks")
+             .long("record-hooks")
+             .takes_value(true)
+             .value_name("FILE")
+             .help("Don't run hooks, append what would have run (command, environment, key and op) to FILE as JSON lines"))
+        .subcommand(list_subcommand("list")
+                    .about("List keys, cmds, or hooks. Same as kv keys, kv cmds and kv hooks")
+                    .arg(Arg::with_name("to-list")
+                         .takes_value(true)
+                         .required(false)
+                    .possible_values(&["keys", "cmds", "hooks"]))
+                    .args(&key_filter_args()))
+        .subcommand(list_subcommand("keys")
+                    .about("List keys")
+                    .args(&key_filter_args()))
+        .subcommand(list_subcommand("cmds").about("List cmds"))
+        .subcommand(list_subcommand("hooks")
+                    .about("List hooks")
+                    .subcommand(SubCommand::with_name("show")
+                                .about("Show everything about hook <hook-name>")
+                                .arg(Arg::with_name("hook-name").takes_value(true).required(true)))
+                    .subcommand(SubCommand::with_name("for-key")
+                                .about("List the hooks that fire when <key> is read or changed, including wildcard ones")
+                                .arg(Arg::with_name("key").takes_value(true).required(true)))
+                    .subcommand(SubCommand::with_name("describe")
+                                .about("Set what hook <hook-name> is for")
+                                .arg(Arg::with_name("hook-name").takes_value(true).required(true))
+                                .arg(Arg::with_name("description").takes_value(true).required(true))))
+        .subcommand(SubCommand::with_name("scan")
+                    .about("Look for the values of secret keys in files, e.g. from a git pre-commit hook. Exits 1 if any turn up")
+                    .arg(Arg::with_name("paths")
+                         .takes_value(true)
+                         .multiple(true)
+                         .default_value(".")
+                         .help("Files, or directories to search recursively (skipping .git)")))
+        .subcommand(SubCommand::with_name("lint")
+                    .about("Check the store against the lint.* settings: key names, plain text secrets, value sizes and hook descriptions. Exits 1 if anything is found; kv -o json lint prints the findings as JSON for CI"))
+        .subcommand(
+            SubCommand::with_name("env")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Compare the environment with the store")
+                .subcommand(
+                    SubCommand::with_name("diff")
+                        .about("List keys under --prefix whose environment variable (named as kv export --format shell names it) is unset or differs. Values aren't printed. Exits 1 if anything drifted")
+                        .arg(Arg::with_name("prefix")
+                             .long("prefix")
+                             .takes_value(true)
+                             .help("Compare keys starting with <prefix>, named without the prefix")),
+                ),
+        )
+        .subcommand(SubCommand::with_name("meta")
+                    .about("Show when <key> was created and last modified, and what set it")
+                    .arg(Arg::with_name("key").takes_value(true).required(true)))
+        .subcommand(SubCommand::with_name("search")
+                    .about("Search keys (and optionally values) for <query>")
+                    .arg(Arg::with_name("query").takes_value(true).required(true))
+                    .arg(Arg::with_name("values")
+                         .long("values")
+                         .help("Also match values. Uses the search index if one was built."))
+                    .arg(Arg::with_name("all-stores")
+                         .long("all-stores")
+                         .help("Search kv.json and the store and namespace of every context, labelling results by store")))
+        .subcommand(SubCommand::with_name("grep")
+                    .about("Print key:value lines whose value matches <pattern>, like grep")
+                    .arg(Arg::with_name("pattern").takes_value(true).required(true))
+                    .arg(Arg::with_name("ignore-case").short("i").long("ignore-case"))
+                    .arg(Arg::with_name("after").short("A").takes_value(true).value_name("NUM")
+                         .help("Print NUM lines of trailing context for multi-line values"))
+                    .arg(Arg::with_name("before").short("B").takes_value(true).value_name("NUM")
+                         .help("Print NUM lines of leading context for multi-line values"))
+                    .arg(Arg::with_name("context").short("C").takes_value(true).value_name("NUM")
+                         .help("Print NUM lines of context on both sides"))
+                    .arg(Arg::with_name("color")
+                         .long("color")
+                         .takes_value(true)
+                         .possible_values(&["auto", "always", "never"])
+                         .default_value("auto")))
+        .subcommand(
+            SubCommand::with_name("shell-init")
+                .about("Print a shell function that cd's to the directory stored in a key, for your shell's rc file")
+                .help(
+                    r#"kv shell-init <bash|zsh|fish> [--name <name>]
+
+Prints a function (kvcd by default) that changes to the directory
+stored in a key, with completion of the keys holding a directory.
+
+Example:
+~> echo 'eval "$(kv shell-init bash)"' >> ~/.bashrc
+~> kv set proj ~/src/kv
+~> kvcd proj
+"#,
+                )
+                .arg(Arg::with_name("shell").takes_value(true).required(true).possible_values(shell_init::SHELLS))
+                .arg(Arg::with_name("name")
+                     .long("name")
+                     .takes_value(true)
+                     .default_value("kvcd")
+                     .help("Name of the function: letters, digits and _, not starting with a digit")),
+        )
+        .subcommand(
+            SubCommand::with_name("cd-keys")
+                .setting(AppSettings::Hidden)
+                .about("List the keys holding an existing directory, for kv shell-init completion"),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Print the value of <key> every time it changes, until interrupted")
+                .arg(Arg::with_name("key").takes_value(true).required(true))
+                .arg(Arg::with_name("exec")
+                     .long("exec")
+                     .takes_value(true)
+                     .value_name("cmd")
+                     .help("Run shell command <cmd> on each change instead, with KV_KEY and KV_VALUE set (KV_VALUE unset once deleted)"))
+                .arg(Arg::with_name("initial")
+                     .long("initial")
+                     .help("Also print (or run --exec for) the current value on start"))
+                .arg(Arg::with_name("interval")
+                     .long("interval")
+                     .takes_value(true)
+                     .default_value("500ms")
+                     .help("How often to check the store file")),
+        )
+        .subcommand(
+            SubCommand::with_name("open")
+                .about("Open the value of <key>, a URL or path, with its default app (xdg-open, open or explorer.exe)")
+                .arg(Arg::with_name("key").takes_value(true).required(true))
+                .arg(Arg::with_name("with")
+                     .long("with")
+                     .takes_value(true)
+                     .value_name("program")
+                     .help("Open it with <program> instead, e.g. firefox")),
+        )
+        .subcommand(
+            SubCommand::with_name("simulate")
+                .about("Show what a set, del or get would do: the store change, the hooks that would fire in order with their commands and environments, and the computed keys that would be recomputed. Nothing is written or run")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("set")
+                    .about("Simulate kv set <key> <val>")
+                    .arg(Arg::with_name("key").takes_value(true).required(true))
+                    .arg(Arg::with_name("val").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("del")
+                    .about("Simulate kv del <key>")
+                    .arg(Arg::with_name("key").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("get")
+                    .about("Simulate kv get <key>")
+                    .arg(Arg::with_name("key").takes_value(true).required(true))),
+        )
+        .subcommand(
+            SubCommand::with_name("mktemp")
+                .about("Create a new, unique key and print its name, like mktemp for the store")
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .default_value("tmp-")
+                     .help("Start the key name with <prefix>"))
+                .arg(Arg::with_name("val")
+                     .takes_value(true)
+                     .required(false)
+                     .help("Initial value (default empty)")),
+        )
+        .subcommand(
+            SubCommand::with_name("lock")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Advisory locks with a time-to-live, so scripts can take turns")
+                .help(
+                    r#"kv lock <acquire|renew|release|list>
+
+Advisory locks stored in kv. A lock expires after its ttl, so a crashed
+script can't hold it forever. `acquire` prints a token; pass it to `renew`
+and `release` so only the holder can change the lock.
+
+Example:
+~> token=$(kv lock acquire deploy --ttl 5m --wait 1m) || exit 1
+~> ./deploy.sh
+~> kv lock release deploy --token "$token"
+"#,
+                )
+                .subcommand(SubCommand::with_name("acquire")
+                            .about("Take lock <name>, printing its token. Fails if someone else holds it.")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("ttl").long("ttl").takes_value(true).default_value("60s")
+                                 .help("Release the lock automatically after this long"))
+                            .arg(Arg::with_name("wait").long("wait").takes_value(true).default_value("0s")
+                                 .help("Keep trying for this long before giving up"))
+                            .arg(Arg::with_name("token").long("token").takes_value(true)
+                                 .help("Use this token instead of a random one. Re-acquiring with the same token succeeds.")))
+                .subcommand(SubCommand::with_name("renew")
+                            .about("Extend lock <name> to expire --ttl from now")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("ttl").long("ttl").takes_value(true).default_value("60s"))
+                            .arg(Arg::with_name("token").long("token").takes_value(true).required(true)
+                                 .help("Token from acquire")))
+                .subcommand(SubCommand::with_name("release")
+                            .about("Release lock <name>")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("token").long("token").takes_value(true).required_unless("force")
+                                 .help("Token from acquire"))
+                            .arg(Arg::with_name("force").long("force").conflicts_with("token")
+                                 .help("Break the lock, whoever holds it")))
+                .subcommand(SubCommand::with_name("list").about("List locks and when they expire")),
+        )
+        .subcommand(
+            SubCommand::with_name("queue")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("FIFO queues stored in a key, for producer/consumer scripts")
+                .help(
+                    r#"kv queue <push|pop|len>
+
+A queue is a key holding a JSON array of strings. Pushes and pops hold the
+store lock, so any number of scripts can share one queue, and each item is
+popped exactly once. `pop` exits 1 when the queue is empty.
+
+Example:
+~> kv queue push jobs build test
+~> kv queue pop jobs
+build
+~> while job=$(kv queue pop jobs --wait 30s); do run "$job"; done
+"#,
+                )
+                .subcommand(SubCommand::with_name("push")
+                            .about("Append <val>s to queue <key>")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))
+                            .arg(Arg::with_name("val").takes_value(true).required(true).multiple(true)))
+                .subcommand(SubCommand::with_name("pop")
+                            .about("Remove and print the first item of queue <key>")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))
+                            .arg(Arg::with_name("wait").long("wait").takes_value(true).default_value("0s")
+                                 .help("If the queue is empty, keep checking for this long")))
+                .subcommand(SubCommand::with_name("len")
+                            .about("Print the number of items in queue <key>")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import keys from a JSON file (a kv store file, or an object of keys to values), a .env file or a CSV export, e.g. a browser's or password manager's")
+                .arg(Arg::with_name("file").takes_value(true).required_unless("from-k8s"))
+                .arg(Arg::with_name("from-k8s")
+                     .long("from-k8s")
+                     .takes_value(true)
+                     .value_name("configmap/<name>|secret/<name>")
+                     .conflicts_with("file")
+                     .help("Import the data of a ConfigMap or Secret with kubectl (uses your kubeconfig)"))
+                .arg(Arg::with_name("namespace")
+                     .short("n")
+                     .long("namespace")
+                     .takes_value(true)
+                     .requires("from-k8s")
+                     .help("Namespace for --from-k8s"))
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .help("Put <prefix> in front of every imported key"))
+                .arg(Arg::with_name("format")
+                     .long("format")
+                     .takes_value(true)
+                     .possible_values(&["json", "env", "csv"])
+                     .help("How to read <file>. By default .csv files are CSV with a header row, .env, .env.* and *.env files are .env files, and anything else JSON"))
+                .arg(Arg::with_name("csv")
+                     .long("csv")
+                     .conflicts_with("format")
+                     .help("Same as --format csv"))
+                .arg(Arg::with_name("map")
+                     .long("map")
+                     .takes_value(true)
+                     .value_name("<column>=key,<column>=value")
+                     .help("The CSV columns holding the keys and values, e.g. name=key,password=value. By default a name, title or url column and a password column are used"))
+                .arg(Arg::with_name("secret")
+                     .long("secret")
+                     .help("Flag every imported key as secret, so listings mask it"))
+                .arg(Arg::with_name("strategy")
+                     .long("strategy")
+                     .takes_value(true)
+                     .possible_values(&["keep", "replace", "fail"])
+                     .default_value("fail")
+                     .help("What to do with keys that already exist with a different value"))
+                .arg(Arg::with_name("interactive")
+                     .short("i")
+                     .long("interactive")
+                     .help("Ask what to do with each conflicting key"))
+                .arg(Arg::with_name("replace")
+                     .long("replace")
+                     .conflicts_with_all(&["strategy", "interactive"])
+                     .help("Replace all keys with the file's keys. The store is snapshotted first."))
+                .arg(Arg::with_name("no-snapshot")
+                     .long("no-snapshot")
+                     .requires("replace")
+                     .help("Don't snapshot the store before --replace")),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print keys as a JSON object, .env file or CSV (which kv import reads), or write them to files or Kubernetes")
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .help("Only export keys starting with <prefix>, without the prefix"))
+                .arg(Arg::with_name("format")
+                     .long("format")
+                     .takes_value(true)
+                     .possible_values(&["json", "env", "csv", "compose-env", "shell"])
+                     .help("env prints a .env file, compose-env one for docker compose and shell export lines, all with keys as upper case variable names. csv prints key,value rows"))
+                .arg(Arg::with_name("split-per-key")
+                     .long("split-per-key")
+                     .takes_value(true)
+                     .value_name("DIR")
+                     .conflicts_with("to-k8s")
+                     .help("Write each value to its own file in DIR, e.g. for Docker secrets"))
+                .arg(Arg::with_name("to-k8s")
+                     .long("to-k8s")
+                     .takes_value(true)
+                     .value_name("configmap/<name>|secret/<name>")
+                     .help("Create or update a ConfigMap or Secret with kubectl apply (uses your kubeconfig)"))
+                .arg(Arg::with_name("namespace")
+                     .short("n")
+                     .long("namespace")
+                     .takes_value(true)
+                     .requires("to-k8s")
+                     .help("Namespace for --to-k8s")),
+        )
+        .subcommand(
+            SubCommand::with_name("publish")
+                .about("Write a sanitized JSON snapshot of the store, to commit to a repo or serve to teammates, who can read it with kv --store-from-file")
+                .arg(Arg::with_name("to")
+                     .long("to")
+                     .takes_value(true)
+                     .value_name("FILE")
+                     .help("Write it to FILE instead of printing it, e.g. ./public-kv.json"))
+                .arg(Arg::with_name("exclude-secrets")
+                     .long("exclude-secrets")
+                     .help("Leave secret keys out. Without this, publishing a store with secret keys is an error"))
+                .arg(Arg::with_name("exclude")
+                     .long("exclude")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .value_name("GLOB")
+                     .help("Also leave out keys matching GLOB, e.g. 'personal/*'. Can be repeated"))
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .help("Only publish keys starting with <prefix>, without the prefix")),
+        )
+        .subcommand(
+            SubCommand::with_name("totp")
+                .about("Print the current TOTP code for the base32 seed (or otpauth:// URI) stored in <key>")
+                .arg(Arg::with_name("key").takes_value(true).required(true))
+                .arg(Arg::with_name("copy")
+                     .long("copy")
+                     .help("Copy the code to the clipboard instead of printing it")),
+        )
+        .subcommand(
+            SubCommand::with_name("secret")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Values encrypted at rest with a passphrase (or the secrets.keyfile setting). Key names stay readable")
+                .subcommand(SubCommand::with_name("set")
+                            .about("Encrypt <val> (typed at a prompt if not given) and store it in <key>, marked secret")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))
+                            .arg(Arg::with_name("val").takes_value(true)))
+                .subcommand(SubCommand::with_name("get")
+                            .about("Print the decrypted value of <key>")
+                            .arg(Arg::with_name("key").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("del")
+                            .about("Delete <key>")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))),
+        )
+        .subcommand(
+            SubCommand::with_name("ask")
+                .about("Set <key> to a value typed at a prompt that doesn't echo, keeping it out of shell history")
+                .arg(Arg::with_name("key").takes_value(true).required(true))
+                .arg(Arg::with_name("prompt")
+                     .long("prompt")
+                     .takes_value(true)
+                     .help("Text to prompt with [default: Value for <key>:]"))
+                .arg(Arg::with_name("confirm")
+                     .long("confirm")
+                     .help("Ask twice, and fail if the values differ"))
+                .arg(Arg::with_name("not-secret")
+                     .long("not-secret")
+                     .help("Don't mark the key secret. Secret values are hidden in list and search, and skipped by grep")),
+        )
+        .subcommand(
+            SubCommand::with_name("share")
+                .about("Share a value with a teammate through an encrypted, expiring, one-time link")
+                .help(
+                    r#"kv share [--expires <duration>] [--to-file <path>] <key>
+kv share --receive <link> [--as <key> | --print]
+
+Encrypt the value of <key> with a new random key, upload it to the
+share.endpoint setting (or write it to a file), and print the command
+your teammate runs to get it. The key is only in that command, never
+uploaded. Receiving stores the value as a secret key, and deletes the
+shared copy where possible (files, and endpoints that accept DELETE).
+
+Example:
+~> kv share --expires 1h db-password
+Shared db-password until 2024-01-01 13:00:00 UTC. To get it, your teammate runs
+ kv share --receive 'https://paste.example.com/abc#Qm9...'
+"#,
+                )
+                .arg(Arg::with_name("key").takes_value(true).required_unless("receive"))
+                .arg(Arg::with_name("expires")
+                     .long("expires")
+                     .takes_value(true)
+                     .default_value("1h")
+                     .help("How long the link works"))
+                .arg(Arg::with_name("to-file")
+                     .long("to-file")
+                     .takes_value(true)
+                     .value_name("path")
+                     .help("Write the encrypted value to <path> instead of uploading it"))
+                .arg(Arg::with_name("receive")
+                     .long("receive")
+                     .takes_value(true)
+                     .value_name("link")
+                     .conflicts_with_all(&["key", "to-file"])
+                     .help("Get a shared value, and store it under the key it was shared from"))
+                .arg(Arg::with_name("as")
+                     .long("as")
+                     .takes_value(true)
+                     .value_name("key")
+                     .requires("receive")
+                     .help("Store the received value under <key> instead"))
+                .arg(Arg::with_name("print")
+                     .long("print")
+                     .requires("receive")
+                     .conflicts_with("as")
+                     .help("Print the received value instead of storing it")),
+        )
+        .subcommand(
+            SubCommand::with_name("require")
+                .about("Check that keys exist and are valid, e.g. as a preflight in scripts. Exits 1 if not.")
+                .help(
+                    r#"kv require [--interactive] <key[:type]>...
+
+Check that every key exists, and that its value has the given type:
+nonempty, int, number, bool, port, url or json. Exits 0 if they all
+do, and lists the problems and exits 1 if not. With --interactive,
+asks for each missing or invalid value instead, then exits 0.
+
+Example:
+~> kv require db-host db-port:port
+Error! Missing or invalid keys:
+ db-host (missing)
+~> kv require --interactive db-host db-port:port
+db-host (missing)
+db-host: localhost
+"#,
+                )
+                .arg(Arg::with_name("keys").takes_value(true).multiple(true).required(true))
+                .arg(Arg::with_name("interactive")
+                     .short("i")
+                     .long("interactive")
+                     .help("Ask for missing or invalid values instead of failing")),
+        )
+        .subcommand(
+            SubCommand::with_name("direnv")
+                .about("Print a block for .envrc that exports keys (under --prefix) as environment variables")
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .help("Only export keys starting with <prefix>, without the prefix"))
+                .arg(Arg::with_name("stdlib")
+                     .long("stdlib")
+                     .conflicts_with("prefix")
+                     .help("Print a `use kv [prefix]` function for ~/.config/direnv/direnvrc instead")),
+        )
+        .subcommand(
+            SubCommand::with_name("inject")
+                .about("Replace {{kv:key}} markers in a file with the values of the keys")
+                .help(
+                    r#"kv inject [--stdout | --to <file> [--watch]] <template>
+
+Replace every {{kv:key}} marker in <template> with the current value of
+<key>, in place. --stdout prints the result instead, and --to writes it to
+<file>, leaving <template> as it is. If a key doesn't exist, nothing is
+written and the missing keys are listed.
+
+With --watch, kv also adds a cmd and a set hook for each key in
+<template>, so <file> is written again whenever one of them changes.
+That needs --to, since injecting in place removes the markers.
+
+Example:
+~> cat app.conf.tmpl
+listen = {{kv:app/port}}
+~> kv inject --to app.conf --watch app.conf.tmpl
+~> kv set app/port 9090
+~> cat app.conf
+listen = 9090
+"#,
+                )
+                .arg(Arg::with_name("template")
+                     .takes_value(true)
+                     .required(true)
+                     .help("File with {{kv:key}} markers"))
+                .arg(Arg::with_name("stdout")
+                     .long("stdout")
+                     .conflicts_with("to")
+                     .help("Print the result instead of changing <template>"))
+                .arg(Arg::with_name("to")
+                     .long("to")
+                     .takes_value(true)
+                     .value_name("file")
+                     .help("Write the result to <file> instead of changing <template>"))
+                .arg(Arg::with_name("watch")
+                     .long("watch")
+                     .requires("to")
+                     .help("Inject again whenever a key in <template> is set")),
+        )
+        .subcommand(
+            SubCommand::with_name("tmux-refresh")
+                .about("Set the keys under --prefix in the tmux session environment, e.g. after a token changed")
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .takes_value(true)
+                     .required(true)
+                     .help("Keys to set, as upper case variable names without the prefix"))
+                .arg(Arg::with_name("target")
+                     .short("t")
+                     .long("target")
+                     .takes_value(true)
+                     .value_name("session")
+                     .help("tmux session [default: the current one]"))
+                .arg(Arg::with_name("panes")
+                     .long("panes")
+                     .help("Also re-export them in every pane sitting at a shell prompt")),
+        )
+        .subcommand(
+            SubCommand::with_name("cloud")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Sync keys with AWS SSM Parameter Store or Secrets Manager, using the aws CLI")
+                .subcommand(cloud_subcommand("pull")
+                            .about("Store remote parameters or secret fields as keys under --prefix [default: the remote path]"))
+                .subcommand(cloud_subcommand("push")
+                            .about("Write the keys under --prefix [default: the remote path] to the remote")
+                            .arg(Arg::with_name("secure")
+                                 .long("secure")
+                                 .requires("aws-ssm")
+                                 .help("Store SSM parameters as SecureString"))),
+        )
+        .subcommand(
+            SubCommand::with_name("vault")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Copy secrets between kv and HashiCorp Vault (KV v2). Uses VAULT_ADDR and VAULT_TOKEN.")
+                .subcommand(SubCommand::with_name("pull")
+                            .about("Store the fields of the Vault secret at <path> as keys under <path>/ (or --prefix)")
+                            .arg(Arg::with_name("path").takes_value(true).required(true)
+                                 .help("<mount>/<path>, e.g. secret/myapp"))
+                            .arg(Arg::with_name("prefix").long("prefix").takes_value(true)
+                                 .help("Prefix for the pulled keys [default: <path>/]")))
+                .subcommand(SubCommand::with_name("push")
+                            .about("Write the keys under <path>/ (or --prefix) as a new version of the Vault secret at <path>")
+                            .arg(Arg::with_name("path").takes_value(true).required(true)
+                                 .help("<mount>/<path>, e.g. secret/myapp"))
+                            .arg(Arg::with_name("prefix").long("prefix").takes_value(true)
+                                 .help("Prefix of the keys to push [default: <path>/]"))),
+        )
+        .subcommand(
+            SubCommand::with_name("rename-prefix")
+                .about("Rename every key starting with <from> to start with <to> instead")
+                .arg(Arg::with_name("from").takes_value(true).required(true))
+                .arg(Arg::with_name("to").takes_value(true).required(true))
+                .arg(Arg::with_name("force")
+                     .long("force")
+                     .help("Overwrite keys that already have a new name"))
+                .arg(Arg::with_name("no-snapshot")
+                     .long("no-snapshot")
+                     .help("Don't snapshot the store first")),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Save and restore copies of the whole store. Bulk changes snapshot automatically.")
+                .subcommand(SubCommand::with_name("create")
+                            .about("Snapshot the store now, and print the snapshot name")
+                            .arg(Arg::with_name("label").takes_value(true).required(false)))
+                .subcommand(SubCommand::with_name("list").about("List snapshots, oldest first"))
+                .subcommand(SubCommand::with_name("restore")
+                            .about("Replace the store with snapshot <name>. The current store is snapshotted first.")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("no-snapshot")
+                                 .long("no-snapshot")
+                                 .help("Don't snapshot the current store first")))
+                .subcommand(SubCommand::with_name("del")
+                            .about("Delete snapshot <name>")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))),
+        )
+        .subcommand(
+            SubCommand::with_name("events")
+                .about("Show the log of changes to keys, cmds and hooks")
+                .arg(Arg::with_name("follow")
+                     .short("f")
+                     .long("follow")
+                     .help("Keep running and print new changes as they happen"))
+                .arg(Arg::with_name("format")
+                     .long("format")
+                     .takes_value(true)
+                     .possible_values(&["table", "jsonl"])
+                     .default_value("table")
+                     .help("jsonl prints one JSON object per change, for other programs to consume"))
+                .arg(Arg::with_name("since")
+                     .long("since")
+                     .takes_value(true)
+                     .value_name("TIME")
+                     .help("Only changes at or after TIME (a date like 2024-01-01, or a duration ago like 2d)"))
+                .arg(Arg::with_name("key")
+                     .long("key")
+                     .takes_value(true)
+                     .value_name("PATTERN")
+                     .help("Only changes to keys matching PATTERN (* and ?)")),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-config")
+                .about("Check the config, the store and what hooks need to run; exit non-zero on errors")
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Summarize locally recorded usage: commands, failures, hook errors and store growth")
+                .help(
+                    r#"kv report
+
+Summarize how you have used kv: how often each command ran and failed,
+which hooks failed to run, and how the store grew per day.
+
+Recording is off by default and everything stays on this machine. To turn
+it on:
+~> kv config set report.enabled true
+"#,
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("self-update")
+                .about("Update kv to the latest GitHub release, verifying its checksum")
+                .arg(Arg::with_name("check")
+                     .long("check")
+                     .help("Only report whether a newer version exists")),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Measure how long kv operations take")
+                .subcommand(SubCommand::with_name("hooks")
+                            .about("Run every hook (or those on [key]) once, waiting for each, and report how long it took")
+                            .arg(Arg::with_name("key").takes_value(true).required(false)))
+                .subcommand(SubCommand::with_name("get")
+                            .about("Look <key> up like kv get, many times, and report how long a lookup takes")
+                            .arg(Arg::with_name("key").takes_value(true).required(true))
+                            .arg(Arg::with_name("runs")
+                                 .long("runs")
+                                 .takes_value(true)
+                                 .default_value("1000")
+                                 .help("How many lookups to time"))),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Get and set kv settings")
+                .subcommand(SubCommand::with_name("get")
+                            .about("Print the value of setting <name>")
+                            .arg(Arg::with_name("name").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("set")
+                            .about("Set setting <name> to <value>")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("value").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("unset")
+                            .about("Reset setting <name> to its default")
+                            .arg(Arg::with_name("name").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("list").about("List all settings and their values")),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Pretty-print the store file (and shards) for editing by hand, e.g. with persist.style set to compact"),
+        )
+        .subcommand(
+            SubCommand::with_name("shard")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Split keys across several files so `kv get` on large stores only reads one of them")
+                .subcommand(SubCommand::with_name("enable")
+                            .about("Spread keys over <shards> files (re-shards if already enabled)")
+                            .arg(Arg::with_name("shards").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("disable").about("Move all keys back into the single store file")),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Check hook setups with a script of steps run against a throwaway store")
+                .help(
+                    r#"kv test <script.toml>
+
+Run the [[step]]s in <script.toml> against an empty, in-memory store that
+has this store's cmds and hooks. Nothing is written and no hooks run;
+each step can check a value and which hooks would have fired.
+
+A step is one of
+  set = "<key>"  value = "<val>"
+  get = "<key>"  expect = "<val>"  (or missing = true)
+  del = "<key>"
+plus, optionally, hooks = ["<hook-name>", ...] for exactly the hooks that
+should fire.
+
+Example script:
+[[step]]
+set = "background-img-loc"
+value = "~/Pictures/bg1.png"
+hooks = ["update-bg-hook"]
+
+[[step]]
+get = "background-img-loc"
+expect = "~/Pictures/bg1.png"
+hooks = []
+"#,
+                )
+                .arg(Arg::with_name("script").takes_value(true).required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+                .about("Draw which hooks bind which keys to which cmds, as Graphviz dot or Mermaid")
+                .arg(Arg::with_name("format")
+                     .long("format")
+                     .takes_value(true)
+                     .possible_values(&["dot", "mermaid"])
+                     .default_value("dot")
+                     .help("Hooks referring to cmds that don't exist are drawn dashed red")),
+        )
+        .subcommand(
+            SubCommand::with_name("transform")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Rhai cmds that change or reject values before kv set or kv serve stores them (needs the rhai feature)")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Run rhai cmd <cmd-name> on each value set on <key>. The script has key, op and value in scope: the value it ends with is stored instead (value itself if it ends with ()), and throw rejects it")
+                        .arg(Arg::with_name("key").takes_value(true).required(true)
+                             .help("Key to transform, or a pattern like deploy/* (* and ? wildcards) for every key it matches"))
+                        .arg(Arg::with_name("cmd-name").takes_value(true).required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("del")
+                        .about("Stop transforming values set on <key>")
+                        .arg(Arg::with_name("key").takes_value(true).required(true)),
+                )
+                .subcommand(SubCommand::with_name("list").about("List transforms")),
+        )
+        .subcommand(
+            SubCommand::with_name("computed")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Keys whose value is computed by a command from other keys, and kept up to date when they change")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Compute <key> with <cmd> whenever a key in --from is set or deleted")
+                        .arg(Arg::with_name("key").takes_value(true).required(true))
+                        .arg(Arg::with_name("cmd")
+                             .takes_value(true)
+                             .required(true)
+                             .help("Shell command printing the value. Each --from key is in its environment under its kv export --format env name, e.g. $HOST for host"))
+                        .arg(Arg::with_name("from")
+                             .long("from")
+                             .takes_value(true)
+                             .required(true)
+                             .use_delimiter(true)
+                             .value_name("keys")
+                             .help("Comma separated keys <key> is computed from")),
+                )
+                .subcommand(
+                    SubCommand::with_name("del")
+                        .about("Stop computing <key>. It keeps its current value")
+                        .arg(Arg::with_name("key").takes_value(true).required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("refresh")
+                        .about("Compute <key> again now")
+                        .arg(Arg::with_name("key").takes_value(true).required(true)),
+                )
+                .subcommand(SubCommand::with_name("list").about("List computed keys")),
+        )
+        .subcommand(
+            SubCommand::with_name("poll")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Keys refreshed from external sources on an interval by kv poll run")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Set <key> to the output of --cmd every --every")
+                        .arg(Arg::with_name("key").takes_value(true).required(true))
+                        .arg(Arg::with_name("every")
+                             .long("every")
+                             .takes_value(true)
+                             .required(true)
+                             .value_name("duration")
+                             .help("How often to run --cmd, e.g. 30s, 5m, 1h"))
+                        .arg(Arg::with_name("cmd")
+                             .long("cmd")
+                             .takes_value(true)
+                             .required(true)
+                             .help("Shell command printing the value")),
+                )
+                .subcommand(
+                    SubCommand::with_name("del")
+                        .about("Stop polling <key>. It keeps its current value")
+                        .arg(Arg::with_name("key").takes_value(true).required(true)),
+                )
+                .subcommand(SubCommand::with_name("list").about("List polled keys"))
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Refresh polled keys as they come due until interrupted. Set hooks only fire when a value changes")
+                        .arg(Arg::with_name("once")
+                             .long("once")
+                             .help("Refresh every polled key once and exit, e.g. from cron")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bot")
+                .about("Answer get and set messages from your phone as a Telegram bot, until interrupted")
+                .arg(Arg::with_name("telegram-token")
+                     .long("telegram-token")
+                     .takes_value(true)
+                     .env("KV_TELEGRAM_TOKEN")
+                     .hide_env_values(true)
+                     .required(true)
+                     .value_name("token")
+                     .help("Bot token from @BotFather. Prefer setting KV_TELEGRAM_TOKEN, which ps can't see"))
+                .arg(Arg::with_name("allow")
+                     .long("allow")
+                     .takes_value(true)
+                     .required(true)
+                     .use_delimiter(true)
+                     .value_name("users")
+                     .help("Comma separated numeric Telegram user ids to answer. Messages from anyone else are ignored"))
+                .arg(Arg::with_name("telegram-api")
+                     .long("telegram-api")
+                     .takes_value(true)
+                     .default_value("https://api.telegram.org")
+                     .value_name("url")
+                     .help("Bot API server, e.g. a self-hosted one")),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serve the store over HTTP until interrupted, with hooks firing on changes")
+                .help(
+                    r#"kv serve [--port <port>] [--bind <address>] [--token <token> | --insecure] [--host <name>]...
+
+  GET    /keys         all keys and values as JSON, secret values hidden.
+                       ?prefix=<p> lists keys starting with <p>, ?limit=<n>
+                       at most n of them, with a Link header to the next page
+  PUT    /keys         set every key in the JSON object sent to its value
+  DELETE /keys         delete every key in the JSON array sent
+  GET    /keys/<key>   the value of <key>, 404 if unset, 403 if secret
+  PUT    /keys/<key>   set <key> to the request body
+  DELETE /keys/<key>   delete <key>
+  GET    /cmds         cmds as JSON
+  GET    /hooks        hooks as JSON
+  POST   /graphql      GraphQL queries of keys and compare-and-swap sets,
+                       if kv was built with the graphql feature
+
+With Connection: keep-alive a client can send more requests on one
+connection. Rust services can use the kv-client crate.
+
+Escape / and other special characters in keys as %XX. Only this machine
+can connect unless --bind says otherwise, e.g. --bind 0.0.0.0.
+
+Every request needs Authorization: Bearer <token>. Without --token (or
+KV_SERVE_TOKEN) a token is generated and printed at startup. Requests must
+be addressed to the server's IP address, localhost, or a --host name.
+Secret keys can't be read, set or deleted.
+
+More tokens, limited to key prefixes, can be set up in config, e.g.
+~> kv config set serve.policies 't0k3n:rw:team-a/,t0k3n:r:shared/'
+lets t0k3n read and change team-a/* and read shared/*. Only the server's
+own token sees /cmds and /hooks.
+
+GETs send an ETag and, for keys, Last-Modified, and answer 304 Not
+Modified to If-None-Match or If-Modified-Since while nothing changed.
+Cache-Control comes from the serve.cache-control setting.
+
+With the serve.flush-interval setting, changes go to a write-ahead log
+and reach the store (and fire hooks) together, at most that often.
+
+Started by systemd socket activation (LISTEN_FDS), it serves the socket
+systemd passes in and ignores --port and --bind. With --idle-timeout it
+exits when idle, to be started again by the next connection.
+
+Example:
+~> KV_SERVE_TOKEN=s3cret kv serve --bind 0.0.0.0 --port 8080
+~> curl -H 'Authorization: Bearer s3cret' -X PUT -d ~/bg.png http://10.0.0.5:8080/keys/bg-img
+"#,
+                )
+                .arg(Arg::with_name("port")
+                     .long("port")
+                     .takes_value(true)
+                     .default_value("8080"))
+                .arg(Arg::with_name("bind")
+                     .long("bind")
+                     .takes_value(true)
+                     .default_value("127.0.0.1")
+                     .value_name("address")
+                     .help("Address to listen on. 0.0.0.0 lets other machines connect"))
+                .arg(Arg::with_name("token")
+                     .long("token")
+                     .takes_value(true)
+                     .env("KV_SERVE_TOKEN")
+                     .hide_env_values(true)
+                     .help("Token every request must send as Authorization: Bearer <token>, generated if not given. Prefer setting KV_SERVE_TOKEN, which ps can't see"))
+                .arg(Arg::with_name("insecure")
+                     .long("insecure")
+                     .conflicts_with("token")
+                     .help("Serve without a token, so anyone who can connect can read and change the store"))
+                .arg(Arg::with_name("host")
+                     .long("host")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .value_name("name")
+                     .help("Host name clients reach the server by, e.g. laptop.lan. IP addresses and localhost need no --host"))
+                .arg(Arg::with_name("idle-timeout")
+                     .long("idle-timeout")
+                     .takes_value(true)
+                     .value_name("duration")
+                     .help("Exit after no requests for this long, e.g. 10m. Meant for socket activation, which starts it again")),
+        )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Keep kv serve running as a user service, started at login")
+                .help(
+                    r#"kv daemon <install-service|uninstall-service>
+
+install-service writes a systemd user unit (~/.config/systemd/user/kv.service),
+or on macOS a launchd agent (~/Library/LaunchAgents/com.github.obaraelijah.kv.plist),
+running this kv binary's `kv serve` with the arguments after --, on the
+current store, then enables and starts it. The unit restarts it if it fails
+and locks it down with systemd's sandboxing options. KV_SERVE_TOKEN, if set,
+is kept in a file only you can read rather than in the unit.
+uninstall-service stops it and removes those files.
+
+Example:
+~> KV_SERVE_TOKEN=s3cret kv daemon install-service -- --port 8080 --host laptop
+~> kv daemon uninstall-service
+"#,
+                )
+                .subcommand(SubCommand::with_name("install-service")
+                            .about("Write, enable and start a user service running kv serve")
+                            .arg(Arg::with_name("name").long("name").takes_value(true).default_value("kv")
+                                 .help("Name of the service, to run more than one, e.g. for another --store"))
+                            .arg(Arg::with_name("no-start").long("no-start")
+                                 .help("Only write the unit, don't enable or start it"))
+                            .arg(Arg::with_name("serve-args").multiple(true).last(true).value_name("serve args")
+                                 .help("Arguments for kv serve, e.g. -- --port 8080")))
+                .subcommand(SubCommand::with_name("uninstall-service")
+                            .about("Stop and remove the user service written by install-service")
+                            .arg(Arg::with_name("name").long("name").takes_value(true).default_value("kv")
+                                 .help("Name of the service"))
+                            .arg(Arg::with_name("no-stop").long("no-stop")
+                                 .help("Only remove the unit, don't stop or disable it"))),
+        )
+        .subcommand(
+            SubCommand::with_name("digest")
+                .about("Send a summary of what changed since the last digest (from kv events) to a webhook, e.g. from cron")
+                .arg(Arg::with_name("to")
+                     .long("to")
+                     .takes_value(true)
+                     .required(true)
+                     .value_name("url")
+                     .help("Webhook the digest is POSTed to as JSON. Its text field suits Slack incoming webhooks"))
+                .arg(Arg::with_name("daily")
+                     .long("daily")
+                     .conflicts_with("every")
+                     .help("Only send if the last digest to <url> is at least a day old, so cron can run this hourly"))
+                .arg(Arg::with_name("every")
+                     .long("every")
+                     .takes_value(true)
+                     .value_name("duration")
+                     .help("Like --daily, for another interval, e.g. 1w"))
+                .arg(Arg::with_name("print")
+                     .long("print")
+                     .help("Print the digest instead of sending it")),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Find and merge the conflicted copies Dropbox, Syncthing, iCloud or OneDrive make of a store in a synced folder")
+                .subcommand(SubCommand::with_name("status")
+                            .about("Show which sync service the store is in and its conflicted copies"))
+                .subcommand(SubCommand::with_name("merge")
+                            .about("Merge the keys of conflicted copy <file> into the store, asking about each conflict, then remove it")
+                            .arg(Arg::with_name("file").takes_value(true).required(true))),
+        )
+        .subcommand(
+            SubCommand::with_name("context")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Switch between named bundles of store, namespace and export format, like kubectl contexts")
+                .subcommand(SubCommand::with_name("create")
+                            .about("Create (or replace) context <name>")
+                            .arg(Arg::with_name("name").takes_value(true).required(true))
+                            .arg(Arg::with_name("store")
+                                 .long("store")
+                                 .takes_value(true)
+                                 .help("Store file to use instead of kv.json"))
+                            .arg(Arg::with_name("namespace")
+                                 .long("namespace")
+                                 .takes_value(true)
+                                 .help("Read and write keys as <namespace>/<key>"))
+                            .arg(Arg::with_name("format")
+                                 .long("format")
+                                 .takes_value(true)
+                                 .possible_values(&["json", "env", "csv", "compose-env", "shell"])
+                                 .help("Default format for kv export")))
+                .subcommand(SubCommand::with_name("use")
+                            .about("Use context <name> from now on. KV_CONTEXT=<name> picks one for a single shell.")
+                            .arg(Arg::with_name("name").takes_value(true).required_unless("none"))
+                            .arg(Arg::with_name("none").long("none").conflicts_with("name").help("Stop using a context")))
+                .subcommand(SubCommand::with_name("delete")
+                            .about("Delete context <name>. Its store file is kept, unless kv profile create made it")
+                            .arg(Arg::with_name("name").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("show").about("List contexts, marking the one in use")),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Separate stores, e.g. for work, personal and each project. Profiles are contexts with a store of their own")
+                .subcommand(SubCommand::with_name("create")
+                            .about("Create profile <name>, with an empty store in the config dir")
+                            .arg(Arg::with_name("name").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("use")
+                            .about("Use profile <name> from now on. kv --store <name> picks one for a single call.")
+                            .arg(Arg::with_name("name").takes_value(true).required_unless("none"))
+                            .arg(Arg::with_name("none").long("none").conflicts_with("name").help("Go back to kv.json")))
+                .subcommand(SubCommand::with_name("delete")
+                            .about("Delete profile <name> and its store")
+                            .arg(Arg::with_name("name").takes_value(true).required(true)))
+                .subcommand(SubCommand::with_name("list").about("List profiles and contexts, marking the one in use")),
+        )
+        .subcommand(
+            SubCommand::with_name("index")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Manage the optional value search index used by `kv search --values`")
+                .subcommand(SubCommand::with_name("build").about("Build the index. It is kept up to date on every set/del."))
+                .subcommand(SubCommand::with_name("drop").about("Remove the index")),
+        )
+        .subcommand(
+            SubCommand::with_name("cmd")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .about("Add, and Run bash commands. Add hooks to run commands on variable update.")
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Run commands <cmd-name>")
+                        .arg(Arg::with_name("cmd-name").takes_value(true).required(true))
+                        .arg(Arg::with_name("capture")
+                             .long("capture")
+                             .takes_value(true)
+                             .value_name("key")
+                             .help("Store the command's output in <key> and its exit code in <key>.status, firing their set hooks, instead of printing it")),
+                )
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about("Show past `kv cmd run`s with their exit code and duration")
+                        .arg(Arg::with_name("count")
+                             .short("n")
+                             .takes_value(true)
+                             .default_value("20")
+                             .help("How many of the latest runs to show")),
+                )
+                .subcommand(
+                    SubCommand::with_name("rerun")
+                        .about("Run the command from history entry <id> again, exactly as it ran then")
+                        .arg(Arg::with_name("id").takes_value(true).required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add command with name <cmd-name>, and value <cmd-value>")
+                        .arg(Arg::with_name("cmd-name").takes_value(true).required(true))
+                        .arg(Arg::with_name("cmd-value").takes_value(true).required(true))
+                        .arg(Arg::with_name("allow-dangerous")
+                             .long("allow-dangerous")
+                             .help("Add the command even if cmds.safety-check flags it"))
+                        .arg(Arg::with_name("lang")
+                             .long("lang")
+                             .takes_value(true)
+                             .possible_values(script::LANGS)
+                             .default_value("shell")
+                             .help("What <cmd-value> is written in. A lua script gets the key, op and value that fired its hook as the locals key, op and value. A rhai script runs in kv itself (needs the rhai feature) with them as the variables key, op and value")),
+                )
+            .subcommand(
+                SubCommand::with_name("add-hook")
+                    .about("Add hook with name <hook-name> to run <cmd-name> when [key] is updated (kv get, kv set, kv del)")
+                    .arg(Arg::with_name("hook-name").takes_value(true).required(true))
+                    .arg(Arg::with_name("cmd-name").takes_value(true).required(true))
+                    .arg(Arg::with_name("trigger").takes_value(false).required(true).possible_values(&["get", "set", "del"]))
+                    .arg(Arg::with_name("key").takes_value(true).required(true)
+                         .help("Key to hook, or a pattern like deploy/* (* and ? wildcards) for every key it matches"))
+                    .arg(Arg::with_name("sandbox")
+                         .long("sandbox")
+                         .help("Run the hook with no network and a read-only filesystem (Linux, needs bwrap)"))
+                    .arg(Arg::with_name("run-as")
+                         .long("run-as")
+                         .takes_value(true)
+                         .value_name("user")
+                         .help("Run the hook as <user> with sudo -n or doas -n. Fails instead of prompting for a password"))
+                    .arg(Arg::with_name("elevate-with")
+                         .long("elevate-with")
+                         .takes_value(true)
+                         .requires("run-as")
+                         .possible_values(elevate::TOOLS)
+                         .help("Tool --run-as uses [default: sudo, or doas if sudo is not installed]"))
+                    .arg(Arg::with_name("action")
+                         .long("action")
+                         .takes_value(true)
+                         .possible_values(action::BUILT_IN)
+                         .default_value("shell")
+                         .help("shell runs <cmd-name> as a command. webhook POSTs the hook, key, op and value as JSON to the URL stored in <cmd-name>. notify shows <cmd-name>'s text as a desktop notification, with {key}, {op} and {value} filled in. wasm calls on_event(key, op, value) in the wasm module at the path in <cmd-name> (needs the wasm feature)"))
+                    .arg(Arg::with_name("description")
+                         .long("description")
+                         .takes_value(true)
+                         .help("What the hook is for, shown by kv hooks show"))
+            )
+            .subcommand(
+                SubCommand::with_name("del-hook")
+                    .about("Remove hook with name <hook-name>")
+                    .arg(Arg::with_name("hook-name").takes_value(true).required(true))
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Get key from storage")
+                .help(
+                    r#"kv get [--raw] [--qr] [--stores <names>] <key>...
+
+Get the value of <key> from storage. With several keys, each value is
+printed on its own line in the order given, an empty line for a key that
+doesn't exist. With -o json they are printed as one object instead.
+
+Values that are password manager references are looked up when you get
+them, so the store never holds the secret itself:
+  op://<vault>/<item>/<field>   1Password, with the op CLI
+  bw://<item>/<field>           Bitwarden, with the bw CLI
+Use --raw to print the reference instead.
+
+Example:
+~> kv set my-key my-key-value
+~> kv get my-key
+my-key-value
+~> kv get my-key other-key
+my-key-value
+
+~> kv set db-password op://dev/postgres/password
+~> kv get db-password
+hunter2
+"#,
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .help("keys to get from storage")
+                        .takes_value(true)
+                        .multiple(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help("Print op:// and bw:// references instead of resolving them"),
+                )
+                .arg(
+                    Arg::with_name("qr")
+                        .long("qr")
+                        .help("Show the value as a QR code, e.g. to scan a wifi password with a phone"),
+                )
+                .arg(
+                    Arg::with_name("stores")
+                        .long("stores")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .value_name("names")
+                        .help("Look <key> up in these stores in order and print the first value found. A store is default (kv.json) or a context name, whose namespace applies. Get hooks don't run"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("del")
+                .help(
+                    r#"kv del [--glob [--no-snapshot]] <key>
+kv del --local-machine <key>
+
+Delete <key> in storage (and its value)
+
+With --local-machine, only this machine's override of <key> is deleted.
+
+With --glob, <key> is a pattern (* and ?) and every matching key is deleted.
+The deleted keys are printed, and the store is snapshotted first unless
+--no-snapshot is given.
+
+Example:
+~> kv set my-key my-key-value
+~> kv del my-key
+~> kv get my-key
+
+~> kv del --glob 'deploy/*'
+"#,
+                )
+                .about("Delete key and value from storage")
+                .arg(
+                    Arg::with_name("key")
+                        .help("key to delete from storage")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(Arg::with_name("glob").long("glob"))
+                .arg(Arg::with_name("no-snapshot").long("no-snapshot").requires("glob"))
+                .arg(
+                    Arg::with_name("local-machine")
+                        .long("local-machine")
+                        .conflicts_with("glob")
+                        .help("Delete this machine's override of <key>, leaving the shared value"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("set key to value in storage")
+                .help(
+                    r#"kv set [--secret | --local-machine] <key> <val>
+kv set [--secret | --local-machine] --prompt <key>
+kv set [--secret | --local-machine] <key> -
+kv set [--secret | --local-machine] --from-file <path> <key>
+kv set [--secret | --local-machine] --many <key>=<val>...
+
+Set <key> to <val> in storage. With --prompt, type the value at a prompt
+that doesn't echo instead, so it stays out of `ps` and shell history.
+--secret marks the key secret, like `kv ask` does.
+
+A <val> of - reads the value from stdin, and --from-file reads it from
+<path>. Either is stored exactly as read, newlines included, so certificates
+and other multiline values round-trip.
+
+With --many, every argument is a <key>=<val> pair and all the keys are set in
+one write of the store, which is much faster than a kv set per key. --secret
+and --ttl apply to them all. Without it, kv set 'a=b' c sets the key a=b.
+
+With --local-machine, <val> only applies on this machine: it goes to
+kv.<hostname>.json next to the store and is read over the shared value,
+e.g. for paths and ports that differ between synced machines. Overrides
+can't be secret or expire, so it can't be combined with --secret or --ttl.
+
+Example:
+~> kv set my-key my-key-value
+~> kv get my-key
+my-key-value
+~> kv set --secret --prompt api-token
+Value for api-token:
+~> kv set --many host=db.local port=5432
+~> kv set --from-file ca.pem tls.ca
+~> kv set motd - < /etc/motd
+"#,
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .help("key to set in storage")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("val")
+                        .help("<val> you wish to set <key> to, or with --many more <key>=<val> pairs")
+                        .takes_value(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("many")
+                        .long("many")
+                        .conflicts_with_all(&["prompt", "from-file"])
+                        .help("Set several keys: <key> and every <val> are <key>=<val> pairs"),
+                )
+                .arg(
+                    Arg::with_name("prompt")
+                        .long("prompt")
+                        .conflicts_with("val")
+                        .help("Read the value from a prompt that doesn't echo instead of the command line"),
+                )
+                .arg(
+                    Arg::with_name("from-file")
+                        .long("from-file")
+                        .takes_value(true)
+                        .value_name("path")
+                        .conflicts_with_all(&["val", "prompt"])
+                        .help("Read the value from the file at <path>"),
+                )
+                .arg(
+                    Arg::with_name("secret")
+                        .long("secret")
+                        .help("Mark the key secret: its value is hidden in list and search, and skipped by grep"),
+                )
+                .arg(
+                    Arg::with_name("local-machine")
+                        .long("local-machine")
+                        .conflicts_with("secret")
+                        .help("Set <key> only on this machine, over the shared value"),
+                )
+                .arg(
+                    Arg::with_name("ttl")
+                        .long("ttl")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .conflicts_with("local-machine")
+                        .help("Expire <key> after DURATION (seconds, or e.g. 15m, 2h, 1d): after that it reads as unset"),
+                ),
+        )
+        .get_matches();
+    if let Some(var) = matches.value_of("store-from-env") {
+        stateless::from_env(var);
+    }
+    if let Some(path) = matches.value_of("store-from-file") {
+        stateless::from_file(path);
+    }
+    if let Some(name) = matches.value_of("store") {
+        context::select(name);
+    }
+    open_store(
+        matches.subcommand_name(),
+        matches.is_present("no-lock"),
+        matches.is_present("detach"),
+    );
+    if let Some(path) = matches.value_of("record-hooks") {
+        record::start(path);
+    }
+    let command = command_name(&matches);
+    if PAGED.contains(&&command[..]) && !matches.is_present("no-pager") {
+        pager::start();
+    }
+    usage::start(&command);
+    run(matches);
+    usage::finish();
+    exit_for_hooks();
+}
\ No newline at end of file
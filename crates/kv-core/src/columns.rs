@@ -0,0 +1,90 @@
+use serde_json::{json, Value};
+
+use crate::{glob, time, KVStore};
+
+/// Columns `kv keys --columns` can show.
+pub const NAMES: &[&str] = &["key", "value", "created", "modified", "source", "secret", "expires", "hooks"];
+
+/// What `kv keys --long` shows.
+pub const LONG: &[&str] = &["key", "value", "modified", "source", "hooks"];
+
+/// Parse a comma separated list of columns, e.g. `key,modified`.
+pub fn parse(spec: &str) -> Result<Vec<&'static str>, String> {
+    spec.split(',')
+        .map(|name| {
+            let name = name.trim();
+            NAMES.iter().find(|&&n| n == name).copied().ok_or_else(|| {
+                format!("Unknown column '{}', expected some of {}", name, NAMES.join(","))
+            })
+        })
+        .collect()
+}
+
+/// The table header for `columns`, e.g. `Key\t--\tModified`.
+pub fn header(columns: &[&str]) -> String {
+    columns
+        .iter()
+        .map(|name| {
+            let mut chars = name.chars();
+            chars.next().unwrap().to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\t--\t")
+}
+
+fn timestamp(t: Option<u64>) -> String {
+    t.map_or("-".to_owned(), time::format_timestamp)
+}
+
+/// The hooks that fire on `key`, e.g. `bg-hook (set), audit (del)`.
+pub fn hooks(kvstore: &KVStore, key: &str) -> Vec<String> {
+    kvstore
+        .hooks
+        .iter()
+        .filter(|hook| glob::matches(&hook.key, key))
+        .map(|hook| format!("{} ({})", hook.name, hook.run_on))
+        .collect()
+}
+
+/// One table row for `key` in `kvstore`, shown as `shown_key` (without the namespace) with
+/// `value`. Keys set before metadata was recorded show `-` for it.
+pub fn row(columns: &[&str], kvstore: &KVStore, key: &str, shown_key: &str, value: &str) -> String {
+    let meta = kvstore.meta.get(key);
+    columns
+        .iter()
+        .map(|&name| match name {
+            "key" => shown_key.to_owned(),
+            "value" => value.to_owned(),
+            "hooks" => match hooks(kvstore, key) {
+                hooks if hooks.is_empty() => "-".to_owned(),
+                hooks => hooks.join(", "),
+            },
+            "created" => timestamp(meta.map(|m| m.created)),
+            "modified" => timestamp(meta.map(|m| m.modified)),
+            "source" => meta.and_then(|m| m.source.clone()).unwrap_or_else(|| "-".to_owned()),
+            "secret" => if meta.is_some_and(|m| m.secret) { "yes" } else { "no" }.to_owned(),
+            _ => timestamp(meta.and_then(|m| m.expires)),
+        })
+        .collect::<Vec<_>>()
+        .join("\t--\t")
+}
+
+/// One row as a JSON object. Times are seconds since the unix epoch, null when unknown.
+pub fn json_row(columns: &[&str], kvstore: &KVStore, key: &str, shown_key: &str, value: &str) -> Value {
+    let meta = kvstore.meta.get(key);
+    let mut object = serde_json::Map::new();
+    for &name in columns {
+        let cell = match name {
+            "key" => json!(shown_key),
+            "value" => json!(value),
+            "hooks" => json!(hooks(kvstore, key)),
+            "created" => json!(meta.map(|m| m.created)),
+            "modified" => json!(meta.map(|m| m.modified)),
+            "source" => json!(meta.and_then(|m| m.source.as_deref())),
+            "secret" => json!(meta.is_some_and(|m| m.secret)),
+            _ => json!(meta.and_then(|m| m.expires)),
+        };
+        object.insert(name.to_owned(), cell);
+    }
+    Value::Object(object)
+}
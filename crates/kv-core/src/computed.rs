@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export, fail, get_key, get_store, hook_env, print_err, set_key, shell_command,
+    touch_meta, try_get_main_store, try_get_store, try_on_change, try_with_store_lock, try_write_file,
+    with_store_lock, write_file, index, Error, KVStore, OpType,
+};
+
+/// A key whose value is the output of `cmd`, recomputed whenever one of the keys in `from`
+/// is set or deleted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Computed {
+    pub from: Vec<String>,
+    pub cmd: String,
+}
+
+/// Whether `key` is one of the keys `target` is computed from, directly or through other
+/// computed keys.
+fn depends_on(kvstore: &KVStore, target: &str, key: &str) -> bool {
+    let mut pending = vec![target];
+    let mut seen = vec![];
+    while let Some(k) = pending.pop() {
+        if let Some(computed) = kvstore.computed.get(k) {
+            for dep in &computed.from {
+                if dep == key {
+                    return true;
+                }
+                if !seen.contains(&&dep[..]) {
+                    seen.push(&dep[..]);
+                    pending.push(dep);
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Run `computed.cmd` for `key`. Each key it's computed from is in the environment under
+/// its `kv export --format env` name (unset if the key doesn't exist), and `KV_KEY` is `key`.
+fn compute(kvstore: &KVStore, key: &str, computed: &Computed) -> Result<String, String> {
+    let mut command = shell_command(&computed.cmd);
+    hook_env(&mut command);
+    command.env("KV_KEY", key);
+    for dep in &computed.from {
+        if let Some(value) = get_key(dep, &kvstore.kvs) {
+            command.env(export::env_name(dep), value);
+        }
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("cannot run {:?}, error {}", computed.cmd, e))?;
+    if !output.status.success() {
+        return Err(format!("{:?} failed with {}", computed.cmd, output.status));
+    }
+    let value = String::from_utf8_lossy(&output.stdout);
+    Ok(value.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+/// Recompute `key` and store it if its value changed, which fires its own set hooks and
+/// refreshes the keys computed from it in turn.
+pub fn refresh(key: &str) {
+    try_refresh(key).unwrap_or_else(|e| fail(e));
+}
+
+/// `refresh`, returning the error if the store can't be read or written.
+fn try_refresh(key: &str) -> Result<(), Error> {
+    let kvstore = try_get_store()?;
+    let computed = match kvstore.computed.get(key) {
+        Some(computed) => computed.clone(),
+        None => return Err(Error::new(format!("{} is not a computed key", key))),
+    };
+    // The command runs without the store lock, since it may well call kv itself.
+    let value = match compute(&kvstore, key, &computed) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!(
+                "Warning! Cannot compute {}, {}. It keeps its old value.",
+                key, e
+            );
+            return Ok(());
+        }
+    };
+    let changed = try_with_store_lock(|| {
+        let mut kvstore = try_get_store()?;
+        // Removed meanwhile: leave the now plain key alone.
+        if !kvstore.computed.contains_key(key) {
+            return Ok(false);
+        }
+        let old = get_key(key, &kvstore.kvs);
+        if old.as_deref() == Some(&value[..]) {
+            return Ok(false);
+        }
+        set_key(key, &value, &mut kvstore.kvs);
+        touch_meta(key, "computed", &mut kvstore.meta);
+        try_write_file(&kvstore)?;
+        index::try_update(&kvstore, key, old.as_deref(), Some(&value))?;
+        Ok(true)
+    })?;
+    if changed {
+        try_on_change(key, OpType::Set, Some(&value))?;
+    }
+    Ok(())
+}
+
+/// Called after `key` changes: refresh every key computed from it. Returns the error if the
+/// store can't be read or written.
+pub fn try_dependency_changed(key: &str) -> Result<(), Error> {
+    let kvstore = try_get_main_store()?;
+    let dependents: Vec<&String> = kvstore
+        .computed
+        .iter()
+        .filter(|(_, computed)| computed.from.iter().any(|dep| dep == key))
+        .map(|(name, _)| name)
+        .collect();
+    for name in dependents {
+        try_refresh(name)?;
+    }
+    Ok(())
+}
+
+pub fn add(key: &str, computed: Computed) {
+    if computed.from.iter().any(|dep| dep == key) {
+        let err_msg = format!("Error! {} cannot be computed from itself", key);
+        print_err(&err_msg[..]);
+    }
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if let Some(dep) = computed
+            .from
+            .iter()
+            .find(|dep| depends_on(&kvstore, dep, key))
+        {
+            let err_msg = format!(
+                "Error! {} is already computed from {}, so {} cannot be computed from it",
+                dep, key, key
+            );
+            print_err(&err_msg[..]);
+        }
+        kvstore.computed.insert(key.to_owned(), computed);
+        write_file(&kvstore);
+    });
+    refresh(key);
+}
+
+/// Stop computing `key`. It keeps its current value as a plain key.
+pub fn del(key: &str) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if kvstore.computed.remove(key).is_none() {
+            let err_msg = format!("Error! {} is not a computed key", key);
+            print_err(&err_msg[..]);
+        }
+        write_file(&kvstore);
+    });
+}
+
+/// Lines for `kv computed list`.
+pub fn list(kvstore: &KVStore) -> Vec<String> {
+    let mut lines = vec!["Key\t--\tFrom\t--\tCmd".to_owned()];
+    lines.extend(kvstore.computed.iter().map(|(key, computed)| {
+        format!(
+            "{}\t--\t{}\t--\t{}",
+            key,
+            computed.from.join(","),
+            computed.cmd
+        )
+    }));
+    lines
+}
@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::{fail, print_err, time, try_config_dir, Error};
+
+/// What values a setting accepts.
+enum Kind {
+    Bool,
+    Duration,
+    /// Comma separated.
+    List,
+    Text,
+    /// One of these.
+    Choice(&'static [&'static str]),
+    /// A whole number, 0 or more.
+    Number,
+    /// Comma separated `<token>:<r|rw>:<prefix>` entries, see `policy`.
+    Policies,
+}
+
+struct Setting {
+    name: &'static str,
+    kind: Kind,
+    default: &'static str,
+    about: &'static str,
+}
+
+/// Every setting kv understands. `kv config set` refuses anything else.
+const SETTINGS: &[Setting] = &[
+    Setting {
+        name: "read.mmap",
+        kind: Kind::Bool,
+        default: "false",
+        about: "Memory-map the store file instead of reading it into memory before parsing",
+    },
+    Setting {
+        name: "hooks.warn-after",
+        kind: Kind::Duration,
+        default: "1s",
+        about: "Warn about hooks that take longer than this to run",
+    },
+    Setting {
+        name: "report.enabled",
+        kind: Kind::Bool,
+        default: "false",
+        about: "Record command usage locally for `kv report`. Nothing is ever uploaded.",
+    },
+    Setting {
+        name: "events.enabled",
+        kind: Kind::Bool,
+        default: "false",
+        about: "Log every change to keys, cmds and hooks (with new values, except secret ones) for `kv events`",
+    },
+    Setting {
+        name: "hooks.env-allow",
+        kind: Kind::List,
+        default: "",
+        about: "Extra environment variables passed to hooks, e.g. SSH_AUTH_SOCK,AWS_* (* passes everything)",
+    },
+    Setting {
+        name: "hooks.sandbox-writable",
+        kind: Kind::List,
+        default: "",
+        about: "Paths sandboxed hooks may write to, besides the kv config dir",
+    },
+    Setting {
+        name: "secrets.warn-argv",
+        kind: Kind::Bool,
+        default: "true",
+        about: "Warn when a secret key's value is passed on the command line instead of with --prompt",
+    },
+    Setting {
+        name: "secrets.keyfile",
+        kind: Kind::Text,
+        default: "",
+        about: "File whose contents kv secret encrypts values with, instead of asking for a passphrase (or reading KV_PASSPHRASE)",
+    },
+    Setting {
+        name: "secrets.verifier",
+        kind: Kind::Text,
+        default: "",
+        about: "Set by the first kv secret set: a check value encrypted with the passphrase, so a wrong one is refused. Unset it to change passphrases",
+    },
+    Setting {
+        name: "share.endpoint",
+        kind: Kind::Text,
+        default: "",
+        about: "Where kv share uploads encrypted values: a URL that takes a POST and answers with the URL to fetch",
+    },
+    Setting {
+        name: "cmds.safety-check",
+        kind: Kind::Bool,
+        default: "false",
+        about: "Refuse to add cmds that look dangerous (rm -rf, curl | sh, ...) without --allow-dangerous",
+    },
+    Setting {
+        name: "persist.style",
+        kind: Kind::Choice(&["pretty", "compact"]),
+        default: "pretty",
+        about: "How the store is written. compact is about half the size and write time; kv fmt pretty-prints it again.",
+    },
+    Setting {
+        name: "persist.backend",
+        kind: Kind::Choice(&["json", "sqlite"]),
+        default: "json",
+        about: "What the store is kept in: kv.json, or with the sqlite feature kv.db, where a change only writes the keys it touches. kv.db starts with the keys in kv.json.",
+    },
+    Setting {
+        name: "lint.key-pattern",
+        kind: Kind::Text,
+        default: "",
+        about: "Regex every key must match for kv lint, e.g. ^[a-z0-9_./-]+$. Any key goes if empty",
+    },
+    Setting {
+        name: "lint.secret-patterns",
+        kind: Kind::List,
+        default: "AKIA[0-9A-Z]{16},gh[pousr]_[A-Za-z0-9]{36},-----BEGIN [A-Z ]*PRIVATE KEY-----,xox[baprs]-[A-Za-z0-9-]{10}",
+        about: "Comma separated regexes of secrets (AWS keys, GitHub and Slack tokens, private keys) kv lint flags in values not encrypted with kv secret",
+    },
+    Setting {
+        name: "lint.max-value-size",
+        kind: Kind::Number,
+        default: "65536",
+        about: "Values over this many bytes fail kv lint. 0 for no limit",
+    },
+    Setting {
+        name: "lint.hook-descriptions",
+        kind: Kind::Bool,
+        default: "true",
+        about: "Hooks without a --description fail kv lint",
+    },
+    Setting {
+        name: "io.retry-for",
+        kind: Kind::Duration,
+        default: "2s",
+        about: "Keep retrying, with backoff, to read or write a store file that's temporarily unavailable (NFS, sync clients) for this long",
+    },
+    Setting {
+        name: "serve.cache-control",
+        kind: Kind::Text,
+        default: "no-cache",
+        about: "Cache-Control header kv serve sends with keys it serves, e.g. max-age=60. no-cache has clients check back (cheaply, with If-None-Match) every time",
+    },
+    Setting {
+        name: "serve.flush-interval",
+        kind: Kind::Duration,
+        default: "0s",
+        about: "How often kv serve writes changes to the store. Until then they're kept in a write-ahead log next to it, so bursts of writes cost one store write. 0s writes each change right away",
+    },
+    Setting {
+        name: "serve.fsync",
+        kind: Kind::Choice(&["always", "never"]),
+        default: "always",
+        about: "Whether kv serve syncs its write-ahead log to disk before answering a write. never is faster, but a power cut can lose the last changes (a crash can't)",
+    },
+    Setting {
+        name: "serve.policies",
+        kind: Kind::Policies,
+        default: "",
+        about: "Extra kv serve tokens limited to key prefixes, as comma separated <token>:<r|rw>:<prefix>, e.g. t0k3n:rw:team-a/,t0k3n:r:shared/",
+    },
+];
+
+/// One `serve.policies` entry, as the token, whether it may write, and the key prefix it
+/// may use. An empty prefix means every key.
+pub fn policy(entry: &str) -> Result<(&str, bool, &str), String> {
+    let bad = || format!("{:?} in serve.policies is not <token>:<r|rw>:<prefix>", entry);
+    let mut parts = entry.splitn(3, ':');
+    let (Some(token), Some(mode), Some(prefix)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(bad());
+    };
+    let write = match mode {
+        "r" => false,
+        "rw" => true,
+        _ => return Err(bad()),
+    };
+    if token.is_empty() {
+        return Err(bad());
+    }
+    Ok((token, write, prefix))
+}
+
+/// Settings from `config.json` in the kv config dir. Unlike the store, this is never
+/// shared between machines or profiles.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    #[serde(flatten)]
+    values: BTreeMap<String, String>,
+}
+
+fn get_config_location() -> Result<PathBuf, Error> {
+    Ok(try_config_dir()?.join("config.json"))
+}
+
+fn find_setting(name: &str) -> &'static Setting {
+    match SETTINGS.iter().find(|s| s.name == name) {
+        Some(setting) => setting,
+        None => {
+            let err_msg = format!(
+                "Error! Unknown setting {}. To see all settings try\n kv config list",
+                name
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+fn validate(setting: &Setting, value: &str) -> Result<(), String> {
+    match setting.kind {
+        Kind::Bool => match value {
+            "true" | "false" => Ok(()),
+            _ => Err(format!("{} must be true or false", setting.name)),
+        },
+        Kind::Duration => time::parse_duration(value).map(|_| ()),
+        Kind::List | Kind::Text => Ok(()),
+        Kind::Number => match value.parse::<u64>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("{} must be a whole number", setting.name)),
+        },
+        Kind::Policies => value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .try_for_each(|entry| policy(entry).map(|_| ())),
+        Kind::Choice(choices) if choices.contains(&value) => Ok(()),
+        Kind::Choice(choices) => Err(format!("{} must be one of {}", setting.name, choices.join(", "))),
+    }
+}
+
+fn load() -> Result<Config, Error> {
+    let path = get_config_location()?;
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+            Error::new(format!("Cannot parse {}, error {}", path.to_string_lossy(), e))
+        }),
+        Err(_) => Ok(Config::default()),
+    }
+}
+
+fn save(config: &Config) {
+    let path = get_config_location().unwrap_or_else(|e| fail(e));
+    let s = serde_json::to_string_pretty(config).unwrap();
+    if let Err(e) = std::fs::write(&path, s) {
+        let err_msg = format!(
+            "Error! Cannot write {}, error {}",
+            path.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// The config, read once per invocation.
+pub fn get() -> &'static Config {
+    try_get().unwrap_or_else(|e| fail(e))
+}
+
+/// `get`, returning the error if the config can't be read.
+pub fn try_get() -> Result<&'static Config, Error> {
+    static CONFIG: OnceLock<Result<Config, Error>> = OnceLock::new();
+    CONFIG.get_or_init(load).as_ref().map_err(Error::clone)
+}
+
+impl Config {
+    /// Value of setting `name`, falling back to its default.
+    pub fn value(&self, name: &str) -> &str {
+        let setting = find_setting(name);
+        self.values
+            .get(name)
+            .map(|v| &v[..])
+            .unwrap_or(setting.default)
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.value(name) == "true"
+    }
+
+    pub fn get_list(&self, name: &str) -> Vec<&str> {
+        self.value(name)
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    pub fn get_duration(&self, name: &str) -> Duration {
+        time::parse_duration(self.value(name)).unwrap_or_default()
+    }
+
+    pub fn get_number(&self, name: &str) -> u64 {
+        self.value(name).parse().unwrap_or_default()
+    }
+}
+
+/// Problems with the settings in `config.json`: unknown names and invalid values.
+pub fn check() -> Vec<String> {
+    get()
+        .values
+        .iter()
+        .filter_map(|(name, value)| match SETTINGS.iter().find(|s| s.name == name) {
+            None => Some(format!("unknown setting {}", name)),
+            Some(setting) => validate(setting, value).err(),
+        })
+        .collect()
+}
+
+pub fn set(name: &str, value: &str) {
+    let setting = find_setting(name);
+    if let Err(e) = validate(setting, value) {
+        print_err(&format!("Error! {}", e));
+    }
+    let mut config = load().unwrap_or_else(|e| fail(e));
+    config.values.insert(name.to_owned(), value.to_owned());
+    save(&config);
+}
+
+pub fn unset(name: &str) {
+    find_setting(name);
+    let mut config = load().unwrap_or_else(|e| fail(e));
+    config.values.remove(name);
+    save(&config);
+}
+
+/// Lines for `kv config list`: every setting with its current value and description.
+pub fn list() -> Vec<String> {
+    let config = get();
+    let mut lines = vec!["Setting\t--\tValue\t--\tDescription".to_owned()];
+    lines.extend(SETTINGS.iter().map(|s| {
+        format!("{}\t--\t{}\t--\t{}", s.name, config.value(s.name), s.about)
+    }));
+    lines
+}
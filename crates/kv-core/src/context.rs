@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{get_config_dir, print_aligned, print_err, stateless};
+
+/// A named bundle of store, namespace and output defaults, like a kubectl context.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Context {
+    /// Store file used instead of kv.json.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// Keys are read and written as `<namespace>/<key>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Default `kv export --format`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Contexts {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    #[serde(default)]
+    contexts: BTreeMap<String, Context>,
+}
+
+static ACTIVE: OnceLock<Option<(String, Context)>> = OnceLock::new();
+static SELECTED: OnceLock<String> = OnceLock::new();
+
+fn location() -> PathBuf {
+    get_config_dir().join("contexts.json")
+}
+
+fn load() -> Contexts {
+    match std::fs::read(location()) {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(contexts) => contexts,
+            Err(e) => {
+                let err_msg = format!(
+                    "Error! Cannot parse {}, error {}",
+                    location().to_string_lossy(),
+                    e
+                );
+                print_err(&err_msg[..]);
+            }
+        },
+        Err(_) => Contexts::default(),
+    }
+}
+
+fn save(contexts: &Contexts) {
+    stateless::ensure_writable();
+    if let Err(e) = std::fs::write(location(), serde_json::to_string_pretty(contexts).unwrap()) {
+        let err_msg = format!(
+            "Error! Cannot write {}, error {}",
+            location().to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// Use context `name` for the rest of this run, as `kv --store <name>` does.
+pub fn select(name: &str) {
+    let _ = SELECTED.set(name.to_owned());
+}
+
+/// The context in use: the one from `select`, else `$KV_CONTEXT` if set (empty for none),
+/// else the one picked with `kv context use`.
+pub fn active() -> Option<&'static (String, Context)> {
+    ACTIVE
+        .get_or_init(|| {
+            let mut contexts = load();
+            let name = match (SELECTED.get(), std::env::var("KV_CONTEXT")) {
+                (Some(name), _) => name.clone(),
+                (None, Ok(name)) if name.is_empty() => return None,
+                (None, Ok(name)) => name,
+                (None, Err(_)) => contexts.current.take()?,
+            };
+            match contexts.contexts.remove(&name) {
+                Some(context) => Some((name, context)),
+                None => {
+                    let err_msg = format!(
+                        "Error! Context {} does not exist. To create it try\n kv context create {}",
+                        name, name
+                    );
+                    print_err(&err_msg[..]);
+                }
+            }
+        })
+        .as_ref()
+}
+
+pub fn store() -> Option<&'static PathBuf> {
+    active()?.1.store.as_ref()
+}
+
+pub fn format() -> Option<&'static str> {
+    active()?.1.format.as_deref()
+}
+
+/// The active namespace's key prefix, e.g. `work/`, or "" without one.
+pub fn prefix() -> String {
+    match active().and_then(|(_, c)| c.namespace.as_ref()) {
+        Some(ns) => format!("{}/", ns),
+        None => String::new(),
+    }
+}
+
+/// `key` inside the active namespace.
+pub fn key(key: &str) -> String {
+    format!("{}{}", prefix(), key)
+}
+
+/// Context `name`, if it exists.
+pub fn get(name: &str) -> Option<Context> {
+    load().contexts.remove(name)
+}
+
+pub fn names() -> Vec<String> {
+    load().contexts.into_keys().collect()
+}
+
+pub fn create(name: &str, mut context: Context) {
+    if let Some(store) = &context.store {
+        if store.is_relative() {
+            match std::env::current_dir() {
+                Ok(dir) => context.store = Some(dir.join(store)),
+                Err(e) => print_err(&format!(
+                    "Error! Cannot find the current directory, error {}",
+                    e
+                )),
+            }
+        }
+    }
+    let mut contexts = load();
+    contexts.contexts.insert(name.to_owned(), context);
+    save(&contexts);
+}
+
+/// The directory `kv profile create` puts profile stores in.
+fn profiles_dir() -> PathBuf {
+    get_config_dir().join("profiles")
+}
+
+/// Create context `name` with a store of its own, `profiles/<name>.json` in the config dir.
+pub fn create_profile(name: &str) {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        print_err(&format!("Error! {:?} can't be a profile name!", name));
+    }
+    if get(name).is_some() {
+        let err_msg = format!(
+            "Error! Context {} already exists. To delete it try\n kv profile delete {}",
+            name, name
+        );
+        print_err(&err_msg[..]);
+    }
+    stateless::ensure_writable();
+    if let Err(e) = std::fs::create_dir_all(profiles_dir()) {
+        let err_msg = format!(
+            "Error! Cannot create path {}, error {}",
+            profiles_dir().to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+    let context = Context {
+        store: Some(profiles_dir().join(format!("{}.json", name))),
+        ..Context::default()
+    };
+    create(name, context);
+}
+
+/// Delete context `name`, and its store if `kv profile create` made it. Stores elsewhere
+/// are left alone.
+pub fn delete(name: &str) {
+    let mut contexts = load();
+    let context = match contexts.contexts.remove(name) {
+        Some(context) => context,
+        None => {
+            let err_msg = format!("Error! Context {} does not exist!", name);
+            print_err(&err_msg[..]);
+        }
+    };
+    if contexts.current.as_deref() == Some(name) {
+        contexts.current = None;
+    }
+    save(&contexts);
+    if let Some(store) = context.store.filter(|store| store.starts_with(profiles_dir())) {
+        for path in [store.clone(), crate::with_suffix(&store, ".bak")] {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Warning! Cannot remove {}, error {}", path.to_string_lossy(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Make `name` the default context, or go back to none.
+pub fn use_context(name: Option<&str>) {
+    let mut contexts = load();
+    if let Some(name) = name {
+        if !contexts.contexts.contains_key(name) {
+            let err_msg = format!(
+                "Error! Context {} does not exist. To create it try\n kv context create {}",
+                name, name
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+    contexts.current = name.map(|n| n.to_owned());
+    save(&contexts);
+    if std::env::var_os("KV_CONTEXT").is_some() {
+        eprintln!("Warning! KV_CONTEXT is set, so this shell keeps using its context.");
+    }
+}
+
+/// Print every context, marking the active one.
+pub fn show() {
+    let contexts = load();
+    let active = active().map(|(name, _)| &name[..]);
+    let mut lines = vec!["Context\t--\tStore\t--\tNamespace\t--\tFormat".to_owned()];
+    for (name, context) in &contexts.contexts {
+        let marker = if Some(&name[..]) == active {
+            "* "
+        } else {
+            "  "
+        };
+        lines.push(format!(
+            "{}{}\t--\t{}\t--\t{}\t--\t{}",
+            marker,
+            name,
+            context
+                .store
+                .as_ref()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "-".to_owned()),
+            context.namespace.as_deref().unwrap_or("-"),
+            context.format.as_deref().unwrap_or("-"),
+        ));
+    }
+    print_aligned(lines);
+    if let Ok(name) = std::env::var("KV_CONTEXT") {
+        if !name.is_empty() {
+            println!("(from KV_CONTEXT)");
+        }
+    }
+}
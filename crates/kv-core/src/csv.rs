@@ -0,0 +1,172 @@
+use crate::{print_err, KV};
+
+/// Columns used for the key and the value when there's no `--map`, in order of preference.
+/// They cover `kv export --format csv` and the exports of Chrome, Firefox, Bitwarden,
+/// 1Password, LastPass and KeePass.
+const KEY_COLUMNS: &[&str] = &["key", "name", "title", "url", "login_uri", "origin_url"];
+const VALUE_COLUMNS: &[&str] = &["password", "login_password", "value"];
+
+/// Which CSV columns hold the keys and which the values.
+pub struct Mapping {
+    key: String,
+    value: String,
+}
+
+/// Parse `--map`, e.g. `name=key,password=value`. Column names are matched ignoring case.
+pub fn parse_map(spec: &str) -> Result<Mapping, String> {
+    let (mut key, mut value) = (None, None);
+    for pair in spec.split(',') {
+        let Some((column, target)) = pair.split_once('=') else {
+            return Err(format!(
+                "'{}' should look like <column>=key or <column>=value",
+                pair
+            ));
+        };
+        let column = Some(column.trim().to_owned());
+        match target.trim() {
+            "key" => key = column,
+            "value" => value = column,
+            other => return Err(format!("'{}' should be key or value", other)),
+        }
+    }
+    match (key, value) {
+        (Some(key), Some(value)) => Ok(Mapping { key, value }),
+        _ => Err(
+            "--map needs both a key and a value column, e.g. name=key,password=value".to_owned(),
+        ),
+    }
+}
+
+/// The delimiter of `text`: whichever of `,`, `;` and tab is most common on the header line.
+fn delimiter(text: &str) -> char {
+    let header = text.lines().next().unwrap_or("");
+    [',', ';', '\t']
+        .into_iter()
+        .max_by_key(|&d| header.matches(d).count())
+        .unwrap()
+}
+
+/// Split `text` into rows of fields. Quoted fields may hold delimiters, newlines and `""`
+/// for a quote. Blank lines are skipped and a stray quote mid-field is kept as is, so
+/// slightly malformed exports still load.
+fn parse(text: &str) -> Vec<Vec<String>> {
+    let text = text.trim_start_matches('\u{feff}');
+    let delim = delimiter(text);
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            c if c == delim => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(row);
+                }
+                row = vec![];
+            }
+            c => field.push(c),
+        }
+    }
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+/// The position of the first of `names` in `header`, ignoring case and surrounding spaces.
+fn column(header: &[String], names: &[&str]) -> Option<usize> {
+    names.iter().find_map(|name| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    })
+}
+
+/// Read keys and values from a CSV export with a header row, such as a browser's or password
+/// manager's. The key column defaults to the first of `KEY_COLUMNS` there is, the value
+/// column to the first of `VALUE_COLUMNS`. Rows without a key or value are skipped with a warning, and a key seen again
+/// gets `-2`, `-3` and so on, since exports often hold several logins for one site.
+pub fn read_file(path: &str, mapping: Option<&Mapping>) -> KV {
+    let text = match std::fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(e) => {
+            let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let mut rows = parse(&text).into_iter();
+    let header = rows.next().unwrap_or_default();
+    let (key_col, value_col) = match mapping {
+        Some(m) => (column(&header, &[&m.key]), column(&header, &[&m.value])),
+        None => (column(&header, KEY_COLUMNS), column(&header, VALUE_COLUMNS)),
+    };
+    let (Some(key_col), Some(value_col)) = (key_col, value_col) else {
+        let err_msg = format!(
+            "Error! Cannot tell which columns of {} hold the keys and values. Its columns are\n {}\nTo choose try\n kv import --csv --map <column>=key,<column>=value {}",
+            path,
+            header.join(", "),
+            path
+        );
+        print_err(&err_msg[..]);
+    };
+    let mut kvs = KV::new();
+    for (i, row) in rows.enumerate() {
+        // Line numbers as a spreadsheet shows them, counting the header.
+        let line = i + 2;
+        let field = |col: usize| row.get(col).map(|f| &f[..]).unwrap_or("");
+        // Passwords may well start or end with spaces, so only keys are trimmed.
+        let (key, value) = (field(key_col).trim(), field(value_col));
+        if key.is_empty() || value.is_empty() {
+            eprintln!(
+                "Warning! Skipping row {} of {}, it has no {}",
+                line,
+                path,
+                if key.is_empty() {
+                    &header[key_col]
+                } else {
+                    &header[value_col]
+                }
+            );
+            continue;
+        }
+        let mut unique = key.to_owned();
+        let mut n = 1;
+        while kvs.contains_key(&unique) {
+            n += 1;
+            unique = format!("{}-{}", key, n);
+        }
+        kvs.insert(unique, value.to_owned());
+    }
+    kvs
+}
+
+/// A field quoted if it has to be: when it holds a comma, quote or line break, or starts or
+/// ends with a space that a reader might trim.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// `kvs` as CSV with a `key,value` header, which `kv import` reads back.
+pub fn write(kvs: &KV) -> String {
+    let mut out = String::from("key,value\n");
+    for (key, val) in kvs {
+        out.push_str(&format!("{},{}\n", quote(key), quote(val)));
+    }
+    out
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{config, events, get_config_dir, print_err, time};
+
+/// When the last digest to each destination was sent, in `digest.json`.
+#[derive(Serialize, Deserialize, Default)]
+struct Sent {
+    #[serde(flatten)]
+    last: BTreeMap<String, u64>,
+}
+
+fn location() -> PathBuf {
+    get_config_dir().join("digest.json")
+}
+
+fn load() -> Sent {
+    std::fs::read(location())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(sent: &Sent) {
+    if let Err(e) = std::fs::write(location(), serde_json::to_string_pretty(sent).unwrap()) {
+        let err_msg = format!(
+            "Error! Cannot write {}, error {}",
+            location().to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// How often one key, cmd or hook changed, and how the last change left it.
+#[derive(Serialize)]
+struct Change {
+    kind: String,
+    name: String,
+    /// The last op: `set` or `del`.
+    op: String,
+    count: usize,
+}
+
+/// Sum up logged changes. Values are left out, so secrets can't leak into the digest.
+fn summarize(logged: Vec<events::Event>) -> Vec<Change> {
+    let mut changes: BTreeMap<(String, String), Change> = BTreeMap::new();
+    for event in logged {
+        changes
+            .entry((event.kind.clone(), event.name.clone()))
+            .and_modify(|c| {
+                c.count += 1;
+                c.op = event.op.clone();
+            })
+            .or_insert(Change {
+                kind: event.kind,
+                name: event.name,
+                op: event.op,
+                count: 1,
+            });
+    }
+    changes.into_values().collect()
+}
+
+fn text(since: u64, until: u64, changes: &[Change]) -> String {
+    let mut text = format!(
+        "kv changes from {} to {} UTC:",
+        time::format_timestamp(since),
+        time::format_timestamp(until)
+    );
+    for c in changes {
+        let times = if c.count > 1 {
+            format!(" ({} changes)", c.count)
+        } else {
+            String::new()
+        };
+        let last = if c.op == "del" { "deleted" } else { "set" };
+        text.push_str(&format!("\n{} {} {}{}", c.kind, c.name, last, times));
+    }
+    text
+}
+
+/// POST a summary of what changed since the last digest to `to`, unless the last one was
+/// sent less than `every` ago or nothing changed. With `print`, print the summary instead
+/// of sending it and remember nothing.
+pub fn send(to: &str, every: Option<Duration>, print: bool) {
+    if !config::get().get_bool("events.enabled") {
+        eprintln!("Warning! The event log is off, so digests can't see any changes. To turn it on try\n kv config set events.enabled true");
+    }
+    let mut sent = load();
+    let last = sent.last.get(to).copied();
+    let now = time::now();
+    if let (Some(last), Some(every)) = (last, every) {
+        let due = last + every.as_secs();
+        if now < due && !print {
+            println!(
+                "Not due yet, the last digest was sent at {} UTC. The next one is due at {} UTC.",
+                time::format_timestamp(last),
+                time::format_timestamp(due)
+            );
+            return;
+        }
+    }
+    let logged = events::read(last.map_or(0, |last| last + 1));
+    let since = last.or(logged.first().map(|e| e.ts)).unwrap_or(now);
+    let changes = summarize(logged);
+    let text = text(since, now, &changes);
+    if print {
+        println!("{}", text);
+        return;
+    }
+    if changes.is_empty() {
+        println!("Nothing changed since the last digest, so none was sent.");
+        return;
+    }
+    // `text` lets chat webhooks like Slack's show the digest as is.
+    let body = serde_json::json!({
+        "since": since,
+        "until": now,
+        "changes": changes,
+        "text": text,
+    });
+    if let Err(e) = ureq::post(to)
+        .timeout(Duration::from_secs(10))
+        .send_json(body)
+    {
+        let err_msg = format!("Error! Cannot send the digest to {}, error {}", to, e);
+        print_err(&err_msg[..]);
+    }
+    sent.last.insert(to.to_owned(), now);
+    save(&sent);
+    println!("Sent the digest to {}", to);
+}
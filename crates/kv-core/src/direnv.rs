@@ -0,0 +1,36 @@
+use crate::get_file_location;
+
+/// Quote `s` for sh.
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// A block for a project's `.envrc` exporting the keys under `prefix` (all keys if empty).
+/// direnv reloads it whenever the store changes.
+pub fn envrc(prefix: &str) -> String {
+    format!(
+        "# Export kv keys{} as environment variables (generated by kv direnv)\n\
+         watch_file {}\n\
+         eval \"$(kv export --format shell --prefix {})\"\n",
+        if prefix.is_empty() {
+            String::new()
+        } else {
+            format!(" under {}", prefix)
+        },
+        quote(&get_file_location().to_string_lossy()),
+        quote(prefix)
+    )
+}
+
+/// A `use_kv` function for `~/.config/direnv/direnvrc`, so `.envrc` files only need
+/// `use kv [prefix]`.
+pub fn stdlib() -> String {
+    format!(
+        "# `use kv [prefix]` in .envrc exports kv keys (under prefix) as environment variables\n\
+         use_kv() {{\n\
+         \x20 watch_file {}\n\
+         \x20 eval \"$(kv export --format shell --prefix \"${{1:-}}\")\"\n\
+         }}\n",
+        quote(&get_file_location().to_string_lossy())
+    )
+}
@@ -0,0 +1,96 @@
+use crate::{print_err, KV};
+
+/// Whether `path` looks like a `.env` file: `.env`, `.env.local`, `prod.env` and so on.
+pub fn is_env_file(path: &str) -> bool {
+    let name = std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name == ".env" || name.starts_with(".env.") || name.ends_with(".env")
+}
+
+/// The value of a double quoted string with its escapes undone, and what follows it.
+fn double_quoted(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Parse a `.env` file: `NAME=value` lines, optionally starting with `export`, with `#`
+/// comments. Values may be single quoted (taken literally), double quoted (with `\n` style
+/// escapes) or bare, and quoted values may span lines. `${VAR}` is kept as is, since kv
+/// has no environment to expand it from.
+pub fn parse(text: &str) -> Result<KV, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut kvs = KV::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let line = text[..text.len() - rest.len()].matches('\n').count() + 1;
+        let (current, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        let entry = current.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            rest = next;
+            continue;
+        }
+        let entry = entry.strip_prefix("export ").unwrap_or(entry);
+        let Some((name, _)) = entry.split_once('=') else {
+            return Err(format!("line {} has no =", line));
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("line {} has no name before the =", line));
+        }
+        // Quoted values may run over several lines, so the value is read from the rest of
+        // the text rather than the current line.
+        let value = rest[rest.find('=').unwrap() + 1..].trim_start_matches([' ', '\t']);
+        let (value, after) = if let Some(quoted) = value.strip_prefix('\'') {
+            match quoted.split_once('\'') {
+                Some((value, after)) => (value.to_owned(), after),
+                None => return Err(format!("line {} has an unclosed '", line)),
+            }
+        } else if let Some(quoted) = value.strip_prefix('"') {
+            match double_quoted(quoted) {
+                Some(parsed) => parsed,
+                None => return Err(format!("line {} has an unclosed \"", line)),
+            }
+        } else {
+            let bare = value.split('\n').next().unwrap();
+            let unquoted = bare.split(" #").next().unwrap().trim();
+            (unquoted.to_owned(), &value[bare.len()..])
+        };
+        kvs.insert(name.to_owned(), value);
+        // Anything after a closing quote, such as a comment, is skipped.
+        rest = after.split_once('\n').map_or("", |(_, next)| next);
+    }
+    Ok(kvs)
+}
+
+/// Read the keys to import from a `.env` file, named as the variables are.
+pub fn read_file(path: &str) -> KV {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    };
+    match parse(&text) {
+        Ok(kvs) => kvs,
+        Err(e) => {
+            let err_msg = format!("Error! {} is not a valid .env file, {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+use crate::{export, KV};
+
+/// A key under the prefix whose environment variable doesn't match it.
+#[derive(Serialize)]
+pub struct Drift {
+    pub variable: String,
+    pub key: String,
+    /// `differs` or `unset`.
+    pub drift: &'static str,
+}
+
+/// Compare the keys in `kvs` under `prefix` with the environment, naming variables the way
+/// `kv export --format shell` does (`app/db.url` under `app/` is `DB_URL`). Values are
+/// compared as stored, without resolving references, and never reported.
+pub fn diff(kvs: &KV, prefix: &str) -> Vec<Drift> {
+    let mut drift = vec![];
+    for (key, value) in export::strip_prefix(kvs, prefix) {
+        let variable = export::env_name(&key);
+        let found = match std::env::var_os(&variable) {
+            None => "unset",
+            Some(env) if env.to_str() != Some(&value[..]) => "differs",
+            Some(_) => continue,
+        };
+        drift.push(Drift {
+            variable,
+            key: format!("{}{}", prefix, key),
+            drift: found,
+        });
+    }
+    drift.sort_by(|a, b| a.variable.cmp(&b.variable));
+    drift
+}
@@ -0,0 +1,53 @@
+use std::ffi::OsString;
+use std::process::{Command, Stdio};
+
+use crate::sandbox::find_program;
+
+/// Tools a hook can switch users with, in the order they are tried.
+pub const TOOLS: &[&str] = &["sudo", "doas"];
+
+fn current_user() -> Option<String> {
+    std::env::var("USER").ok().or_else(|| {
+        let out = Command::new("id").arg("-un").output().ok()?;
+        Some(String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    })
+}
+
+/// Wrap `argv` to run as `user` through `tool` (or the first of `TOOLS` installed).
+/// Never prompts for a password: if the tool can't switch to `user` non-interactively this
+/// fails with an error instead, so the hook doesn't hang or silently run as the wrong user.
+pub fn wrap(user: &str, tool: Option<&str>, argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    if current_user().as_deref() == Some(user) {
+        return Ok(argv);
+    }
+    let (tool, path) = match tool {
+        Some(tool) => match find_program(tool) {
+            Some(path) => (tool, path),
+            None => return Err(format!("{} is not installed", tool)),
+        },
+        None => TOOLS
+            .iter()
+            .find_map(|tool| find_program(tool).map(|path| (*tool, path)))
+            .ok_or_else(|| format!("running as {} needs sudo or doas on PATH", user))?,
+    };
+    let mut wrapped: Vec<OsString> = vec![path.into(), "-n".into(), "-u".into(), user.into()];
+    if tool == "sudo" {
+        wrapped.push("--".into());
+    }
+    let allowed = Command::new(&wrapped[0])
+        .args(&wrapped[1..])
+        .arg("true")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !allowed {
+        return Err(format!(
+            "{} cannot run commands as {} without a password",
+            tool, user
+        ));
+    }
+    wrapped.extend(argv);
+    Ok(wrapped)
+}
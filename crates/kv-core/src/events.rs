@@ -0,0 +1,303 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    config, fail, get_config_dir, glob, print_aligned, stateless, time, try_config_dir,
+    Error, KVStore, OpType,
+};
+
+/// One line of `events.jsonl`: a change to a key, cmd or hook.
+#[derive(Serialize, Deserialize)]
+pub struct Event {
+    pub ts: u64,
+    /// `set` or `del`.
+    pub op: String,
+    /// `key`, `cmd` or `hook`.
+    pub kind: String,
+    pub name: String,
+    /// The new value. Absent for deletes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// A change to a key, as `KVStore::subscribe` delivers it.
+#[derive(Debug)]
+pub struct ChangeEvent {
+    pub ts: u64,
+    pub op: OpType,
+    pub key: String,
+    /// The new value. None for deletes.
+    pub value: Option<String>,
+}
+
+impl ChangeEvent {
+    fn from_line(line: &str) -> Option<ChangeEvent> {
+        let event: Event = serde_json::from_str(line).ok()?;
+        if event.kind != "key" {
+            return None;
+        }
+        Some(ChangeEvent {
+            ts: event.ts,
+            op: event.op.parse().ok()?,
+            key: event.name,
+            value: event.value,
+        })
+    }
+}
+
+fn get_events_location() -> PathBuf {
+    get_config_dir().join("events.jsonl")
+}
+
+/// Whether changes are logged: `events.enabled` is on and the store isn't read-only.
+pub fn enabled() -> Result<bool, Error> {
+    Ok(config::try_get()?.get_bool("events.enabled") && !stateless::is_active())
+}
+
+/// Append a change to the event log, if `events.enabled` is on.
+pub fn record(kind: &str, op: &OpType, name: &str, value: Option<&str>) {
+    try_record(kind, op, name, value).unwrap_or_else(|e| fail(e));
+}
+
+/// `record`, returning the error if the event log can't be written.
+pub fn try_record(kind: &str, op: &OpType, name: &str, value: Option<&str>) -> Result<(), Error> {
+    if !enabled()? {
+        return Ok(());
+    }
+    let event = Event {
+        ts: time::now(),
+        op: op.to_string(),
+        kind: kind.to_owned(),
+        name: name.to_owned(),
+        value: value.map(|v| v.to_owned()),
+    };
+    let path = try_config_dir()?.join("events.jsonl");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&event).unwrap()))
+        .map_err(|e| Error::new(format!("Cannot write event log {}, error {}", path.to_string_lossy(), e)))
+}
+
+/// Whether `line` is a change to a key matching glob `pattern`, or anything without one.
+fn key_ok(line: &str, pattern: Option<&str>) -> bool {
+    match pattern {
+        None => true,
+        Some(pattern) => {
+            ChangeEvent::from_line(line).is_some_and(|e| glob::matches(pattern, &e.key))
+        }
+    }
+}
+
+impl From<ChangeEvent> for Event {
+    fn from(change: ChangeEvent) -> Event {
+        Event {
+            ts: change.ts,
+            op: change.op.to_string(),
+            kind: "key".to_owned(),
+            name: change.key,
+            value: change.value,
+        }
+    }
+}
+
+fn format_event(event: &Event, jsonl: bool) -> String {
+    if jsonl {
+        return serde_json::to_string(event).unwrap();
+    }
+    format!(
+        "{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}",
+        time::format_timestamp(event.ts),
+        event.op,
+        event.kind,
+        event.name,
+        event.value.as_deref().unwrap_or_default()
+    )
+}
+
+fn format_line(line: &str, jsonl: bool) -> Option<String> {
+    if jsonl {
+        return Some(line.to_owned());
+    }
+    let event: Event = serde_json::from_str(line).ok()?;
+    Some(format_event(&event, jsonl))
+}
+
+fn since_ok(line: &str, since: Option<u64>) -> bool {
+    match since {
+        None => true,
+        Some(since) => serde_json::from_str::<Event>(line).is_ok_and(|e| e.ts >= since),
+    }
+}
+
+/// Every event in the log at or after `since`, oldest first.
+#[cfg_attr(not(feature = "net"), allow(dead_code))] // Only kv digest reads the log at once.
+pub fn read(since: u64) -> Vec<Event> {
+    let Ok(file) = File::open(get_events_location()) else {
+        return vec![];
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<Event>(&line).ok())
+        .filter(|event| event.ts >= since)
+        .collect()
+}
+
+/// Call `f` with each complete line appended to the event log after `reader`'s position
+/// (or from the start if the log doesn't exist yet), until `f` returns false or, checked on
+/// every poll, `alive` does.
+fn tail(
+    path: &Path,
+    mut reader: Option<BufReader<File>>,
+    alive: impl Fn() -> bool,
+    mut f: impl FnMut(&str) -> bool,
+) {
+    let mut line = String::new();
+    loop {
+        std::thread::sleep(Duration::from_millis(200));
+        if !alive() {
+            return;
+        }
+        let reader = match reader.as_mut() {
+            Some(reader) => reader,
+            None => {
+                reader = File::open(path).ok().map(BufReader::new);
+                continue;
+            }
+        };
+        // Only hand out complete lines; a writer may be halfway through one.
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            if !line.ends_with('\n') {
+                break;
+            }
+            if !f(line.trim_end()) {
+                return;
+            }
+            line.clear();
+        }
+    }
+}
+
+/// Changes from `KVStore::subscribe`: iterate over it, or use the `Receiver` methods like
+/// `recv_timeout`. Dropping it stops the thread reading the event log within one poll.
+pub struct Subscription {
+    changes: Receiver<ChangeEvent>,
+    alive: Arc<AtomicBool>,
+}
+
+impl Iterator for Subscription {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<ChangeEvent> {
+        self.changes.recv().ok()
+    }
+}
+
+impl std::ops::Deref for Subscription {
+    type Target = Receiver<ChangeEvent>;
+
+    fn deref(&self) -> &Receiver<ChangeEvent> {
+        &self.changes
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::Relaxed);
+    }
+}
+
+impl KVStore {
+    /// Changes to keys matching glob `pattern` from now on, made by this or any other process.
+    /// Backed by the event log, so it's an error if `events.enabled` is off.
+    pub fn subscribe(pattern: &str) -> Result<Subscription, Error> {
+        if !enabled()? {
+            return Err(Error::new(
+                "The event log is off, so there are no changes to subscribe to. To turn it on try\n kv config set events.enabled true",
+            ));
+        }
+        let path = try_config_dir()?.join("events.jsonl");
+        let reader = File::open(&path).ok().map(|file| {
+            let mut reader = BufReader::new(file);
+            let _ = std::io::copy(&mut reader, &mut std::io::sink());
+            reader
+        });
+        let pattern = pattern.to_owned();
+        let (sender, changes) = channel();
+        let alive = Arc::new(AtomicBool::new(true));
+        let tailing = alive.clone();
+        std::thread::spawn(move || {
+            tail(
+                &path,
+                reader,
+                || tailing.load(Ordering::Relaxed),
+                |line| match ChangeEvent::from_line(line) {
+                    Some(change) if glob::matches(&pattern, &change.key) => sender.send(change).is_ok(),
+                    _ => true,
+                },
+            )
+        });
+        Ok(Subscription { changes, alive })
+    }
+}
+
+/// Print the event log, then with `follow` keep printing new events as they are appended,
+/// like `tail -f`. With `key`, only changes to keys matching that glob.
+pub fn show(jsonl: bool, follow: bool, since: Option<u64>, key: Option<&str>) {
+    // Subscribe before reading the log, so no change falls between the two.
+    let changes = match key {
+        Some(pattern) if follow => Some(KVStore::subscribe(pattern).unwrap_or_else(|e| fail(e))),
+        _ => None,
+    };
+    if !config::get().get_bool("events.enabled") {
+        eprintln!("The event log is off. To turn it on try\n kv config set events.enabled true");
+    }
+    let path = get_events_location();
+    let mut reader = match File::open(&path) {
+        Ok(file) => Some(BufReader::new(file)),
+        Err(_) => None,
+    };
+    let mut lines = vec![];
+    if !jsonl {
+        lines.push("When\t--\tOp\t--\tKind\t--\tName\t--\tValue".to_owned());
+    }
+    if let Some(reader) = reader.as_mut() {
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            if since_ok(line.trim_end(), since) && key_ok(line.trim_end(), key) {
+                lines.extend(format_line(line.trim_end(), jsonl));
+            }
+            line.clear();
+        }
+    }
+    if jsonl {
+        lines.iter().for_each(|l| println!("{}", l));
+    } else {
+        print_aligned(lines);
+    }
+    if !follow {
+        return;
+    }
+    std::io::stdout().flush().unwrap();
+    let print = |out: String| {
+        println!("{}", out.replace('\t', " "));
+        std::io::stdout().flush().unwrap();
+    };
+    match changes {
+        Some(changes) => changes
+            .into_iter()
+            .for_each(|change| print(format_event(&change.into(), jsonl))),
+        None => tail(&path, reader, || true, |line| {
+            format_line(line, jsonl).into_iter().for_each(print);
+            true
+        }),
+    }
+}
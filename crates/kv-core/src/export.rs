@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::{glob, is_secret, KVStore, KV};
+
+/// Keys starting with `prefix`, with the prefix removed.
+pub fn strip_prefix(kvs: &KV, prefix: &str) -> KV {
+    kvs.iter()
+        .filter_map(|(key, val)| {
+            key.strip_prefix(prefix)
+                .filter(|key| !key.is_empty())
+                .map(|key| (key.to_owned(), val.clone()))
+        })
+        .collect()
+}
+
+/// `kvs` as a JSON object, sorted by key. `kv import` reads this back.
+pub fn json(kvs: &KV) -> String {
+    let sorted: BTreeMap<&String, &String> = kvs.iter().collect();
+    serde_json::to_string_pretty(&sorted).unwrap()
+}
+
+/// What `kv publish` writes: the store's keys without those matching an `exclude` glob and,
+/// with `exclude_secrets`, without secret keys. Without it, the secret keys that would be
+/// published are an error, so they can't leak by accident.
+pub fn public(
+    kvstore: &KVStore,
+    exclude_secrets: bool,
+    exclude: &[String],
+) -> Result<KV, Vec<String>> {
+    let kvs: KV = kvstore
+        .kvs
+        .iter()
+        .filter(|(key, _)| !exclude.iter().any(|pattern| glob::matches(pattern, key)))
+        .map(|(key, val)| (key.clone(), val.clone()))
+        .collect();
+    let secrets: Vec<String> = kvs
+        .keys()
+        .filter(|key| is_secret(key, &kvstore.meta))
+        .cloned()
+        .collect();
+    if exclude_secrets {
+        Ok(kvs
+            .into_iter()
+            .filter(|(key, _)| !secrets.contains(key))
+            .collect())
+    } else if secrets.is_empty() {
+        Ok(kvs)
+    } else {
+        Err(secrets)
+    }
+}
+
+/// Key as an environment variable name: upper case, anything but letters, digits and `_`
+/// replaced by `_`, e.g. `db.host` -> `DB_HOST`.
+pub fn env_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Quote a value for a `.env` file. Plain values are left alone, values without `'` or
+/// newlines are single quoted (taken literally), and anything else is double quoted with
+/// escapes. For docker `compose`, `$` is doubled too so it isn't interpolated.
+fn env_quote(val: &str, compose: bool) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:@,+".contains(c);
+    if !val.is_empty() && val.chars().all(plain) {
+        val.to_owned()
+    } else if !val.contains(['\'', '\n', '\r']) {
+        format!("'{}'", val)
+    } else {
+        let mut quoted = String::from("\"");
+        for c in val.chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                '$' if compose => quoted.push_str("$$"),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+/// Variable names for `kvs`, sorted. Fails if two keys map to the same name.
+pub fn env_vars(kvs: &KV) -> Result<BTreeMap<String, (&String, &String)>, String> {
+    let mut vars = BTreeMap::new();
+    for (key, val) in kvs {
+        if let Some((other, _)) = vars.insert(env_name(key), (key, val)) {
+            return Err(format!(
+                "keys {} and {} both become {}",
+                other,
+                key,
+                env_name(key)
+            ));
+        }
+    }
+    Ok(vars)
+}
+
+/// `kvs` as a docker compose env file, after a comment `header`.
+pub fn compose_env(kvs: &KV, header: &[String]) -> Result<String, String> {
+    let mut out: String = header.iter().map(|line| format!("# {}\n", line)).collect();
+    for (name, (_, val)) in env_vars(kvs)? {
+        out.push_str(&format!("{}={}\n", name, env_quote(val, true)));
+    }
+    Ok(out)
+}
+
+/// `kvs` as a plain `.env` file, as dotenv libraries read it. `kv import` reads it back,
+/// though the keys come back as the variable names.
+pub fn dotenv(kvs: &KV) -> Result<String, String> {
+    Ok(env_vars(kvs)?
+        .into_iter()
+        .map(|(name, (_, val))| format!("{}={}\n", name, env_quote(val, false)))
+        .collect())
+}
+
+/// `kvs` as `export NAME='value'` lines for sh, bash and zsh to `eval`.
+pub fn shell(kvs: &KV) -> Result<String, String> {
+    Ok(env_vars(kvs)?
+        .into_iter()
+        .map(|(name, (_, val))| format!("export {}='{}'\n", name, val.replace('\'', "'\\''")))
+        .collect())
+}
+
+/// Write each key's value, as is, to its own file in `dir` (e.g. for Docker secrets). `/` in
+/// keys becomes `_`. Returns the number of files written.
+pub fn split_per_key(kvs: &KV, dir: &Path) -> std::io::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = 0;
+    for (key, val) in kvs {
+        let file_name = key.replace('/', "_");
+        if file_name == "." || file_name == ".." {
+            continue;
+        }
+        let path = dir.join(file_name);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all(val.as_bytes())?;
+        written += 1;
+    }
+    Ok(written)
+}
@@ -0,0 +1,66 @@
+use crate::print_err;
+
+/// The optional cargo features this kv was built with.
+pub const ENABLED: &[&str] = &[
+    #[cfg(feature = "net")]
+    "net",
+    #[cfg(feature = "crypto")]
+    "crypto",
+    #[cfg(feature = "mmap")]
+    "mmap",
+    #[cfg(feature = "graphql")]
+    "graphql",
+    #[cfg(feature = "rhai")]
+    "rhai",
+    #[cfg(feature = "sqlite")]
+    "sqlite",
+    #[cfg(feature = "wasm")]
+    "wasm",
+];
+
+/// Subcommands that only work with some features.
+const NEEDED: &[(&str, &[&str])] = &[
+    ("bot", &["net"]),
+    ("daemon", &["net"]),
+    ("digest", &["net"]),
+    ("scan", &["crypto"]),
+    ("secret", &["crypto"]),
+    ("self-update", &["net", "crypto"]),
+    ("serve", &["net"]),
+    ("share", &["net", "crypto"]),
+    ("totp", &["crypto"]),
+    ("transform", &["rhai"]),
+    ("vault", &["net"]),
+];
+
+/// Why something that needs `feature` can't run.
+pub fn missing(feature: &str) -> String {
+    format!(
+        "this kv was built without the {} feature. To build it with the feature try\n cargo install --path crates/kv-cli --features {}",
+        feature, feature
+    )
+}
+
+/// Exit with an error if subcommand `name` needs a feature this kv was built without.
+pub fn check(name: &str) {
+    let needed = NEEDED.iter().find(|(n, _)| *n == name);
+    if let Some((_, features)) = needed {
+        if let Some(feature) = features.iter().find(|f| !ENABLED.contains(f)) {
+            print_err(&format!(
+                "Error! Cannot run kv {}, {}",
+                name,
+                missing(feature)
+            ));
+        }
+    }
+}
+
+/// `kv --version --features`.
+pub fn print_version() {
+    println!("kv {}", env!("CARGO_PKG_VERSION"));
+    if ENABLED.is_empty() {
+        println!("features: none");
+    } else {
+        println!("features: {}", ENABLED.join(", "));
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use crate::{context, get_config_dir, print_err, shown_value, KVStore};
+
+/// The store name for kv.json itself, with no namespace.
+pub const DEFAULT: &str = "default";
+
+/// A store `--stores` can name: `default` or a context.
+pub struct Source {
+    pub name: String,
+    kvstore: KVStore,
+    /// The context's namespace as a key prefix, e.g. `work/`.
+    prefix: String,
+}
+
+fn location(store: Option<PathBuf>) -> PathBuf {
+    store.unwrap_or_else(|| get_config_dir().join("kv.json"))
+}
+
+/// Read the store file at `path`. Missing files are empty stores.
+fn load(name: &str, path: &PathBuf) -> KVStore {
+    let kvstore: KVStore = match std::fs::read(path) {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(kvstore) => kvstore,
+            Err(e) => {
+                let err_msg = format!(
+                    "Error! Cannot parse {}, error {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                print_err(&err_msg[..]);
+            }
+        },
+        Err(_) => KVStore::default(),
+    };
+    if kvstore.shards.is_some() {
+        eprintln!(
+            "Warning! Store {} is sharded, which other stores can't be queried with. Skipping it.",
+            name
+        );
+        return KVStore::default();
+    }
+    kvstore
+}
+
+/// The store called `name`.
+pub fn source(name: &str) -> Source {
+    let (path, prefix) = if name == DEFAULT {
+        (location(None), String::new())
+    } else {
+        match context::get(name) {
+            Some(context) => (
+                location(context.store),
+                context
+                    .namespace
+                    .map(|ns| format!("{}/", ns))
+                    .unwrap_or_default(),
+            ),
+            None => {
+                let err_msg = format!(
+                    "Error! There is no store {}. Stores are {} and the names of contexts, to create one try\n kv context create {} --store <path>",
+                    name, DEFAULT, name
+                );
+                print_err(&err_msg[..]);
+            }
+        }
+    };
+    Source {
+        name: name.to_owned(),
+        kvstore: load(name, &path),
+        prefix,
+    }
+}
+
+/// `default` and every context.
+pub fn all() -> Vec<Source> {
+    std::iter::once(DEFAULT.to_owned())
+        .chain(context::names())
+        .map(|name| source(&name))
+        .collect()
+}
+
+impl Source {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.kvstore
+            .kvs
+            .get(&format!("{}{}", self.prefix, key))
+            .map(|v| &v[..])
+    }
+
+    /// Lines of `Store -- Key -- Value` for keys (and with `values`, non-secret values)
+    /// containing `query`, ignoring case. Keys are shown without the namespace.
+    pub fn search(&self, query: &str, values: bool) -> Vec<String> {
+        let query = query.to_lowercase();
+        self.kvstore
+            .scan(&self.prefix)
+            .filter(|e| {
+                let key = &e.key[self.prefix.len()..];
+                key.to_lowercase().contains(&query)
+                    || values
+                        && !e.meta.is_some_and(|m| m.secret)
+                        && e.value.to_lowercase().contains(&query)
+            })
+            .map(|e| {
+                format!(
+                    "{}\t--\t{}\t--\t{}",
+                    self.name,
+                    &e.key[self.prefix.len()..],
+                    shown_value(e.key, e.value, &self.kvstore.meta)
+                )
+            })
+            .collect()
+    }
+}
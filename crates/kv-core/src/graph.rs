@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+
+use crate::KVStore;
+
+/// Keys that hooks watch and every cmd, each with a node id, in a stable order.
+struct Nodes {
+    keys: Vec<String>,
+    cmds: Vec<String>,
+}
+
+impl Nodes {
+    fn new(kvstore: &KVStore) -> Nodes {
+        let keys: BTreeSet<&String> = kvstore.hooks.iter().map(|h| &h.key).collect();
+        let mut cmds: BTreeSet<&String> = kvstore.cmds.keys().collect();
+        cmds.extend(kvstore.hooks.iter().map(|h| &h.cmd_name));
+        Nodes {
+            keys: keys.into_iter().cloned().collect(),
+            cmds: cmds.into_iter().cloned().collect(),
+        }
+    }
+
+    fn key_id(&self, key: &str) -> String {
+        format!("k{}", self.keys.iter().position(|k| k == key).unwrap())
+    }
+
+    fn cmd_id(&self, cmd: &str) -> String {
+        format!("c{}", self.cmds.iter().position(|c| c == cmd).unwrap())
+    }
+}
+
+/// The edge label for a hook: its name, trigger and how it runs.
+fn hook_label(hook: &crate::Hook) -> String {
+    match &hook.options()[..] {
+        "-" => format!("{} ({})", hook.name, hook.run_on),
+        options => format!("{} ({}, {})", hook.name, hook.run_on, options),
+    }
+}
+
+/// Graphviz: keys are ellipses, cmds are boxes, and cmds no hook could run are dashed red.
+pub fn dot(kvstore: &KVStore) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let nodes = Nodes::new(kvstore);
+    let mut out = String::from("digraph kv {\n    rankdir=LR;\n");
+    for key in &nodes.keys {
+        out.push_str(&format!(
+            "    {} [label={}, shape=ellipse];\n",
+            nodes.key_id(key),
+            quote(key)
+        ));
+    }
+    for cmd in &nodes.cmds {
+        let style = if kvstore.cmds.contains_key(cmd) {
+            ""
+        } else {
+            ", style=dashed, color=red"
+        };
+        out.push_str(&format!(
+            "    {} [label={}, shape=box{}];\n",
+            nodes.cmd_id(cmd),
+            quote(cmd),
+            style
+        ));
+    }
+    for hook in &kvstore.hooks {
+        out.push_str(&format!(
+            "    {} -> {} [label={}];\n",
+            nodes.key_id(&hook.key),
+            nodes.cmd_id(&hook.cmd_name),
+            quote(&hook_label(hook))
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid flowchart, with the same shapes as `dot`.
+pub fn mermaid(kvstore: &KVStore) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "#quot;"));
+    let nodes = Nodes::new(kvstore);
+    let mut out = String::from("flowchart LR\n");
+    for key in &nodes.keys {
+        out.push_str(&format!("    {}([{}])\n", nodes.key_id(key), quote(key)));
+    }
+    for cmd in &nodes.cmds {
+        out.push_str(&format!("    {}[{}]\n", nodes.cmd_id(cmd), quote(cmd)));
+    }
+    for hook in &kvstore.hooks {
+        out.push_str(&format!(
+            "    {} -->|{}| {}\n",
+            nodes.key_id(&hook.key),
+            quote(&hook_label(hook)),
+            nodes.cmd_id(&hook.cmd_name)
+        ));
+    }
+    let missing: Vec<String> = nodes
+        .cmds
+        .iter()
+        .filter(|cmd| !kvstore.cmds.contains_key(*cmd))
+        .map(|cmd| nodes.cmd_id(cmd))
+        .collect();
+    if !missing.is_empty() {
+        out.push_str("    classDef missing stroke:#f00,stroke-dasharray:5 5\n");
+        out.push_str(&format!("    class {} missing\n", missing.join(",")));
+    }
+    out
+}
@@ -0,0 +1,189 @@
+//! `POST /graphql` on `kv serve`, for clients that want to filter keys server-side, pick
+//! fields out of JSON values, or change a key only if it still holds what they last read.
+
+use std::sync::{Arc, OnceLock};
+
+use async_graphql::{Context, EmptySubscription, Json, MaybeUndefined, Object, Schema, SimpleObject};
+
+use crate::serve::{self, Grant, Server};
+use crate::{glob, is_secret, KVStore, Meta};
+
+type KvSchema = Schema<Query, Mutation, EmptySubscription>;
+
+fn schema() -> &'static KvSchema {
+    static SCHEMA: OnceLock<KvSchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(Query, Mutation, EmptySubscription)
+            .limit_depth(8)
+            .limit_complexity(1000)
+            .finish()
+    })
+}
+
+/// Run the request in `body`, `{"query": ..., "variables": ...}`, with what `grant` allows.
+/// GraphQL errors are part of the answer; only a body that isn't a request is an error.
+pub fn execute(body: &[u8], grant: &Grant, server: Arc<Server>) -> Result<serde_json::Value, String> {
+    let request: async_graphql::Request =
+        serde_json::from_slice(body).map_err(|e| format!("Not a GraphQL request, {}", e))?;
+    let request = request.data(grant.clone()).data(server);
+    let response = pollster::block_on(schema().execute(request));
+    Ok(serde_json::to_value(&response).unwrap())
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Keys in order, only those starting with `prefix`, matching the glob `pattern` and
+    /// changed at or after `modifiedSince` (seconds since the epoch), as far as given. At
+    /// most `first` of them, starting after the key `after`.
+    async fn keys(
+        &self,
+        ctx: &Context<'_>,
+        prefix: Option<String>,
+        pattern: Option<String>,
+        modified_since: Option<u64>,
+        first: Option<usize>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Vec<Entry>> {
+        let (grant, kvstore) = read(ctx)?;
+        let prefix = prefix.unwrap_or_default();
+        let cursor = after.as_deref().map(crate::scan::Cursor::after);
+        Ok(kvstore
+            .scan_from(&prefix, cursor.as_ref())
+            .filter(|e| grant.can_read(e.key))
+            .filter(|e| pattern.as_deref().is_none_or(|pattern| glob::matches(pattern, e.key)))
+            .filter(|e| modified_since.is_none_or(|t| e.meta.is_some_and(|meta| meta.modified >= t)))
+            .filter_map(|e| Entry::new(e.key, &kvstore))
+            .take(first.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    /// The key `name`, null if it doesn't exist or the token can't read it.
+    async fn key(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Option<Entry>> {
+        let (grant, kvstore) = read(ctx)?;
+        Ok(Some(&name)
+            .filter(|name| grant.can_read(name))
+            .and_then(|name| Entry::new(name, &kvstore)))
+    }
+}
+
+fn read(ctx: &Context<'_>) -> async_graphql::Result<(Grant, Arc<KVStore>)> {
+    let kvstore = ctx.data::<Arc<Server>>()?.store()?;
+    Ok((ctx.data::<Grant>()?.clone(), kvstore))
+}
+
+/// A key as GraphQL clients see it.
+pub struct Entry {
+    key: String,
+    /// None if secret.
+    value: Option<String>,
+    meta: Option<Meta>,
+}
+
+impl Entry {
+    fn new(key: &str, kvstore: &KVStore) -> Option<Entry> {
+        let value = serve::live(kvstore, key)?;
+        Some(Entry {
+            key: key.to_owned(),
+            value: (!is_secret(key, &kvstore.meta)).then(|| value.to_owned()),
+            meta: kvstore.meta.get(key).cloned(),
+        })
+    }
+}
+
+#[Object]
+impl Entry {
+    async fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Null for secret keys.
+    async fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    async fn secret(&self) -> bool {
+        self.value.is_none()
+    }
+
+    /// Seconds since the epoch.
+    async fn created(&self) -> Option<u64> {
+        self.meta.as_ref().map(|meta| meta.created)
+    }
+
+    /// Seconds since the epoch.
+    async fn modified(&self) -> Option<u64> {
+        self.meta.as_ref().map(|meta| meta.modified)
+    }
+
+    /// How the value was last set, e.g. `cli` or `serve`.
+    async fn source(&self) -> Option<&str> {
+        self.meta.as_ref()?.source.as_deref()
+    }
+
+    /// The value parsed as JSON, or only the part at `path`, e.g. `db.hosts.0`. Null if the
+    /// value isn't JSON or has nothing at `path`.
+    async fn json(&self, path: Option<String>) -> Option<Json<serde_json::Value>> {
+        let value: serde_json::Value = serde_json::from_str(self.value.as_deref()?).ok()?;
+        let pointer: String = path
+            .iter()
+            .flat_map(|path| path.split('.'))
+            .filter(|part| !part.is_empty())
+            .map(|part| format!("/{}", part.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        value.pointer(&pointer).cloned().map(Json)
+    }
+}
+
+pub struct Mutation;
+
+/// The outcome of `set`.
+#[derive(SimpleObject)]
+pub struct SetResult {
+    /// Whether the change was made. False if `expected` didn't match.
+    ok: bool,
+    /// The value before the change if it was made, the current value if not. Null if
+    /// there is none.
+    value: Option<String>,
+}
+
+#[Object]
+impl Mutation {
+    /// Set `key` to `value`, or delete it if `value` is null. With `expected`, only if `key`
+    /// still has that value, or with `expected: null` doesn't exist: a compare-and-set that
+    /// fails rather than overwrite someone else's change.
+    async fn set(
+        &self,
+        ctx: &Context<'_>,
+        key: String,
+        value: Option<String>,
+        expected: MaybeUndefined<String>,
+    ) -> async_graphql::Result<SetResult> {
+        let server = ctx.data::<Arc<Server>>()?;
+        if key.is_empty() || !ctx.data::<Grant>()?.can_write(&key) {
+            return Err(format!("This token can't change {}", key).into());
+        }
+        let expected = match expected {
+            MaybeUndefined::Undefined => None,
+            MaybeUndefined::Null => Some(None),
+            MaybeUndefined::Value(value) => Some(Some(value)),
+        };
+        let current = |kvstore: &KVStore| serve::live(kvstore, &key).map(|v| v.to_owned());
+        let check = |kvstore: &KVStore| match &expected {
+            Some(expected) if current(kvstore) != *expected => Err(serve::Response::text(409, "")),
+            _ => Ok(()),
+        };
+        match serve::apply(vec![(key.clone(), value)], &check, server)? {
+            Ok(mut old) => Ok(SetResult {
+                ok: true,
+                value: old.pop().flatten(),
+            }),
+            Err(response) if response.status == 409 => Ok(SetResult {
+                ok: false,
+                value: current(&*server.store()?),
+            }),
+            Err(response) => Err(response.body.trim_end().to_owned().into()),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+
+use crate::KV;
+
+const MATCH_COLOR: &str = "\x1b[01;31m";
+const KEY_COLOR: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+pub struct GrepOptions {
+    pub before: usize,
+    pub after: usize,
+    pub color: bool,
+}
+
+fn highlight(line: &str, re: &Regex) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for m in re.find_iter(line) {
+        out.push_str(&line[last..m.start()]);
+        out.push_str(MATCH_COLOR);
+        out.push_str(m.as_str());
+        out.push_str(RESET);
+        last = m.end();
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// Lines of `value` matching `re`, with `-B`/`-A` context, formatted like grep output.
+///
+/// Matching lines are printed as `key:line` and context lines as `key-line`. Non-adjacent
+/// groups within one value are separated by `--`.
+fn grep_value(key: &str, value: &str, re: &Regex, opts: &GrepOptions) -> Vec<String> {
+    let lines: Vec<&str> = value.lines().collect();
+    let matched: Vec<usize> = (0..lines.len()).filter(|&i| re.is_match(lines[i])).collect();
+    let mut shown = BTreeSet::new();
+    for &i in &matched {
+        let start = i.saturating_sub(opts.before);
+        let end = i.saturating_add(opts.after).min(lines.len() - 1);
+        shown.extend(start..=end);
+    }
+
+    let key = if opts.color {
+        format!("{}{}{}", KEY_COLOR, key, RESET)
+    } else {
+        key.to_owned()
+    };
+    let mut out = vec![];
+    let mut prev: Option<usize> = None;
+    for i in shown {
+        if prev.is_some_and(|p| p + 1 != i) {
+            out.push("--".to_owned());
+        }
+        if matched.binary_search(&i).is_ok() {
+            let line = if opts.color {
+                highlight(lines[i], re)
+            } else {
+                lines[i].to_owned()
+            };
+            out.push(format!("{}:{}", key, line));
+        } else {
+            out.push(format!("{}-{}", key, lines[i]));
+        }
+        prev = Some(i);
+    }
+    out
+}
+
+/// Grep every value in `kvs`, returning the lines to print (sorted by key).
+pub fn grep(kvs: &KV, re: &Regex, opts: &GrepOptions) -> Vec<String> {
+    let mut keys: Vec<&String> = kvs.keys().collect();
+    keys.sort();
+    keys.into_iter()
+        .flat_map(|key| grep_value(key, &kvs[key], re, opts))
+        .collect()
+}
@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{del_key, get_key, print_aligned, print_err, set_key, KVStore, OpType};
+
+/// A value in a test script.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+type Step = BTreeMap<String, Value>;
+
+/// Parse the small part of TOML test scripts use: `[[step]]` tables of `key = value` lines,
+/// where values are strings, booleans or single-line arrays of strings.
+fn parse(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let err = |e: &str| format!("line {}: {}", n + 1, e);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if strip_comment(line).trim() != "[[step]]" {
+                return Err(err("only [[step]] tables are supported"));
+            }
+            steps.push(Step::new());
+            continue;
+        }
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected key = value"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(err("bad key"));
+        }
+        let (value, rest) = parse_value(rest.trim_start()).map_err(|e| err(&e))?;
+        if !strip_comment(rest).trim().is_empty() {
+            return Err(err("unexpected text after the value"));
+        }
+        let step = steps
+            .last_mut()
+            .ok_or_else(|| err("keys must be inside a [[step]]"))?;
+        if step.insert(key.to_owned(), value).is_some() {
+            return Err(err(&format!("{} is set twice", key)));
+        }
+    }
+    Ok(steps)
+}
+
+fn strip_comment(s: &str) -> &str {
+    s.split_once('#').map_or(s, |(before, _)| before)
+}
+
+/// Parse the value at the start of `s`, returning it and the rest of `s`.
+fn parse_value(s: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = s.strip_prefix("true") {
+        return Ok((Value::Bool(true), rest));
+    }
+    if let Some(rest) = s.strip_prefix("false") {
+        return Ok((Value::Bool(false), rest));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::List(items), after));
+            }
+            let (item, after) = parse_string(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+    let (value, rest) = parse_string(s)?;
+    Ok((Value::Str(value), rest))
+}
+
+/// A "basic" (with \ escapes) or 'literal' string at the start of `s`.
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    if let Some(rest) = s.strip_prefix('\'') {
+        return match rest.split_once('\'') {
+            Some((value, rest)) => Ok((value.to_owned(), rest)),
+            None => Err("unterminated string".to_owned()),
+        };
+    }
+    let rest = s
+        .strip_prefix('"')
+        .ok_or("expected a string, true, false or [...]")?;
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &rest[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                _ => return Err("unsupported escape".to_owned()),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated string".to_owned())
+}
+
+fn text<'a>(step: &'a Step, name: &str) -> Result<Option<&'a str>, String> {
+    match step.get(name) {
+        None => Ok(None),
+        Some(Value::Str(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("{} must be a string", name)),
+    }
+}
+
+/// Run one step against `kvstore`, returning what it did, or why it failed.
+fn run_step(kvstore: &mut KVStore, step: &Step) -> Result<String, String> {
+    const KNOWN: [&str; 7] = ["set", "value", "get", "expect", "missing", "del", "hooks"];
+    if let Some(unknown) = step.keys().find(|k| !KNOWN.contains(&&k[..])) {
+        return Err(format!("unknown field {}", unknown));
+    }
+    let (op, key) = match (text(step, "set")?, text(step, "get")?, text(step, "del")?) {
+        (Some(key), None, None) => (OpType::Set, key),
+        (None, Some(key), None) => (OpType::Get, key),
+        (None, None, Some(key)) => (OpType::Del, key),
+        _ => return Err("a step needs exactly one of set, get or del".to_owned()),
+    };
+    let done = format!("{} {}", op, key);
+    match op {
+        OpType::Set => {
+            let value = text(step, "value")?.ok_or("set needs a value")?;
+            set_key(key, value, &mut kvstore.kvs);
+        }
+        OpType::Del => {
+            del_key(key, &mut kvstore.kvs);
+        }
+        OpType::Get => {
+            let value = get_key(key, &kvstore.kvs);
+            if let Some(expected) = text(step, "expect")? {
+                match &value {
+                    Some(value) if value == expected => {}
+                    Some(value) => return Err(format!("expected {:?}, got {:?}", expected, value)),
+                    None => return Err(format!("expected {:?}, but the key is missing", expected)),
+                }
+            }
+            if let (Some(Value::Bool(true)), Some(value)) = (step.get("missing"), value) {
+                return Err(format!("expected no value, got {:?}", value));
+            }
+        }
+    }
+    let fired: BTreeSet<&str> = kvstore
+        .hooks_for(key, &op)
+        .iter()
+        .map(|h| &h.name[..])
+        .collect();
+    match step.get("hooks") {
+        None => {}
+        Some(Value::List(expected)) => {
+            let expected: BTreeSet<&str> = expected.iter().map(|h| &h[..]).collect();
+            if expected != fired {
+                return Err(format!("expected hooks {:?}, fired {:?}", expected, fired));
+            }
+        }
+        Some(_) => return Err("hooks must be a list of hook names".to_owned()),
+    }
+    if fired.is_empty() {
+        Ok(done)
+    } else {
+        let fired: Vec<&str> = fired.into_iter().collect();
+        Ok(format!("{}, fired {}", done, fired.join(", ")))
+    }
+}
+
+/// Run the steps in `script` against a throwaway store that has the real store's cmds and
+/// hooks but no keys. Hooks aren't run; steps only check which ones would fire.
+pub fn run(script: &str, cmds_and_hooks: &KVStore) {
+    let text = match std::fs::read_to_string(script) {
+        Ok(text) => text,
+        Err(e) => print_err(&format!("Error! Cannot read {}, error {}", script, e)),
+    };
+    let steps = match parse(&text) {
+        Ok(steps) => steps,
+        Err(e) => print_err(&format!("Error! Cannot parse {}, {}", script, e)),
+    };
+    let mut kvstore = KVStore::in_memory();
+    kvstore.cmds = cmds_and_hooks.cmds.clone();
+    kvstore.hooks = cmds_and_hooks.hooks.clone();
+    let mut lines = vec!["Step\t--\tResult\t--\tDetails".to_owned()];
+    let mut failed = 0;
+    for (n, step) in steps.iter().enumerate() {
+        let (status, details) = match run_step(&mut kvstore, step) {
+            Ok(details) => ("ok", details),
+            Err(details) => {
+                failed += 1;
+                ("FAILED", details)
+            }
+        };
+        lines.push(format!("{}\t--\t{}\t--\t{}", n + 1, status, details));
+    }
+    print_aligned(lines);
+    if failed > 0 {
+        print_err(&format!(
+            "\nError! {} of {} steps failed.",
+            failed,
+            steps.len()
+        ));
+    }
+}
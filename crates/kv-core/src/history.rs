@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{get_config_dir, print_err, stateless, time};
+
+/// How many runs to remember.
+const MAX_ENTRIES: usize = 1000;
+
+/// One `kv cmd run`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    /// Sequential, never reused, so `kv cmd rerun <id>` stays valid as old entries drop off.
+    pub id: u64,
+    pub ts: u64,
+    pub name: String,
+    /// The command as it was run, even if the stored cmd changed since.
+    pub cmd: String,
+    /// The cmd's language, if not shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// `None` if the command was killed by a signal or failed to start.
+    pub code: Option<i32>,
+    pub millis: u64,
+}
+
+fn get_history_location() -> PathBuf {
+    get_config_dir().join("history.json")
+}
+
+fn load() -> Vec<Entry> {
+    std::fs::read(get_history_location())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Append a run to the history, dropping the oldest entries past `MAX_ENTRIES`.
+pub fn record(name: &str, cmd: &str, lang: &str, code: Option<i32>, took: Duration) {
+    if stateless::is_active() {
+        return;
+    }
+    let mut entries = load();
+    let id = entries.last().map_or(1, |e| e.id + 1);
+    entries.push(Entry {
+        id,
+        ts: time::now(),
+        name: name.to_owned(),
+        cmd: cmd.to_owned(),
+        lang: Some(lang.to_owned()).filter(|lang| lang != "shell"),
+        code,
+        millis: took.as_millis() as u64,
+    });
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+    let path = get_history_location();
+    if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&entries).unwrap()) {
+        let err_msg = format!(
+            "Error! Cannot write history {}, error {}",
+            path.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// Lines for `kv cmd history`: the last `count` runs, oldest first.
+pub fn list(count: usize) -> Vec<String> {
+    let entries = load();
+    let skip = entries.len().saturating_sub(count);
+    let mut lines = vec!["#\t--\tWhen\t--\tCmd Name\t--\tExit\t--\tTime\t--\tCommand".to_owned()];
+    lines.extend(entries.iter().skip(skip).map(|e| {
+        format!(
+            "{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}",
+            e.id,
+            time::format_timestamp(e.ts),
+            e.name,
+            e.code.map_or("-".to_owned(), |c| c.to_string()),
+            time::format_duration(Duration::from_millis(e.millis)),
+            e.cmd
+        )
+    }));
+    lines
+}
+
+pub fn find(id: u64) -> Entry {
+    match load().into_iter().find(|e| e.id == id) {
+        Some(entry) => entry,
+        None => {
+            let err_msg = format!(
+                "Error! No run #{} in history. To see past runs try\n kv cmd history",
+                id
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use crate::{fail, index, options, print_err, prompt, touch_meta, try_get_store, try_on_change, try_with_store_lock,
+            try_write_file, Error, KVStore, OpType, KV};
+
+/// What to do when an imported key already exists with a different value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    /// Keep the local value.
+    Keep,
+    /// Take the incoming value.
+    Replace,
+    /// Refuse to import anything.
+    Fail,
+    /// Ask about each conflict.
+    Interactive,
+}
+
+/// Read the keys to import from a JSON file: either a kv store file, or a flat object of
+/// keys to values. Numbers and booleans are stored as their JSON text.
+pub fn read_file(path: &str) -> KV {
+    let bytes = match std::fs::read(path) {
+        Ok(b) => b,
+        Err(e) => {
+            let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let json: serde_json::Value = match serde_json::from_slice(&bytes) {
+        Ok(j) => j,
+        Err(e) => {
+            let err_msg = format!("Error! {} is not valid JSON, error {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let map = match json.get("kvs").unwrap_or(&json) {
+        serde_json::Value::Object(map) => map,
+        _ => {
+            let err_msg = format!("Error! {} must contain a JSON object of keys to values", path);
+            print_err(&err_msg[..]);
+        }
+    };
+    map.iter()
+        .map(|(key, val)| {
+            let val = match val {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Number(_) | serde_json::Value::Bool(_) => val.to_string(),
+                _ => {
+                    let err_msg = format!("Error! Value of {} in {} is not a string", key, path);
+                    print_err(&err_msg[..]);
+                }
+            };
+            (key.clone(), val)
+        })
+        .collect()
+}
+
+fn prompt(msg: &str) -> String {
+    match prompt::line(msg) {
+        Some(line) => line,
+        None => print_err("Error! No input, import aborted. Nothing was changed."),
+    }
+}
+
+/// Ask how to resolve one conflict. Returns the value to store, or `None` to keep the local
+/// value. Sets `skip_rest` when the user wants to keep local values for all later conflicts.
+fn resolve(key: &str, local: &str, incoming: &str, skip_rest: &mut bool) -> Option<String> {
+    eprintln!("Conflict on key {}:", key);
+    eprintln!("  local:    {}", local);
+    eprintln!("  incoming: {}", incoming);
+    loop {
+        match &prompt("[k]eep local, [r]eplace with incoming, [e]dit, [s]kip remaining conflicts? ")[..] {
+            "k" => return None,
+            "r" => return Some(incoming.to_owned()),
+            "e" => return Some(prompt("New value: ")),
+            "s" => {
+                *skip_rest = true;
+                return None;
+            }
+            _ => eprintln!("Please answer k, r, e or s."),
+        }
+    }
+}
+
+/// Merge `incoming` into the store, returning the keys whose value changed.
+pub fn merge(kvstore: &mut KVStore, incoming: KV, strategy: Strategy) -> Vec<String> {
+    try_merge(kvstore, incoming, strategy).unwrap_or_else(|e| fail(e))
+}
+
+/// `merge` that returns the error instead of exiting when `Strategy::Fail` meets a conflict.
+fn try_merge(kvstore: &mut KVStore, incoming: KV, strategy: Strategy) -> Result<Vec<String>, Error> {
+    let mut conflicts: Vec<&String> = incoming
+        .iter()
+        .filter(|(key, val)| kvstore.kvs.get(*key).is_some_and(|local| local != *val))
+        .map(|(key, _)| key)
+        .collect();
+    conflicts.sort();
+    if strategy == Strategy::Fail && !conflicts.is_empty() {
+        let names: Vec<&str> = conflicts.iter().map(|k| &k[..]).collect();
+        return Err(Error::new(format!(
+            "These keys already exist with different values:\n {}\nTo choose what happens try\n kv import --strategy keep|replace <file>\nor\n kv import --interactive <file>",
+            names.join("\n ")
+        )));
+    }
+
+    let mut resolved = KV::new();
+    let mut skip_rest = false;
+    for key in conflicts {
+        let value = match strategy {
+            Strategy::Replace => Some(incoming[key].clone()),
+            Strategy::Interactive if !skip_rest => {
+                resolve(key, &kvstore.kvs[key], &incoming[key], &mut skip_rest)
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            resolved.insert(key.clone(), value);
+        }
+    }
+
+    let mut changed = vec![];
+    for (key, val) in incoming {
+        let val = match kvstore.kvs.get(&key) {
+            None => val,
+            Some(local) if *local == val => continue,
+            Some(_) => match resolved.remove(&key) {
+                Some(val) => val,
+                None => continue,
+            },
+        };
+        kvstore.kvs.insert(key.clone(), val);
+        changed.push(key);
+    }
+    changed.sort();
+    Ok(changed)
+}
+
+impl KVStore {
+    /// Merge `incoming` into the store like `kv import`, settling keys that exist with a
+    /// different value by `strategy`, and return the keys that changed, sorted.
+    /// `Strategy::Fail` returns an error and changes nothing if there is such a key, and
+    /// `Strategy::Interactive`, which needs a terminal, is refused. For an opened store the
+    /// merge happens under the lock and the changed keys' set hooks run.
+    pub fn merge(&mut self, incoming: BTreeMap<String, String>, strategy: Strategy) -> Result<Vec<String>, Error> {
+        if strategy == Strategy::Interactive {
+            return Err(Error::new("An interactive merge needs the kv command line"));
+        }
+        if self.path.is_none() {
+            let changed = try_merge(self, incoming, strategy)?;
+            for key in &changed {
+                touch_meta(key, "lib", &mut self.meta);
+            }
+            return Ok(changed);
+        }
+        let options = self.options.clone();
+        options::with(options.as_ref(), || {
+            let (changed, kvstore) = try_with_store_lock(|| {
+                let mut kvstore = try_get_store()?;
+                let changed = try_merge(&mut kvstore, incoming, strategy)?;
+                if !changed.is_empty() {
+                    for key in &changed {
+                        touch_meta(key, "lib", &mut kvstore.meta);
+                    }
+                    try_write_file(&kvstore)?;
+                    index::try_refresh(&kvstore)?;
+                }
+                Ok((changed, kvstore))
+            })?;
+            self.reload(kvstore);
+            for key in &changed {
+                try_on_change(key, OpType::Set, self.get(key))?;
+            }
+            Ok(changed)
+        })
+    }
+}
+
+/// Make the store's keys exactly `incoming`. Returns the changed and the removed keys.
+pub fn replace(kvstore: &mut KVStore, incoming: KV) -> (Vec<String>, Vec<String>) {
+    let mut removed: Vec<String> = kvstore
+        .kvs
+        .keys()
+        .filter(|key| !incoming.contains_key(*key))
+        .cloned()
+        .collect();
+    removed.sort();
+    for key in &removed {
+        kvstore.kvs.remove(key);
+        kvstore.meta.remove(key);
+    }
+    let changed = merge(kvstore, incoming, Strategy::Replace);
+    (changed, removed)
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use crate::{fail, get_file_location, is_secret, print_err, replace_file, stateless, Error, KVStore};
+
+/// Inverted index over stored values: lowercase token -> keys whose value contains it.
+///
+/// The index is optional. It only exists once `kv index build` has been run, and from
+/// then on every `set`/`del` keeps it up to date, under the store lock. Secret values are
+/// left out, so the index file never holds any of their words.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Index {
+    tokens: BTreeMap<String, BTreeSet<String>>,
+}
+
+fn get_index_location() -> PathBuf {
+    get_file_location().with_file_name("kv.index.json")
+}
+
+fn tokenize(s: &str) -> impl Iterator<Item = String> + '_ {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+}
+
+impl Index {
+    fn from_store(kvstore: &KVStore) -> Index {
+        let mut index = Index::default();
+        for (key, val) in &kvstore.kvs {
+            if !is_secret(key, &kvstore.meta) {
+                index.insert(key, val);
+            }
+        }
+        index
+    }
+
+    fn insert(&mut self, key: &str, val: &str) {
+        for token in tokenize(val) {
+            self.tokens.entry(token).or_default().insert(key.to_owned());
+        }
+    }
+
+    fn remove(&mut self, key: &str, val: &str) {
+        for token in tokenize(val) {
+            if let Some(keys) = self.tokens.get_mut(&token) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.tokens.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Keys whose value may contain `query`, or `None` if the query has no tokens to look up.
+    ///
+    /// Every token of the query must appear inside some token of the value, so the result
+    /// is a superset of the real matches and callers still confirm each candidate.
+    pub fn candidates(&self, query: &str) -> Option<BTreeSet<String>> {
+        let mut result: Option<BTreeSet<String>> = None;
+        for query_token in tokenize(query) {
+            let keys: BTreeSet<String> = self
+                .tokens
+                .iter()
+                .filter(|(token, _)| token.contains(&query_token))
+                .flat_map(|(_, keys)| keys.iter().cloned())
+                .collect();
+            result = Some(match result {
+                Some(prev) => prev.intersection(&keys).cloned().collect(),
+                None => keys,
+            });
+        }
+        result
+    }
+}
+
+/// Load the index, if one has been built.
+pub fn load() -> Option<Index> {
+    // The index on disk belongs to the store file, not to a store loaded read-only.
+    if stateless::is_active() {
+        return None;
+    }
+    let file = File::open(get_index_location()).ok()?;
+    serde_json::from_reader(BufReader::new(file)).ok()
+}
+
+fn save(index: &Index) {
+    try_save(index).unwrap_or_else(|e| fail(e));
+}
+
+fn try_save(index: &Index) -> Result<(), Error> {
+    stateless::check_writable()?;
+    replace_file(&get_index_location(), &serde_json::to_vec(index).unwrap(), false)
+}
+
+/// Build (or rebuild) the index from every stored value but secret ones. Call it with the
+/// store locked.
+pub fn build(kvstore: &KVStore) {
+    save(&Index::from_store(kvstore));
+}
+
+/// Remove the index. Searches fall back to scanning every value.
+pub fn delete() {
+    stateless::ensure_writable();
+    let path = get_index_location();
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            let err_msg = format!(
+                "Error! Cannot remove index {}, error {}",
+                path.to_string_lossy(),
+                e
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Record that `key` changed from `old` to `new` in `kvstore`, just written. Does nothing
+/// if there is no index. Call it with the store still locked, so concurrent changes can't
+/// interleave their updates.
+pub fn update(kvstore: &KVStore, key: &str, old: Option<&str>, new: Option<&str>) {
+    try_update(kvstore, key, old, new).unwrap_or_else(|e| fail(e));
+}
+
+/// `update`, returning the error if the index can't be written.
+pub fn try_update(kvstore: &KVStore, key: &str, old: Option<&str>, new: Option<&str>) -> Result<(), Error> {
+    if let Some(mut index) = load() {
+        if let Some(old) = old {
+            index.remove(key, old);
+        }
+        if let Some(new) = new.filter(|_| !is_secret(key, &kvstore.meta)) {
+            index.insert(key, new);
+        }
+        try_save(&index)?;
+    }
+    Ok(())
+}
+
+/// Rebuild the index after a bulk change, if there is one. Call it with the store locked.
+pub fn refresh(kvstore: &KVStore) {
+    try_refresh(kvstore).unwrap_or_else(|e| fail(e))
+}
+
+/// `refresh` that returns the error instead of exiting.
+pub fn try_refresh(kvstore: &KVStore) -> Result<(), Error> {
+    if load().is_some() {
+        try_save(&Index::from_store(kvstore))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+use crate::{direnv, events, get_store, set_key, with_store_lock, write_file, Hook, OpType};
+
+/// `{{kv:key}}`, with spaces allowed inside the braces.
+fn marker() -> Regex {
+    Regex::new(r"\{\{\s*kv:([^\s}]+)\s*\}\}").unwrap()
+}
+
+/// The keys `template` refers to, in order of first use.
+pub fn keys(template: &str) -> Vec<String> {
+    let mut keys: Vec<String> = vec![];
+    for caps in marker().captures_iter(template) {
+        if !keys.iter().any(|k| k == &caps[1]) {
+            keys.push(caps[1].to_owned());
+        }
+    }
+    keys
+}
+
+/// `template` with every marker replaced by the value `lookup` gives its key. If any key has
+/// no value they are all returned instead, so a file is never half injected.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Vec<String>> {
+    let mut missing = vec![];
+    let mut values = vec![];
+    for key in keys(template) {
+        match lookup(&key) {
+            Some(value) => values.push((key, value)),
+            None => missing.push(key),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    let rendered = marker().replace_all(template, |caps: &regex::Captures| {
+        values.iter().find(|(key, _)| key == &caps[1]).unwrap().1.clone()
+    });
+    Ok(rendered.into_owned())
+}
+
+/// `path` made absolute, since hooks don't run in the directory kv was called from.
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_owned()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    }
+}
+
+/// Add a cmd that injects `template` into `to`, and a set hook running it for each of
+/// `keys` (full key names, namespace included). Hooks that already exist are kept, so
+/// running `kv inject --watch` again after adding markers only adds the new ones.
+pub fn watch(template: &Path, to: &Path, keys: &[String]) {
+    let to_name = to.display().to_string();
+    let cmd_name = format!("inject:{}", to_name);
+    let cmd = format!(
+        "kv inject --to {} {}",
+        direnv::quote(&to_name),
+        direnv::quote(&template.display().to_string())
+    );
+    let added = with_store_lock(|| {
+        let mut kvstore = get_store();
+        set_key(&cmd_name, &cmd, &mut kvstore.cmds);
+        kvstore.cmd_langs.remove(&cmd_name);
+        let mut added = vec![];
+        for key in keys {
+            let name = format!("{}:{}", cmd_name, key);
+            if kvstore.hooks.iter().any(|hook| hook.name == name) {
+                continue;
+            }
+            kvstore.hooks.push(Hook {
+                name: name.clone(),
+                cmd_name: cmd_name.clone(),
+                run_on: OpType::Set,
+                key: key.clone(),
+                sandbox: false,
+                run_as: None,
+                elevate_with: None,
+                action: None,
+                description: Some(format!("Re-inject {} when {} is set", to_name, key)),
+            });
+            added.push(name);
+        }
+        write_file(&kvstore);
+        added
+    });
+    events::record("cmd", &OpType::Set, &cmd_name, Some(&cmd));
+    for name in &added {
+        events::record("hook", &OpType::Set, name, Some(&format!("{} set", cmd_name)));
+    }
+    eprintln!(
+        "{} is re-injected whenever one of its {} keys is set. To stop for a key try\n kv cmd del-hook {}:<key>",
+        to_name,
+        keys.len(),
+        cmd_name
+    );
+}
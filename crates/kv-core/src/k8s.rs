@@ -0,0 +1,126 @@
+use base64::Engine;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::{print_err, KV};
+
+/// A ConfigMap or Secret, from `configmap/<name>` or `secret/<name>`.
+pub struct Resource {
+    kind: &'static str,
+    name: String,
+}
+
+pub fn parse_resource(s: &str) -> Resource {
+    let (kind, name) = match s.split_once('/') {
+        Some(("configmap" | "configmaps" | "cm", name)) if !name.is_empty() => ("ConfigMap", name),
+        Some(("secret" | "secrets", name)) if !name.is_empty() => ("Secret", name),
+        _ => {
+            let err_msg = format!(
+                "Error! {} is not a ConfigMap or Secret. Use configmap/<name> or secret/<name>",
+                s
+            );
+            print_err(&err_msg[..]);
+        }
+    };
+    Resource {
+        kind,
+        name: name.to_owned(),
+    }
+}
+
+fn kubectl(args: &[&str], namespace: Option<&str>) -> Command {
+    let mut command = Command::new("kubectl");
+    command.args(args);
+    if let Some(ns) = namespace {
+        command.args(["--namespace", ns]);
+    }
+    command
+}
+
+fn kubectl_error(e: impl std::fmt::Display) -> ! {
+    let err_msg = format!("Error! Cannot run kubectl, error {}", e);
+    print_err(&err_msg[..]);
+}
+
+/// Read the keys of a ConfigMap or Secret with `kubectl get`. Secret values are decoded;
+/// binary ones are refused, since kv only stores text.
+pub fn read(resource: &Resource, namespace: Option<&str>) -> KV {
+    let kind = resource.kind.to_lowercase();
+    let output = kubectl(&["get", &kind, &resource.name, "-o", "json"], namespace)
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap_or_else(|e| kubectl_error(e));
+    if !output.status.success() {
+        print_err("Error! kubectl get failed. Nothing was changed.");
+    }
+    let json: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(json) => json,
+        Err(e) => kubectl_error(e),
+    };
+    let data = json["data"].as_object().cloned().unwrap_or_default();
+    data.into_iter()
+        .map(|(key, val)| {
+            let val = val.as_str().unwrap_or_default();
+            let val = if resource.kind == "Secret" {
+                base64::engine::general_purpose::STANDARD
+                    .decode(val)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .unwrap_or_else(|| {
+                        let err_msg = format!("Error! Value of {} is not UTF-8 text", key);
+                        print_err(&err_msg[..]);
+                    })
+            } else {
+                val.to_owned()
+            };
+            (key, val)
+        })
+        .collect()
+}
+
+/// Create or update a ConfigMap or Secret holding exactly `kvs`, with `kubectl apply`.
+pub fn write(resource: &Resource, namespace: Option<&str>, kvs: &KV) {
+    // Kubernetes only allows these characters in data keys.
+    let mut invalid: Vec<&str> = kvs
+        .keys()
+        .filter(|key| {
+            !key.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._".contains(c))
+        })
+        .map(|key| &key[..])
+        .collect();
+    if !invalid.is_empty() {
+        invalid.sort();
+        let err_msg = format!(
+            "Error! These keys are not valid {} keys (only letters, digits, '-', '_' and '.'):\n {}\nTo export keys under a prefix without it try\n kv export --prefix <prefix> --to-k8s ...",
+            resource.kind,
+            invalid.join("\n ")
+        );
+        print_err(&err_msg[..]);
+    }
+    let data = if resource.kind == "Secret" {
+        "stringData"
+    } else {
+        "data"
+    };
+    let manifest = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": resource.kind,
+        "metadata": { "name": resource.name },
+        data: kvs,
+    });
+    let mut child = kubectl(&["apply", "-f", "-"], namespace)
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| kubectl_error(e));
+    let written = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(manifest.to_string().as_bytes());
+    match child.wait() {
+        Ok(status) if status.success() && written.is_ok() => {}
+        Ok(_) => print_err("Error! kubectl apply failed."),
+        Err(e) => kubectl_error(e),
+    }
+}
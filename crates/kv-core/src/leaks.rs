@@ -0,0 +1,150 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{is_secret, secret, KVStore};
+
+/// Values shorter than this aren't looked for.
+const MIN_LEN: usize = 8;
+
+/// Base of the rolling hash that picks out windows worth hashing with SHA-256.
+const BASE: u64 = 257;
+
+/// A secret key's value, kept only as hashes: a rolling hash to find candidate windows
+/// cheaply, and SHA-256 to confirm them.
+struct Fingerprint {
+    key: String,
+    rolling: u64,
+    sha: [u8; 32],
+}
+
+/// Where a secret value turned up.
+pub struct Leak {
+    pub path: PathBuf,
+    pub line: usize,
+    pub key: String,
+}
+
+/// What `kv scan` looks for: the values of secret keys, grouped by length.
+pub struct Secrets {
+    by_len: HashMap<usize, Vec<Fingerprint>>,
+}
+
+fn rolling_hash(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0u64, |h, &b| h.wrapping_mul(BASE).wrapping_add(b as u64))
+}
+
+fn sha(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+impl Secrets {
+    /// The store's secret values, and the secret keys that can't be looked for: values
+    /// encrypted with `kv secret` aren't known without the passphrase, and short ones would
+    /// turn up by chance.
+    pub fn from_store(kvstore: &KVStore) -> (Secrets, Vec<String>) {
+        let mut by_len: HashMap<usize, Vec<Fingerprint>> = HashMap::new();
+        let mut skipped = vec![];
+        let secrets = kvstore
+            .kvs
+            .iter()
+            .filter(|(key, _)| is_secret(key, &kvstore.meta));
+        for (key, value) in secrets {
+            if secret::is_encrypted(value) || value.len() < MIN_LEN {
+                skipped.push(key.clone());
+                continue;
+            }
+            by_len.entry(value.len()).or_default().push(Fingerprint {
+                key: key.clone(),
+                rolling: rolling_hash(value.as_bytes()),
+                sha: sha(value.as_bytes()),
+            });
+        }
+        (Secrets { by_len }, skipped)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_len.is_empty()
+    }
+
+    /// The secret keys whose values appear in `data`, with the line they're on.
+    fn find(&self, data: &[u8]) -> Vec<(usize, &str)> {
+        let mut found = vec![];
+        for (&len, fingerprints) in &self.by_len {
+            if data.len() < len {
+                continue;
+            }
+            // BASE^(len-1), to drop the byte leaving the window.
+            let top = (1..len).fold(1u64, |p, _| p.wrapping_mul(BASE));
+            let mut h = rolling_hash(&data[..len]);
+            for start in 0..=data.len() - len {
+                if start > 0 {
+                    h = h
+                        .wrapping_sub((data[start - 1] as u64).wrapping_mul(top))
+                        .wrapping_mul(BASE)
+                        .wrapping_add(data[start + len - 1] as u64);
+                }
+                let candidates = fingerprints.iter().filter(|f| f.rolling == h);
+                let mut window_sha = None;
+                for f in candidates {
+                    let window = *window_sha.get_or_insert_with(|| sha(&data[start..start + len]));
+                    if window == f.sha {
+                        let line = data[..start].iter().filter(|&&b| b == b'\n').count() + 1;
+                        found.push((line, &f.key[..]));
+                    }
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+}
+
+/// Look for secret values in `paths`, files or directories searched recursively (skipping
+/// `.git` and symlinked directories). Unreadable files are reported and skipped.
+pub fn scan(secrets: &Secrets, paths: &[&str]) -> Vec<Leak> {
+    let mut files = vec![];
+    for path in paths {
+        collect_files(Path::new(path), &mut files);
+    }
+    let mut leaks = vec![];
+    for path in files {
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Warning! Cannot read {}, error {}", path.display(), e);
+                continue;
+            }
+        };
+        for (line, key) in secrets.find(&data) {
+            leaks.push(Leak {
+                path: path.clone(),
+                line,
+                key: key.to_owned(),
+            });
+        }
+    }
+    leaks
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    let is_dir = std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+    if is_dir {
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(path) else {
+            eprintln!("Warning! Cannot read directory {}", path.display());
+            return;
+        };
+        let mut entries: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        entries.sort();
+        for entry in entries {
+            collect_files(&entry, files);
+        }
+    } else if !path.is_dir() {
+        files.push(path.to_owned());
+    }
+}
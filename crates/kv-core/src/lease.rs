@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{get_store, print_err, random_id, time, with_store_lock, write_file};
+
+/// An advisory lock held by whoever knows `token`, until `expires`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Lease {
+    token: String,
+    /// Seconds since the epoch.
+    expires: u64,
+}
+
+impl Lease {
+    fn expired(&self) -> bool {
+        self.expires <= time::now()
+    }
+}
+
+fn held_err(name: &str, lease: &Lease) -> String {
+    format!(
+        "Error! Lock {} is held until {}",
+        name,
+        time::format_timestamp(lease.expires)
+    )
+}
+
+/// Take lock `name` for `ttl`, retrying until `wait` runs out. Prints and returns the token
+/// needed to renew or release it. Holding the store lock makes the check-and-take atomic.
+pub fn acquire(name: &str, ttl: Duration, token: Option<&str>, wait: Duration) -> String {
+    let token = token.map(|t| t.to_owned()).unwrap_or_else(random_id);
+    // A wait too long to add to now is as good as forever.
+    let deadline = std::time::Instant::now().checked_add(wait);
+    loop {
+        let taken = with_store_lock(|| {
+            let mut kvstore = get_store();
+            match kvstore.locks.get(name) {
+                Some(lease) if !lease.expired() && lease.token != token => Err(lease.clone()),
+                _ => {
+                    kvstore.locks.insert(
+                        name.to_owned(),
+                        Lease {
+                            token: token.clone(),
+                            expires: time::now().saturating_add(ttl.as_secs().max(1)),
+                        },
+                    );
+                    write_file(&kvstore);
+                    Ok(())
+                }
+            }
+        });
+        match taken {
+            Ok(()) => return token,
+            Err(_) if deadline.is_none_or(|d| std::time::Instant::now() < d) => {
+                std::thread::sleep(Duration::from_millis(100))
+            }
+            Err(lease) => print_err(&held_err(name, &lease)),
+        }
+    }
+}
+
+/// Check that `token` holds lock `name`, then apply `f` to it. Without a token, `f` is
+/// applied whoever holds it.
+fn with_lease(name: &str, token: Option<&str>, f: impl FnOnce(&mut Option<Lease>)) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        let mut lease = kvstore.locks.remove(name);
+        match (&lease, token) {
+            (None, _) => {
+                let err_msg = format!("Error! Lock {} is not held!", name);
+                print_err(&err_msg[..]);
+            }
+            (Some(l), Some(token)) if l.token != token && !l.expired() => {
+                print_err(&held_err(name, l));
+            }
+            _ => {}
+        }
+        f(&mut lease);
+        if let Some(lease) = lease {
+            kvstore.locks.insert(name.to_owned(), lease);
+        }
+        write_file(&kvstore);
+    })
+}
+
+/// Release lock `name` held with `token`. Without a token the lock is broken whoever holds
+/// it, see `kv lock release --force`.
+pub fn release(name: &str, token: Option<&str>) {
+    with_lease(name, token, |lease| *lease = None);
+}
+
+/// Extend lock `name`, held with `token`, to expire `ttl` from now.
+pub fn renew(name: &str, token: &str, ttl: Duration) {
+    with_lease(name, Some(token), |lease| {
+        if let Some(lease) = lease {
+            lease.expires = time::now().saturating_add(ttl.as_secs().max(1));
+        }
+    });
+}
+
+/// Lines for `kv lock list`.
+pub fn list() -> Vec<String> {
+    let kvstore = get_store();
+    let mut lines = vec!["Lock\t--\tExpires\t--\tState".to_owned()];
+    lines.extend(kvstore.locks.iter().map(|(name, lease)| {
+        format!(
+            "{}\t--\t{}\t--\t{}",
+            name,
+            time::format_timestamp(lease.expires),
+            if lease.expired() { "expired" } else { "held" }
+        )
+    }));
+    lines
+}
@@ -0,0 +1,1264 @@
+//! Key, value storage with hooks: the store behind the `kv` binary.
+//!
+//! ```no_run
+//! use kv::{KVStore, OpType};
+//!
+//! # fn main() -> Result<(), kv::Error> {
+//! let mut store = KVStore::builder().path("/tmp/kv.json").build()?;
+//! store.set("greeting", "hello")?;
+//! assert_eq!(store.get("greeting"), Some("hello"));
+//! store.run_hooks("greeting", &OpType::Set)?;
+//! store.del("greeting")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Reading or writing the store returns a `kv::Error` with the message `kv` would print.
+
+// Most of the crate implements kv's subcommands and is only reached through `cli`, which
+// library users leave out along with clap.
+#![cfg_attr(not(feature = "cli"), allow(dead_code, unused_imports))]
+
+use std::env;
+use std::process::{Command, Stdio};
+use std::{collections::BTreeMap, collections::HashMap, str::FromStr};
+use std::io::{IsTerminal, Read, Seek, Write};
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::fs::OpenOptions;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::OnceLock;
+
+use tabwriter::TabWriter;
+
+pub mod action;
+mod aws;
+mod bench;
+// The kv binary, see src/main.rs.
+#[doc(hidden)]
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "net")]
+mod bot;
+// Only kv totp --copy uses the clipboard.
+#[cfg(feature = "crypto")]
+mod clipboard;
+mod columns;
+mod computed;
+mod config;
+mod context;
+mod csv;
+#[cfg(feature = "net")]
+mod digest;
+mod drift;
+mod direnv;
+mod dotenv;
+mod elevate;
+mod error;
+mod events;
+mod export;
+mod features;
+mod federation;
+mod glob;
+mod graph;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod grep;
+mod harness;
+mod history;
+mod import;
+mod index;
+mod inject;
+mod k8s;
+mod lease;
+#[cfg(feature = "crypto")]
+mod leaks;
+mod lint;
+mod machine;
+mod opener;
+mod options;
+mod pager;
+mod poll;
+mod prompt;
+#[cfg(feature = "cli")]
+mod qr;
+mod queue;
+mod record;
+mod refs;
+mod require;
+mod retry;
+mod safety;
+mod sandbox;
+mod scan;
+mod script;
+#[cfg(feature = "crypto")]
+mod secret;
+#[cfg(feature = "net")]
+mod serve;
+#[cfg(feature = "net")]
+mod service;
+mod shard;
+mod shell_init;
+mod simulate;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(all(feature = "net", feature = "crypto"))]
+mod share;
+mod snapshot;
+mod sort;
+mod stateless;
+mod sync;
+mod time;
+mod tmux;
+#[cfg(feature = "crypto")]
+mod totp;
+mod transform;
+mod typed;
+#[cfg(all(feature = "net", feature = "crypto"))]
+mod update;
+mod usage;
+#[cfg(feature = "net")]
+mod vault;
+mod verify;
+#[cfg(feature = "net")]
+mod wal;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod watch;
+
+pub use error::Error;
+pub use events::{ChangeEvent, Subscription};
+pub use import::Strategy;
+pub use options::{Backend, Builder, Fs};
+pub use scan::{Cursor, Entry};
+
+type KV = BTreeMap<String, String>;
+
+/// What happened to a key: what a hook fires on.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum OpType {
+    Get,
+    Set,
+    Del,
+}
+
+/// Runs cmd `cmd_name` when `key` is read or changed by `run_on`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hook {
+    name: String,
+    cmd_name: String,
+    run_on: OpType,
+    key: String,
+    /// Run the command in a sandbox, see `sandbox::command`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    sandbox: bool,
+    /// Run the command as this user, see `elevate::wrap`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_as: Option<String>,
+    /// `sudo` or `doas`. Whichever is installed when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elevate_with: Option<String>,
+    /// What the hook does with its cmd, see `action::HookAction`. A shell command when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    action: Option<String>,
+    /// What the hook is for, which `kv lint` can insist on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl Hook {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cmd_name(&self) -> &str {
+        &self.cmd_name
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn run_on(&self) -> &OpType {
+        &self.run_on
+    }
+
+    /// What the hook does with its cmd, see `action::HookAction`.
+    pub fn action(&self) -> &str {
+        self.action.as_deref().unwrap_or("shell")
+    }
+
+    fn options(&self) -> String {
+        let mut options = vec![];
+        if self.action() != "shell" {
+            options.push(self.action().to_owned());
+        }
+        if self.sandbox {
+            options.push("sandbox".to_owned());
+        }
+        if let Some(user) = &self.run_as {
+            match &self.elevate_with {
+                Some(tool) => options.push(format!("as {} via {}", user, tool)),
+                None => options.push(format!("as {}", user)),
+            }
+        }
+        if options.is_empty() {
+            "-".to_owned()
+        } else {
+            options.join(", ")
+        }
+    }
+}
+
+/// `hook`'s row in `kv hooks` and `kv hooks for-key`.
+fn hook_row(hook: &Hook) -> String {
+    format!(
+        "{}\t--\t{}\t--\t{}\t--\t{}\t--\t{}",
+        hook.name,
+        hook.cmd_name,
+        hook.run_on,
+        hook.key,
+        hook.options()
+    )
+}
+
+/// Lines for `kv hooks show`.
+fn hook_details(hook: &Hook, kvstore: &KVStore) -> Vec<String> {
+    let cmd = match kvstore.cmds.get(&hook.cmd_name) {
+        Some(cmd) => match kvstore.cmd_lang(&hook.cmd_name) {
+            "shell" => cmd.to_owned(),
+            lang => format!("{} ({})", cmd, lang),
+        },
+        None => "(missing)".to_owned(),
+    };
+    vec![
+        format!("Name\t--\t{}", hook.name),
+        format!("Description\t--\t{}", hook.description.as_deref().unwrap_or("-")),
+        format!("Key\t--\t{}", hook.key),
+        format!("Trigger\t--\t{}", hook.run_on),
+        format!("Cmd Name\t--\t{}", hook.cmd_name),
+        format!("Cmd\t--\t{}", cmd),
+        format!("Action\t--\t{}", hook.action()),
+        format!("Sandbox\t--\t{}", if hook.sandbox { "yes" } else { "no" }),
+        format!("Run As\t--\t{}", hook.run_as.as_deref().unwrap_or("-")),
+        format!("Elevate With\t--\t{}", hook.elevate_with.as_deref().unwrap_or("-")),
+    ]
+}
+
+/// Metadata kept for each key in `kvs`. Times are seconds since the unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Meta {
+    pub created: u64,
+    pub modified: u64,
+    /// How the value was last set, e.g. `cli`, `import:<file>` or `hook:<name>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Hide the value in listings and searches, e.g. for tokens set with `kv ask`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+    /// When the key expires, see `kv set --ttl`. Expired keys read as absent and are dropped
+    /// on the next write.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+impl Meta {
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|t| t <= time::now())
+    }
+}
+
+/// The store: keys, cmds and hooks, as read from the store file.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct KVStore {
+    kvs: KV,
+    cmds: KV,
+    hooks: Vec<Hook>,
+    #[serde(default)]
+    meta: BTreeMap<String, Meta>,
+    /// When set, `kvs` and `meta` live in this many shard files instead of this file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shards: Option<u32>,
+    #[serde(skip)]
+    shard_digests: Vec<u64>,
+    #[serde(default)]
+    locks: BTreeMap<String, lease::Lease>,
+    /// The language of each cmd that isn't a shell command, see `script::LANGS`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    cmd_langs: BTreeMap<String, String>,
+    /// Keys whose value is computed from other keys, see `kv computed add`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    computed: BTreeMap<String, computed::Computed>,
+    /// The rhai cmd run on values set on keys matching each pattern, see `kv transform add`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    transforms: BTreeMap<String, String>,
+    /// Keys refreshed from external sources by `kv poll run`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    polls: BTreeMap<String, poll::Poll>,
+    /// Keys overridden for this machine, with the shared value they hide (if any) and the
+    /// override. See `machine::apply`.
+    #[serde(skip)]
+    machine: HashMap<String, (Option<String>, String)>,
+    /// The store file `set`, `del` and `save` write to. None for a store in memory only.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// The options the builder opened the store with. None for the options in effect.
+    #[serde(skip)]
+    options: Option<std::sync::Arc<options::Options>>,
+}
+
+impl KVStore {
+    /// An empty store that lives only in memory: `set` and `del` change it and nothing else.
+    /// No file is read or written, and no hooks run.
+    pub fn in_memory() -> KVStore {
+        KVStore::default()
+    }
+
+    /// What cmd `name` is written in.
+    fn cmd_lang(&self, name: &str) -> &str {
+        self.cmd_langs.get(name).map_or("shell", |lang| &lang[..])
+    }
+
+    /// The hooks that fire when `key` is read or changed by `op`. A hook's key may be a
+    /// wildcard pattern like `deploy/*`, see `glob::matches`.
+    fn hooks_for(&self, key: &str, op: &OpType) -> Vec<&Hook> {
+        self.hooks
+            .iter()
+            .filter(|&x| x.run_on == *op && glob::matches(&x.key, key))
+            .collect()
+    }
+
+    /// Load the store with the options in effect, see `KVStore::builder`. Changes made
+    /// through it are written back to the store file.
+    pub fn open() -> Result<KVStore, Error> {
+        let mut kvstore = try_get_store()?;
+        kvstore.path = Some(try_file_location()?);
+        Ok(kvstore)
+    }
+
+    /// The store file changes are written to, None for an in-memory store.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.kvs.get(key).map(|v| &v[..])
+    }
+
+    /// Set `key` to `value` and return the old value. For an opened store this is like
+    /// `kv set`: the store file is changed too and the key's set hooks run. Other keys are
+    /// reread from the file under the lock, so changes other processes made since `open`
+    /// are kept.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<Option<String>, Error> {
+        if self.path.is_none() {
+            let old = self.kvs.insert(key.to_owned(), value.to_owned());
+            touch_meta(key, "lib", &mut self.meta);
+            return Ok(old);
+        }
+        let options = self.options.clone();
+        options::with(options.as_ref(), || self.set_in_file(key, value))
+    }
+
+    fn set_in_file(&mut self, key: &str, value: &str) -> Result<Option<String>, Error> {
+        let (old, kvstore) = try_with_store_lock(|| {
+            let mut kvstore = try_get_store()?;
+            let old = get_key(key, &kvstore.kvs);
+            set_key(key, value, &mut kvstore.kvs);
+            touch_meta(key, "lib", &mut kvstore.meta);
+            try_write_file(&kvstore)?;
+            index::try_update(&kvstore, key, old.as_deref(), Some(value))?;
+            Ok((old, kvstore))
+        })?;
+        self.reload(kvstore);
+        try_on_change(key, OpType::Set, Some(value))?;
+        Ok(old)
+    }
+
+    /// Delete `key` and return the old value, like `kv del` for an opened store. Runs no
+    /// hooks if there was no value.
+    pub fn del(&mut self, key: &str) -> Result<Option<String>, Error> {
+        if self.path.is_none() {
+            self.meta.remove(key);
+            return Ok(self.kvs.remove(key));
+        }
+        let options = self.options.clone();
+        options::with(options.as_ref(), || self.del_in_file(key))
+    }
+
+    fn del_in_file(&mut self, key: &str) -> Result<Option<String>, Error> {
+        let (old, kvstore) = try_with_store_lock(|| {
+            let mut kvstore = try_get_store()?;
+            let old = del_key(key, &mut kvstore.kvs);
+            if old.is_some() {
+                kvstore.meta.remove(key);
+                try_write_file(&kvstore)?;
+                index::try_update(&kvstore, key, old.as_deref(), None)?;
+            }
+            Ok((old, kvstore))
+        })?;
+        self.reload(kvstore);
+        if old.is_some() {
+            try_on_change(key, OpType::Del, None)?;
+        }
+        Ok(old)
+    }
+
+    /// Set `key` to `value`, or delete it if `value` is None, but only if its value is still
+    /// `expected` (None: it doesn't exist). Returns whether it did. For an opened store the
+    /// check and the change happen under the lock, so no other writer gets in between, and
+    /// the key's hooks run if it changed.
+    pub fn compare_and_set(&mut self, key: &str, expected: Option<&str>, value: Option<&str>) -> Result<bool, Error> {
+        if self.path.is_none() {
+            if self.get(key) != expected {
+                return Ok(false);
+            }
+            match value {
+                Some(value) => {
+                    self.kvs.insert(key.to_owned(), value.to_owned());
+                    touch_meta(key, "lib", &mut self.meta);
+                }
+                None => {
+                    self.kvs.remove(key);
+                    self.meta.remove(key);
+                }
+            }
+            return Ok(true);
+        }
+        let options = self.options.clone();
+        options::with(options.as_ref(), || self.compare_and_set_in_file(key, expected, value))
+    }
+
+    fn compare_and_set_in_file(&mut self, key: &str, expected: Option<&str>, value: Option<&str>) -> Result<bool, Error> {
+        let (swapped, kvstore) = try_with_store_lock(|| {
+            let mut kvstore = try_get_store()?;
+            let old = get_key(key, &kvstore.kvs);
+            if old.as_deref() != expected {
+                return Ok((false, kvstore));
+            }
+            match value {
+                Some(value) => {
+                    set_key(key, value, &mut kvstore.kvs);
+                    touch_meta(key, "lib", &mut kvstore.meta);
+                }
+                None if old.is_some() => {
+                    del_key(key, &mut kvstore.kvs);
+                    kvstore.meta.remove(key);
+                }
+                None => return Ok((true, kvstore)),
+            }
+            try_write_file(&kvstore)?;
+            index::try_update(&kvstore, key, old.as_deref(), value)?;
+            Ok((true, kvstore))
+        })?;
+        self.reload(kvstore);
+        match value {
+            Some(value) if swapped => try_on_change(key, OpType::Set, Some(value))?,
+            None if swapped && expected.is_some() => try_on_change(key, OpType::Del, None)?,
+            _ => {}
+        }
+        Ok(swapped)
+    }
+
+    /// Take on `kvstore`, just read from the file, keeping this store's path and options.
+    fn reload(&mut self, kvstore: KVStore) {
+        let path = self.path.take();
+        let options = self.options.take();
+        *self = KVStore { path, options, ..kvstore };
+    }
+
+    /// Run the hooks on `key` for `op` now, as if it had just happened. An in-memory store
+    /// runs none.
+    pub fn run_hooks(&self, key: &str, op: &OpType) -> Result<(), Error> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+        options::with(self.options.as_ref(), || try_run_hooks(key, op))
+    }
+
+    pub fn hooks(&self) -> &[Hook] {
+        &self.hooks
+    }
+
+    /// Write the store to its store file, replacing it. An in-memory store has none.
+    pub fn save(&self) -> Result<(), Error> {
+        if self.path.is_none() {
+            return Err(Error::new("The store is in memory only, it has no file to save to"));
+        }
+        options::with(self.options.as_ref(), || try_with_store_lock(|| try_write_file(self)))
+    }
+}
+
+impl std::fmt::Display for OpType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let str_rep = match self {
+            OpType::Get => "get",
+            OpType::Set => "set",
+            OpType::Del => "del",
+        };
+        write!(f, "{}", str_rep)
+    }
+}
+
+impl FromStr for OpType {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "get" => Ok(OpType::Get),
+            "set" => Ok(OpType::Set),
+            "del" => Ok(OpType::Del),
+            _ => Err("No match found!"),
+        }
+    }
+}
+
+/// The kv config dir, usually `~/.config/kv`. Created if missing.
+fn get_config_dir() -> PathBuf {
+    try_config_dir().unwrap_or_else(|e| fail(e))
+}
+
+/// `get_config_dir`, returning the error if there is none.
+fn try_config_dir() -> Result<PathBuf, Error> {
+    static CONFIG_DIR: OnceLock<Result<PathBuf, Error>> = OnceLock::new();
+    CONFIG_DIR.get_or_init(find_config_dir).clone()
+}
+
+fn find_config_dir() -> Result<PathBuf, Error> {
+    let home = dirs::config_dir().ok_or_else(|| Error::new("Cannot find the config directory!"))?;
+    let store_file_dir_path = Path::new(&home).join("kv");
+    if !store_file_dir_path.exists() && !stateless::is_active() {
+        if let Err(e) = std::fs::create_dir_all(&store_file_dir_path) {
+            return Err(Error::new(format!(
+                "Cannot create path {}, error {}",
+                store_file_dir_path.to_string_lossy(),
+                e
+            )));
+        }
+    }
+    Ok(store_file_dir_path)
+}
+
+fn get_file_location() -> PathBuf {
+    try_file_location().unwrap_or_else(|e| fail(e))
+}
+
+fn try_file_location() -> Result<PathBuf, Error> {
+    match options::get().path() {
+        Some(path) => Ok(path.to_owned()),
+        None => Ok(try_config_dir()?.join(options::get().backend().file_name())),
+    }
+}
+
+fn try_get_file() -> Result<std::fs::File, Error> {
+    let path = try_file_location()?;
+    retry::io("open", &path, || {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .append(false)
+            .open(&path)
+    })
+}
+
+/// Hold an exclusive lock on the store while `f` runs, so concurrent kv processes can't
+/// interleave their read-modify-write cycles. The lock is on a separate `kv.lock` file.
+fn with_store_lock<R>(f: impl FnOnce() -> R) -> R {
+    try_with_store_lock(|| Ok(f())).unwrap_or_else(|e| fail(e))
+}
+
+/// `with_store_lock` for an `f` that can fail, returning the error if the lock can't be
+/// taken or `f` fails.
+fn try_with_store_lock<R>(f: impl FnOnce() -> Result<R, Error>) -> Result<R, Error> {
+    stateless::check_writable()?;
+    if !options::get().autolock() {
+        return f();
+    }
+    let path = try_config_dir()?.join("kv.lock");
+    let lock = retry::io("lock", &path, || {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .and_then(|file| file.lock().map(|_| file))
+    })?;
+    let res = f();
+    drop(lock);
+    res
+}
+
+/// Store JSON, indented for people editing the file by hand or compact, see `persist.style`.
+fn store_json<T: Serialize>(value: &T, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(value).unwrap()
+    } else {
+        serde_json::to_string(value).unwrap()
+    }
+}
+
+fn write_file(m: &KVStore) {
+    try_write_file(m).unwrap_or_else(|e| fail(e));
+}
+
+fn try_write_file(m: &KVStore) -> Result<(), Error> {
+    let pretty = config::try_get()?.value("persist.style") == "pretty";
+    try_write_store(m, pretty)
+}
+
+fn write_store(m: &KVStore, pretty: bool) {
+    try_write_store(m, pretty).unwrap_or_else(|e| fail(e));
+}
+
+fn try_write_store(m: &KVStore, pretty: bool) -> Result<(), Error> {
+    stateless::check_writable()?;
+    let shared;
+    let m = if m.machine.is_empty() {
+        m
+    } else {
+        shared = machine::without_overrides(m);
+        &shared
+    };
+    #[cfg(feature = "sqlite")]
+    if options::get().backend() == Backend::Sqlite {
+        return sqlite::save(m, &try_file_location()?);
+    }
+    let s = match m.shards {
+        Some(shards) => {
+            shard::save(m, shards, pretty)?;
+            let mut main = serde_json::to_value(m).unwrap();
+            main["kvs"] = serde_json::json!({});
+            main["meta"] = serde_json::json!({});
+            store_json(&main, pretty)
+        }
+        None => store_json(m, pretty),
+    };
+    let path = try_file_location()?;
+    if let Some(fs) = options::get().fs() {
+        return fs
+            .write(&path, s.as_bytes())
+            .map_err(|e| Error::new(format!("Cannot write {}, error {}", path.display(), e)));
+    }
+    replace_file(&path, s.as_bytes(), true)
+}
+
+/// Replace the file at `path` with `bytes` so a crash never leaves it half-written: write a
+/// temp file next to it and rename that over it. With `backup`, the previous version stays
+/// in `<path>.bak`. A symlinked store stays a symlink, the file it points to is replaced.
+/// A new file is readable only by the user, an existing one keeps its permissions.
+fn replace_file(path: &Path, bytes: &[u8], backup: bool) -> Result<(), Error> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let tmp = with_suffix(&path, &format!(".{}.tmp", std::process::id()));
+    retry::io("write", &tmp, || {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        // Keep the store's permissions, e.g. 600 for stores with secrets.
+        if let Ok(meta) = std::fs::metadata(&path) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.write_all(bytes)?;
+        file.sync_all()
+    })?;
+    if backup && path.exists() {
+        let bak = with_suffix(&path, ".bak");
+        let _ = std::fs::remove_file(&bak);
+        // A hard link keeps the old version without copying it, since the rename below
+        // gives `path` a new file rather than changing this one.
+        if std::fs::hard_link(&path, &bak).is_err() {
+            if let Err(e) = std::fs::copy(&path, &bak) {
+                eprintln!(
+                    "Warning! Cannot back up {} to {}, error {}",
+                    path.to_string_lossy(),
+                    bak.to_string_lossy(),
+                    e
+                );
+            }
+        }
+    }
+    retry::io("replace", &path, || std::fs::rename(&tmp, &path))
+}
+
+/// `path` with `suffix` appended to the file name, e.g. `kv.json.bak`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The shell from the store options: the user's, falling back to bash
+fn shell() -> String {
+    options::get().shell().to_owned()
+}
+
+/// Build the process running `cmd` with the user's shell
+fn shell_command(cmd: &str) -> Command {
+    let mut command = Command::new(shell());
+    command.arg("-c").arg(cmd);
+    command
+}
+
+/// Variables every hook gets, besides `KV_*` and `hooks.env-allow`: enough to find programs,
+/// the user's files and kv store, and the desktop session.
+const HOOK_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_*",
+    "TERM",
+    "TZ",
+    "TMPDIR",
+    "XDG_*",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "KV_*",
+];
+
+/// Build the process running `hook`'s command. Hooks run stored commands nobody
+/// reviews at run time, so instead of the caller's whole environment (and any secrets in
+/// it) they only get `HOOK_ENV` plus the `hooks.env-allow` setting. Sandboxed hooks fail
+/// here if the sandbox is not available, rather than running unconfined, and hooks with a
+/// `run_as` user if switching to it would need a password.
+fn hook_command(cmd: &str, hook: &Hook) -> Result<Command, String> {
+    hook_process(vec![shell().into(), "-c".into(), cmd.into()], hook)
+}
+
+/// The process running `argv` for `hook`: wrapped in its sandbox or user switch, with only
+/// the allowed environment.
+fn hook_process(mut argv: Vec<std::ffi::OsString>, hook: &Hook) -> Result<Command, String> {
+    if hook.sandbox {
+        argv = sandbox::wrap(argv)?;
+    }
+    if let Some(user) = &hook.run_as {
+        argv = elevate::wrap(user, hook.elevate_with.as_deref(), argv)?;
+    }
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    hook_env(&mut command);
+    command.env("KV_HOOK", &hook.name);
+    Ok(command)
+}
+
+/// Give `command` only the environment a hook gets: `HOOK_ENV` and `hooks.env-allow`.
+fn hook_env(command: &mut Command) {
+    let allow = config::get().get_list("hooks.env-allow");
+    command.env_clear();
+    command.envs(env::vars_os().filter(|(name, _)| {
+        let name = name.to_string_lossy();
+        HOOK_ENV
+            .iter()
+            .chain(allow.iter())
+            .any(|pattern| glob::matches(pattern, &name))
+    }));
+}
+
+/// Run a hook's command, see `wait_hook`.
+fn run_command(cmd_name: &str, mut command: Command) -> Result<(), String> {
+    let child = command
+        .spawn()
+        .map_err(|e| format!("cannot run '{}', error {}", cmd_name, e))?;
+    wait_hook(child)
+}
+
+/// The exit status of the first hook that failed in this kv call, 0 if none did.
+static HOOK_STATUS: AtomicI32 = AtomicI32::new(0);
+
+fn hook_status_failed(code: i32) {
+    let _ = HOOK_STATUS.compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+/// Wait for a hook's process, so its output doesn't interleave with kv's, unless hooks are
+/// detached (`kv --detach`). A nonzero exit is an error, and kv exits with it too.
+fn wait_hook(mut child: std::process::Child) -> Result<(), String> {
+    if options::get().detach_hooks() {
+        return Ok(());
+    }
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if status.success() {
+        return Ok(());
+    }
+    hook_status_failed(status.code().unwrap_or(1));
+    Err(format!("it exited with {}", status))
+}
+
+/// Exit with the status of the first hook that failed, if one did.
+fn exit_for_hooks() {
+    match HOOK_STATUS.load(Ordering::Relaxed) {
+        0 => {}
+        code => std::process::exit(code),
+    }
+}
+
+/// Run a stored command in the foreground, record it in the history, and exit with its
+/// status if it failed. With `capture`, its stdout goes into that key instead of the
+/// terminal, see `capture_output`.
+fn run_command_recorded(cmd_name: &str, cmd: &str, lang: &str, capture: Option<&str>) {
+    let start = std::time::Instant::now();
+    let stdout = || if capture.is_some() { Stdio::piped() } else { Stdio::inherit() };
+    let wait = |child: Result<std::process::Child, String>| -> Result<_, String> {
+        let output = child?.wait_with_output().map_err(|e| e.to_string())?;
+        Ok((output.status.code(), output.stdout))
+    };
+    let output = match lang {
+        "lua" => wait(script::lua_argv(&[]).and_then(|argv| {
+            let mut command = Command::new(&argv[0]);
+            command.args(&argv[1..]).env("KV_CMD", cmd_name).stdout(stdout());
+            script::spawn_with_source(command, &script::lua_source(cmd)).map_err(|e| e.to_string())
+        })),
+        "rhai" => Ok(run_rhai_cmd(cmd_name, cmd, capture.is_some())),
+        _ => wait(shell_command(cmd).env("KV_CMD", cmd_name).stdout(stdout()).spawn().map_err(|e| e.to_string())),
+    };
+    let (code, stdout) = match output {
+        Ok(output) => output,
+        Err(e) => {
+            history::record(cmd_name, cmd, lang, None, start.elapsed());
+            let err_msg = format!(
+                "Error! Failed to run '{}' with error:\n {:?}",
+                cmd_name,
+                e
+            );
+            print_err(&err_msg[..]);
+        }
+    };
+    history::record(cmd_name, cmd, lang, code, start.elapsed());
+    if let Some(key) = capture {
+        capture_output(cmd_name, key, &stdout, code.unwrap_or(1));
+    }
+    if code != Some(0) {
+        usage::finish();
+        std::process::exit(code.unwrap_or(1));
+    }
+}
+
+/// Run a rhai cmd in kv itself, giving its exit code and, with `capture`, what it printed.
+/// A script that throws fails like a command exiting with 1.
+fn run_rhai_cmd(cmd_name: &str, cmd: &str, capture: bool) -> (Option<i32>, Vec<u8>) {
+    match script::run_rhai(cmd, None, None, None) {
+        Ok((printed, _)) if capture => (Some(0), printed.into_bytes()),
+        Ok((printed, _)) => {
+            print!("{}", printed);
+            (Some(0), vec![])
+        }
+        Err(e) => {
+            eprintln!("Error! {} failed, {}", cmd_name, e);
+            (Some(1), vec![])
+        }
+    }
+}
+
+/// Store what cmd `cmd_name` printed (without the trailing newline) in `key`, and its exit
+/// code in `<key>.status`, firing their set hooks.
+fn capture_output(cmd_name: &str, key: &str, stdout: &[u8], code: i32) {
+    let value = String::from_utf8_lossy(stdout);
+    let value = value.trim_end_matches(['\n', '\r']);
+    let status_key = format!("{}.status", key);
+    let code = code.to_string();
+    let source = format!("cmd:{}", cmd_name);
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        let old = [key, &status_key[..]].map(|k| get_key(k, &kvstore.kvs));
+        set_key(key, value, &mut kvstore.kvs);
+        set_key(&status_key, &code, &mut kvstore.kvs);
+        touch_meta(key, &source, &mut kvstore.meta);
+        touch_meta(&status_key, &source, &mut kvstore.meta);
+        write_file(&kvstore);
+        for (k, old, new) in [(key, &old[0], value), (&status_key[..], &old[1], &code[..])] {
+            index::update(&kvstore, k, old.as_deref(), Some(new));
+        }
+    });
+    for (k, new) in [(key, value), (&status_key[..], &code[..])] {
+        on_change(k, OpType::Set, Some(new));
+    }
+}
+
+/// Key `key` was just set or deleted: log the change and run its hooks.
+fn on_change(key: &str, op: OpType, value: Option<&str>) {
+    try_on_change(key, op, value).unwrap_or_else(|e| fail(e));
+}
+
+/// `on_change`, returning the error if the store, config or event log can't be read or
+/// written. A failing hook is reported, not returned.
+fn try_on_change(key: &str, op: OpType, value: Option<&str>) -> Result<(), Error> {
+    if events::enabled()? {
+        // Like listings and grep, the event log leaves secret values out.
+        let secret = value.is_some() && is_secret(key, &try_get_main_store()?.meta);
+        let value = value.filter(|_| !secret);
+        events::try_record("key", &op, key, value)?;
+    }
+    try_run_hooks(key, &op)?;
+    computed::try_dependency_changed(key)
+}
+
+fn run_hooks(key_name: &str, current_op: &OpType) {
+    try_run_hooks(key_name, current_op).unwrap_or_else(|e| fail(e));
+}
+
+fn try_run_hooks(key_name: &str, current_op: &OpType) -> Result<(), Error> {
+    let kvstore: KVStore = try_get_main_store()?;
+    if kvstore.hooks_for(key_name, current_op).is_empty() {
+        return Ok(());
+    }
+    let value = match current_op {
+        OpType::Del => None,
+        _ if is_secret(key_name, &kvstore.meta) => None,
+        _ => try_get_value_in(key_name, &kvstore)?,
+    };
+    fire_hooks(&kvstore, key_name, current_op, value.as_deref());
+    Ok(())
+}
+
+/// Run the hooks on `current_op` for `key_name` in order, passing them `value`.
+fn fire_hooks(kvstore: &KVStore, key_name: &str, current_op: &OpType, value: Option<&str>) {
+    for hook in kvstore.hooks_for(key_name, current_op) {
+        let cmd = match get_key(&hook.cmd_name[..], &kvstore.cmds) {
+            Some(cmd) => cmd,
+            None => {
+                hook_failed(&hook.name);
+                println!("Error! Bad hook! Hook {:?} has no cmd!", hook.name);
+                continue;
+            }
+        };
+        let event = action::HookEvent {
+            hook,
+            cmd: &cmd,
+            key: key_name,
+            op: current_op,
+            value,
+        };
+        // A shell hook runs its cmd in whatever language the cmd is written in.
+        let action_name = match hook.action() {
+            "shell" => kvstore.cmd_lang(&hook.cmd_name),
+            other => other,
+        };
+        let res = match action::get(action_name) {
+            Some(action) => action.run(&event),
+            None => Err(format!("there is no {} action", action_name)),
+        };
+        if let Err(e) = res {
+            hook_failed(&hook.name);
+            println!("Error! Cannot run hook {:?}, {}", hook.name, e)
+        }
+    }
+}
+
+/// Count a hook failure for `kv report`, unless hooks are only being recorded or simulated,
+/// and make kv exit nonzero.
+fn hook_failed(name: &str) {
+    hook_status_failed(1);
+    if !record::is_active() {
+        usage::hook_error(name);
+    }
+}
+
+/// Get the store as KVStore
+fn get_store() -> KVStore {
+    try_get_store().unwrap_or_else(|e| fail(e))
+}
+
+fn try_get_store() -> Result<KVStore, Error> {
+    let mut kvstore = try_get_main_store()?;
+    if let Some(shards) = kvstore.shards {
+        shard::load_all(&mut kvstore, shards)?;
+    }
+    #[cfg(feature = "sqlite")]
+    if options::get().backend() == Backend::Sqlite && !stateless::is_active() {
+        sqlite::load_kvs(&mut kvstore, &try_file_location()?)?;
+    }
+    drop_expired(&mut kvstore);
+    machine::apply(&mut kvstore)?;
+    Ok(kvstore)
+}
+
+/// Forget keys whose ttl ran out. Whatever writes the store next leaves them out of the file.
+fn drop_expired(kvstore: &mut KVStore) {
+    let expired: Vec<String> = kvstore
+        .meta
+        .iter()
+        .filter(|(_, meta)| meta.expired())
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in expired {
+        kvstore.kvs.remove(&key);
+        kvstore.meta.remove(&key);
+    }
+}
+
+/// Get the store file without loading any shards. Enough for cmds and hooks.
+fn get_main_store() -> KVStore {
+    try_get_main_store().unwrap_or_else(|e| fail(e))
+}
+
+fn try_get_main_store() -> Result<KVStore, Error> {
+    if let Some(bytes) = stateless::store_bytes() {
+        return Ok(serde_json::from_slice(bytes).unwrap());
+    }
+    match options::get().backend() {
+        Backend::Json => {}
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => return sqlite::load_main(&try_file_location()?),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => {
+            return Err(Error::new(format!("Cannot open an SQLite store, {}", features::missing("sqlite"))))
+        }
+    }
+    let options = options::get();
+    if let Some(fs) = options.fs() {
+        let path = try_file_location()?;
+        let bytes = fs.read(&path).map_err(|e| Error::new(format!("Cannot read {}, error {}", path.display(), e)))?;
+        return Ok(bytes.and_then(|bytes| serde_json::from_slice(&bytes).ok()).unwrap_or_default());
+    }
+    let kvstore = with_file_bytes(&try_get_file()?, &try_file_location()?, |bytes| serde_json::from_slice(bytes))?;
+    Ok(kvstore.unwrap_or_default())
+}
+
+/// Call `f` with the contents of `file`. With `read.mmap` set the file is memory-mapped
+/// rather than copied into a buffer first.
+fn with_file_bytes<R>(file: &std::fs::File, path: &Path, f: impl FnOnce(&[u8]) -> R) -> Result<R, Error> {
+    #[cfg(feature = "mmap")]
+    if config::try_get()?.get_bool("read.mmap") {
+        // Safety: the map only lives while `f` parses it. If another process truncates the
+        // file in the meantime reads will fault, which is why mapping is opt-in.
+        if let Ok(map) = unsafe { memmap2::Mmap::map(file) } {
+            return Ok(f(&map));
+        }
+    }
+    let mut buf = vec![];
+    retry::io("read", path, || {
+        buf.clear();
+        (&*file).rewind()?;
+        (&*file).read_to_end(&mut buf)
+    })?;
+    Ok(f(&buf))
+}
+
+/// Save keys an import changed or removed, recording `source` as where they came from, and
+/// fire their hooks. Only those keys are copied from `kvstore` into the store as it is on
+/// disk now, so keys set since it was read (an import may prompt for a while) are kept.
+fn save_imported(kvstore: &mut KVStore, changed: &[String], removed: &[String], source: &str) {
+    for key in changed {
+        touch_meta(key, source, &mut kvstore.meta);
+    }
+    *kvstore = with_store_lock(|| {
+        let mut current = get_store();
+        for key in removed {
+            current.kvs.remove(key);
+            current.meta.remove(key);
+        }
+        for key in changed {
+            if let Some(value) = kvstore.kvs.get(key) {
+                current.kvs.insert(key.clone(), value.clone());
+            }
+            if let Some(meta) = kvstore.meta.get(key) {
+                current.meta.insert(key.clone(), meta.clone());
+            }
+        }
+        write_file(&current);
+        index::refresh(&current);
+        current
+    });
+    for key in removed {
+        on_change(key, OpType::Del, None);
+    }
+    for key in changed {
+        on_change(key, OpType::Set, kvstore.kvs.get(key).map(|v| &v[..]));
+    }
+}
+
+/// Get a single key. For a sharded store only the shard holding the key is parsed, and an
+/// SQLite store only reads the key's row.
+fn get_value(key: &str) -> Option<String> {
+    get_value_in(key, &get_main_store())
+}
+
+/// `get_value` with the store file already loaded by `get_main_store`.
+fn get_value_in(key: &str, kvstore: &KVStore) -> Option<String> {
+    try_get_value_in(key, kvstore).unwrap_or_else(|e| fail(e))
+}
+
+/// `get_value_in`, returning the error if an override file, shard or database can't be read.
+fn try_get_value_in(key: &str, kvstore: &KVStore) -> Result<Option<String>, Error> {
+    if let Some(val) = machine::try_load()?.remove(key) {
+        return Ok(Some(val));
+    }
+    #[cfg(feature = "sqlite")]
+    if options::get().backend() == Backend::Sqlite && !stateless::is_active() {
+        let found = sqlite::get(key, &try_file_location()?)?;
+        return Ok(found.filter(|(_, meta)| !meta.as_ref().is_some_and(|m| m.expired())).map(|(value, _)| value));
+    }
+    Ok(match kvstore.shards {
+        Some(shards) => shard::try_get(key, shards)?,
+        None if kvstore.meta.get(key).is_some_and(|m| m.expired()) => None,
+        None => get_key(key, &kvstore.kvs),
+    })
+}
+
+fn add_hook(new_hook: Hook) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if kvstore.hooks.iter().filter(|&x| x.name == new_hook.name).count() > 0 {
+            let err_msg = format!(
+                "Error! {} already exists. To delete it try\n kv cmd del-hook {}",
+                new_hook.name, new_hook.name
+            );
+            print_err(&err_msg[..]);
+        }
+        if new_hook.action() != "shell" && (new_hook.sandbox || new_hook.run_as.is_some()) {
+            print_err("Error! --sandbox and --run-as only apply to shell hooks!");
+        }
+
+        let summary = format!("{} {} {}", new_hook.cmd_name, new_hook.run_on, new_hook.key);
+        events::record("hook", &OpType::Set, &new_hook.name, Some(&summary));
+        kvstore.hooks.push(new_hook);
+        write_file(&kvstore)
+    });
+}
+
+
+fn rm_hook(name: &str) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        match kvstore.hooks.iter().position(|x| x.name == name) {
+            Some(pos) => {
+                kvstore.hooks.remove(pos);
+            }
+            None => {
+                let err_msg = format!("Error! Hook {} does not exist!", name);
+                print_err(&err_msg[..]);
+            }
+        }
+        write_file(&kvstore);
+        events::record("hook", &OpType::Del, name, None);
+    });
+}
+
+
+fn get_key(s: &str, map: &KV) -> Option<String> {
+    map.get(&s.to_owned()).cloned()
+}
+
+fn set_key(k: &str, v: &str, map: &mut KV) {
+    map.insert(k.to_owned(), v.to_owned());
+}
+
+fn del_key(k: &str, map: &mut KV) -> Option<String> {
+    map.remove(&k.to_owned())
+}
+
+/// A random 10 character id like `k3j9x0q2ab`.
+fn random_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    // RandomState is seeded randomly per process, which is all the randomness needed here.
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(time::now());
+    hasher.write_u32(std::process::id());
+    let mut n = hasher.finish();
+    (0..10)
+        .map(|_| {
+            let c = CHARS[(n % CHARS.len() as u64) as usize] as char;
+            n /= CHARS.len() as u64;
+            c
+        })
+        .collect()
+}
+
+/// A key starting with `prefix` that isn't in `map` yet, e.g. `job-k3j9x0q2ab`.
+fn unused_key(prefix: &str, map: &KV) -> String {
+    loop {
+        let key = format!("{}{}", prefix, random_id());
+        if !map.contains_key(&key) {
+            return key;
+        }
+    }
+}
+
+/// Where a write comes from, for `Meta::source`. `KV_SOURCE` wins, then the hook or cmd
+/// kv was started from (they export `KV_HOOK`/`KV_CMD`), then `default`.
+fn current_source(default: &str) -> String {
+    if let Ok(source) = env::var("KV_SOURCE") {
+        return source;
+    }
+    if let Ok(hook) = env::var("KV_HOOK") {
+        return format!("hook:{}", hook);
+    }
+    if let Ok(cmd) = env::var("KV_CMD") {
+        return format!("cmd:{}", cmd);
+    }
+    default.to_owned()
+}
+
+/// Record that key `k` was just written by `source` (see `current_source`).
+fn touch_meta(k: &str, source: &str, meta: &mut BTreeMap<String, Meta>) {
+    let now = time::now();
+    let source = current_source(source);
+    meta.entry(k.to_owned())
+        .and_modify(|m| {
+            m.modified = now;
+            m.source = Some(source.clone());
+            // A new value starts without a ttl, unless the writer sets one again.
+            m.expires = None;
+        })
+        .or_insert(Meta {
+            created: now,
+            modified: now,
+            source: Some(source),
+            secret: false,
+            expires: None,
+        });
+}
+
+fn is_secret(k: &str, meta: &BTreeMap<String, Meta>) -> bool {
+    meta.get(k).is_some_and(|m| m.secret)
+}
+
+/// `val`, or a placeholder if `k` is secret, for listings.
+fn shown_value<'a>(k: &str, val: &'a str, meta: &BTreeMap<String, Meta>) -> &'a str {
+    if is_secret(k, meta) {
+        "********"
+    } else {
+        val
+    }
+}
+
+fn print_res(s: Option<String>) {
+    match s {
+        Some(s) => println!("{}", s),
+        None => println!(),
+    }
+}
+
+fn print_aligned(v: Vec<String>) {
+    let mut t = TabWriter::new(vec![]);
+    write!(&mut t, "{}", v.join("\n")).unwrap();
+    t.flush().unwrap();
+    println!("{}", String::from_utf8(t.into_inner().unwrap()).unwrap());
+}
+
+/// Set by the kv binary. Without it errors panic rather than exit, see `print_err`.
+static CLI: AtomicBool = AtomicBool::new(false);
+
+/// Print `s` and exit 1. Code embedding the store gets a panic with `s` instead, which it
+/// can catch, rather than losing its process.
+fn print_err(s: &str) -> ! {
+    if !CLI.load(Ordering::Relaxed) {
+        panic!("{}", s);
+    }
+    println!("{}", s);
+    usage::failure();
+    std::process::exit(1);
+}
+
+/// `print_err` for an `Error` from the library API.
+fn fail(e: Error) -> ! {
+    print_err(&format!("Error! {}", e))
+}
@@ -0,0 +1,97 @@
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{config, print_err, KVStore};
+
+/// One problem `kv lint` found.
+#[derive(Serialize)]
+pub struct Finding {
+    /// `key-pattern`, `plaintext-secret`, `value-size` or `hook-description`.
+    pub rule: &'static str,
+    /// The key or hook it's about.
+    pub subject: String,
+    pub message: String,
+}
+
+fn regex(setting: &str, pattern: &str) -> Regex {
+    match Regex::new(pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            let err_msg = format!("Error! {} has a bad regex {:?}, {}", setting, pattern, e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+fn encrypted(value: &str) -> bool {
+    crate::secret::is_encrypted(value)
+}
+
+#[cfg(not(feature = "crypto"))]
+fn encrypted(_value: &str) -> bool {
+    false
+}
+
+/// Check the store against the `lint.*` settings. Values are never part of a finding, so
+/// the output is safe to keep in CI logs.
+pub fn check(kvstore: &KVStore) -> Vec<Finding> {
+    let config = config::get();
+    let key_pattern = config.value("lint.key-pattern");
+    let key_re = (!key_pattern.is_empty()).then(|| regex("lint.key-pattern", key_pattern));
+    let secret_res: Vec<Regex> = config
+        .get_list("lint.secret-patterns")
+        .into_iter()
+        .map(|pattern| regex("lint.secret-patterns", pattern))
+        .collect();
+    let max_size = config.get_number("lint.max-value-size");
+
+    let mut findings = vec![];
+    for (key, value) in &kvstore.kvs {
+        if key_re.as_ref().is_some_and(|re| !re.is_match(key)) {
+            findings.push(Finding {
+                rule: "key-pattern",
+                subject: key.clone(),
+                message: format!("doesn't match lint.key-pattern {}", key_pattern),
+            });
+        }
+        if !encrypted(value) && secret_res.iter().any(|re| re.is_match(value)) {
+            findings.push(Finding {
+                rule: "plaintext-secret",
+                subject: key.clone(),
+                message: format!(
+                    "looks like a secret stored in plain text. To encrypt it try: kv secret set {}",
+                    key
+                ),
+            });
+        }
+        if max_size > 0 && value.len() as u64 > max_size {
+            findings.push(Finding {
+                rule: "value-size",
+                subject: key.clone(),
+                message: format!(
+                    "is {} bytes, over lint.max-value-size of {}",
+                    value.len(),
+                    max_size
+                ),
+            });
+        }
+    }
+    if config.get_bool("lint.hook-descriptions") {
+        for hook in kvstore
+            .hooks
+            .iter()
+            .filter(|hook| hook.description.is_none())
+        {
+            findings.push(Finding {
+                rule: "hook-description",
+                subject: hook.name.clone(),
+                message: format!(
+                    "has no description. To add one try: kv hooks describe {} <text>",
+                    hook.name
+                ),
+            });
+        }
+    }
+    findings
+}
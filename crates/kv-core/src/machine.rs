@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use crate::{fail, get_file_location, print_err, stateless, try_file_location, Error, KVStore, KV};
+
+/// This machine's name, as used in its override file name.
+pub fn hostname() -> String {
+    let mut name = String::new();
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // Safety: gethostname writes at most `buf.len()` bytes into `buf`.
+        if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+            let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            name = String::from_utf8_lossy(&buf[..end]).into_owned();
+        }
+    }
+    if name.is_empty() {
+        name = std::env::var("COMPUTERNAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| "localhost".to_owned());
+    }
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// `kv.<hostname>.json` next to the store file.
+pub fn location() -> PathBuf {
+    get_file_location().with_file_name(format!("kv.{}.json", hostname()))
+}
+
+/// This machine's overrides: a JSON object of keys to values.
+pub fn load() -> KV {
+    try_load().unwrap_or_else(|e| fail(e))
+}
+
+pub fn try_load() -> Result<KV, Error> {
+    if stateless::is_active() {
+        return Ok(KV::new());
+    }
+    let path = try_file_location()?.with_file_name(format!("kv.{}.json", hostname()));
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+            Error::new(format!("Cannot parse {}, error {}", path.to_string_lossy(), e))
+        }),
+        Err(_) => Ok(KV::new()),
+    }
+}
+
+pub fn save(kvs: &KV) {
+    stateless::ensure_writable();
+    let path = location();
+    let res = if kvs.is_empty() {
+        std::fs::remove_file(&path).or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+    } else {
+        let sorted: std::collections::BTreeMap<_, _> = kvs.iter().collect();
+        std::fs::write(&path, serde_json::to_string_pretty(&sorted).unwrap())
+    };
+    if let Err(e) = res {
+        let err_msg = format!(
+            "Error! Cannot write {}, error {}",
+            path.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// Lay this machine's overrides over the store's keys, remembering what they hid.
+pub fn apply(kvstore: &mut KVStore) -> Result<(), Error> {
+    for (key, val) in try_load()? {
+        let base = kvstore.kvs.insert(key.clone(), val.clone());
+        kvstore.machine.insert(key, (base, val));
+    }
+    Ok(())
+}
+
+/// The store as it should be written: keys still holding this machine's override get their
+/// shared value back, so overrides never leak into the shared store.
+pub fn without_overrides(kvstore: &KVStore) -> KVStore {
+    let mut base = kvstore.clone();
+    for (key, (shared, local)) in &kvstore.machine {
+        if base.kvs.get(key) == Some(local) {
+            match shared {
+                Some(shared) => base.kvs.insert(key.clone(), shared.clone()),
+                None => base.kvs.remove(key),
+            };
+        }
+    }
+    base.machine.clear();
+    base
+}
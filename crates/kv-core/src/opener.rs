@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// The platform's program for opening files and URLs with their default app.
+fn opener() -> Command {
+    if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // Not `cmd /C start`: cmd would parse the target, so a value with `&` in it could run
+        // commands. explorer.exe takes it as a single argument.
+        Command::new("explorer.exe")
+    } else {
+        Command::new("xdg-open")
+    }
+}
+
+fn is_url(value: &str) -> bool {
+    value
+        .split_once("://")
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)))
+        || value.starts_with("mailto:")
+}
+
+/// What `value` opens: a URL as is, a path with `~/` expanded. Paths must exist.
+fn target(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if is_url(value) {
+        return Ok(value.to_owned());
+    }
+    let path = match (value.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(value),
+    };
+    if !path.exists() {
+        return Err(format!("{} is neither a URL nor an existing path", path.to_string_lossy()));
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Open `value` as a URL or path with `with`, or the platform's opener (`xdg-open`, `open` or
+/// `explorer.exe`).
+pub fn open(value: &str, with: Option<&str>) -> Result<(), String> {
+    let target = target(value)?;
+    let mut command = match with {
+        Some(program) => Command::new(program),
+        None => opener(),
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .arg(&target)
+        .stdin(Stdio::null())
+        .status()
+        .map_err(|e| format!("cannot run {}, error {}", program, e))?;
+    // explorer.exe exits with 1 even when it opened the target.
+    let explorer = with.is_none() && cfg!(windows);
+    if !status.success() && !explorer {
+        return Err(format!("{} failed to open {}", program, target));
+    }
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::{Error, KVStore};
+
+/// How a store is opened. `KVStore::builder()` sets them for the store it builds, and the
+/// first builder also for the rest of the process; everything that finds the store file,
+/// runs hooks or writes reads them with `get`.
+#[derive(Clone)]
+pub struct Options {
+    path: Option<PathBuf>,
+    backend: Backend,
+    read_only: bool,
+    autolock: bool,
+    shell: Option<String>,
+    clock: Option<fn() -> u64>,
+    fs: Option<Arc<dyn Fs>>,
+    detach_hooks: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            path: None,
+            backend: Backend::Json,
+            read_only: false,
+            autolock: true,
+            shell: None,
+            clock: None,
+            fs: None,
+            detach_hooks: false,
+        }
+    }
+}
+
+impl PartialEq for Options {
+    fn eq(&self, other: &Options) -> bool {
+        // The same clock function may have several addresses, this may miss that.
+        self.path == other.path
+            && self.backend == other.backend
+            && self.read_only == other.read_only
+            && self.autolock == other.autolock
+            && self.shell == other.shell
+            && self.clock.map(|clock| clock as usize) == other.clock.map(|clock| clock as usize)
+            && match (&self.fs, &other.fs) {
+                (Some(fs), Some(other)) => Arc::ptr_eq(fs, other),
+                (fs, other) => fs.is_none() && other.is_none(),
+            }
+            && self.detach_hooks == other.detach_hooks
+    }
+}
+
+/// Where the store file is read from and written to, see `Builder::fs`.
+pub trait Fs: Send + Sync {
+    /// The contents of the file at `path`, None if there is none.
+    fn read(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>>;
+    /// Replace the file at `path` with `bytes`.
+    fn write(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()>;
+}
+
+/// What the store is kept in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Backend {
+    /// One JSON file, `kv.json`, rewritten on every change. Easy to read, edit and diff.
+    #[default]
+    Json,
+    /// An SQLite database, `kv.db`, where a change only writes the rows it touches and a
+    /// lookup only reads its key. Needs the `sqlite` feature.
+    Sqlite,
+}
+
+impl Backend {
+    /// The store file's name in the config dir.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Backend::Json => "kv.json",
+            Backend::Sqlite => "kv.db",
+        }
+    }
+}
+
+static OPTIONS: OnceLock<Arc<Options>> = OnceLock::new();
+
+thread_local! {
+    /// The options of the store whose method is running on this thread, see `with`.
+    static SCOPED: RefCell<Option<Arc<Options>>> = const { RefCell::new(None) };
+}
+
+/// The options in effect: those of the store being worked on, else the ones the builder
+/// applied, or the defaults.
+pub fn get() -> Arc<Options> {
+    SCOPED
+        .with(|scoped| scoped.borrow().clone())
+        .unwrap_or_else(|| OPTIONS.get_or_init(Default::default).clone())
+}
+
+/// Run `f` with `options` in effect on this thread, if given.
+pub(crate) fn with<R>(options: Option<&Arc<Options>>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Arc<Options>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED.with(|scoped| *scoped.borrow_mut() = self.0.take());
+        }
+    }
+    let Some(options) = options else { return f() };
+    let _restore = Restore(SCOPED.with(|scoped| scoped.replace(Some(options.clone()))));
+    f()
+}
+
+impl Options {
+    /// The store file, if set. Otherwise the context's store or the backend's file in the
+    /// config dir.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether read-modify-write cycles take `kv.lock`.
+    pub fn autolock(&self) -> bool {
+        self.autolock
+    }
+
+    /// The shell hooks and cmds run in.
+    pub fn shell(&self) -> &str {
+        self.shell.as_deref().unwrap_or("bash")
+    }
+
+    /// The clock set with `Builder::clock`, if any.
+    pub fn clock(&self) -> Option<fn() -> u64> {
+        self.clock
+    }
+
+    /// The filesystem set with `Builder::fs`, if any.
+    pub fn fs(&self) -> Option<&dyn Fs> {
+        self.fs.as_deref()
+    }
+
+    /// Whether hook commands are started without waiting for them.
+    pub fn detach_hooks(&self) -> bool {
+        self.detach_hooks
+    }
+}
+
+pub struct Builder {
+    options: Options,
+}
+
+impl KVStore {
+    /// Configure how the store is opened, e.g.
+    /// `KVStore::builder().path(p).read_only(true).build()`.
+    pub fn builder() -> Builder {
+        Builder {
+            options: Options::default(),
+        }
+    }
+}
+
+impl Builder {
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.options.path = Some(path.into());
+        self
+    }
+
+    /// Keep the store in a JSON file (the default) or an SQLite database. Opening an SQLite
+    /// store that doesn't exist yet imports the JSON store next to it, if there is one.
+    pub fn backend(mut self, backend: Backend) -> Builder {
+        self.options.backend = backend;
+        self
+    }
+
+    /// Refuse every write, to this store and kv's other files.
+    pub fn read_only(mut self, read_only: bool) -> Builder {
+        self.options.read_only = read_only;
+        self
+    }
+
+    /// Lock the store around read-modify-write cycles (the default). Turn it off only when
+    /// the caller already serializes access.
+    pub fn autolock(mut self, autolock: bool) -> Builder {
+        self.options.autolock = autolock;
+        self
+    }
+
+    /// The shell hooks and cmds run in. bash if not set.
+    pub fn shell(mut self, shell: impl Into<String>) -> Builder {
+        self.options.shell = Some(shell.into());
+        self
+    }
+
+    /// Where kv reads the time (seconds since the unix epoch) for metadata, leases and
+    /// expiry. The system clock if not set. Lets tests pin or step the time.
+    pub fn clock(mut self, clock: fn() -> u64) -> Builder {
+        self.options.clock = Some(clock);
+        self
+    }
+
+    /// Read and write the store file through `fs` instead of the disk, e.g. a map in memory
+    /// for tests. Only the JSON store file goes through it: shards, the lock and kv's other
+    /// files are still on disk.
+    pub fn fs(mut self, fs: Arc<dyn Fs>) -> Builder {
+        self.options.fs = Some(fs);
+        self
+    }
+
+    /// Start hook commands and move on, rather than wait for each and treat a nonzero exit
+    /// as a failure (the default).
+    pub fn detach_hooks(mut self, detach: bool) -> Builder {
+        self.options.detach_hooks = detach;
+        self
+    }
+
+    /// Use these options for the rest of the process. Fails if different options were
+    /// applied already.
+    pub fn apply(self) -> Result<(), Error> {
+        let options = Arc::new(self.options);
+        if **OPTIONS.get_or_init(|| options.clone()) != *options {
+            return Err(Error::new("Different store options were applied already"));
+        }
+        Ok(())
+    }
+
+    /// Load the store with these options, see `KVStore::open`. The store keeps them, so
+    /// stores built with different options can be used side by side. The first builder's
+    /// options are also applied for the rest of the process.
+    pub fn build(self) -> Result<KVStore, Error> {
+        let options = Arc::new(self.options);
+        OPTIONS.get_or_init(|| options.clone());
+        let mut kvstore = with(Some(&options), KVStore::open)?;
+        kvstore.options = Some(options);
+        Ok(kvstore)
+    }
+}
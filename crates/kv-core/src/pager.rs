@@ -0,0 +1,66 @@
+/// The pager: `$KV_PAGER`, then `$PAGER`, then `less`. None if set to "" or `cat`.
+#[cfg(unix)]
+fn pager_command() -> Option<String> {
+    let cmd = std::env::var("KV_PAGER")
+        .or_else(|_| std::env::var("PAGER"))
+        .unwrap_or_else(|_| "less".to_owned());
+    if cmd.trim().is_empty() || cmd.trim() == "cat" {
+        None
+    } else {
+        Some(cmd)
+    }
+}
+
+#[cfg(unix)]
+static PAGER: std::sync::Mutex<Option<std::process::Child>> = std::sync::Mutex::new(None);
+
+/// Send everything kv prints from now on through the pager, like git does, if stdout is a
+/// terminal. `less` gets `LESS=FRX` unless `$LESS` is set, so output that fits on the screen
+/// is printed as is. If the pager can't be started output goes to the terminal instead.
+pub fn start() {
+    #[cfg(unix)]
+    {
+        use std::io::IsTerminal;
+        use std::os::unix::io::AsRawFd;
+
+        if !std::io::stdout().is_terminal() {
+            return;
+        }
+        let Some(cmd) = pager_command() else {
+            return;
+        };
+        let mut command = crate::shell_command(&cmd);
+        command.stdin(std::process::Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        let Ok(mut child) = command.spawn() else {
+            return;
+        };
+        let stdin = child.stdin.take().unwrap();
+        // Safety: plain fd calls. Once the pager quits, the next write kills kv quietly with
+        // SIGPIPE rather than panicking on the broken pipe.
+        unsafe {
+            libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO);
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+        drop(stdin);
+        *PAGER.lock().unwrap() = Some(child);
+        // Safety: `wait` is an extern "C" fn taking nothing, as atexit expects.
+        unsafe { libc::atexit(wait) };
+    }
+}
+
+/// Registered with atexit, so the pager gets to show everything (and keeps the terminal)
+/// however kv exits: close stdout so it sees the end, and wait for the user to quit it.
+#[cfg(unix)]
+extern "C" fn wait() {
+    use std::io::Write;
+
+    let _ = std::io::stdout().flush();
+    // Safety: nothing writes to stdout after this.
+    unsafe { libc::close(libc::STDOUT_FILENO) };
+    if let Some(mut child) = PAGER.lock().ok().and_then(|mut pager| pager.take()) {
+        let _ = child.wait();
+    }
+}
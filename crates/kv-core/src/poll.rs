@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{
+    get_key, get_main_store, get_store, index, on_change, print_err, set_key, shell_command, time,
+    touch_meta, with_store_lock, write_file, KVStore, OpType,
+};
+
+/// A key refreshed from the output of `cmd` every `every` (a duration like `5m`) by
+/// `kv poll run`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Poll {
+    pub every: String,
+    pub cmd: String,
+}
+
+impl Poll {
+    fn interval(&self) -> Duration {
+        time::parse_duration(&self.every).unwrap_or_default()
+    }
+}
+
+pub fn add(key: &str, poll: Poll) {
+    match time::parse_duration(&poll.every) {
+        Err(e) => print_err(&format!("Error! {}", e)),
+        Ok(d) if d.is_zero() => print_err("Error! --every must be longer than 0s"),
+        Ok(_) => {}
+    }
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        kvstore.polls.insert(key.to_owned(), poll);
+        write_file(&kvstore);
+    });
+}
+
+pub fn del(key: &str) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if kvstore.polls.remove(key).is_none() {
+            let err_msg = format!("Error! {} is not polled", key);
+            print_err(&err_msg[..]);
+        }
+        write_file(&kvstore);
+    });
+}
+
+/// Lines for `kv poll list`.
+pub fn list(kvstore: &KVStore) -> Vec<String> {
+    let mut lines = vec!["Key\t--\tEvery\t--\tCmd".to_owned()];
+    lines.extend(
+        kvstore
+            .polls
+            .iter()
+            .map(|(key, poll)| format!("{}\t--\t{}\t--\t{}", key, poll.every, poll.cmd)),
+    );
+    lines
+}
+
+/// Run `poll.cmd` and store its output (without the trailing newline) in `key`. Hooks
+/// only fire if the value changed. Returns whether it did.
+fn refresh(key: &str, poll: &Poll) -> Result<bool, String> {
+    let output = shell_command(&poll.cmd)
+        .output()
+        .map_err(|e| format!("cannot run {:?}, error {}", poll.cmd, e))?;
+    if !output.status.success() {
+        return Err(format!("{:?} failed with {}", poll.cmd, output.status));
+    }
+    let value = String::from_utf8_lossy(&output.stdout);
+    let value = value.trim_end_matches(['\n', '\r']);
+    let old = with_store_lock(|| {
+        let mut kvstore = get_store();
+        let old = get_key(key, &kvstore.kvs);
+        if old.as_deref() != Some(value) {
+            set_key(key, value, &mut kvstore.kvs);
+            touch_meta(key, "poll", &mut kvstore.meta);
+            write_file(&kvstore);
+            index::update(&kvstore, key, old.as_deref(), Some(value));
+        }
+        old
+    });
+    if old.as_deref() == Some(value) {
+        return Ok(false);
+    }
+    on_change(key, OpType::Set, Some(value));
+    Ok(true)
+}
+
+/// Refresh polled keys as they come due, forever, or just once each with `once`. Polls
+/// are re-read from the store every second, so `kv poll add` and `del` take effect
+/// without a restart.
+pub fn run(once: bool) {
+    let mut last_run: HashMap<String, Instant> = HashMap::new();
+    loop {
+        for (key, poll) in get_main_store().polls {
+            let due = last_run
+                .get(&key)
+                .is_none_or(|last| last.elapsed() >= poll.interval());
+            if !due {
+                continue;
+            }
+            last_run.insert(key.clone(), Instant::now());
+            match refresh(&key, &poll) {
+                Ok(true) => println!("{}\t{} changed", time::format_timestamp(time::now()), key),
+                Ok(false) => {}
+                Err(e) => eprintln!("Warning! Cannot poll {}, {}", key, e),
+            }
+        }
+        if once {
+            return;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
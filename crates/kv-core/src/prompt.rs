@@ -0,0 +1,34 @@
+use std::io::{self, BufRead, Write};
+
+/// Print `msg` on stderr and read a line from stdin, without the newline. `None` at the end
+/// of input.
+pub fn line(msg: &str) -> Option<String> {
+    eprint!("{}", msg);
+    io::stderr().flush().unwrap();
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+        return None;
+    }
+    Some(line.trim_end_matches(['\n', '\r']).to_owned())
+}
+
+/// Like `line`, but when stdin is a terminal what is typed isn't echoed.
+pub fn hidden(msg: &str) -> Option<String> {
+    #[cfg(unix)]
+    {
+        let fd = libc::STDIN_FILENO;
+        let mut term = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // Safety: tcgetattr fills `term` when it returns 0, and only then is it read.
+        if unsafe { libc::isatty(fd) == 1 && libc::tcgetattr(fd, term.as_mut_ptr()) == 0 } {
+            let original = unsafe { term.assume_init() };
+            let mut silent = original;
+            silent.c_lflag &= !libc::ECHO;
+            silent.c_lflag |= libc::ECHONL;
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) };
+            let line = line(msg);
+            unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+            return line;
+        }
+    }
+    line(msg)
+}
@@ -0,0 +1,23 @@
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+
+use crate::print_err;
+
+/// Print `value` as a QR code on the terminal, two rows of modules per line of half blocks.
+/// Light modules are the drawn ones, so the code reads dark on light in the usual dark
+/// terminal, as phone cameras expect.
+pub fn print(value: &str) {
+    let code = match QrCode::new(value.as_bytes()) {
+        Ok(code) => code,
+        Err(e) => {
+            let err_msg = format!("Error! Cannot show the value as a QR code, {}", e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{}", image);
+}
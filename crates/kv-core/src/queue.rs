@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use crate::{get_key, get_store, index, print_err, set_key, touch_meta, usage, with_store_lock, write_file, KVStore};
+
+/// The items of queue `key`. A queue is a key whose value is a JSON array of strings; a
+/// missing key is an empty queue.
+fn items(kvstore: &KVStore, key: &str) -> Vec<String> {
+    match kvstore.get_json(key) {
+        None => vec![],
+        Some(Ok(items)) => items,
+        Some(Err(_)) => {
+            let err_msg = format!(
+                "Error! {} is not a queue (its value is not a JSON array of strings)",
+                key
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+fn store_items(kvstore: &mut KVStore, key: &str, items: &[String]) -> (Option<String>, String) {
+    let old = get_key(key, &kvstore.kvs);
+    let new = serde_json::to_string(items).unwrap();
+    set_key(key, &new, &mut kvstore.kvs);
+    touch_meta(key, "queue", &mut kvstore.meta);
+    (old, new)
+}
+
+/// Append `vals` to queue `key`. Returns the key's new value.
+pub fn push(key: &str, vals: &[&str]) -> String {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        let mut queue = items(&kvstore, key);
+        queue.extend(vals.iter().map(|v| v.to_string()));
+        let (old, new) = store_items(&mut kvstore, key, &queue);
+        write_file(&kvstore);
+        index::update(&kvstore, key, old.as_deref(), Some(&new));
+        new
+    })
+}
+
+/// Take the first item off queue `key`, polling for up to `wait` if it is empty. Returns
+/// the item and the key's new value, or `None` if the queue stayed empty.
+pub fn pop(key: &str, wait: Duration) -> Option<(String, String)> {
+    // A wait too long to add to now is as good as forever.
+    let deadline = Instant::now().checked_add(wait);
+    loop {
+        let popped = with_store_lock(|| {
+            let mut kvstore = get_store();
+            let mut queue = items(&kvstore, key);
+            if queue.is_empty() {
+                return None;
+            }
+            let item = queue.remove(0);
+            let (old, new) = store_items(&mut kvstore, key, &queue);
+            write_file(&kvstore);
+            index::update(&kvstore, key, old.as_deref(), Some(&new));
+            Some((item, new))
+        });
+        match popped {
+            Some((item, new)) => return Some((item, new)),
+            None if deadline.is_none_or(|d| Instant::now() < d) => std::thread::sleep(Duration::from_millis(100)),
+            None => return None,
+        }
+    }
+}
+
+pub fn len(key: &str) -> usize {
+    items(&get_store(), key).len()
+}
+
+/// Exit with status 1 after reporting `msg` on stderr, keeping stdout clean for scripts
+/// reading popped items.
+pub fn exit_empty(key: &str) -> ! {
+    eprintln!("Queue {} is empty", key);
+    usage::failure();
+    std::process::exit(1);
+}
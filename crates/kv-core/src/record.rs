@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+
+use crate::{print_err, time, Hook, OpType};
+
+/// Where `--record-hooks` appends hook invocations, if given.
+static FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Invocations kept in memory instead, while `kv simulate` runs.
+static COLLECTED: Mutex<Option<Vec<Collected>>> = Mutex::new(None);
+
+/// A hook invocation kept by `collect`.
+pub struct Collected {
+    pub hook: String,
+    pub argv: Vec<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+/// One line of the record file: a hook kv would have run.
+#[derive(Serialize)]
+struct Invocation<'a> {
+    ts: u64,
+    hook: &'a str,
+    cmd_name: &'a str,
+    key: &'a str,
+    op: String,
+    /// The full command line, including any sandbox or sudo wrapper. For webhooks, the
+    /// method and URL.
+    argv: Vec<String>,
+    /// The hook's whole environment.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+}
+
+/// Record hooks to `path` instead of running them, for the rest of this kv call.
+pub fn start(path: &str) {
+    let _ = FILE.set(PathBuf::from(path));
+}
+
+/// Keep hooks in memory instead of running them, until `take`.
+pub fn collect() {
+    *COLLECTED.lock().unwrap() = Some(vec![]);
+}
+
+/// The hooks kept since `collect`, in the order they fired. Hooks run again after this.
+pub fn take() -> Vec<Collected> {
+    COLLECTED.lock().unwrap().take().unwrap_or_default()
+}
+
+pub fn is_active() -> bool {
+    FILE.get().is_some() || COLLECTED.lock().unwrap().is_some()
+}
+
+/// The command line and environment `command` would run with.
+pub fn command_parts(command: &Command) -> (Vec<String>, BTreeMap<String, String>) {
+    let argv = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let env = command
+        .get_envs()
+        .filter_map(|(name, val)| {
+            Some((
+                name.to_string_lossy().into_owned(),
+                val?.to_string_lossy().into_owned(),
+            ))
+        })
+        .collect();
+    (argv, env)
+}
+
+/// Append the invocation of `hook`, as it would have run, to the record file.
+pub fn hook(hook: &Hook, key: &str, op: &OpType, argv: Vec<String>, env: BTreeMap<String, String>) {
+    if let Some(collected) = COLLECTED.lock().unwrap().as_mut() {
+        collected.push(Collected {
+            hook: hook.name.clone(),
+            argv,
+            env,
+        });
+        return;
+    }
+    let Some(path) = FILE.get() else {
+        return;
+    };
+    let invocation = Invocation {
+        ts: time::now(),
+        hook: &hook.name,
+        cmd_name: &hook.cmd_name,
+        key,
+        op: op.to_string(),
+        argv,
+        env,
+    };
+    let res = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&invocation).unwrap()));
+    if let Err(e) = res {
+        let err_msg = format!(
+            "Error! Cannot record hooks to {}, error {}",
+            path.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
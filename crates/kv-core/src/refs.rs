@@ -0,0 +1,77 @@
+use std::process::{Command, Stdio};
+
+use crate::print_err;
+
+/// Fields `bw get <field> <item>` returns directly. Anything else is looked up in the
+/// item's custom fields.
+const BW_FIELDS: &[&str] = &["password", "username", "totp", "notes", "uri"];
+
+/// Run a password manager CLI and return what it printed, without the trailing newline.
+fn run(program: &str, args: &[&str], reference: &str) -> String {
+    let output = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            let err_msg = format!(
+                "Error! Cannot run {} to resolve {}, error {}",
+                program, reference, e
+            );
+            print_err(&err_msg[..]);
+        }
+    };
+    if !output.status.success() {
+        let err_msg = format!("Error! {} could not resolve {}", program, reference);
+        print_err(&err_msg[..]);
+    }
+    let mut value = String::from_utf8_lossy(&output.stdout).into_owned();
+    if value.ends_with('\n') {
+        value.pop();
+    }
+    value
+}
+
+/// `bw://<item>/<field>`, where `<item>` is a name or id.
+fn bitwarden(reference: &str, path: &str) -> String {
+    let Some((item, field)) = path.rsplit_once('/') else {
+        let err_msg = format!("Error! {} must look like bw://<item>/<field>", reference);
+        print_err(&err_msg[..]);
+    };
+    if BW_FIELDS.contains(&field) {
+        return run("bw", &["get", field, item], reference);
+    }
+    let json = run("bw", &["get", "item", item], reference);
+    let item: serde_json::Value = serde_json::from_str(&json).unwrap_or_default();
+    let found = item["fields"].as_array().and_then(|fields| {
+        fields
+            .iter()
+            .find(|f| f["name"] == field)
+            .and_then(|f| f["value"].as_str())
+    });
+    match found {
+        Some(value) => value.to_owned(),
+        None => {
+            let err_msg = format!(
+                "Error! Bitwarden item has no field {} ({})",
+                field, reference
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// If `value` is a reference to a password manager entry (`op://vault/item/field` for
+/// 1Password, `bw://item/field` for Bitwarden), look it up with that manager's CLI.
+/// Anything else is returned unchanged.
+pub fn resolve(value: String) -> String {
+    if value.starts_with("op://") {
+        run("op", &["read", "--no-newline", &value], &value)
+    } else if let Some(path) = value.strip_prefix("bw://") {
+        bitwarden(&value, path)
+    } else {
+        value
+    }
+}
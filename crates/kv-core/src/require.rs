@@ -0,0 +1,109 @@
+use crate::{print_err, prompt, KVStore};
+
+/// What a required key's value must look like.
+#[derive(Clone, Copy)]
+enum Kind {
+    Any,
+    NonEmpty,
+    Int,
+    Number,
+    Bool,
+    Port,
+    Url,
+    Json,
+}
+
+const KINDS: &[(&str, Kind)] = &[
+    ("nonempty", Kind::NonEmpty),
+    ("int", Kind::Int),
+    ("number", Kind::Number),
+    ("bool", Kind::Bool),
+    ("port", Kind::Port),
+    ("url", Kind::Url),
+    ("json", Kind::Json),
+];
+
+/// `key` or `key:<type>`. A suffix that isn't a known type is part of the key.
+fn parse(spec: &str) -> (&str, Kind) {
+    if let Some((key, kind)) = spec.rsplit_once(':') {
+        if let Some((_, kind)) = KINDS.iter().find(|(name, _)| *name == kind) {
+            return (key, *kind);
+        }
+    }
+    (spec, Kind::Any)
+}
+
+fn check(kind: Kind, val: &str) -> Result<(), &'static str> {
+    let ok = match kind {
+        Kind::Any => true,
+        Kind::NonEmpty => !val.trim().is_empty(),
+        Kind::Int => val.parse::<i64>().is_ok(),
+        Kind::Number => val.parse::<f64>().is_ok_and(|n| n.is_finite()),
+        Kind::Bool => matches!(val, "true" | "false"),
+        Kind::Port => val.parse::<u16>().is_ok_and(|p| p > 0),
+        Kind::Url => val
+            .split_once("://")
+            .is_some_and(|(scheme, rest)| !scheme.is_empty() && !rest.is_empty()),
+        Kind::Json => serde_json::from_str::<serde_json::Value>(val).is_ok(),
+    };
+    if ok {
+        return Ok(());
+    }
+    Err(match kind {
+        Kind::Any => unreachable!(),
+        Kind::NonEmpty => "must not be empty",
+        Kind::Int => "must be an integer",
+        Kind::Number => "must be a number",
+        Kind::Bool => "must be true or false",
+        Kind::Port => "must be a port number",
+        Kind::Url => "must be a URL like https://host",
+        Kind::Json => "must be valid JSON",
+    })
+}
+
+/// Check that every key in `specs` exists and is valid. With `interactive`, ask for the
+/// value of each one that isn't, and return the keys that were filled in; otherwise fail
+/// listing them.
+pub fn require(kvstore: &mut KVStore, specs: &[&str], interactive: bool) -> Vec<String> {
+    let problems: Vec<(&str, Kind, &str)> = specs
+        .iter()
+        .map(|spec| parse(spec))
+        .filter_map(|(key, kind)| match kvstore.kvs.get(key) {
+            None => Some((key, kind, "missing")),
+            Some(val) => check(kind, val).err().map(|why| (key, kind, why)),
+        })
+        .collect();
+    if problems.is_empty() {
+        return vec![];
+    }
+    if !interactive {
+        let lines: Vec<String> = problems
+            .iter()
+            .map(|(key, _, why)| format!("{} ({})", key, why))
+            .collect();
+        let err_msg = format!(
+            "Error! Missing or invalid keys:\n {}\nTo fill them in try\n kv require --interactive {}",
+            lines.join("\n "),
+            specs.join(" ")
+        );
+        print_err(&err_msg[..]);
+    }
+
+    let mut filled = vec![];
+    for (key, kind, why) in problems {
+        eprintln!("{} ({})", key, why);
+        let val = loop {
+            let val = match prompt::line(&format!("{}: ", key)) {
+                Some(val) => val,
+                None => print_err("Error! No input. Nothing was changed."),
+            };
+            match check(kind, &val) {
+                Ok(()) => break val,
+                Err(why) => eprintln!("{} ({})", key, why),
+            }
+        };
+        kvstore.kvs.insert(key.to_owned(), val);
+        filled.push(key.to_owned());
+    }
+    filled
+}
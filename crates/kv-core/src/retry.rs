@@ -0,0 +1,56 @@
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{config, time, Error};
+
+const FIRST_WAIT: Duration = Duration::from_millis(50);
+const MAX_WAIT: Duration = Duration::from_secs(2);
+
+/// Run `op` on the file at `path` until it succeeds, retrying with exponential backoff
+/// (50ms, 100ms, 200ms, ... up to 2s apart) for `io.retry-for`. That rides out NFS
+/// hiccups and sync clients briefly holding the file. `what` says what failed, e.g.
+/// `read`, in the error if it never succeeds.
+pub fn io<T>(what: &str, path: &Path, mut op: impl FnMut() -> io::Result<T>) -> Result<T, Error> {
+    let config = config::try_get()?;
+    let retry_for = config.get_duration("io.retry-for");
+    let start = Instant::now();
+    let mut wait = FIRST_WAIT;
+    loop {
+        let e = match op() {
+            Ok(res) => return Ok(res),
+            Err(e) => e,
+        };
+        let left = retry_for.saturating_sub(start.elapsed());
+        if left.is_zero() {
+            let err_msg = if retry_for.is_zero() {
+                format!(
+                    "Cannot {} {}, error {}",
+                    what,
+                    path.to_string_lossy(),
+                    e
+                )
+            } else {
+                format!(
+                    "Cannot {} {}, error {}. Gave up after retrying for {}. To retry for longer try\n kv config set io.retry-for 30s",
+                    what,
+                    path.to_string_lossy(),
+                    e,
+                    time::format_duration(start.elapsed())
+                )
+            };
+            return Err(Error::new(err_msg));
+        }
+        if wait == FIRST_WAIT {
+            eprintln!(
+                "Warning! Cannot {} {}, error {}. Retrying for up to {}...",
+                what,
+                path.to_string_lossy(),
+                e,
+                config.value("io.retry-for")
+            );
+        }
+        std::thread::sleep(wait.min(left));
+        wait = (wait * 2).min(MAX_WAIT);
+    }
+}
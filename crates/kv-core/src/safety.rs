@@ -0,0 +1,38 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Patterns that make a stored command worth a second look, and why.
+const DANGEROUS: &[(&str, &str)] = &[
+    (
+        r"\brm\s+(-\w*r\w*f|-\w*f\w*r|(-\w+\s+)*--recursive\s+(-\w+\s+)*--force|(-\w+\s+)*--force\s+(-\w+\s+)*--recursive)",
+        "force-deletes recursively (rm -rf)",
+    ),
+    (
+        r"\b(curl|wget)\b[^|;&]*\|\s*(sudo\s+)?(ba|z|da|k)?sh\b",
+        "pipes a download straight into a shell (curl | sh)",
+    ),
+    (r"\bdd\b.*\bof=/dev/", "writes to a raw device (dd of=/dev/...)"),
+    (r">\s*/dev/(sd|nvme|hd|vd|mmcblk)", "overwrites a disk device"),
+    (r"\bmkfs(\.\w+)?\b", "formats a filesystem (mkfs)"),
+    (r"\bchmod\s+(-\w+\s+)*-R\s+(0?777|a\+rwx)", "makes files world-writable recursively"),
+    (r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:", "is a fork bomb"),
+];
+
+fn patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        DANGEROUS
+            .iter()
+            .map(|(re, why)| (Regex::new(re).unwrap(), *why))
+            .collect()
+    })
+}
+
+/// Reasons `cmd` looks dangerous. Empty if nothing was flagged.
+pub fn review(cmd: &str) -> Vec<&'static str> {
+    patterns()
+        .iter()
+        .filter(|(re, _)| re.is_match(cmd))
+        .map(|(_, why)| *why)
+        .collect()
+}
@@ -0,0 +1,40 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::{config, get_config_dir};
+
+/// Find `program` on `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|path| {
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(program))
+            .find(|p| p.is_file())
+    })
+}
+
+/// Wrap `argv` to run inside a bubblewrap sandbox: no network, no other namespaces shared
+/// with the host, the filesystem read-only and a private `/tmp`. Only the kv config dir (so
+/// the hook can still use kv) and the `hooks.sandbox-writable` paths can be written to.
+pub fn wrap(argv: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("sandboxed hooks are only supported on Linux".to_owned());
+    }
+    let bwrap = find_program("bwrap")
+        .ok_or_else(|| "sandboxed hooks need bubblewrap (bwrap) on PATH".to_owned())?;
+    let mut wrapped: Vec<OsString> = vec![bwrap.into()];
+    wrapped.extend(
+        "--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp --unshare-all --die-with-parent --new-session"
+            .split(' ')
+            .map(OsString::from),
+    );
+    let config_dir = get_config_dir();
+    let writable = config::get().get_list("hooks.sandbox-writable");
+    for path in std::iter::once(config_dir.as_path()).chain(writable.iter().map(Path::new)) {
+        if path.exists() {
+            wrapped.extend(["--bind".into(), path.into(), path.into()]);
+        }
+    }
+    wrapped.push("--".into());
+    wrapped.extend(argv);
+    Ok(wrapped)
+}
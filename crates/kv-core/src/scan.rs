@@ -0,0 +1,71 @@
+use std::ops::Bound;
+
+use crate::{KVStore, Meta};
+
+/// A key with its value and metadata, as `KVStore::scan` yields them.
+pub struct Entry<'a> {
+    pub key: &'a str,
+    pub value: &'a str,
+    pub meta: Option<&'a Meta>,
+}
+
+impl Entry<'_> {
+    /// Where to pick up after this entry, see `KVStore::scan_from`.
+    pub fn cursor(&self) -> Cursor {
+        Cursor(self.key.to_owned())
+    }
+}
+
+/// A position in a scan. Keys are kept sorted, so a cursor stays valid when the store changes
+/// in between: the scan continues with the first key after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor(String);
+
+impl Cursor {
+    /// The position right after `key`, whether or not it exists.
+    pub fn after(key: &str) -> Cursor {
+        Cursor(key.to_owned())
+    }
+
+    /// The last key seen.
+    pub fn key(&self) -> &str {
+        &self.0
+    }
+}
+
+impl KVStore {
+    /// The entry for `key`, if it exists.
+    pub fn entry(&self, key: &str) -> Option<Entry<'_>> {
+        let (key, value) = self.kvs.get_key_value(key)?;
+        Some(Entry {
+            key,
+            value,
+            meta: self.meta.get(key),
+        })
+    }
+
+    /// Keys starting with `prefix`, in order, without collecting them first.
+    pub fn scan<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = Entry<'a>> + 'a {
+        self.scan_from(prefix, None)
+    }
+
+    /// Like `scan`, but starting after `cursor`, e.g. to stream a large store in pages.
+    pub fn scan_from<'a>(
+        &'a self,
+        prefix: &'a str,
+        cursor: Option<&Cursor>,
+    ) -> impl Iterator<Item = Entry<'a>> + 'a {
+        let start = match cursor {
+            Some(Cursor(after)) if &after[..] >= prefix => Bound::Excluded(after.clone()),
+            _ => Bound::Included(prefix.to_owned()),
+        };
+        self.kvs
+            .range((start, Bound::Unbounded))
+            .take_while(move |(key, _)| key.starts_with(prefix))
+            .map(move |(key, value)| Entry {
+                key,
+                value,
+                meta: self.meta.get(key),
+            })
+    }
+}
@@ -0,0 +1,114 @@
+use std::ffi::OsString;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+use crate::sandbox;
+
+/// What cmds can be written in. Shell cmds are command lines; the others are scripts run by
+/// their interpreter, or by the embedded Rhai engine with the rhai feature.
+pub const LANGS: &[&str] = &["shell", "lua", "rhai"];
+
+/// Operations a Rhai script may take before it's stopped, so a runaway loop can't hang kv.
+#[cfg(feature = "rhai")]
+const RHAI_MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The command line running a Lua script read from stdin, with `args` as its `...`.
+pub fn lua_argv(args: &[&str]) -> Result<Vec<OsString>, String> {
+    if sandbox::find_program("lua").is_none() {
+        return Err("lua cmds need the lua interpreter".to_owned());
+    }
+    let mut argv: Vec<OsString> = vec!["lua".into(), "-".into()];
+    argv.extend(args.iter().map(OsString::from));
+    Ok(argv)
+}
+
+/// The script with its arguments named `key`, `op` and `value`. Kept on the script's first
+/// line so line numbers in errors still match.
+pub fn lua_source(script: &str) -> String {
+    format!("local key, op, value = ...; {}", script)
+}
+
+/// Start `command` with `source` on its stdin.
+pub fn spawn_with_source(mut command: Command, source: &str) -> std::io::Result<Child> {
+    command.stdin(Stdio::piped());
+    let mut child = command.spawn()?;
+    // Dropping stdin closes it, so the interpreter sees the end of the script.
+    child.stdin.take().unwrap().write_all(source.as_bytes())?;
+    Ok(child)
+}
+
+#[cfg(feature = "rhai")]
+fn rhai_engine() -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine.set_max_operations(RHAI_MAX_OPERATIONS);
+    engine
+}
+
+/// Why a Rhai script failed: the thrown value as is for `throw`, rhai's message otherwise.
+#[cfg(feature = "rhai")]
+fn rhai_error(e: rhai::EvalAltResult) -> String {
+    match e {
+        rhai::EvalAltResult::ErrorRuntime(thrown, _) => thrown.to_string(),
+        e => e.to_string(),
+    }
+}
+
+/// Parse the Rhai `script` without running it, for `kv cmd add` to refuse broken scripts.
+#[cfg(feature = "rhai")]
+pub fn check_rhai(script: &str) -> Result<(), String> {
+    rhai_engine().compile(script).map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "rhai"))]
+pub fn check_rhai(_script: &str) -> Result<(), String> {
+    Err(crate::features::missing("rhai"))
+}
+
+/// Run the Rhai `script` with `key`, `op` and `value` in scope (`()` when missing). Gives
+/// what it printed and the value it ended with or, if that's `()`, `value` as the script
+/// left it.
+#[cfg(feature = "rhai")]
+pub fn run_rhai(
+    script: &str,
+    key: Option<&str>,
+    op: Option<&str>,
+    value: Option<&str>,
+) -> Result<(String, Option<String>), String> {
+    use std::sync::{Arc, Mutex};
+
+    let printed = Arc::new(Mutex::new(String::new()));
+    let mut engine = rhai_engine();
+    let out = printed.clone();
+    engine.on_print(move |line| {
+        let mut out = out.lock().unwrap();
+        out.push_str(line);
+        out.push('\n');
+    });
+    let mut scope = rhai::Scope::new();
+    for (name, val) in [("key", key), ("op", op), ("value", value)] {
+        match val {
+            Some(val) => scope.push(name, val.to_owned()),
+            None => scope.push(name, ()),
+        };
+    }
+    let result = engine
+        .eval_with_scope::<rhai::Dynamic>(&mut scope, script)
+        .map_err(|e| rhai_error(*e))?;
+    drop(engine);
+    let printed = std::mem::take(&mut *printed.lock().unwrap());
+    let result = match result.is_unit() {
+        true => scope.get_value::<rhai::Dynamic>("value").filter(|value| !value.is_unit()),
+        false => Some(result),
+    };
+    Ok((printed, result.map(|result| result.to_string())))
+}
+
+#[cfg(not(feature = "rhai"))]
+pub fn run_rhai(
+    _script: &str,
+    _key: Option<&str>,
+    _op: Option<&str>,
+    _value: Option<&str>,
+) -> Result<(String, Option<String>), String> {
+    Err(crate::features::missing("rhai"))
+}
@@ -0,0 +1,133 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+use std::sync::OnceLock;
+
+use crate::{config, print_err, prompt};
+
+const PREFIX: &str = "kv-secret-v1:";
+const SALT_LEN: usize = 16;
+const ITERATIONS: u32 = 100_000;
+
+/// The key name the `secrets.verifier` setting is sealed under.
+const VERIFIER_KEY: &str = "kv-passphrase-check";
+
+/// The passphrase or keyfile contents. A typed passphrase is asked for twice when `confirm`.
+fn read_passphrase(confirm: bool) -> Vec<u8> {
+    let keyfile = config::get().value("secrets.keyfile");
+    if !keyfile.is_empty() {
+        return match std::fs::read(keyfile) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let err_msg = format!("Error! Cannot read keyfile {}, error {}", keyfile, e);
+                print_err(&err_msg[..]);
+            }
+        };
+    }
+    if let Ok(passphrase) = std::env::var("KV_PASSPHRASE") {
+        return passphrase.into_bytes();
+    }
+    let passphrase = match prompt::hidden("Passphrase: ") {
+        Some(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => print_err("Error! No passphrase entered."),
+    };
+    if confirm && prompt::hidden("Again to confirm: ").as_deref() != Some(&passphrase[..]) {
+        print_err("Error! The passphrases don't match. Nothing was changed.");
+    }
+    passphrase.into_bytes()
+}
+
+/// The passphrase everything is encrypted with, read once per run and checked against the
+/// `secrets.verifier` setting. The first `seal` without one asks for the passphrase twice and
+/// stores the verifier, so a mistyped passphrase can't encrypt values nothing else opens.
+fn passphrase(sealing: bool) -> &'static [u8] {
+    static PASSPHRASE: OnceLock<Vec<u8>> = OnceLock::new();
+    PASSPHRASE.get_or_init(|| {
+        let verifier = config::get().value("secrets.verifier");
+        if !verifier.is_empty() {
+            let passphrase = read_passphrase(false);
+            if open_with(&passphrase, VERIFIER_KEY, verifier).is_err() {
+                print_err("Error! Wrong passphrase, it isn't the one kv secret values are encrypted with.");
+            }
+            return passphrase;
+        }
+        let passphrase = read_passphrase(sealing);
+        if sealing {
+            config::set("secrets.verifier", &seal_with(&passphrase, VERIFIER_KEY, "ok"));
+        }
+        passphrase
+    })
+}
+
+fn cipher(passphrase: &[u8], salt: &[u8]) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(ITERATIONS).unwrap();
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase, &mut key);
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).unwrap())
+}
+
+/// Whether `value` was stored by `kv secret set`.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypt `value` for `key`. The key name is authenticated too, so the result can't be
+/// moved to another key.
+pub fn seal(key: &str, value: &str) -> String {
+    seal_with(passphrase(true), key, value)
+}
+
+fn seal_with(passphrase: &[u8], key: &str, value: &str) -> String {
+    let mut random = [0u8; SALT_LEN + NONCE_LEN];
+    SystemRandom::new().fill(&mut random).unwrap();
+    let (salt, nonce) = random.split_at(SALT_LEN);
+    let mut data = value.as_bytes().to_vec();
+    cipher(passphrase, salt)
+        .seal_in_place_append_tag(
+            Nonce::try_assume_unique_for_key(nonce).unwrap(),
+            Aad::from(key.as_bytes()),
+            &mut data,
+        )
+        .unwrap();
+    let mut envelope = random.to_vec();
+    envelope.extend(data);
+    format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(envelope))
+}
+
+/// Decrypt what `seal` stored for `key`.
+pub fn open(key: &str, value: &str) -> Result<String, String> {
+    if !is_encrypted(value) {
+        return Err("it isn't encrypted".to_owned());
+    }
+    open_with(passphrase(false), key, value)
+}
+
+fn open_with(passphrase: &[u8], key: &str, value: &str) -> Result<String, String> {
+    let bad = || "the value is damaged, or the passphrase is wrong".to_owned();
+    let envelope = value.strip_prefix(PREFIX).ok_or("it isn't encrypted")?;
+    let mut data = URL_SAFE_NO_PAD.decode(envelope).map_err(|_| bad())?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(bad());
+    }
+    let mut sealed = data.split_off(SALT_LEN + NONCE_LEN);
+    let (salt, nonce) = data.split_at(SALT_LEN);
+    let plain = cipher(passphrase, salt)
+        .open_in_place(
+            Nonce::try_assume_unique_for_key(nonce).unwrap(),
+            Aad::from(key.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| bad())?;
+    String::from_utf8(plain.to_vec()).map_err(|_| bad())
+}
+
+/// `open`, exiting with an error if it fails.
+pub fn reveal(key: &str, value: &str) -> String {
+    match open(key, value) {
+        Ok(plain) => plain,
+        Err(e) => print_err(&format!("Error! Cannot decrypt {}, {}", key, e)),
+    }
+}
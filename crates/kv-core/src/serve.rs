@@ -0,0 +1,993 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    config, del_key, fail, get_key, index, is_secret, options, print_err, set_key, shard,
+    scan, shown_value, time, touch_meta, transform, try_file_location, try_get_store, try_on_change, try_with_store_lock, try_write_file,
+    wal::{Change, Wal},
+    Error, KVStore, OpType,
+};
+
+/// Bodies larger than this are refused, values are small.
+const MAX_BODY: usize = 1 << 20;
+
+/// How long a client gets to send its whole request, however slowly it trickles in. A kept
+/// open connection is closed if the next request doesn't arrive in this time either.
+const REQUEST_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Connections served at once. More are answered 503 straight away.
+const MAX_CONNECTIONS: usize = 64;
+
+/// The longest request line or header line taken, in bytes.
+const MAX_LINE: usize = 8 << 10;
+
+/// Header lines taken per request.
+const MAX_HEADERS: usize = 100;
+
+struct Request {
+    method: String,
+    path: String,
+    host: Option<String>,
+    token: Option<String>,
+    /// The ETags in If-None-Match, or `*`.
+    if_none_match: Option<String>,
+    if_modified_since: Option<u64>,
+    /// Whether the client asked with `Connection: keep-alive` to send more requests.
+    keep_alive: bool,
+    body: Vec<u8>,
+}
+
+/// What every connection shares: who may talk to the server, and the store.
+pub struct Server {
+    /// None only with `--insecure`.
+    token: Option<String>,
+    local: SocketAddr,
+    /// Host names accepted besides IP addresses, see `host_ok`.
+    hosts: Vec<String>,
+    /// Tokens limited to some keys, from `serve.policies`.
+    policies: Vec<Policy>,
+    snapshot: RwLock<Snapshot>,
+    /// With `serve.flush-interval` set, the changes not yet written to the store.
+    batch: Option<Mutex<Batch>>,
+}
+
+/// Changes acknowledged but not yet in the store file. The snapshot already has them.
+struct Batch {
+    wal: Wal,
+    pending: Vec<Change>,
+}
+
+/// A `serve.policies` entry: `token` may read, and if `write` change, keys starting with
+/// `prefix`.
+#[derive(Clone)]
+struct Policy {
+    token: String,
+    write: bool,
+    prefix: String,
+}
+
+/// What a request may do, going by its token.
+#[derive(Clone)]
+pub struct Grant {
+    /// The server's own token (or --insecure): every key, cmds and hooks.
+    all: bool,
+    policies: Vec<Policy>,
+}
+
+impl Grant {
+    pub fn can_read(&self, key: &str) -> bool {
+        self.all || self.policies.iter().any(|p| key.starts_with(&p.prefix))
+    }
+
+    pub fn can_write(&self, key: &str) -> bool {
+        self.all || self.policies.iter().any(|p| p.write && key.starts_with(&p.prefix))
+    }
+}
+
+/// The store as last read or written. Requests work on an `Arc` of it rather than the
+/// shared copy, so a long listing never sees half of a write and never holds up writers,
+/// who swap in a new version once theirs is on disk.
+struct Snapshot {
+    store: Arc<KVStore>,
+    /// The store file as `store` was read from it, to notice other kv processes writing it.
+    stamp: Option<Stamp>,
+}
+
+/// Enough about a file to tell it was replaced. Writes rename a new file over the store, so
+/// on unix the inode alone would do.
+#[derive(Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+    #[cfg(unix)]
+    ino: u64,
+}
+
+fn stamp() -> Option<Stamp> {
+    let meta = std::fs::metadata(try_file_location().ok()?).ok()?;
+    Some(Stamp {
+        modified: meta.modified().ok()?,
+        len: meta.len(),
+        #[cfg(unix)]
+        ino: std::os::unix::fs::MetadataExt::ino(&meta),
+    })
+}
+
+impl Server {
+    /// The current store, read again only if the file changed since it was last read.
+    pub fn store(&self) -> Result<Arc<KVStore>, Error> {
+        let stamp = stamp();
+        {
+            let snapshot = self.snapshot.read().unwrap();
+            if stamp.is_some() && snapshot.stamp == stamp {
+                return Ok(snapshot.store.clone());
+            }
+        }
+        // Parse outside the lock so other requests go on with the old version meanwhile.
+        let mut store = try_get_store()?;
+        let batch = self.batch.as_ref().map(|batch| batch.lock().unwrap());
+        if let Some(batch) = &batch {
+            change(&mut store, &batch.pending);
+        }
+        let store = Arc::new(store);
+        *self.snapshot.write().unwrap() = Snapshot {
+            store: store.clone(),
+            stamp,
+        };
+        Ok(store)
+    }
+
+    /// Write the pending changes to the store in one go, then fire their hooks. Changes to
+    /// keys another kv process made secret meanwhile are dropped, and said so on stderr
+    /// like hooks that fail, since the clients that made them have had their answer.
+    fn flush(&self) -> Result<(), Error> {
+        let Some(batch) = &self.batch else {
+            return Ok(());
+        };
+        let mut batch = batch.lock().unwrap();
+        if batch.pending.is_empty() {
+            return Ok(());
+        }
+        let changes = std::mem::take(&mut batch.pending);
+        let old = try_with_store_lock(|| {
+            let mut kvstore = try_get_store()?;
+            // Keys can't have turned secret since, unless another kv process did that.
+            let (changes, dropped): (Vec<Change>, Vec<Change>) =
+                changes.iter().cloned().partition(|(key, _)| !is_secret(key, &kvstore.meta));
+            let old = change(&mut kvstore, &changes);
+            try_write_file(&kvstore)?;
+            reindex(&kvstore, &changes, &old)?;
+            self.replace(kvstore);
+            Ok((changes, dropped, old))
+        });
+        let (changes, dropped, old) = match old {
+            Ok(written) => written,
+            Err(e) => {
+                // Keep them for the next try, the log still has them.
+                batch.pending = changes;
+                return Err(e);
+            }
+        };
+        batch.wal.clear()?;
+        drop(batch);
+        for (key, _) in dropped {
+            eprintln!("Error! Dropped a change to {}, it was made secret before the change was written", key);
+        }
+        if let Err(e) = changed(&changes, &old) {
+            eprintln!("Error! {}", e);
+        }
+        Ok(())
+    }
+
+    /// Make `store`, just written under the store lock, the current version.
+    fn replace(&self, store: KVStore) {
+        *self.snapshot.write().unwrap() = Snapshot {
+            store: Arc::new(store),
+            stamp: stamp(),
+        };
+    }
+}
+
+/// The value of `key` in `kvstore`, unless its ttl ran out since the store was read.
+pub fn live<'a>(kvstore: &'a KVStore, key: &str) -> Option<&'a str> {
+    let value = kvstore.kvs.get(key)?;
+    match kvstore.meta.get(key) {
+        Some(meta) if meta.expired() => None,
+        _ => Some(value),
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    content_type: &'static str,
+    /// When what's served last changed, as seconds since the epoch.
+    last_modified: Option<u64>,
+    /// Extra header lines, each ending in CRLF.
+    headers: String,
+    pub body: String,
+}
+
+impl Response {
+    pub fn text(status: u16, body: impl Into<String>) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            last_modified: None,
+            headers: String::new(),
+            body: body.into(),
+        }
+    }
+
+    fn json(value: &impl serde::Serialize) -> Response {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            last_modified: None,
+            headers: String::new(),
+            body: serde_json::to_string_pretty(value).unwrap(),
+        }
+    }
+
+    fn empty() -> Response {
+        Response::text(204, "")
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Error",
+    }
+}
+
+/// Serve the store over HTTP on `addr`, or the socket systemd passed in, until interrupted
+/// or, with `idle_timeout`, until no request came for that long. Each connection gets its
+/// own thread. Every request needs `Authorization: Bearer <token>`, with a token generated
+/// and printed if `token` is None, unless `insecure`. Requests must be addressed to the
+/// bound address, `localhost` on loopback, or one of `hosts`.
+pub fn run(
+    addr: &str,
+    token: Option<&str>,
+    insecure: bool,
+    hosts: Vec<String>,
+    idle_timeout: Option<Duration>,
+) {
+    let listener = match activated_listener() {
+        Some(listener) => listener,
+        None => match TcpListener::bind(addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                let err_msg = format!("Error! Cannot listen on {}, error {}", addr, e);
+                print_err(&err_msg[..]);
+            }
+        },
+    };
+    let local = match listener.local_addr() {
+        Ok(local) => local,
+        Err(e) => print_err(&format!("Error! Cannot listen on {}, error {}", addr, e)),
+    };
+    let token = match (token, insecure) {
+        (_, true) => {
+            eprintln!(
+                "Warning! Anyone who can reach {} can read and change the store, since --insecure turns off the token",
+                local
+            );
+            None
+        }
+        (Some(token), false) => Some(token.to_owned()),
+        (None, false) => {
+            let token = new_token();
+            eprintln!("Send Authorization: Bearer {} with every request", token);
+            Some(token)
+        }
+    };
+    let policies = config::get()
+        .get_list("serve.policies")
+        .into_iter()
+        .map(|entry| match config::policy(entry) {
+            Ok((token, write, prefix)) => Policy {
+                token: token.to_owned(),
+                write,
+                prefix: prefix.to_owned(),
+            },
+            Err(e) => print_err(&format!(
+                "Error! {}. To fix it try\n kv config set serve.policies <token>:<r|rw>:<prefix>,...",
+                e
+            )),
+        })
+        .collect();
+    let config = config::get();
+    let flush_interval = config.get_duration("serve.flush-interval");
+    let batch = match flush_interval.is_zero() {
+        true => None,
+        false => {
+            let mut wal = Wal::open(config.value("serve.fsync") == "always").unwrap_or_else(|e| fail(e));
+            // Changes logged by a server that stopped before writing them.
+            let pending = wal.read().unwrap_or_else(|e| fail(e)).concat();
+            Some(Mutex::new(Batch { wal, pending }))
+        }
+    };
+    let server = Arc::new(Server {
+        token,
+        local,
+        hosts,
+        policies,
+        snapshot: RwLock::new(Snapshot {
+            store: Arc::default(),
+            stamp: None,
+        }),
+        batch,
+    });
+    server.flush().unwrap_or_else(|e| fail(e));
+    if !flush_interval.is_zero() {
+        let server = server.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(flush_interval);
+            if let Err(e) = server.flush() {
+                eprintln!("Error! {}", e);
+            }
+        });
+    }
+    eprintln!("Serving the store on http://{}", local);
+    let activity = Arc::new(Activity {
+        open: AtomicUsize::new(0),
+        last: Mutex::new(Instant::now()),
+    });
+    if let Some(timeout) = idle_timeout {
+        let (activity, server) = (activity.clone(), server.clone());
+        std::thread::spawn(move || exit_when_idle(&activity, &server, timeout));
+    }
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        if activity.open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            activity.done();
+            let _ = write_response(&mut stream, &Response::text(503, "Too many connections\n"), false);
+            continue;
+        }
+        let (server, activity) = (server.clone(), activity.clone());
+        std::thread::spawn(move || {
+            serve_connection(&mut stream, &server);
+            activity.done();
+        });
+    }
+}
+
+/// The listening socket systemd passed in with socket activation (`LISTEN_FDS`), so the
+/// server only starts once a client connects. See `man sd_listen_fds`.
+#[cfg(unix)]
+fn activated_listener() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+    // The first passed socket is always fd 3.
+    const SD_LISTEN_FDS_START: i32 = 3;
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: u32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    // Meant for this process, not inherited from a parent that was activated itself.
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    // Safety: systemd hands over fd 3 for this process to own, and nothing else uses it.
+    let listener = unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    match listener.local_addr() {
+        Ok(_) => Some(listener),
+        Err(e) => print_err(&format!(
+            "Error! The socket passed in with LISTEN_FDS is not a TCP socket, error {}",
+            e
+        )),
+    }
+}
+
+#[cfg(not(unix))]
+fn activated_listener() -> Option<TcpListener> {
+    None
+}
+
+/// Connections being served, and when the last one ended.
+struct Activity {
+    open: AtomicUsize,
+    last: Mutex<Instant>,
+}
+
+impl Activity {
+    fn done(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Exit once no connection has been open for `timeout`. With socket activation systemd
+/// keeps listening meanwhile and starts the server again for the next client.
+fn exit_when_idle(activity: &Activity, server: &Server, timeout: Duration) {
+    loop {
+        let idle = activity.last.lock().unwrap().elapsed();
+        if activity.open.load(Ordering::SeqCst) == 0 && idle >= timeout {
+            eprintln!("No requests for {}, exiting", time::format_duration(timeout));
+            server.flush().unwrap_or_else(|e| fail(e));
+            std::process::exit(0);
+        }
+        std::thread::sleep(timeout.saturating_sub(idle).clamp(Duration::from_millis(100), Duration::from_secs(1)));
+    }
+}
+
+/// Answer requests on `stream`, more than one while the client asks to keep it open.
+fn serve_connection(stream: &mut TcpStream, server: &Arc<Server>) {
+    let mut reader = BufReader::new(Deadline {
+        stream: &*stream,
+        until: Instant::now() + REQUEST_DEADLINE,
+    });
+    let mut first = true;
+    loop {
+        reader.get_mut().until = Instant::now() + REQUEST_DEADLINE;
+        // A kept open connection the client closes, or leaves idle, ends quietly.
+        if !first && !reader.fill_buf().is_ok_and(|buf| !buf.is_empty()) {
+            return;
+        }
+        first = false;
+        let (response, keep_alive) = match read_request(&mut reader) {
+            Ok(req) if !req.host.as_deref().is_some_and(|host| host_ok(host, server)) => {
+                (Response::text(421, "The Host header must name this server\n"), req.keep_alive)
+            }
+            Ok(req) => {
+                let response = match grant(req.token.as_deref(), server) {
+                    Some(grant) => handle(&req, &grant, server),
+                    None => Ok(Response::text(401, "A valid Authorization: Bearer <token> header is needed\n")),
+                };
+                let response = response.unwrap_or_else(|e| Response::text(500, format!("{}\n", e)));
+                let response = conditional(&req, response);
+                eprintln!(
+                    "{}\t{} {}\t{}",
+                    time::format_timestamp(time::now()),
+                    req.method,
+                    req.path,
+                    response.status
+                );
+                (response, req.keep_alive)
+            }
+            // What's left of a bad request can't be told from the next one. It's read
+            // (while the request's time lasts) before closing, so the client gets the
+            // answer rather than a reset.
+            Err(response) => {
+                if write_response(&mut &*stream, &response, false).is_ok() {
+                    let _ = stream.shutdown(Shutdown::Write);
+                    let _ = io::copy(&mut reader.by_ref().take(MAX_BODY as u64), &mut io::sink());
+                }
+                return;
+            }
+        };
+        if write_response(&mut &*stream, &response, keep_alive).is_err() || !keep_alive {
+            return;
+        }
+    }
+}
+
+/// 32 hex digits from the system's random source.
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    let urandom = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if urandom.is_err() {
+        // No /dev/urandom, e.g. on Windows. RandomState is seeded from the OS's random source.
+        use std::hash::{BuildHasher, Hasher};
+        for chunk in bytes.chunks_mut(8) {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// What the request with token `given` may do, None if the token is unknown. Every token is
+/// compared, so the time taken says nothing about which one nearly matched.
+fn grant(given: Option<&str>, server: &Server) -> Option<Grant> {
+    let given = given.unwrap_or_default();
+    let all = match &server.token {
+        Some(token) => same(given, token),
+        None => true,
+    };
+    let policies: Vec<Policy> = server
+        .policies
+        .iter()
+        .filter(|policy| same(given, &policy.token))
+        .cloned()
+        .collect();
+    (all || !policies.is_empty()).then_some(Grant { all, policies })
+}
+
+/// Whether `a` and `b` are equal, taking as long for a near miss as for a wild guess.
+fn same(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Whether a request with Host header `host` is meant for this server. Any IP address the
+/// server listens on is fine; names must be `localhost` (for a loopback server) or given
+/// with --host. That stops DNS rebinding, where a web page gets a browser to send requests
+/// to a server on its network under the page's own domain name.
+fn host_ok(host: &str, server: &Server) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    let ip = server.local.ip();
+    match name.parse::<IpAddr>() {
+        Ok(addr) => ip.is_unspecified() || addr == ip || (ip.is_loopback() && addr.is_loopback()),
+        Err(_) => {
+            (ip.is_loopback() && name.eq_ignore_ascii_case("localhost"))
+                || server.hosts.iter().any(|h| h.eq_ignore_ascii_case(name))
+        }
+    }
+}
+
+/// Reads from `stream` that fail once `until` has passed, so a client can't hold a
+/// connection open by sending a byte at a time.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        (&*self.stream).read(buf)
+    }
+}
+
+/// Read a line into `line`, giving the bytes read, or `too_long` if it's longer than
+/// `MAX_LINE`.
+fn read_line(reader: &mut BufReader<Deadline>, line: &mut String, too_long: impl Fn() -> Response) -> Result<usize, Response> {
+    let read = reader
+        .take(MAX_LINE as u64 + 1)
+        .read_line(line)
+        .map_err(|_| Response::text(400, "Bad request\n"))?;
+    if line.len() > MAX_LINE {
+        return Err(too_long());
+    }
+    Ok(read)
+}
+
+fn read_request(reader: &mut BufReader<Deadline>) -> Result<Request, Response> {
+    let bad = || Response::text(400, "Bad request\n");
+    let too_large = || Response::text(431, "Headers are limited to 100 lines of 8KiB\n");
+    let mut line = String::new();
+    read_line(reader, &mut line, || Response::text(414, "The request line is limited to 8KiB\n"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad());
+    };
+    let (method, path) = (method.to_owned(), path.to_owned());
+    let mut keep_alive = false;
+    let mut length = 0;
+    let mut host = None;
+    let mut token = None;
+    let mut if_none_match = None;
+    let mut if_modified_since = None;
+    for lines in 0.. {
+        line.clear();
+        if read_line(reader, &mut line, too_large)? == 0 {
+            return Err(bad());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if lines == MAX_HEADERS {
+            return Err(too_large());
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(bad());
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().map_err(|_| bad())?;
+        } else if name.eq_ignore_ascii_case("host") {
+            host = Some(value.to_owned());
+        } else if name.eq_ignore_ascii_case("authorization") {
+            token = value.strip_prefix("Bearer ").map(|t| t.trim().to_owned());
+        } else if name.eq_ignore_ascii_case("if-none-match") {
+            if_none_match = Some(value.to_owned());
+        } else if name.eq_ignore_ascii_case("if-modified-since") {
+            if_modified_since = time::parse_http_date(value);
+        } else if name.eq_ignore_ascii_case("connection") {
+            keep_alive = value.split(',').any(|option| option.trim().eq_ignore_ascii_case("keep-alive"));
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Response::text(413, "Values are limited to 1MiB\n"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|_| bad())?;
+    Ok(Request {
+        method,
+        path,
+        host,
+        token,
+        if_none_match,
+        if_modified_since,
+        keep_alive,
+        body,
+    })
+}
+
+/// Add caching headers to a successful GET: an ETag from the body, Last-Modified if known,
+/// and the `serve.cache-control` setting. Answer 304 without the body if the client's copy,
+/// going by If-None-Match or else If-Modified-Since, is still current.
+fn conditional(req: &Request, mut response: Response) -> Response {
+    if req.method != "GET" || response.status != 200 {
+        return response;
+    }
+    let etag = format!("\"{:016x}\"", shard::fnv1a(response.body.as_bytes()));
+    let fresh = match (&req.if_none_match, req.if_modified_since) {
+        (Some(tags), _) => tags
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag),
+        (None, Some(since)) => response.last_modified.is_some_and(|t| t <= since),
+        (None, None) => false,
+    };
+    if fresh {
+        response.status = 304;
+        response.body.clear();
+    }
+    let mut headers = format!("ETag: {}\r\n", etag);
+    if let Some(t) = response.last_modified {
+        headers += &format!("Last-Modified: {}\r\n", time::format_http_date(t));
+    }
+    if let Ok(config) = config::try_get() {
+        let cache_control = config.value("serve.cache-control");
+        if !cache_control.is_empty() {
+            headers += &format!("Cache-Control: {}\r\n", cache_control);
+        }
+    }
+    response.headers += &headers;
+    response
+}
+
+fn write_response(mut stream: impl Write, response: &Response, keep_alive: bool) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n{}",
+        response.status,
+        reason(response.status),
+        response.headers
+    )?;
+    if response.status != 304 {
+        write!(
+            stream,
+            "Content-Type: {}\r\nContent-Length: {}\r\n",
+            response.content_type,
+            response.body.len()
+        )?;
+    }
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    write!(stream, "Connection: {}\r\n\r\n{}", connection, response.body)?;
+    stream.flush()
+}
+
+/// Escape everything but letters, digits and `-._~` as `%XX`, for keys in URLs.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes, so keys with `/` or spaces can be addressed.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn handle(req: &Request, grant: &Grant, server: &Arc<Server>) -> Result<Response, Error> {
+    let (path, query) = req.path.split_once('?').unwrap_or((&req.path, ""));
+    Ok(match (&req.method[..], path) {
+        ("GET", "/keys") => match Page::parse(query) {
+            Some(page) => list(&page, grant, server)?,
+            None => Response::text(400, "Use /keys?prefix=<prefix>&limit=<n>&cursor=<key>\n"),
+        },
+        ("PUT", "/keys") => match serde_json::from_slice::<BTreeMap<String, String>>(&req.body) {
+            Ok(kvs) => {
+                let changes = kvs.into_iter().map(|(key, value)| (key, Some(value))).collect();
+                batch(changes, grant, server)?
+            }
+            Err(_) => Response::text(400, "Send a JSON object of keys and their new values\n"),
+        },
+        ("DELETE", "/keys") => match serde_json::from_slice::<Vec<String>>(&req.body) {
+            Ok(keys) => batch(keys.into_iter().map(|key| (key, None)).collect(), grant, server)?,
+            Err(_) => Response::text(400, "Send a JSON array of the keys to delete\n"),
+        },
+        #[cfg(feature = "graphql")]
+        ("POST", "/graphql") => match crate::graphql::execute(&req.body, grant, server.clone()) {
+            Ok(response) => Response::json(&response),
+            Err(e) => Response::text(400, format!("{}\n", e)),
+        },
+        ("GET", "/cmds" | "/hooks") if !grant.all => {
+            Response::text(403, "Only the server's own token can see cmds and hooks\n")
+        }
+        ("GET", "/cmds") => Response::json(&server.store()?.cmds),
+        ("GET", "/hooks") => Response::json(&server.store()?.hooks),
+        (method, path) => match path.strip_prefix("/keys/").map(percent_decode) {
+            Some(Some(key)) if !key.is_empty() && !grant.can_read(&key) => {
+                Response::text(403, format!("This token can't use {}\n", key))
+            }
+            Some(Some(key)) if !key.is_empty() && method != "GET" && !grant.can_write(&key) => {
+                Response::text(403, format!("This token can't change {}\n", key))
+            }
+            Some(Some(key)) if !key.is_empty() => match method {
+                "GET" => get(&key, server)?,
+                "PUT" => match String::from_utf8(req.body.clone()) {
+                    Ok(value) => match apply(vec![(key, Some(value))], &|_| Ok(()), server)? {
+                        Ok(_) => Response::empty(),
+                        Err(response) => response,
+                    },
+                    Err(_) => Response::text(400, "Values must be UTF-8\n"),
+                },
+                "DELETE" => match apply(vec![(key.clone(), None)], &|_| Ok(()), server)? {
+                    Ok(old) if old[0].is_some() => Response::empty(),
+                    Ok(_) => Response::text(404, format!("{} does not exist\n", key)),
+                    Err(response) => response,
+                },
+                _ => Response::text(405, "Use GET, PUT or DELETE\n"),
+            },
+            Some(_) => Response::text(400, "Bad key\n"),
+            None => Response::text(404, "Not found. Try /keys, /keys/<key>, /cmds, /hooks or /graphql\n"),
+        },
+    })
+}
+
+/// Which part of the store `GET /keys` lists.
+struct Page {
+    prefix: String,
+    limit: Option<usize>,
+    cursor: Option<scan::Cursor>,
+}
+
+impl Page {
+    /// From a query string like `prefix=team-a/&limit=100&cursor=team-a/db`.
+    fn parse(query: &str) -> Option<Page> {
+        let mut page = Page {
+            prefix: String::new(),
+            limit: None,
+            cursor: None,
+        };
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            match name {
+                "prefix" => page.prefix = value,
+                "limit" => page.limit = Some(value.parse().ok().filter(|&n| n > 0)?),
+                "cursor" => page.cursor = Some(scan::Cursor::after(&value)),
+                _ => return None,
+            }
+        }
+        Some(page)
+    }
+}
+
+/// Keys and values as a JSON object, secret values hidden. If there are more keys than
+/// the page's limit, a `Link` header points to the next page.
+fn list(page: &Page, grant: &Grant, server: &Server) -> Result<Response, Error> {
+    let kvstore = server.store()?;
+    let mut entries = kvstore
+        .scan_from(&page.prefix, page.cursor.as_ref())
+        .filter(|entry| grant.can_read(entry.key))
+        .filter_map(|entry| Some((entry.key, shown_value(entry.key, live(&kvstore, entry.key)?, &kvstore.meta))))
+        .peekable();
+    let keys: BTreeMap<&str, &str> = entries.by_ref().take(page.limit.unwrap_or(usize::MAX)).collect();
+    let mut response = Response::json(&keys);
+    if let (Some(limit), Some(_), Some(last)) = (page.limit, entries.peek(), keys.keys().last()) {
+        response.headers = format!(
+            "Link: </keys?prefix={}&limit={}&cursor={}>; rel=\"next\"\r\n",
+            percent_encode(&page.prefix),
+            limit,
+            percent_encode(last)
+        );
+    }
+    Ok(response)
+}
+
+/// The raw value of `key`. Secret keys aren't served, nor are password manager references
+/// resolved, since the answer goes over the network.
+fn get(key: &str, server: &Server) -> Result<Response, Error> {
+    let kvstore = server.store()?;
+    Ok(match live(&kvstore, key) {
+        Some(_) if is_secret(key, &kvstore.meta) => secret(key),
+        Some(value) => Response {
+            last_modified: kvstore.meta.get(key).map(|meta| meta.modified),
+            ..Response::text(200, value)
+        },
+        None => Response::text(404, format!("{} does not exist\n", key)),
+    })
+}
+
+/// `PUT /keys` and `DELETE /keys`: all of `changes` or, if the token can't make one of
+/// them, none. Deletes answer with the keys that existed.
+fn batch(changes: Vec<Change>, grant: &Grant, server: &Server) -> Result<Response, Error> {
+    if let Some((key, _)) = changes.iter().find(|(key, _)| key.is_empty() || !grant.can_write(key)) {
+        return Ok(Response::text(403, format!("This token can't change {:?}. Nothing was changed\n", key)));
+    }
+    let deleting = changes.first().is_some_and(|(_, value)| value.is_none());
+    Ok(match apply(changes.clone(), &|_| Ok(()), server)? {
+        Ok(_) if !deleting => Response::empty(),
+        Ok(old) => {
+            let deleted: Vec<&str> = changes
+                .iter()
+                .zip(&old)
+                .filter(|(_, old)| old.is_some())
+                .map(|((key, _), _)| &key[..])
+                .collect();
+            Response::json(&deleted)
+        }
+        Err(response) => response,
+    })
+}
+
+/// Secret keys can't be read, changed or deleted over HTTP: a client that can't see the
+/// value shouldn't be able to replace it either.
+fn secret(key: &str) -> Response {
+    Response::text(403, format!("{} is secret\n", key))
+}
+
+/// Checks a change before it's made, on the very version of the store it's made to.
+pub type Check<'a> = &'a dyn Fn(&KVStore) -> Result<(), Response>;
+
+/// Make `changes`, then fire hooks for them in order. Nothing is changed if any of the
+/// keys is secret or `check` fails. Gives the old value of each key, or a 500 saying so if
+/// the changes were made but their hooks couldn't all be run.
+pub fn apply(mut changes: Vec<Change>, check: Check, server: &Server) -> Result<Result<Vec<Option<String>>, Response>, Error> {
+    if options::get().read_only() {
+        return Ok(Err(Response::text(403, "The store is read-only\n")));
+    }
+    if let Some(batch) = &server.batch {
+        return log(changes, check, batch, server);
+    }
+    let old = try_with_store_lock(|| {
+        let mut kvstore = try_get_store()?;
+        if let Some((key, _)) = changes.iter().find(|(key, _)| is_secret(key, &kvstore.meta)) {
+            return Ok(Err(secret(key)));
+        }
+        if let Err(response) = check(&kvstore) {
+            return Ok(Err(response));
+        }
+        if let Err(response) = transform_all(&kvstore, &mut changes) {
+            return Ok(Err(response));
+        }
+        let old = change(&mut kvstore, &changes);
+        if changes.iter().zip(&old).any(|((_, value), old)| value.is_some() || old.is_some()) {
+            try_write_file(&kvstore)?;
+            reindex(&kvstore, &changes, &old)?;
+            server.replace(kvstore);
+        }
+        Ok(Ok(old))
+    })?;
+    let Ok(old) = old else {
+        return Ok(old);
+    };
+    if let Err(e) = changed(&changes, &old) {
+        return Ok(Err(Response::text(500, format!("The change was saved, but {}\n", e))));
+    }
+    Ok(Ok(old))
+}
+
+/// `apply` with `serve.flush-interval` set: log `changes` and make them in the snapshot,
+/// leaving the store file and hooks to the next flush.
+fn log(mut changes: Vec<Change>, check: Check, batch: &Mutex<Batch>, server: &Server) -> Result<Result<Vec<Option<String>>, Response>, Error> {
+    // Refresh the snapshot first if another kv process wrote the store.
+    server.store()?;
+    let mut batch = batch.lock().unwrap();
+    let mut snapshot = server.snapshot.write().unwrap();
+    if let Some((key, _)) = changes.iter().find(|(key, _)| is_secret(key, &snapshot.store.meta)) {
+        return Ok(Err(secret(key)));
+    }
+    if let Err(response) = check(&snapshot.store) {
+        return Ok(Err(response));
+    }
+    if let Err(response) = transform_all(&snapshot.store, &mut changes) {
+        return Ok(Err(response));
+    }
+    batch.wal.append(&changes)?;
+    // Clones the store only while a request still reads the old version.
+    let old = change(Arc::make_mut(&mut snapshot.store), &changes);
+    batch.pending.extend(changes);
+    Ok(Ok(old))
+}
+
+/// Put the values in `changes` through their keys' transforms. Nothing is changed if one of
+/// them rejects its value.
+fn transform_all(kvstore: &KVStore, changes: &mut [Change]) -> Result<(), Response> {
+    for (key, value) in changes.iter_mut() {
+        if let Some(value) = value {
+            *value = transform::apply(kvstore, key, value).map_err(|e| {
+                Response::text(422, format!("Cannot set {}, {}. Nothing was changed\n", key, e))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Make `changes` to `kvstore`, giving the old value of each key.
+fn change(kvstore: &mut KVStore, changes: &[Change]) -> Vec<Option<String>> {
+    changes
+        .iter()
+        .map(|(key, value)| match value {
+            Some(value) => {
+                let old = get_key(key, &kvstore.kvs);
+                set_key(key, value, &mut kvstore.kvs);
+                touch_meta(key, "serve", &mut kvstore.meta);
+                old
+            }
+            None => {
+                let old = del_key(key, &mut kvstore.kvs);
+                if old.is_some() {
+                    kvstore.meta.remove(key);
+                }
+                old
+            }
+        })
+        .collect()
+}
+
+/// Update the search index for `changes`, just written to the store file as `kvstore`.
+/// Called under the store lock.
+fn reindex(kvstore: &KVStore, changes: &[Change], old: &[Option<String>]) -> Result<(), Error> {
+    for ((key, value), old) in changes.iter().zip(old) {
+        if value.is_some() || old.is_some() {
+            index::try_update(kvstore, key, old.as_deref(), value.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Fire hooks for `changes`, now in the store file. One that can't be fired doesn't stop
+/// the rest; the first error is returned once they've all had their turn.
+fn changed(changes: &[Change], old: &[Option<String>]) -> Result<(), Error> {
+    let mut result = Ok(());
+    for ((key, value), old) in changes.iter().zip(old) {
+        if value.is_none() && old.is_none() {
+            continue;
+        }
+        let op = if value.is_some() { OpType::Set } else { OpType::Del };
+        let fired = try_on_change(key, op, value.as_deref());
+        result = result.and(fired);
+    }
+    result
+}
@@ -0,0 +1,223 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{get_config_dir, print_err};
+
+/// Hardening for the unit: kv serve only needs to read and write the store and config under
+/// the home directory, run hooks and listen. /tmp is private to the service, so hooks don't
+/// see other programs' files there.
+const HARDENING: &str = "NoNewPrivileges=yes
+PrivateTmp=yes
+ProtectSystem=full
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+RestrictSUIDSGID=yes
+RestrictRealtime=yes
+LockPersonality=yes
+UMask=0077
+";
+
+/// Where the unit (or plist) for service `name` goes, e.g. `~/.config/systemd/user/kv.service`.
+fn unit_path(name: &str) -> PathBuf {
+    let dir = if cfg!(target_os = "macos") {
+        dirs::home_dir().map(|home| home.join("Library/LaunchAgents"))
+    } else {
+        dirs::config_dir().map(|config| config.join("systemd/user"))
+    };
+    let dir = dir.unwrap_or_else(|| print_err("Error! Cannot find the home directory!"));
+    if cfg!(target_os = "macos") {
+        dir.join(format!("com.github.obaraelijah.{}.plist", name))
+    } else {
+        dir.join(format!("{}.service", name))
+    }
+}
+
+/// The file keeping KV_SERVE_TOKEN out of the unit, which systemctl shows to anyone.
+fn env_path(name: &str) -> PathBuf {
+    get_config_dir().join(format!("{}.service.env", name))
+}
+
+/// `arg` as one word of a systemd command line.
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty() && !escaped.contains(char::is_whitespace) && escaped == arg {
+        escaped
+    } else {
+        format!("\"{}\"", escaped)
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A systemd user unit running `command`, with `vars` set and the token read from `env_file`.
+fn systemd_unit(command: &[String], vars: &[(String, String)], env_file: Option<&Path>) -> String {
+    let mut unit = String::from(
+        "# Written by kv daemon install-service\n[Unit]\nDescription=kv serve, the kv store over HTTP\nDocumentation=https://github.com/obaraelijah/kv\nAfter=network.target\n\n[Service]\n",
+    );
+    let words: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    unit += &format!("ExecStart={}\n", words.join(" "));
+    for (name, value) in vars {
+        unit += &format!("Environment={}\n", systemd_quote(&format!("{}={}", name, value)));
+    }
+    if let Some(env_file) = env_file {
+        unit += &format!("EnvironmentFile={}\n", env_file.to_string_lossy());
+    }
+    unit += "Restart=on-failure\nRestartSec=5\n";
+    unit += HARDENING;
+    unit += "\n[Install]\nWantedBy=default.target\n";
+    unit
+}
+
+/// A launchd agent `label` running `command` at login and again if it fails.
+fn launchd_plist(label: &str, command: &[String], vars: &[(String, String)]) -> String {
+    let mut plist = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n<!-- Written by kv daemon install-service -->\n<plist version=\"1.0\">\n<dict>\n",
+    );
+    plist += &format!("  <key>Label</key>\n  <string>{}</string>\n", xml_escape(label));
+    plist += "  <key>ProgramArguments</key>\n  <array>\n";
+    for arg in command {
+        plist += &format!("    <string>{}</string>\n", xml_escape(arg));
+    }
+    plist += "  </array>\n";
+    if !vars.is_empty() {
+        plist += "  <key>EnvironmentVariables</key>\n  <dict>\n";
+        for (name, value) in vars {
+            plist += &format!("    <key>{}</key>\n    <string>{}</string>\n", xml_escape(name), xml_escape(value));
+        }
+        plist += "  </dict>\n";
+    }
+    let log = dirs::home_dir().unwrap_or_default().join("Library/Logs/kv.log");
+    plist += &format!(
+        "  <key>RunAtLoad</key>\n  <true/>\n  <key>KeepAlive</key>\n  <dict>\n    <key>SuccessfulExit</key>\n    <false/>\n  </dict>\n  <key>ProcessType</key>\n  <string>Background</string>\n  <key>Umask</key>\n  <integer>63</integer>\n  <key>StandardErrorPath</key>\n  <string>{}</string>\n",
+        xml_escape(&log.to_string_lossy())
+    );
+    plist += "</dict>\n</plist>\n";
+    plist
+}
+
+/// Write `contents` to `path`, readable only by the user if `private`.
+fn write(path: &Path, contents: &str, private: bool) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            if private {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            #[cfg(not(unix))]
+            let _ = private;
+            options.open(path)?.write_all(contents.as_bytes())
+        });
+    if let Err(e) = result {
+        print_err(&format!("Error! Cannot write {}, error {}", path.to_string_lossy(), e));
+    }
+}
+
+/// Run systemctl or launchctl, saying so if it fails.
+fn control(program: &str, args: &[&str]) -> bool {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => true,
+        Ok(_) => {
+            eprintln!("{} {} failed", program, args.join(" "));
+            false
+        }
+        Err(e) => {
+            eprintln!("Cannot run {}, error {}", program, e);
+            false
+        }
+    }
+}
+
+/// `kv daemon install-service`: write a user service `name` running `kv serve` with
+/// `serve_args`, on the store picked by `store` (a profile or context) or the current one, and
+/// unless `no_start`, enable and start it.
+pub fn install(name: &str, store: Option<&str>, serve_args: &[&str], no_start: bool) {
+    let exe = std::env::current_exe()
+        .unwrap_or_else(|e| print_err(&format!("Error! Cannot find the kv binary, error {}", e)));
+    let mut command = vec![exe.to_string_lossy().into_owned()];
+    if let Some(store) = store {
+        command.extend(["--store".to_owned(), store.to_owned()]);
+    }
+    command.push("serve".to_owned());
+    command.extend(serve_args.iter().map(|arg| (*arg).to_owned()));
+
+    // The service must find the same store and config as this kv, which a login session may
+    // have set up differently than the service manager does.
+    let mut vars = vec![];
+    if !cfg!(target_os = "macos") {
+        if let Some(config) = dirs::config_dir() {
+            vars.push(("XDG_CONFIG_HOME".to_owned(), config.to_string_lossy().into_owned()));
+        }
+    }
+    if let (None, Ok(context)) = (store, std::env::var("KV_CONTEXT")) {
+        vars.push(("KV_CONTEXT".to_owned(), context));
+    }
+    if let Ok(shell) = std::env::var("SHELL") {
+        vars.push(("SHELL".to_owned(), shell));
+    }
+    let token = std::env::var("KV_SERVE_TOKEN").ok();
+
+    let path = unit_path(name);
+    if cfg!(target_os = "macos") {
+        let label = format!("com.github.obaraelijah.{}", name);
+        vars.extend(token.map(|token| ("KV_SERVE_TOKEN".to_owned(), token)));
+        write(&path, &launchd_plist(&label, &command, &vars), true);
+        println!("Wrote {}", path.to_string_lossy());
+        if !no_start && control("launchctl", &["load", "-w", &path.to_string_lossy()]) {
+            println!("Started {}, logging to ~/Library/Logs/kv.log", label);
+        }
+        return;
+    }
+    let env_file = token.map(|token| {
+        let env_file = env_path(name);
+        write(&env_file, &format!("KV_SERVE_TOKEN={}\n", token), true);
+        env_file
+    });
+    write(&path, &systemd_unit(&command, &vars, env_file.as_deref()), false);
+    println!("Wrote {}", path.to_string_lossy());
+    let unit = format!("{}.service", name);
+    if !no_start
+        && control("systemctl", &["--user", "daemon-reload"])
+        && control("systemctl", &["--user", "enable", "--now", &unit])
+    {
+        println!("Started {}. To see its output try\n journalctl --user -u {}", unit, name);
+    }
+}
+
+/// `kv daemon uninstall-service`: unless `no_stop`, stop and disable service `name`, then
+/// remove its files.
+pub fn uninstall(name: &str, no_stop: bool) {
+    let path = unit_path(name);
+    if !path.exists() {
+        print_err(&format!(
+            "Error! There is no {}. To install it try\n kv daemon install-service --name {}",
+            path.to_string_lossy(),
+            name
+        ));
+    }
+    if !no_stop {
+        if cfg!(target_os = "macos") {
+            control("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+        } else {
+            control("systemctl", &["--user", "disable", "--now", &format!("{}.service", name)]);
+        }
+    }
+    for path in [path, env_path(name)] {
+        match std::fs::remove_file(&path) {
+            Ok(()) => println!("Removed {}", path.to_string_lossy()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => print_err(&format!("Error! Cannot remove {}, error {}", path.to_string_lossy(), e)),
+        }
+    }
+    if !no_stop && !cfg!(target_os = "macos") {
+        control("systemctl", &["--user", "daemon-reload"]);
+    }
+}
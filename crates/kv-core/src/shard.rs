@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    fail, get_file_location, print_err, replace_file, store_json, try_file_location, try_write_file,
+    with_file_bytes, Error, KVStore, Meta,
+};
+
+/// The part of `kvs`/`meta` stored in one shard file. Sorted, so that an unchanged shard
+/// serializes to the same bytes and can be skipped on write.
+#[derive(Serialize, Deserialize, Default)]
+struct Shard {
+    kvs: BTreeMap<String, String>,
+    meta: BTreeMap<String, Meta>,
+}
+
+fn get_shard_dir() -> PathBuf {
+    get_file_location().with_file_name("kv.shards")
+}
+
+fn try_shard_dir() -> Result<PathBuf, Error> {
+    Ok(try_file_location()?.with_file_name("kv.shards"))
+}
+
+fn get_shard_location(shard: u32) -> PathBuf {
+    shard_file(&get_shard_dir(), shard)
+}
+
+fn shard_file(dir: &Path, shard: u32) -> PathBuf {
+    dir.join(format!("{:04}.json", shard))
+}
+
+/// FNV-1a. Unlike `DefaultHasher` this is stable across Rust versions, which matters since
+/// it decides which file a key lives in.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn shard_of(key: &str, shards: u32) -> u32 {
+    (fnv1a(key.as_bytes()) % u64::from(shards)) as u32
+}
+
+/// A shard file and the digest of its bytes. A missing file is an empty shard, but one that
+/// doesn't parse is an error: loading it as empty would have the next save wipe its keys.
+fn read_shard(path: &Path) -> Result<(Shard, u64), Error> {
+    match std::fs::File::open(path) {
+        Ok(file) => with_file_bytes(&file, path, |bytes| {
+            let shard = serde_json::from_slice(bytes).map_err(|e| {
+                Error::new(format!("Cannot parse the shard {}, error {}", path.to_string_lossy(), e))
+            })?;
+            Ok((shard, fnv1a(bytes)))
+        })?,
+        Err(_) => Ok((Shard::default(), 0)),
+    }
+}
+
+/// Fill `kvs` and `meta` of a sharded store from every shard file.
+pub fn load_all(kvstore: &mut KVStore, shards: u32) -> Result<(), Error> {
+    let dir = try_shard_dir()?;
+    kvstore.shard_digests.clear();
+    for shard in 0..shards {
+        let (contents, digest) = read_shard(&shard_file(&dir, shard))?;
+        kvstore.kvs.extend(contents.kvs);
+        kvstore.meta.extend(contents.meta);
+        kvstore.shard_digests.push(digest);
+    }
+    Ok(())
+}
+
+/// Look up one key, parsing only the shard it lives in.
+pub fn get(key: &str, shards: u32) -> Option<String> {
+    try_get(key, shards).unwrap_or_else(|e| fail(e))
+}
+
+/// `get`, returning the error if the shard can't be read.
+pub fn try_get(key: &str, shards: u32) -> Result<Option<String>, Error> {
+    let path = shard_file(&try_shard_dir()?, shard_of(key, shards));
+    let (mut contents, _) = read_shard(&path)?;
+    if contents.meta.get(key).is_some_and(|m| m.expired()) {
+        return Ok(None);
+    }
+    Ok(contents.kvs.remove(key))
+}
+
+/// Problems with the shard files: missing or unparsable ones.
+pub fn check(shards: u32) -> Vec<String> {
+    (0..shards)
+        .filter_map(|shard| {
+            let path = get_shard_location(shard);
+            match std::fs::read(&path) {
+                Ok(bytes) => serde_json::from_slice::<Shard>(&bytes)
+                    .err()
+                    .map(|e| format!("cannot parse {}: {}", path.to_string_lossy(), e)),
+                Err(e) => Some(format!("cannot read {}: {}", path.to_string_lossy(), e)),
+            }
+        })
+        .collect()
+}
+
+/// Write `kvs` and `meta` out to the shard files, skipping shards whose contents did not change
+/// since they were loaded.
+pub fn save(kvstore: &KVStore, shards: u32, pretty: bool) -> Result<(), Error> {
+    write_shards(kvstore, shards, pretty, &try_shard_dir()?).map(|_| ())
+}
+
+/// Split `kvstore` into `shards` files and point the store file at them. The new shards are
+/// written next to the old ones first, and the old ones are only removed once the store file
+/// is written, so a failure on the way leaves the store as it was.
+pub fn reshard(kvstore: &mut KVStore, shards: u32) -> Result<(), Error> {
+    let dir = try_shard_dir()?;
+    let new = dir.with_file_name("kv.shards.new");
+    let old = dir.with_file_name("kv.shards.old");
+    for stale in [&new, &old] {
+        if stale.exists() {
+            std::fs::remove_dir_all(stale).map_err(|e| move_error(stale, e))?;
+        }
+    }
+    kvstore.shard_digests.clear();
+    let pretty = crate::config::try_get()?.value("persist.style") == "pretty";
+    let digests = write_shards(kvstore, shards, pretty, &new)?;
+    let had_shards = dir.exists();
+    if had_shards {
+        std::fs::rename(&dir, &old).map_err(|e| move_error(&dir, e))?;
+    }
+    let previous = kvstore.shards.replace(shards);
+    kvstore.shard_digests = digests;
+    let written = std::fs::rename(&new, &dir)
+        .map_err(|e| move_error(&new, e))
+        .and_then(|_| try_write_file(kvstore));
+    if let Err(e) = written {
+        kvstore.shards = previous;
+        kvstore.shard_digests.clear();
+        let _ = std::fs::remove_dir_all(&dir);
+        if had_shards {
+            let _ = std::fs::rename(&old, &dir);
+        }
+        let _ = std::fs::remove_dir_all(&new);
+        return Err(e);
+    }
+    if had_shards {
+        if let Err(e) = std::fs::remove_dir_all(&old) {
+            eprintln!("Warning! Cannot remove the old shards in {}, error {}", old.to_string_lossy(), e);
+        }
+    }
+    Ok(())
+}
+
+fn move_error(path: &Path, e: std::io::Error) -> Error {
+    Error::new(format!("Cannot move {}, error {}", path.to_string_lossy(), e))
+}
+
+/// Write the shards that changed since they were loaded into `dir`, giving the digest of
+/// each shard.
+fn write_shards(kvstore: &KVStore, shards: u32, pretty: bool, dir: &Path) -> Result<Vec<u64>, Error> {
+    let mut split: Vec<Shard> = (0..shards).map(|_| Shard::default()).collect();
+    for (key, val) in &kvstore.kvs {
+        split[shard_of(key, shards) as usize]
+            .kvs
+            .insert(key.clone(), val.clone());
+    }
+    for (key, meta) in &kvstore.meta {
+        split[shard_of(key, shards) as usize]
+            .meta
+            .insert(key.clone(), meta.clone());
+    }
+
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        return Err(Error::new(format!(
+            "Cannot create path {}, error {}",
+            dir.to_string_lossy(),
+            e
+        )));
+    }
+    let mut digests = vec![];
+    for (shard, contents) in split.iter().enumerate() {
+        let s = store_json(contents, pretty);
+        let digest = fnv1a(s.as_bytes());
+        digests.push(digest);
+        if kvstore.shard_digests.get(shard) == Some(&digest) {
+            continue;
+        }
+        replace_file(&shard_file(dir, shard as u32), s.as_bytes(), false)?;
+    }
+    Ok(digests)
+}
+
+/// Remove every shard file, e.g. before re-sharding or after going back to a single file.
+pub fn remove_all() {
+    let dir = get_shard_dir();
+    if dir.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            let err_msg = format!(
+                "Error! Cannot remove {}, error {}",
+                dir.to_string_lossy(),
+                e
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
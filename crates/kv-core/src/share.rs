@@ -0,0 +1,202 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::time::Duration;
+
+use crate::{config, print_err, time};
+
+const PREFIX: &str = "kv-share-v1:";
+
+/// What gets encrypted. The expiry is inside, so it can't be changed without the key.
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    key: String,
+    value: String,
+    expires: u64,
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes
+}
+
+fn cipher(key: &[u8]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).unwrap())
+}
+
+/// Encrypt `key`'s `value` with a fresh random key. Returns the envelope to upload or write,
+/// and the key, which only ever goes into the retrieval command.
+fn seal(key: &str, value: &str, expires_in: Duration) -> (String, String) {
+    let payload = Payload {
+        key: key.to_owned(),
+        value: value.to_owned(),
+        expires: time::now().saturating_add(expires_in.as_secs()),
+    };
+    let secret: [u8; 32] = random_bytes();
+    let nonce: [u8; NONCE_LEN] = random_bytes();
+    let mut data = serde_json::to_vec(&payload).unwrap();
+    cipher(&secret)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .unwrap();
+    let mut envelope = nonce.to_vec();
+    envelope.extend(data);
+    (
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(envelope)),
+        URL_SAFE_NO_PAD.encode(secret),
+    )
+}
+
+fn open(envelope: &str, secret: &str) -> Result<Payload, String> {
+    let bad = || "the share is damaged, or the key is wrong".to_owned();
+    let envelope = envelope
+        .trim()
+        .strip_prefix(PREFIX)
+        .ok_or("this is not a kv share")?;
+    let mut data = URL_SAFE_NO_PAD.decode(envelope).map_err(|_| bad())?;
+    let secret = URL_SAFE_NO_PAD.decode(secret).map_err(|_| bad())?;
+    if data.len() < NONCE_LEN || secret.len() != 32 {
+        return Err(bad());
+    }
+    let nonce = Nonce::try_assume_unique_for_key(&data[..NONCE_LEN]).unwrap();
+    let mut sealed = data.split_off(NONCE_LEN);
+    let plain = cipher(&secret)
+        .open_in_place(nonce, Aad::empty(), &mut sealed)
+        .map_err(|_| bad())?;
+    serde_json::from_slice(plain).map_err(|_| bad())
+}
+
+/// Encrypt a value and upload it to the `share.endpoint` setting, or write it to `to_file`.
+/// Prints the command a teammate runs to get it.
+pub fn share(key: &str, value: &str, expires_in: Duration, to_file: Option<&str>) {
+    let (envelope, secret) = seal(key, value, expires_in);
+    let location = match to_file {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, &envelope) {
+                let err_msg = format!("Error! Cannot write {}, error {}", path, e);
+                print_err(&err_msg[..]);
+            }
+            path.to_owned()
+        }
+        None => upload(&envelope),
+    };
+    println!(
+        "Shared {} until {} UTC. To get it, your teammate runs\n kv share --receive '{}#{}'",
+        key,
+        time::format_timestamp(time::now().saturating_add(expires_in.as_secs())),
+        location,
+        secret
+    );
+}
+
+/// POST the envelope to the endpoint, which answers with the URL to fetch it from (like
+/// paste.rs does).
+fn upload(envelope: &str) -> String {
+    let endpoint = config::get().value("share.endpoint");
+    if endpoint.is_empty() {
+        print_err("Error! No share endpoint. Set one with\n kv config set share.endpoint https://paste.example.com\nor write the share to a file with\n kv share --to-file <path> <key>");
+    }
+    let url = ureq::post(endpoint)
+        .set("Content-Type", "text/plain")
+        .send_string(envelope)
+        .map_err(|e| e.to_string())
+        .and_then(|res| res.into_string().map_err(|e| e.to_string()));
+    match url {
+        Ok(url) if url.trim().starts_with("http") => url.trim().to_owned(),
+        Ok(res) => {
+            let err_msg = format!(
+                "Error! {} did not answer with a URL, but:\n {}",
+                endpoint,
+                res.trim()
+            );
+            print_err(&err_msg[..]);
+        }
+        Err(e) => {
+            let err_msg = format!("Error! Cannot upload to {}, error {}", endpoint, e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Fetch and decrypt a share from `link` (`<url or path>#<key>`). The shared copy is deleted
+/// afterwards where possible, so the link only works once: files are removed, and endpoints
+/// get a DELETE request. Returns the shared key and value.
+pub fn receive(link: &str) -> (String, String) {
+    let Some((location, secret)) = link.rsplit_once('#') else {
+        print_err("Error! The link must look like <url or file>#<key>, as kv share printed it");
+    };
+    let is_url = location.starts_with("http://") || location.starts_with("https://");
+    let envelope = if is_url {
+        let mut body = String::new();
+        let res = ureq::get(location)
+            .call()
+            .map_err(|e| e.to_string())
+            .and_then(|r| {
+                r.into_reader()
+                    .read_to_string(&mut body)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = res {
+            let err_msg = format!("Error! Cannot download {}, error {}", location, e);
+            print_err(&err_msg[..]);
+        }
+        body
+    } else {
+        match std::fs::read_to_string(location) {
+            Ok(body) => body,
+            Err(e) => {
+                let err_msg = format!("Error! Cannot read {}, error {}", location, e);
+                print_err(&err_msg[..]);
+            }
+        }
+    };
+    let payload = match open(&envelope, secret) {
+        Ok(payload) => payload,
+        Err(e) => print_err(&format!("Error! Cannot open the share, {}", e)),
+    };
+    if is_url {
+        let _ = ureq::delete(location).call();
+    } else {
+        let _ = std::fs::remove_file(location);
+    }
+    if payload.expires < time::now() {
+        let err_msg = format!(
+            "Error! The share of {} expired at {} UTC",
+            payload.key,
+            time::format_timestamp(payload.expires)
+        );
+        print_err(&err_msg[..]);
+    }
+    (payload.key, payload.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_its_key_only() {
+        let (envelope, secret) = seal("db-password", "hunter2", Duration::from_secs(60));
+        let payload = open(&envelope, &secret).unwrap();
+        assert_eq!((&payload.key[..], &payload.value[..]), ("db-password", "hunter2"));
+        assert!(payload.expires > time::now());
+
+        let (_, other) = seal("db-password", "hunter2", Duration::from_secs(60));
+        assert!(open(&envelope, &other).is_err());
+        assert!(open(&envelope, "short").is_err());
+        assert!(open("not a share", &secret).is_err());
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let (envelope, secret) = seal("k", "v", Duration::from_secs(60));
+        let mut bytes = URL_SAFE_NO_PAD.decode(envelope.strip_prefix(PREFIX).unwrap()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(bytes));
+        assert!(open(&tampered, &secret).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use crate::KV;
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+const BASH: &str = r#"# kv directory bookmarks (generated by kv shell-init)
+NAME() {
+  local dir
+  dir="$(kv get "$1")"
+  if [ -z "$dir" ]; then
+    echo "NAME: $1 is not set" >&2
+    return 1
+  fi
+  cd "${dir/#\~/$HOME}"
+}
+_NAME() {
+  COMPREPLY=($(compgen -W "$(kv cd-keys 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+}
+complete -F _NAME NAME
+"#;
+
+const ZSH: &str = r#"# kv directory bookmarks (generated by kv shell-init)
+NAME() {
+  local dir
+  dir="$(kv get "$1")"
+  if [ -z "$dir" ]; then
+    echo "NAME: $1 is not set" >&2
+    return 1
+  fi
+  cd "${dir/#\~/$HOME}"
+}
+_NAME() {
+  compadd -- ${(f)"$(kv cd-keys 2>/dev/null)"}
+}
+(( $+functions[compdef] )) && compdef _NAME NAME
+"#;
+
+const FISH: &str = r#"# kv directory bookmarks (generated by kv shell-init)
+function NAME
+    set -l dir (kv get $argv[1])
+    if test -z "$dir"
+        echo "NAME: $argv[1] is not set" >&2
+        return 1
+    end
+    cd (string replace -r '^~' $HOME -- $dir)
+end
+complete -c NAME -f -a '(kv cd-keys 2>/dev/null)'
+"#;
+
+/// Whether `name` is `[A-Za-z_][A-Za-z0-9_]*`, a function name every shell takes as is.
+fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The function `name` for `shell`, which cd's to the directory stored in a key, with
+/// completion of the keys that hold one. `name` is spliced into the script, so anything but a
+/// plain identifier is refused.
+pub fn script(shell: &str, name: &str) -> Result<String, String> {
+    if !is_function_name(name) {
+        return Err(format!(
+            "{:?} can't be a shell function name, use letters, digits and _ and don't start with a digit",
+            name
+        ));
+    }
+    let template = match shell {
+        "zsh" => ZSH,
+        "fish" => FISH,
+        _ => BASH,
+    };
+    Ok(template.replace("NAME", name))
+}
+
+/// Keys whose value is an existing directory (`~/` expanded), for completion.
+pub fn dir_keys(kvs: &KV) -> Vec<&str> {
+    let home = dirs::home_dir();
+    kvs.iter()
+        .filter(|(_, value)| {
+            let value = value.trim();
+            match (value.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest).is_dir(),
+                _ => Path::new(value).is_dir(),
+            }
+        })
+        .map(|(key, _)| &key[..])
+        .collect()
+}
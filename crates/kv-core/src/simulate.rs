@@ -0,0 +1,109 @@
+use crate::{
+    direnv, fire_hooks, get_key, get_main_store, get_value_in, machine, record, shard, shown_value,
+    KVStore, OpType,
+};
+
+/// The keys recomputed, directly or in turn, when `key` changes.
+fn dependents(kvstore: &KVStore, key: &str) -> Vec<String> {
+    let mut found: Vec<String> = vec![];
+    let mut pending = vec![key.to_owned()];
+    while let Some(changed) = pending.pop() {
+        for (name, computed) in &kvstore.computed {
+            if computed.from.contains(&changed) && !found.contains(name) && name != key {
+                found.push(name.clone());
+                pending.push(name.clone());
+            }
+        }
+    }
+    found
+}
+
+/// `arg` as typed in a shell, quoted only if it needs to be.
+fn shell_word(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_owned()
+    } else {
+        direnv::quote(arg)
+    }
+}
+
+/// Show what `op` on `key` would do, `value` being the new value for a set: how the store
+/// changes, which hooks fire in order with the command line and environment each would
+/// get, and which computed keys would be recomputed. Nothing is written or run.
+pub fn run(key: &str, op: OpType, value: Option<&str>) {
+    let kvstore = get_main_store();
+    let old = match kvstore.shards {
+        Some(shards) => shard::get(key, shards),
+        None => get_key(key, &kvstore.kvs),
+    };
+    let mask = |val: &str| shown_value(key, val, &kvstore.meta).to_owned();
+    println!("Store:");
+    match (&op, old.as_deref(), value) {
+        (OpType::Get, _, _) => match get_value_in(key, &kvstore) {
+            Some(val) => println!("  unchanged, {} reads {}", key, mask(&val)),
+            None => println!("  unchanged, {} does not exist", key),
+        },
+        (OpType::Set, Some(old), Some(new)) if old == new => {
+            println!(
+                "  {} is already {}, only its modified time changes",
+                key,
+                mask(new)
+            )
+        }
+        (OpType::Set, Some(old), Some(new)) => {
+            println!("  - {}: {}", key, mask(old));
+            println!("  + {}: {}", key, mask(new));
+        }
+        (OpType::Set, None, Some(new)) => println!("  + {}: {}", key, mask(new)),
+        (OpType::Del, Some(old), _) => println!("  - {}: {}", key, mask(old)),
+        _ => println!("  unchanged, {} does not exist", key),
+    }
+    if op != OpType::Get && machine::load().contains_key(key) {
+        println!(
+            "  {} is overridden on this machine, so it still reads the override here",
+            key
+        );
+    }
+
+    if op == OpType::Del && old.is_none() {
+        println!("No hooks fire and no computed keys change.");
+        println!("Nothing was changed.");
+        return;
+    }
+    let value = match op {
+        OpType::Del => None,
+        _ if kvstore.meta.get(key).is_some_and(|m| m.secret) => None,
+        OpType::Get => get_value_in(key, &kvstore),
+        OpType::Set => value.map(str::to_owned),
+    };
+    record::collect();
+    fire_hooks(&kvstore, key, &op, value.as_deref());
+    let fired = record::take();
+    if fired.is_empty() {
+        println!("Hooks: none");
+    } else {
+        println!("Hooks, in the order they would fire:");
+    }
+    for (i, invocation) in fired.iter().enumerate() {
+        let argv: Vec<String> = invocation.argv.iter().map(|arg| shell_word(arg)).collect();
+        println!("  {}. {}", i + 1, invocation.hook);
+        println!("     $ {}", argv.join(" "));
+        for (name, val) in &invocation.env {
+            println!("     {}={}", name, val);
+        }
+    }
+
+    if op != OpType::Get {
+        let dependents = dependents(&kvstore, key);
+        if dependents.is_empty() {
+            println!("Computed keys: none");
+        } else {
+            println!(
+                "Computed keys that would be recomputed: {}",
+                dependents.join(", ")
+            );
+        }
+    }
+    println!("Nothing was changed.");
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use crate::{
+    fail, get_config_dir, get_store, index, machine, print_err, replace_file, stateless, time, with_store_lock, write_file,
+    KVStore,
+};
+
+fn get_snapshot_dir() -> PathBuf {
+    get_config_dir().join("snapshots")
+}
+
+/// Where snapshot `name` is kept. Names come from the command line, so ones that could
+/// point outside the snapshot directory are refused.
+fn get_snapshot_location(name: &str) -> PathBuf {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        let err_msg = format!(
+            "Error! {:?} is not a snapshot name. To see all snapshots try\n kv snapshot list",
+            name
+        );
+        print_err(&err_msg[..]);
+    }
+    get_snapshot_dir().join(format!("{}.json", name))
+}
+
+/// Save the whole store (including sharded keys) as a new snapshot and return its name.
+/// This machine's overrides are left out, as they are when the store is written, and the
+/// snapshot is readable only by the user.
+///
+/// Names are the UTC time plus `label`, e.g. `20240101-120000-rename-prefix`.
+pub fn create(kvstore: &KVStore, label: &str) -> String {
+    stateless::ensure_writable();
+    let dir = get_snapshot_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        let err_msg = format!(
+            "Error! Cannot create path {}, error {}",
+            dir.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+    let label: String = label
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '-' })
+        .collect();
+    let base = format!("{}-{}", time::format_compact(time::now()), label);
+    let mut name = base.clone();
+    let mut n = 1;
+    while get_snapshot_location(&name).exists() {
+        n += 1;
+        name = format!("{}-{}", base, n);
+    }
+    let path = get_snapshot_location(&name);
+    let s = serde_json::to_string_pretty(&machine::without_overrides(kvstore)).unwrap();
+    replace_file(&path, s.as_bytes(), false).unwrap_or_else(|e| fail(e));
+    name
+}
+
+/// Snapshot the store ahead of a bulk-destructive operation and say how to undo it.
+pub fn before(kvstore: &KVStore, label: &str) {
+    let name = create(kvstore, label);
+    eprintln!(
+        "Saved snapshot {}. To undo this try\n kv snapshot restore {}",
+        name, name
+    );
+}
+
+/// Names of all snapshots, oldest first.
+fn names() -> Vec<String> {
+    let mut names: Vec<String> = match std::fs::read_dir(get_snapshot_dir()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                e.file_name()
+                    .to_str()
+                    .and_then(|n| n.strip_suffix(".json"))
+                    .map(|n| n.to_owned())
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    names.sort();
+    names
+}
+
+/// Lines for `kv snapshot list`.
+pub fn list() -> Vec<String> {
+    let mut lines = vec!["Snapshot\t--\tSize".to_owned()];
+    lines.extend(names().iter().map(|name| {
+        let size = std::fs::metadata(get_snapshot_location(name))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        format!("{}\t--\t{} bytes", name, size)
+    }));
+    lines
+}
+
+fn check_exists(name: &str) {
+    if !get_snapshot_location(name).exists() {
+        let err_msg = format!(
+            "Error! Snapshot {} does not exist! To see all snapshots try\n kv snapshot list",
+            name
+        );
+        print_err(&err_msg[..]);
+    }
+}
+
+/// Replace the store with snapshot `name`, keeping the current shard layout. Unless told
+/// otherwise, the current store is snapshotted first so the restore can itself be undone.
+pub fn restore(name: &str, snapshot_first: bool) {
+    check_exists(name);
+    let path = get_snapshot_location(name);
+    with_store_lock(|| {
+        let kvstore = get_store();
+        if snapshot_first {
+            before(&kvstore, "restore");
+        }
+        let mut restored: KVStore = match std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+        {
+            Ok(s) => s,
+            Err(e) => {
+                let err_msg = format!(
+                    "Error! Cannot read snapshot {}, error {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                print_err(&err_msg[..]);
+            }
+        };
+        restored.shards = kvstore.shards;
+        restored.shard_digests = kvstore.shard_digests;
+        write_file(&restored);
+        index::refresh(&restored);
+    })
+}
+
+pub fn delete(name: &str) {
+    check_exists(name);
+    let path = get_snapshot_location(name);
+    if let Err(e) = std::fs::remove_file(&path) {
+        let err_msg = format!(
+            "Error! Cannot remove snapshot {}, error {}",
+            path.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+}
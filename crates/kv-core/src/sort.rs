@@ -0,0 +1,95 @@
+use std::cmp::Ordering;
+
+/// How `kv keys --sort` orders keys.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Order {
+    /// By key, byte by byte: the store's own order.
+    Lexical,
+    /// By key, with runs of digits compared as numbers, so `item2` comes before `item10`.
+    Natural,
+    /// By value as a number, e.g. for counters. Keys whose value isn't a number come last.
+    Numeric,
+}
+
+pub const NAMES: &[&str] = &["lexical", "natural", "numeric"];
+
+impl Order {
+    pub fn parse(name: &str) -> Order {
+        match name {
+            "natural" => Order::Natural,
+            "numeric" => Order::Numeric,
+            _ => Order::Lexical,
+        }
+    }
+
+    /// Sort `(key, value)` rows. Ties are broken by key, so the output never depends on
+    /// the order rows came in.
+    pub fn sort<V: AsRef<str>>(self, rows: &mut [(&str, V)]) {
+        match self {
+            Order::Lexical => rows.sort_by(|a, b| a.0.cmp(b.0)),
+            Order::Natural => rows.sort_by(|a, b| natural(a.0, b.0).then_with(|| a.0.cmp(b.0))),
+            Order::Numeric => rows.sort_by(|a, b| {
+                numeric(a.1.as_ref(), b.1.as_ref()).then_with(|| a.0.cmp(b.0))
+            }),
+        }
+    }
+}
+
+/// Compare `a` and `b` a chunk at a time, where digit runs compare by value.
+fn natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let ord = if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (na, rest_a) = split_digits(a);
+            let (nb, rest_b) = split_digits(b);
+            a = rest_a;
+            b = rest_b;
+            // Without leading zeros the longer run is the larger number.
+            let (ta, tb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+            ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb)).then_with(|| na.len().cmp(&nb.len()))
+        } else {
+            a = &a[ca.len_utf8()..];
+            b = &b[cb.len_utf8()..];
+            ca.cmp(&cb)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+/// Compare values as numbers, with values that aren't numbers after all that are.
+fn numeric(a: &str, b: &str) -> Ordering {
+    let parse = |s: &str| s.trim().parse::<f64>().ok().filter(|n| !n.is_nan());
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap(),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// `rows` as a pretty JSON object with the keys in the order given, which a map would lose.
+pub fn json_object<V: AsRef<str>>(rows: &[(&str, V)]) -> String {
+    if rows.is_empty() {
+        return "{}".to_owned();
+    }
+    let fields: Vec<String> = rows
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "  {}: {}",
+                serde_json::to_string(key).unwrap(),
+                serde_json::to_string(value.as_ref()).unwrap()
+            )
+        })
+        .collect();
+    format!("{{\n{}\n}}", fields.join(",\n"))
+}
@@ -0,0 +1,175 @@
+//! The SQLite backend: the store in `kv.db`, one row per key, cmd and hook, so a change
+//! writes only the rows it touches and `kv get` reads only its key.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension, ToSql, Transaction};
+
+use crate::{Error, KVStore, Meta};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS kvs (key TEXT PRIMARY KEY, value TEXT NOT NULL) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, meta TEXT NOT NULL) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS cmds (name TEXT PRIMARY KEY, cmd TEXT NOT NULL) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS hooks (position INTEGER PRIMARY KEY, hook TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS store (field TEXT PRIMARY KEY, value TEXT NOT NULL) WITHOUT ROWID;
+";
+
+/// The parts of the store that have their own table. Everything else (locks, polls, ...) is
+/// kept as a JSON row per field in `store`.
+const TABLES: &[&str] = &["kvs", "meta", "cmds", "hooks"];
+
+fn sql_error(path: &Path, e: rusqlite::Error) -> Error {
+    Error::new(format!("Cannot use the store {}, error {}", path.to_string_lossy(), e))
+}
+
+/// Open the database at `path`, creating it if missing. A new database takes in the JSON
+/// store next to it (`kv.json` for `kv.db`), so switching backends keeps the keys.
+fn open(path: &Path) -> Result<Connection, Error> {
+    let new = !path.exists();
+    let mut conn = Connection::open(path).map_err(|e| sql_error(path, e))?;
+    // Waits out another kv writing without the store lock (--no-lock).
+    conn.busy_timeout(std::time::Duration::from_secs(5)).map_err(|e| sql_error(path, e))?;
+    conn.execute_batch(SCHEMA).map_err(|e| sql_error(path, e))?;
+    if new {
+        let json = path.with_extension("json");
+        if let Ok(bytes) = std::fs::read(&json) {
+            let kvstore: KVStore = serde_json::from_slice(&bytes).map_err(|e| {
+                Error::new(format!("Cannot import {} into {}, error {}", json.to_string_lossy(), path.to_string_lossy(), e))
+            })?;
+            if kvstore.shards.is_some() {
+                return Err(Error::new(format!(
+                    "Cannot import {}, it's sharded. To import it try\n kv shard disable\nwith persist.backend json first",
+                    json.to_string_lossy()
+                )));
+            }
+            write(&mut conn, &kvstore).map_err(|e| sql_error(path, e))?;
+            eprintln!("Imported {} into {}", json.to_string_lossy(), path.to_string_lossy());
+        }
+    }
+    Ok(conn)
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+/// Everything but the keys: cmds, hooks and the rest, like `get_main_store` reads from a
+/// sharded store's main file.
+pub fn load_main(path: &Path) -> Result<KVStore, Error> {
+    let conn = open(path)?;
+    let read = || -> rusqlite::Result<serde_json::Value> {
+        let mut main = serde_json::Map::new();
+        let mut rows = conn.prepare("SELECT field, value FROM store")?;
+        for row in rows.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (field, value) = row?;
+            main.insert(field, serde_json::from_str(&value).unwrap_or_default());
+        }
+        let cmds: serde_json::Map<String, serde_json::Value> = conn
+            .prepare("SELECT name, cmd FROM cmds")?
+            .query_map([], |row| Ok((row.get(0)?, serde_json::Value::String(row.get(1)?))))?
+            .collect::<Result<_, _>>()?;
+        let hooks: Vec<serde_json::Value> = conn
+            .prepare("SELECT hook FROM hooks ORDER BY position")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|hook| Ok(serde_json::from_str(&hook?).unwrap_or_default()))
+            .collect::<rusqlite::Result<_>>()?;
+        main.insert("kvs".to_owned(), serde_json::json!({}));
+        main.insert("cmds".to_owned(), cmds.into());
+        main.insert("hooks".to_owned(), hooks.into());
+        Ok(main.into())
+    };
+    let main = read().map_err(|e| sql_error(path, e))?;
+    serde_json::from_value(main)
+        .map_err(|e| Error::new(format!("Cannot parse the store {}, error {}", path.to_string_lossy(), e)))
+}
+
+/// Fill in the keys and their metadata of a store read by `load_main`.
+pub fn load_kvs(kvstore: &mut KVStore, path: &Path) -> Result<(), Error> {
+    let conn = open(path)?;
+    let read = |kvstore: &mut KVStore| -> rusqlite::Result<()> {
+        kvstore.kvs = conn
+            .prepare("SELECT key, value FROM kvs")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        let mut rows = conn.prepare("SELECT key, meta FROM meta")?;
+        for row in rows.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (key, meta) = row?;
+            if let Ok(meta) = serde_json::from_str(&meta) {
+                kvstore.meta.insert(key, meta);
+            }
+        }
+        Ok(())
+    };
+    read(kvstore).map_err(|e| sql_error(path, e))
+}
+
+/// The value and metadata of `key` alone, found through the table's index.
+pub fn get(key: &str, path: &Path) -> Result<Option<(String, Option<Meta>)>, Error> {
+    let conn = open(path)?;
+    conn.query_row(
+        "SELECT value, (SELECT meta FROM meta WHERE meta.key = kvs.key) FROM kvs WHERE key = ?1",
+        [key],
+        |row| {
+            let meta: Option<String> = row.get(1)?;
+            Ok((row.get(0)?, meta.and_then(|meta| serde_json::from_str(&meta).ok())))
+        },
+    )
+    .optional()
+    .map_err(|e| sql_error(path, e))
+}
+
+/// Make the database hold `m`, writing only the rows that differ.
+pub fn save(m: &KVStore, path: &Path) -> Result<(), Error> {
+    let mut conn = open(path)?;
+    write(&mut conn, m).map_err(|e| sql_error(path, e))
+}
+
+fn write(conn: &mut Connection, m: &KVStore) -> rusqlite::Result<()> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    sync(&tx, "kvs", "key", "value", m.kvs.iter().map(|(k, v)| (k.clone(), v.clone())).collect())?;
+    sync(&tx, "meta", "key", "meta", m.meta.iter().map(|(k, meta)| (k.clone(), to_json(meta))).collect())?;
+    sync(&tx, "cmds", "name", "cmd", m.cmds.clone())?;
+    let hooks = m.hooks.iter().enumerate().map(|(i, hook)| (i as i64, to_json(hook))).collect();
+    sync(&tx, "hooks", "position", "hook", hooks)?;
+    let mut rest = match serde_json::to_value(m).unwrap() {
+        serde_json::Value::Object(fields) => fields,
+        _ => unreachable!(),
+    };
+    rest.retain(|field, _| !TABLES.contains(&&field[..]));
+    sync(&tx, "store", "field", "value", rest.iter().map(|(field, value)| (field.clone(), to_json(value))).collect())?;
+    tx.commit()
+}
+
+/// Make `table` hold exactly `rows`: insert and update the rows that differ and delete the
+/// ones that are gone, leaving the rest untouched.
+fn sync<K>(tx: &Transaction, table: &str, key: &str, value: &str, mut rows: BTreeMap<K, String>) -> rusqlite::Result<()>
+where
+    K: Ord + ToSql + rusqlite::types::FromSql,
+{
+    let mut gone = vec![];
+    {
+        let mut select = tx.prepare(&format!("SELECT {}, {} FROM {}", key, value, table))?;
+        let mut current = select.query([])?;
+        while let Some(row) = current.next()? {
+            let k: K = row.get(0)?;
+            match rows.get(&k) {
+                Some(v) if *v == row.get::<_, String>(1)? => {
+                    rows.remove(&k);
+                }
+                Some(_) => {}
+                None => gone.push(k),
+            }
+        }
+    }
+    let mut delete = tx.prepare(&format!("DELETE FROM {} WHERE {} = ?1", table, key))?;
+    for k in gone {
+        delete.execute([k])?;
+    }
+    let mut upsert = tx.prepare(&format!("INSERT OR REPLACE INTO {} ({}, {}) VALUES (?1, ?2)", table, key, value))?;
+    for (k, v) in rows {
+        upsert.execute(params![k, v])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::sync::OnceLock;
+
+use crate::{fail, options, print_err, Error, KVStore};
+
+/// Where a read-only store came from, for messages, and its JSON.
+static STORE: OnceLock<(String, Vec<u8>)> = OnceLock::new();
+
+/// Load the store from environment variable `var` instead of the store file. kv won't write
+/// anything afterwards.
+pub fn from_env(var: &str) {
+    match std::env::var(var) {
+        Ok(json) => load(format!("${}", var), json.into_bytes()),
+        Err(_) => {
+            let err_msg = format!("Error! Environment variable {} is not set", var);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Load the store from `path`, e.g. a file mounted read-only into a container. kv won't
+/// write anything afterwards.
+pub fn from_file(path: &str) {
+    match std::fs::read(path) {
+        Ok(bytes) => load(path.to_owned(), bytes),
+        Err(e) => {
+            let err_msg = format!("Error! Cannot read {}, error {}", path, e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Accept a store file, or just an object of keys to values, like `kv import` does.
+fn load(source: String, bytes: Vec<u8>) {
+    let json: serde_json::Value = match serde_json::from_slice(&bytes) {
+        Ok(json) => json,
+        Err(e) => {
+            let err_msg = format!("Error! {} is not valid JSON, error {}", source, e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let mut json = if json.get("kvs").is_some() {
+        json
+    } else {
+        serde_json::json!({ "kvs": json })
+    };
+    // A store for containers usually has no cmds or hooks, so don't insist on them.
+    if let Some(store) = json.as_object_mut() {
+        store.entry("cmds").or_insert_with(|| serde_json::json!({}));
+        store.entry("hooks").or_insert_with(|| serde_json::json!([]));
+    }
+    match serde_json::from_value::<KVStore>(json) {
+        Ok(kvstore) if kvstore.shards.is_some() => {
+            let err_msg = format!(
+                "Error! {} is a sharded store, which cannot be loaded on its own",
+                source
+            );
+            print_err(&err_msg[..]);
+        }
+        Ok(kvstore) => {
+            let bytes = serde_json::to_vec(&kvstore).unwrap();
+            let _ = STORE.set((source, bytes));
+        }
+        Err(e) => {
+            let err_msg = format!("Error! {} is not a kv store, error {}", source, e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// The read-only store's JSON, if kv was started with one.
+pub fn store_bytes() -> Option<&'static [u8]> {
+    STORE.get().map(|(_, bytes)| &bytes[..])
+}
+
+/// Where the read-only store came from, e.g. `$KV_STORE_JSON`.
+pub fn source() -> Option<&'static str> {
+    STORE.get().map(|(source, _)| &source[..])
+}
+
+pub fn is_active() -> bool {
+    STORE.get().is_some()
+}
+
+/// Refuse to go on if the store is read-only. Call before changing anything on disk.
+pub fn ensure_writable() {
+    check_writable().unwrap_or_else(|e| fail(e));
+}
+
+/// `ensure_writable`, returning the error.
+pub fn check_writable() -> Result<(), Error> {
+    if !options::get().read_only() {
+        return Ok(());
+    }
+    Err(Error::new(match source() {
+        Some(source) => format!(
+            "The store is read-only, it was loaded from {}. Nothing was changed.",
+            source
+        ),
+        None => "The store was opened read-only. Nothing was changed.".to_owned(),
+    }))
+}
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use crate::{get_file_location, print_aligned, stateless};
+
+/// The file sync service whose folder `path` is in, by the marker files each one keeps
+/// in the folders it syncs.
+pub fn service(path: &Path) -> Option<&'static str> {
+    let text = path.to_string_lossy();
+    if text.contains("/Library/Mobile Documents/") || text.contains("iCloud Drive") {
+        return Some("iCloud");
+    }
+    for dir in path.ancestors().skip(1) {
+        if dir.join(".dropbox").exists() || dir.join(".dropbox.cache").is_dir() {
+            return Some("Dropbox");
+        }
+        if dir.join(".stfolder").exists() {
+            return Some("Syncthing");
+        }
+        if dir
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("OneDrive"))
+        {
+            return Some("OneDrive");
+        }
+    }
+    None
+}
+
+/// Whether `name` is a sync service's copy of `stem.ext` made when two machines changed it
+/// at once: `kv (conflicted copy 2024-01-01).json` (Dropbox), `kv.sync-conflict-<...>.json`
+/// (Syncthing) or `kv 2.json` (iCloud).
+fn is_conflict_copy(name: &str, stem: &str, ext: &str) -> bool {
+    let Some(middle) = name
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_suffix(ext))
+    else {
+        return false;
+    };
+    middle.starts_with(" (") && middle.contains("conflicted copy")
+        || middle.starts_with(".sync-conflict-")
+        || middle
+            .strip_prefix(' ')
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Conflict copies of the store file `path` next to it, sorted.
+pub fn conflicts(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return vec![];
+    };
+    let stem = stem.to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut found: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|p| {
+                p.file_name()
+                    .is_some_and(|name| is_conflict_copy(&name.to_string_lossy(), &stem, &ext))
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    found.sort();
+    found
+}
+
+fn merge_hint(copies: &[PathBuf]) -> String {
+    copies
+        .iter()
+        .map(|copy| format!("\n kv sync merge '{}'", copy.to_string_lossy()))
+        .collect()
+}
+
+/// Safe mode for stores in a synced folder: warn about conflict copies the sync service
+/// left next to the store, which hold changes the store doesn't have.
+pub fn warn_conflicts() {
+    if stateless::is_active() {
+        return;
+    }
+    let path = get_file_location();
+    let Some(service) = service(&path) else {
+        return;
+    };
+    let copies = conflicts(&path);
+    if !copies.is_empty() {
+        eprintln!(
+            "Warning! {} left conflicted copies of the store next to it, with changes the store doesn't have. To merge them try{}",
+            service,
+            merge_hint(&copies)
+        );
+    }
+}
+
+/// Print the sync service the store is in, if any, and its conflict copies.
+pub fn status() {
+    let path = get_file_location();
+    let service = service(&path);
+    let copies = conflicts(&path);
+    let mut lines = vec![
+        format!("Store\t--\t{}", path.to_string_lossy()),
+        format!("Synced by\t--\t{}", service.unwrap_or("-")),
+    ];
+    lines.extend(
+        copies
+            .iter()
+            .map(|copy| format!("Conflict\t--\t{}", copy.to_string_lossy())),
+    );
+    print_aligned(lines);
+    if service.is_some() {
+        println!("kv's lock only covers this machine, so changing the store on two machines at once makes conflicted copies.");
+    }
+    if !copies.is_empty() {
+        println!("To merge the conflicted copies try{}", merge_hint(&copies));
+    }
+}
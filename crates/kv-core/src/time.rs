@@ -0,0 +1,174 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds since the unix epoch, from the builder's clock if one is set.
+pub fn now() -> u64 {
+    if let Some(clock) = crate::options::get().clock() {
+        return clock();
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// Split seconds since the epoch into UTC (year, month, day, hour, minute, second).
+fn to_utc(t: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let (y, m, d) = civil_from_days((t / 86400) as i64);
+    let secs = t % 86400;
+    (y, m, d, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Format seconds since the epoch as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_timestamp(t: u64) -> String {
+    let (y, m, d, h, min, sec) = to_utc(t);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", y, m, d, h, min, sec)
+}
+
+/// Format seconds since the epoch as `YYYY-MM-DD` (UTC).
+pub fn format_date(t: u64) -> String {
+    let (y, m, d, _, _, _) = to_utc(t);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Format seconds since the epoch as `YYYYMMDD-HHMMSS` (UTC), for use in file names.
+pub fn format_compact(t: u64) -> String {
+    let (y, m, d, h, min, sec) = to_utc(t);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", y, m, d, h, min, sec)
+}
+
+#[cfg(feature = "net")]
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+#[cfg(feature = "net")]
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[cfg(feature = "net")]
+/// Format seconds since the epoch as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(t: u64) -> String {
+    let (y, m, d, h, min, sec) = to_utc(t);
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(t / 86400 % 7) as usize],
+        d,
+        MONTHS[m as usize - 1],
+        y,
+        h,
+        min,
+        sec
+    )
+}
+
+#[cfg(feature = "net")]
+/// Parse an HTTP date as `format_http_date` writes it. The weekday isn't checked.
+pub fn parse_http_date(s: &str) -> Option<u64> {
+    let mut parts = s.split_once(", ")?.1.split(' ');
+    let d: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let m = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let y: i64 = parts.next()?.parse().ok()?;
+    let mut clock = parts.next()?.splitn(3, ':').map(|p| p.parse::<i64>());
+    let (h, min, sec) = (clock.next()?.ok()?, clock.next()?.ok()?, clock.next()?.ok()?);
+    if parts.next() != Some("GMT") {
+        return None;
+    }
+    epoch_secs(y, m, d, h, min, sec)
+}
+
+/// Parse a duration like `500ms`, `30s`, `15m`, `2h`, `3d` or `1w`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let err = || format!("Invalid duration '{}', expected e.g. 30s, 15m, 2h, 3d, 1w", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: u64 = num.parse().map_err(|_| err())?;
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(num)),
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return Err(err()),
+    };
+    num.checked_mul(secs).map(Duration::from_secs).ok_or_else(err)
+}
+
+/// Format a duration for humans, e.g. `1.25s` or `40ms`.
+pub fn format_duration(d: Duration) -> String {
+    if d.as_secs() > 0 {
+        format!("{:.2}s", d.as_secs_f64())
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
+
+/// Seconds since the epoch of a UTC date and time, None if a field is out of range. Years
+/// are limited to 0..=9999 so the arithmetic can't overflow.
+fn epoch_secs(y: i64, m: i64, d: i64, h: i64, min: i64, sec: i64) -> Option<u64> {
+    let in_range = (0..=9999).contains(&y)
+        && (1..=12).contains(&m)
+        && (1..=31).contains(&d)
+        && (0..=23).contains(&h)
+        && (0..=59).contains(&min)
+        && (0..=60).contains(&sec);
+    if !in_range {
+        return None;
+    }
+    let secs = days_from_civil(y, m, d)
+        .checked_mul(86400)?
+        .checked_add(h * 3600 + min * 60 + sec)?;
+    u64::try_from(secs).ok()
+}
+
+/// Parse `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS` (UTC) into seconds since the epoch.
+fn parse_date(s: &str) -> Option<u64> {
+    let (date, clock) = match s.split_once(['T', ' ']) {
+        Some((date, clock)) => (date, Some(clock)),
+        None => (s, None),
+    };
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>());
+    let (y, m, d) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    let (mut h, mut min, mut sec) = (0, 0, 0);
+    if let Some(clock) = clock {
+        let mut parts = clock.trim_end_matches('Z').splitn(3, ':').map(|p| p.parse::<i64>());
+        h = parts.next()?.ok()?;
+        min = parts.next().unwrap_or(Ok(0)).ok()?;
+        sec = parts.next().unwrap_or(Ok(0)).ok()?;
+    }
+    epoch_secs(y, m, d, h, min, sec)
+}
+
+/// Parse a point in time: either a date (`2024-01-01`) or a duration ago (`2d`).
+pub fn parse_time_spec(s: &str) -> Result<u64, String> {
+    if let Some(t) = parse_date(s) {
+        return Ok(t);
+    }
+    parse_duration(s)
+        .map(|ago| now().saturating_sub(ago.as_secs()))
+        .map_err(|_| format!("Invalid time '{}', expected a date like 2024-01-01 or a duration like 2d", s))
+}
@@ -0,0 +1,73 @@
+use std::process::{Command, Stdio};
+
+use crate::{export, print_err, KV};
+
+/// Shells that understand the `eval "$(...)"` line sent to panes.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh"];
+
+fn tmux(args: &[&str]) -> String {
+    let output = Command::new("tmux")
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(_) => {
+            let err_msg = format!("Error! tmux {} failed", args[0]);
+            print_err(&err_msg[..]);
+        }
+        Err(e) => {
+            let err_msg = format!("Error! Cannot run tmux, error {}", e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Set `kvs` (already stripped of `prefix`) in the environment of tmux session `target`, or
+/// the current one, so new panes and windows get them. With `panes`, also make every pane
+/// sitting at a shell prompt re-export them, by typing an `eval` of `kv export` into it.
+/// Values are never typed into panes.
+pub fn refresh(kvs: &KV, prefix: &str, target: Option<&str>, panes: bool) {
+    if target.is_none() && std::env::var_os("TMUX").is_none() {
+        print_err("Error! Not inside tmux. To pick a session try\n kv tmux-refresh --target <session> ...");
+    }
+    let vars = match export::env_vars(kvs) {
+        Ok(vars) => vars,
+        Err(e) => print_err(&format!("Error! Cannot refresh, {}", e)),
+    };
+    let mut target_args = vec![];
+    if let Some(target) = target {
+        target_args = vec!["-t", target];
+    }
+    for (name, (_, val)) in &vars {
+        let mut args = vec!["set-environment"];
+        args.extend(&target_args);
+        args.extend([&name[..], &val[..]]);
+        tmux(&args);
+    }
+    println!("Set {} variables in the tmux session", vars.len());
+    if !panes {
+        return;
+    }
+
+    let mut args = vec!["list-panes", "-s"];
+    args.extend(&target_args);
+    args.extend(["-F", "#{pane_id} #{pane_current_command}"]);
+    let line = format!(
+        " eval \"$(kv export --format shell --prefix '{}')\"",
+        prefix.replace('\'', "'\\''")
+    );
+    let mut refreshed = 0;
+    for pane in tmux(&args).lines() {
+        let Some((id, command)) = pane.split_once(' ') else {
+            continue;
+        };
+        if SHELLS.contains(&command.trim_start_matches('-')) {
+            tmux(&["send-keys", "-t", id, &line, "Enter"]);
+            refreshed += 1;
+        }
+    }
+    println!("Refreshed {} panes at a shell prompt", refreshed);
+}
@@ -0,0 +1,72 @@
+use crate::{get_store, glob, print_err, script, with_store_lock, write_file, KVStore};
+
+/// `value` as the transforms on `key` leave it, or why one of them rejected it. Every
+/// transform whose key pattern matches runs in turn, in the order of the patterns, with
+/// `key`, `op` and `value` in scope. A script ending in a value has that stored instead, one
+/// ending in `()` has `value` stored as it left it, and one that throws rejects it.
+pub fn apply(kvstore: &KVStore, key: &str, value: &str) -> Result<String, String> {
+    let mut value = value.to_owned();
+    for (pattern, cmd_name) in &kvstore.transforms {
+        if !glob::matches(pattern, key) {
+            continue;
+        }
+        let script = kvstore
+            .cmds
+            .get(cmd_name)
+            .ok_or_else(|| format!("its transform cmd {} does not exist", cmd_name))?;
+        let (printed, result) = script::run_rhai(script, Some(key), Some("set"), Some(&value))
+            .map_err(|e| format!("{} rejected it, {}", cmd_name, e))?;
+        // stdout is kv's own output, such as kv serve's answers.
+        eprint!("{}", printed);
+        if let Some(result) = result {
+            value = result;
+        }
+    }
+    Ok(value)
+}
+
+/// Run rhai cmd `cmd_name` on values set on keys matching `pattern`, replacing any transform
+/// of that pattern.
+pub fn add(pattern: &str, cmd_name: &str) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if !kvstore.cmds.contains_key(cmd_name) {
+            let err_msg = format!("Error! Command {} does not exist!", cmd_name);
+            print_err(&err_msg[..]);
+        }
+        if kvstore.cmd_lang(cmd_name) != "rhai" {
+            let err_msg = format!(
+                "Error! Transforms are rhai scripts, and {} is a {} cmd. To add it as one try\n kv cmd add --lang rhai {} '<script>'",
+                cmd_name,
+                kvstore.cmd_lang(cmd_name),
+                cmd_name
+            );
+            print_err(&err_msg[..]);
+        }
+        kvstore.transforms.insert(pattern.to_owned(), cmd_name.to_owned());
+        write_file(&kvstore);
+    });
+}
+
+pub fn del(pattern: &str) {
+    with_store_lock(|| {
+        let mut kvstore = get_store();
+        if kvstore.transforms.remove(pattern).is_none() {
+            let err_msg = format!("Error! There is no transform on {}", pattern);
+            print_err(&err_msg[..]);
+        }
+        write_file(&kvstore);
+    });
+}
+
+/// Lines for `kv transform list`.
+pub fn list(kvstore: &KVStore) -> Vec<String> {
+    let mut lines = vec!["Key\t--\tCmd Name".to_owned()];
+    lines.extend(
+        kvstore
+            .transforms
+            .iter()
+            .map(|(pattern, cmd_name)| format!("{}\t--\t{}", pattern, cmd_name)),
+    );
+    lines
+}
@@ -0,0 +1,31 @@
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Error, KVStore};
+
+/// Typed reads and writes on top of the string values, for code embedding the store.
+impl KVStore {
+    /// `key`'s value parsed as a `T`, e.g. `get_as::<u16>("port")`. None if the key is missing.
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+        self.kvs.get(key).map(|val| val.parse())
+    }
+
+    /// `key`'s value deserialized from JSON. None if the key is missing.
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> Option<serde_json::Result<T>> {
+        self.kvs.get(key).map(|val| serde_json::from_str(val))
+    }
+
+    /// Set `key` to `value` serialized as JSON, returning the old value. Otherwise like `set`:
+    /// an opened store writes the store file and runs the key's set hooks.
+    pub fn set_serialized<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<Option<String>, Error> {
+        let json = serde_json::to_string(value)
+            .map_err(|e| Error::new(format!("Cannot serialize the value of {}, error {}", key, e)))?;
+        self.set(key, &json)
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+use crate::print_err;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/obaraelijah/kv/releases/latest";
+/// Release asset listing `<sha256>  <asset name>` for every binary in the release.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Name of the release binary built for this platform, e.g. `kv-x86_64-linux`.
+fn asset_name() -> String {
+    let ext = if cfg!(windows) { ".exe" } else { "" };
+    format!("kv-{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, ext)
+}
+
+/// `v1.2.3` -> `[1, 2, 3]`, so versions compare numerically.
+fn parse_version(v: &str) -> Vec<u64> {
+    v.trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+fn download(url: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let res = ureq::get(url)
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|r| {
+            r.into_reader()
+                .read_to_end(&mut bytes)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = res {
+        let err_msg = format!("Error! Cannot download {}, error {}", url, e);
+        print_err(&err_msg[..]);
+    }
+    bytes
+}
+
+fn latest_release() -> Release {
+    match ureq::get(RELEASES_URL).call().map(|r| r.into_json::<Release>()) {
+        Ok(Ok(release)) => release,
+        Ok(Err(e)) => {
+            let err_msg = format!("Error! Unexpected response from GitHub, error {}", e);
+            print_err(&err_msg[..]);
+        }
+        Err(e) => {
+            let err_msg = format!("Error! Cannot check for updates, error {}", e);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+fn find_asset<'a>(release: &'a Release, name: &str) -> &'a Asset {
+    match release.assets.iter().find(|a| a.name == name) {
+        Some(asset) => asset,
+        None => {
+            let err_msg = format!(
+                "Error! Release {} has no {} asset. Try installing with cargo instead.",
+                release.tag_name, name
+            );
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+/// Write `bytes` next to the running binary, then rename it over the binary.
+fn replace_binary(exe: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = exe.with_extension("new");
+    std::fs::write(&tmp, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+    }
+    // Windows won't overwrite a running executable, but it will rename one.
+    #[cfg(windows)]
+    std::fs::rename(exe, exe.with_extension("old"))?;
+    std::fs::rename(&tmp, exe)
+}
+
+/// Check GitHub for a newer release and, unless `check_only`, install it over this binary
+/// after verifying its SHA-256 against the release's `SHA256SUMS`.
+pub fn self_update(check_only: bool) {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = latest_release();
+    if parse_version(&release.tag_name) <= parse_version(current) {
+        println!("kv {} is up to date.", current);
+        return;
+    }
+    if check_only {
+        println!(
+            "kv {} is available (you have {}). To update try\n kv self-update",
+            release.tag_name, current
+        );
+        return;
+    }
+
+    let name = asset_name();
+    let binary = download(&find_asset(&release, &name).browser_download_url);
+    let sums = download(&find_asset(&release, CHECKSUMS_ASSET).browser_download_url);
+    let expected = String::from_utf8_lossy(&sums)
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(sum, _)| sum.to_lowercase());
+    let actual: String = Sha256::digest(&binary)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    match expected {
+        Some(sum) if sum == actual => {}
+        Some(_) => print_err("Error! Checksum mismatch, the download may be corrupt. Nothing was changed."),
+        None => {
+            let err_msg = format!("Error! {} has no checksum for {}. Nothing was changed.", CHECKSUMS_ASSET, name);
+            print_err(&err_msg[..]);
+        }
+    }
+
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            let err_msg = format!("Error! Cannot find the running kv binary, error {}", e);
+            print_err(&err_msg[..]);
+        }
+    };
+    if let Err(e) = replace_binary(&exe, &binary) {
+        let err_msg = format!(
+            "Error! Cannot replace {}, error {}",
+            exe.to_string_lossy(),
+            e
+        );
+        print_err(&err_msg[..]);
+    }
+    println!("Updated kv {} -> {}", current, release.tag_name);
+}
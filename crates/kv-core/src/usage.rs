@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::{config, get_config_dir, get_file_location, print_aligned, stateless, time};
+
+/// One line of `usage.jsonl`. Nothing here ever leaves the machine.
+#[derive(Serialize, Deserialize)]
+struct Event {
+    ts: u64,
+    /// `command`, `failure` or `hook-error`.
+    kind: String,
+    /// Command (e.g. `cmd run`) or hook name.
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    millis: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    store_bytes: Option<u64>,
+}
+
+/// The command being run, when it started, and whether recording is on.
+static CURRENT: OnceLock<(String, Instant, bool)> = OnceLock::new();
+
+fn get_usage_location() -> PathBuf {
+    get_config_dir().join("usage.jsonl")
+}
+
+fn enabled() -> bool {
+    CURRENT.get().is_some_and(|(_, _, enabled)| *enabled) && !stateless::is_active()
+}
+
+/// Append an event. Failing to record usage is never worth failing the command for, so
+/// errors are ignored.
+fn append(kind: &str, name: &str, millis: Option<u64>) {
+    let event = Event {
+        ts: time::now(),
+        kind: kind.to_owned(),
+        name: name.to_owned(),
+        millis,
+        store_bytes: std::fs::metadata(get_file_location()).map(|m| m.len()).ok(),
+    };
+    if let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_usage_location())
+    {
+        let _ = writeln!(file, "{}", serde_json::to_string(&event).unwrap());
+    }
+}
+
+/// Remember which command is running. Call once, before running it.
+pub fn start(command: &str) {
+    // Read the config before setting CURRENT: if it is broken, print_err calls `failure`,
+    // which must not try to read it again.
+    let enabled = config::get().get_bool("report.enabled");
+    let _ = CURRENT.set((command.to_owned(), Instant::now(), enabled));
+}
+
+fn current() -> Option<(&'static str, u64)> {
+    CURRENT
+        .get()
+        .map(|(name, start, _)| (&name[..], start.elapsed().as_millis() as u64))
+}
+
+/// Record that the current command finished.
+pub fn finish() {
+    if let Some((name, millis)) = current() {
+        if enabled() {
+            append("command", name, Some(millis));
+        }
+    }
+}
+
+/// Record that the current command failed.
+pub fn failure() {
+    if let Some((name, millis)) = current() {
+        if enabled() {
+            append("failure", name, Some(millis));
+        }
+    }
+}
+
+/// Record that hook `name` could not run.
+pub fn hook_error(name: &str) {
+    if enabled() {
+        append("hook-error", name, None);
+    }
+}
+
+fn read_events() -> Vec<Event> {
+    match std::fs::File::open(get_usage_location()) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+#[derive(Default)]
+struct CommandStats {
+    runs: u64,
+    failures: u64,
+    millis: u64,
+}
+
+/// Print a summary of `usage.jsonl`: how often each command ran and failed, which hooks
+/// failed, and how the store grew per day.
+pub fn report() {
+    let events = read_events();
+    if events.is_empty() {
+        if config::get().get_bool("report.enabled") {
+            println!("Nothing recorded yet.");
+        } else {
+            println!("Usage recording is off. To turn it on try\n kv config set report.enabled true");
+        }
+        return;
+    }
+
+    let mut commands: BTreeMap<&str, CommandStats> = BTreeMap::new();
+    let mut hook_errors: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut sizes: BTreeMap<String, u64> = BTreeMap::new();
+    for event in &events {
+        match &event.kind[..] {
+            "hook-error" => *hook_errors.entry(&event.name).or_default() += 1,
+            kind => {
+                let stats = commands.entry(&event.name).or_default();
+                stats.runs += 1;
+                stats.millis += event.millis.unwrap_or(0);
+                if kind == "failure" {
+                    stats.failures += 1;
+                }
+            }
+        }
+        if let Some(bytes) = event.store_bytes {
+            sizes.insert(time::format_date(event.ts), bytes);
+        }
+    }
+
+    println!(
+        "Usage from {} to {} ({} events)\n",
+        time::format_date(events[0].ts),
+        time::format_date(events[events.len() - 1].ts),
+        events.len()
+    );
+    let mut lines = vec!["Command\t--\tRuns\t--\tFailures\t--\tAvg Time".to_owned()];
+    let mut by_runs: Vec<_> = commands.iter().collect();
+    by_runs.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.runs));
+    lines.extend(by_runs.iter().map(|(name, stats)| {
+        format!(
+            "{}\t--\t{}\t--\t{}\t--\t{}",
+            name,
+            stats.runs,
+            stats.failures,
+            time::format_duration(Duration::from_millis(stats.millis / stats.runs))
+        )
+    }));
+    print_aligned(lines);
+
+    if !hook_errors.is_empty() {
+        println!();
+        let mut lines = vec!["Hook Name\t--\tErrors".to_owned()];
+        lines.extend(
+            hook_errors
+                .iter()
+                .map(|(name, count)| format!("{}\t--\t{}", name, count)),
+        );
+        print_aligned(lines);
+    }
+
+    println!();
+    let mut lines = vec!["Day\t--\tStore Size".to_owned()];
+    let skip = sizes.len().saturating_sub(14);
+    lines.extend(
+        sizes
+            .iter()
+            .skip(skip)
+            .map(|(day, bytes)| format!("{}\t--\t{} bytes", day, bytes)),
+    );
+    print_aligned(lines);
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use crate::{print_err, KV};
+
+/// Where Vault is and how to authenticate, read the same way the `vault` CLI does.
+struct Client {
+    addr: String,
+    token: String,
+    namespace: Option<String>,
+}
+
+fn client() -> Client {
+    let addr = match std::env::var("VAULT_ADDR") {
+        Ok(addr) => addr.trim_end_matches('/').to_owned(),
+        Err(_) => print_err(
+            "Error! VAULT_ADDR is not set, e.g.\n export VAULT_ADDR=https://vault.example.com:8200",
+        ),
+    };
+    let token_file = dirs::home_dir()
+        .map(|home| home.join(".vault-token"))
+        .unwrap_or_else(|| PathBuf::from(".vault-token"));
+    let token = match std::env::var("VAULT_TOKEN") {
+        Ok(token) => token,
+        Err(_) => match std::fs::read_to_string(token_file) {
+            Ok(token) => token.trim().to_owned(),
+            Err(_) => {
+                print_err("Error! No Vault token. Set VAULT_TOKEN, or log in with\n vault login")
+            }
+        },
+    };
+    Client {
+        addr,
+        token,
+        namespace: std::env::var("VAULT_NAMESPACE").ok(),
+    }
+}
+
+/// `secret/myapp/db` -> the KV v2 API url `.../v1/secret/data/myapp/db`. The first path
+/// segment is the mount.
+fn data_url(client: &Client, path: &str) -> String {
+    let path = path.trim_matches('/');
+    match path.split_once('/') {
+        Some((mount, rest)) => format!("{}/v1/{}/data/{}", client.addr, mount, rest),
+        None => {
+            let err_msg = format!("Error! {} must be <mount>/<path>, e.g. secret/myapp", path);
+            print_err(&err_msg[..]);
+        }
+    }
+}
+
+fn request(client: &Client, method: &str, url: &str) -> ureq::Request {
+    let req = ureq::request(method, url).set("X-Vault-Token", &client.token);
+    match &client.namespace {
+        Some(ns) => req.set("X-Vault-Namespace", ns),
+        None => req,
+    }
+}
+
+fn vault_error(path: &str, e: ureq::Error) -> ! {
+    let err_msg = match e {
+        ureq::Error::Status(404, _) => format!("Error! Vault has no secret at {}", path),
+        ureq::Error::Status(403, _) => format!(
+            "Error! Vault denied access to {}. Check your token and its policies.",
+            path
+        ),
+        e => format!("Error! Vault request for {} failed, error {}", path, e),
+    };
+    print_err(&err_msg[..]);
+}
+
+/// The latest version of the KV v2 secret at `path`. Non-string values are stored as their
+/// JSON text.
+pub fn pull(path: &str) -> KV {
+    let client = client();
+    let res = request(&client, "GET", &data_url(&client, path))
+        .call()
+        .unwrap_or_else(|e| vault_error(path, e));
+    let json: serde_json::Value = match res.into_json() {
+        Ok(json) => json,
+        Err(e) => {
+            let err_msg = format!("Error! Unexpected response from Vault, error {}", e);
+            print_err(&err_msg[..]);
+        }
+    };
+    let data = json["data"]["data"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    data.into_iter()
+        .map(|(key, val)| match val {
+            serde_json::Value::String(s) => (key, s),
+            val => (key, val.to_string()),
+        })
+        .collect()
+}
+
+/// Write `kvs` as a new version of the KV v2 secret at `path`.
+pub fn push(path: &str, kvs: &KV) {
+    let client = client();
+    request(&client, "POST", &data_url(&client, path))
+        .send_json(serde_json::json!({ "data": kvs }))
+        .unwrap_or_else(|e| vault_error(path, e));
+}
@@ -0,0 +1,238 @@
+use std::ffi::OsString;
+
+use crate::{
+    action, config, elevate, get_config_dir, get_file_location, options, print_aligned, print_err, safety,
+    sandbox, script, shard, sync, try_get_store, Backend, Hook, KVStore,
+};
+
+/// Result of one check.
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+struct Check {
+    name: String,
+    status: Status,
+    details: String,
+}
+
+fn check(name: &str, result: Result<String, String>) -> Check {
+    let (status, details) = match result {
+        Ok(details) => (Status::Ok, details),
+        Err(details) => (Status::Error, details),
+    };
+    Check {
+        name: name.to_owned(),
+        status,
+        details,
+    }
+}
+
+fn check_store() -> (Check, Option<KVStore>) {
+    let path = get_file_location();
+    if options::get().backend() == Backend::Sqlite {
+        return match try_get_store() {
+            Ok(kvstore) => {
+                let ok = Ok(format!("{} (SQLite, {} keys)", path.to_string_lossy(), kvstore.kvs.len()));
+                (check("store", ok), Some(kvstore))
+            }
+            Err(e) => (check("store", Err(e.to_string())), None),
+        };
+    }
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let ok = Ok(format!("{} does not exist yet", path.to_string_lossy()));
+            return (check("store", ok), None);
+        }
+        Err(e) => {
+            let err = Err(format!("cannot read {}: {}", path.to_string_lossy(), e));
+            return (check("store", err), None);
+        }
+    };
+    match serde_json::from_slice::<KVStore>(&bytes) {
+        Ok(kvstore) => {
+            let ok = Ok(format!(
+                "{} ({} bytes)",
+                path.to_string_lossy(),
+                bytes.len()
+            ));
+            (check("store", ok), Some(kvstore))
+        }
+        Err(e) => {
+            let err = Err(format!("cannot parse {}: {}", path.to_string_lossy(), e));
+            (check("store", err), None)
+        }
+    }
+}
+
+fn check_hooks(kvstore: &KVStore) -> Vec<Check> {
+    let mut checks = vec![];
+    for hook in &kvstore.hooks {
+        let name = format!("hook {}", hook.name);
+        if !kvstore.cmds.contains_key(&hook.cmd_name) {
+            checks.push(check(
+                &name,
+                Err(format!("cmd {} does not exist", hook.cmd_name)),
+            ));
+            continue;
+        }
+        let result = match hook.action() {
+            "shell" if kvstore.cmd_lang(&hook.cmd_name) == "lua" => {
+                script::lua_argv(&[]).and_then(|_| shell_hook_ok(hook))
+            }
+            "shell" if kvstore.cmd_lang(&hook.cmd_name) == "rhai" => {
+                script::check_rhai(&kvstore.cmds[&hook.cmd_name]).map(|_| "rhai script".to_owned())
+            }
+            "shell" => shell_hook_ok(hook),
+            "webhook" => match kvstore.cmds[&hook.cmd_name].trim() {
+                url if url.starts_with("http://") || url.starts_with("https://") => {
+                    Ok(format!("webhook to {}", url))
+                }
+                _ => Err(format!("cmd {} is not an http(s) URL", hook.cmd_name)),
+            },
+            "notify" => action::notify_argv("", "").map(|_| hook.options()),
+            "wasm" => {
+                let module = kvstore.cmds[&hook.cmd_name].trim();
+                check_wasm(module).map(|_| format!("wasm module {}", module))
+            }
+            other => Err(format!("there is no {} action", other)),
+        };
+        checks.push(check(&name, result));
+    }
+    checks
+}
+
+/// Each transform's cmd must be a rhai script that parses.
+fn check_transforms(kvstore: &KVStore) -> Vec<Check> {
+    kvstore
+        .transforms
+        .iter()
+        .map(|(pattern, cmd_name)| {
+            let result = match kvstore.cmds.get(cmd_name) {
+                None => Err(format!("cmd {} does not exist", cmd_name)),
+                Some(_) if kvstore.cmd_lang(cmd_name) != "rhai" => {
+                    Err(format!("cmd {} is not a rhai script", cmd_name))
+                }
+                Some(script) => script::check_rhai(script).map(|_| format!("rhai cmd {}", cmd_name)),
+            };
+            check(&format!("transform {}", pattern), result)
+        })
+        .collect()
+}
+
+/// Load the module a wasm hook would call, which fails the same way.
+#[cfg(feature = "wasm")]
+fn check_wasm(module: &str) -> Result<(), String> {
+    crate::wasm::check(std::path::Path::new(module))
+}
+
+#[cfg(not(feature = "wasm"))]
+fn check_wasm(_module: &str) -> Result<(), String> {
+    Err(crate::features::missing("wasm"))
+}
+
+/// Build the wrapped command the hook would run, which fails the same way.
+fn shell_hook_ok(hook: &Hook) -> Result<String, String> {
+    let mut argv: Vec<OsString> = vec!["true".into()];
+    if hook.sandbox {
+        argv = sandbox::wrap(argv)?;
+    }
+    if let Some(user) = &hook.run_as {
+        elevate::wrap(user, hook.elevate_with.as_deref(), argv)?;
+    }
+    Ok(hook.options())
+}
+
+/// Check the config, the store and everything hooks need to run, print a report, and exit
+/// non-zero if anything is broken. Meant to run before kv is started unattended, e.g. as an
+/// `ExecStartPre=` of a systemd unit, so a bad setup fails fast instead of at the first hook.
+pub fn verify_config() {
+    let mut checks = vec![];
+
+    let dir = get_config_dir();
+    let probe = dir.join(".verify-config");
+    let writable = std::fs::write(&probe, b"").and_then(|_| std::fs::remove_file(&probe));
+    checks.push(check(
+        "config dir",
+        writable
+            .map(|_| dir.to_string_lossy().into_owned())
+            .map_err(|e| format!("{} is not writable: {}", dir.to_string_lossy(), e)),
+    ));
+
+    let problems = config::check();
+    checks.push(check(
+        "settings",
+        if problems.is_empty() {
+            Ok("valid".to_owned())
+        } else {
+            Err(problems.join("; "))
+        },
+    ));
+
+    let (store_check, kvstore) = check_store();
+    checks.push(store_check);
+    let path = get_file_location();
+    if let Some(service) = sync::service(&path) {
+        let copies = sync::conflicts(&path);
+        checks.push(Check {
+            name: "sync".to_owned(),
+            status: Status::Warning,
+            details: if copies.is_empty() {
+                format!("store is in a {} folder; changing it on two machines at once makes conflicted copies", service)
+            } else {
+                format!("{} conflicted copies to merge, see kv sync status", copies.len())
+            },
+        });
+    }
+    if let Some(kvstore) = kvstore {
+        if let Some(shards) = kvstore.shards {
+            let problems = shard::check(shards);
+            checks.push(check(
+                "shards",
+                if problems.is_empty() {
+                    Ok(format!("{} shards", shards))
+                } else {
+                    Err(problems.join("; "))
+                },
+            ));
+        }
+        let mut bad_cmds: Vec<&str> = kvstore
+            .cmds
+            .iter()
+            .filter(|(_, cmd)| !safety::review(cmd).is_empty())
+            .map(|(name, _)| &name[..])
+            .collect();
+        if !bad_cmds.is_empty() {
+            bad_cmds.sort();
+            checks.push(Check {
+                name: "cmds".to_owned(),
+                status: Status::Warning,
+                details: format!("look dangerous: {}", bad_cmds.join(", ")),
+            });
+        }
+        checks.extend(check_hooks(&kvstore));
+        checks.extend(check_transforms(&kvstore));
+    }
+
+    let mut lines = vec!["Check\t--\tStatus\t--\tDetails".to_owned()];
+    let mut errors = 0;
+    for c in &checks {
+        let status = match c.status {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => {
+                errors += 1;
+                "error"
+            }
+        };
+        lines.push(format!("{}\t--\t{}\t--\t{}", c.name, status, c.details));
+    }
+    print_aligned(lines);
+    if errors > 0 {
+        let err_msg = format!("\nError! {} of {} checks failed.", errors, checks.len());
+        print_err(&err_msg[..]);
+    }
+}
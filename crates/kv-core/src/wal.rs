@@ -0,0 +1,69 @@
+//! The write-ahead log of `kv serve` with `serve.flush-interval` set. Changes are logged
+//! here before they're acknowledged and written to the store together later, so a crash in
+//! between loses nothing: the next `kv serve` writes what's logged first.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, Write};
+use std::path::PathBuf;
+
+use crate::{retry, try_file_location, with_suffix, Error};
+
+/// A key with its new value, or None to delete it.
+pub type Change = (String, Option<String>);
+
+pub struct Wal {
+    file: File,
+    path: PathBuf,
+    /// Sync every append to disk, so not even a power cut loses acknowledged changes.
+    fsync: bool,
+}
+
+impl Wal {
+    /// The log next to the store file, e.g. `kv.json.wal`.
+    pub fn open(fsync: bool) -> Result<Wal, Error> {
+        let path = with_suffix(&try_file_location()?, ".wal");
+        let file = retry::io("open", &path, || {
+            OpenOptions::new().read(true).append(true).create(true).open(&path)
+        })?;
+        Ok(Wal { file, path, fsync })
+    }
+
+    /// The batches of changes logged, oldest first. A last batch cut short by a crash wasn't
+    /// acknowledged, so it's left out.
+    pub fn read(&mut self) -> Result<Vec<Vec<Change>>, Error> {
+        self.file.rewind().map_err(|e| self.error("read", e))?;
+        let mut batches = vec![];
+        for line in BufReader::new(&self.file).lines() {
+            let line = line.map_err(|e| self.error("read", e))?;
+            match serde_json::from_str(&line) {
+                Ok(batch) => batches.push(batch),
+                Err(_) => break,
+            }
+        }
+        Ok(batches)
+    }
+
+    /// Log `changes` as one batch, all or nothing.
+    pub fn append(&mut self, changes: &[Change]) -> Result<(), Error> {
+        let mut line = serde_json::to_string(changes).unwrap();
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).map_err(|e| self.error("write", e))?;
+        if self.fsync {
+            self.file.sync_data().map_err(|e| self.error("write", e))?;
+        }
+        Ok(())
+    }
+
+    /// Forget everything logged, once it's in the store.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.file.set_len(0).map_err(|e| self.error("write", e))?;
+        if self.fsync {
+            self.file.sync_data().map_err(|e| self.error("write", e))?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, what: &str, e: std::io::Error) -> Error {
+        Error::new(format!("Cannot {} {}, error {}", what, self.path.to_string_lossy(), e))
+    }
+}
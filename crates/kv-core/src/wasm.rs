@@ -0,0 +1,113 @@
+//! wasm hooks, run in an embedded wasmtime.
+//!
+//! A hook module exports its `memory`, `alloc(len: i32) -> i32` returning room for `len`
+//! bytes, and
+//!
+//! ```text
+//! on_event(key_ptr: i32, key_len: i32, op_ptr: i32, op_len: i32, value_ptr: i32, value_len: i32) -> i32
+//! ```
+//!
+//! which gets the key, op (`get`, `set` or `del`) and value as UTF-8 strings in that
+//! memory, with a `value_len` of -1 when there is no value (del and secret keys). Returning
+//! anything but 0 fails the hook. The module can't import anything, so it has no files,
+//! network or clock, and it runs with limited fuel and memory.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::action::HookEvent;
+
+/// Roughly the instructions a hook may run before it's stopped.
+const FUEL: u64 = 1_000_000_000;
+
+/// The most memory a hook's module may grow to.
+const MAX_MEMORY: usize = 64 << 20;
+
+type OnEvent = TypedFunc<(i32, i32, i32, i32, i32, i32), i32>;
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).unwrap()
+    })
+}
+
+/// Compile the module at `path`, which can be a `.wasm` or `.wat` file, and check that it
+/// imports nothing.
+pub fn load(path: &Path) -> Result<Module, String> {
+    let module = Module::from_file(engine(), path)
+        .map_err(|e| format!("cannot load {}, {:#}", path.to_string_lossy(), e))?;
+    if let Some(import) = module.imports().next() {
+        return Err(format!(
+            "{} imports {}::{}, wasm hooks can't import anything",
+            path.to_string_lossy(),
+            import.module(),
+            import.name()
+        ));
+    }
+    Ok(module)
+}
+
+/// Check that the module at `path` loads and has the exports a hook needs.
+pub fn check(path: &Path) -> Result<(), String> {
+    let module = load(path)?;
+    let mut store = new_store();
+    exports(&mut store, &module).map(|_| ())
+}
+
+/// Call the module's `on_event` with `event`.
+pub fn run(path: &Path, event: &HookEvent) -> Result<(), String> {
+    let module = load(path)?;
+    let mut store = new_store();
+    let (instance, memory, on_event) = exports(&mut store, &module)?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")
+        .map_err(|e| format!("bad alloc export, {:#}", e))?;
+    let op = event.op.to_string();
+    let mut args = vec![];
+    for arg in [Some(event.key), Some(&op[..]), event.value] {
+        let Some(arg) = arg else {
+            args.extend([0, -1]);
+            continue;
+        };
+        let len = i32::try_from(arg.len()).map_err(|_| "the value is too large".to_owned())?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| format!("alloc failed, {:#}", e))?;
+        memory
+            .write(&mut store, ptr as u32 as usize, arg.as_bytes())
+            .map_err(|_| format!("alloc returned {}, outside the module's memory", ptr))?;
+        args.extend([ptr, len]);
+    }
+    let status = on_event
+        .call(&mut store, (args[0], args[1], args[2], args[3], args[4], args[5]))
+        .map_err(|e| format!("on_event failed, {:#}", e))?;
+    match status {
+        0 => Ok(()),
+        status => Err(format!("on_event returned {}", status)),
+    }
+}
+
+fn new_store() -> Store<StoreLimits> {
+    let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).instances(1).build();
+    let mut store = Store::new(engine(), limits);
+    store.limiter(|limits| limits);
+    store.set_fuel(FUEL).unwrap();
+    store
+}
+
+fn exports(store: &mut Store<StoreLimits>, module: &Module) -> Result<(Instance, Memory, OnEvent), String> {
+    let instance = Instance::new(&mut *store, module, &[]).map_err(|e| format!("cannot start the module, {:#}", e))?;
+    let memory = instance
+        .get_memory(&mut *store, "memory")
+        .ok_or_else(|| "the module doesn't export its memory".to_owned())?;
+    if instance.get_func(&mut *store, "alloc").is_none() {
+        return Err("the module doesn't export alloc".to_owned());
+    }
+    let on_event = instance
+        .get_typed_func(&mut *store, "on_event")
+        .map_err(|e| format!("bad on_event export, {:#}", e))?;
+    Ok((instance, memory, on_event))
+}
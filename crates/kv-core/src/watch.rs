@@ -0,0 +1,51 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{get_file_location, get_value, print_res, shell_command};
+
+/// What changes when the store is written: its modification time and size. Writes replace
+/// the file, so this also catches writes within the same mtime tick most of the time.
+fn stamp() -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(get_file_location()).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Print the value of `key` (an empty line once it's deleted) every time it changes, until
+/// interrupted. The store file is checked every `interval`. With `exec`, that shell command
+/// runs on each change instead, with the key and value in `KV_KEY` and `KV_VALUE`.
+pub fn run(key: &str, interval: Duration, exec: Option<&str>, initial: bool) {
+    let mut last_stamp = stamp();
+    let mut last = get_value(key);
+    if initial {
+        changed(key, &last, exec);
+    }
+    loop {
+        std::thread::sleep(interval);
+        let now_stamp = stamp();
+        if now_stamp == last_stamp {
+            continue;
+        }
+        last_stamp = now_stamp;
+        let value = get_value(key);
+        if value != last {
+            changed(key, &value, exec);
+            last = value;
+        }
+    }
+}
+
+fn changed(key: &str, value: &Option<String>, exec: Option<&str>) {
+    match exec {
+        Some(cmd) => {
+            let mut command = shell_command(cmd);
+            command.env("KV_KEY", key);
+            match value {
+                Some(value) => command.env("KV_VALUE", value),
+                None => command.env_remove("KV_VALUE"),
+            };
+            if let Err(e) = command.status() {
+                eprintln!("Warning! Cannot run '{}', error {}", cmd, e);
+            }
+        }
+        None => print_res(value.clone()),
+    }
+}
@@ -0,0 +1,33 @@
+//! Stores built with different options each keep theirs, and applying different options a
+//! second time fails.
+
+use kv::KVStore;
+
+#[test]
+fn builders_with_different_paths() {
+    let dir = std::env::temp_dir().join(format!("kv-lib-builder-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("kv")).unwrap();
+    std::fs::write(dir.join("kv/config.json"), r#"{ "io.retry-for": "0s" }"#).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+    let (first_path, second_path) = (dir.join("first.json"), dir.join("second.json"));
+
+    let mut first = KVStore::builder().path(&first_path).build().unwrap();
+    let mut second = KVStore::builder().path(&second_path).build().unwrap();
+    assert_eq!(second.path(), Some(second_path.as_path()));
+    first.set("which", "first").unwrap();
+    second.set("which", "second").unwrap();
+    second.set("only", "second").unwrap();
+    assert_eq!(first.get("which"), Some("first"));
+    assert_eq!(first.get("only"), None);
+    assert_eq!(second.get("which"), Some("second"));
+    assert!(std::fs::read_to_string(&first_path).unwrap().contains("first"));
+    assert!(!std::fs::read_to_string(&first_path).unwrap().contains("second"));
+    assert!(std::fs::read_to_string(&second_path).unwrap().contains("second"));
+
+    // The first builder's options stay in effect for the process.
+    assert_eq!(KVStore::open().unwrap().get("which"), Some("first"));
+    assert!(KVStore::builder().path(&first_path).apply().is_ok());
+    let e = KVStore::builder().path(&second_path).apply().err().unwrap();
+    assert_eq!(e.to_string(), "Different store options were applied already");
+}
@@ -0,0 +1,41 @@
+//! Hooks and the event log through the library: in-memory stores run no hooks, and a
+//! failure after the store was written comes back as an error instead of a panic.
+
+use kv::{KVStore, OpType};
+
+#[test]
+fn library_hooks_and_errors() {
+    let dir = std::env::temp_dir().join(format!("kv-lib-hooks-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("kv")).unwrap();
+    std::fs::write(
+        dir.join("kv/config.json"),
+        r#"{ "io.retry-for": "0s", "events.enabled": "true" }"#,
+    )
+    .unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+    let marker = dir.join("ran");
+    let store = serde_json::json!({
+        "kvs": {},
+        "cmds": { "touch": format!("touch {}", marker.to_string_lossy()) },
+        "hooks": [{ "name": "touch-hook", "cmd_name": "touch", "run_on": "Set", "key": "k" }],
+    });
+    std::fs::write(dir.join("kv/kv.json"), store.to_string()).unwrap();
+
+    let mut memory = KVStore::in_memory();
+    memory.set("k", "v").unwrap();
+    memory.run_hooks("k", &OpType::Set).unwrap();
+    assert!(!marker.exists());
+
+    let mut store = KVStore::open().unwrap();
+    store.run_hooks("k", &OpType::Set).unwrap();
+    assert!(marker.exists());
+
+    // The event log can't be appended to, so the change is made but reported as failed.
+    std::fs::create_dir(dir.join("kv/events.jsonl")).unwrap();
+    let e = store.set("k", "v").err().unwrap();
+    assert!(e.to_string().starts_with("Cannot write event log"), "{}", e);
+    assert_eq!(KVStore::open().unwrap().get("k"), Some("v"));
+    let e = store.del("k").err().unwrap();
+    assert!(e.to_string().starts_with("Cannot write event log"), "{}", e);
+}
@@ -0,0 +1,212 @@
+//! Property tests of the store: random stores survive a save and load unchanged, merges and
+//! compare-and-set agree with a plain map, and an opened store can run on an injected clock
+//! and filesystem.
+
+use kv::{Fs, KVStore};
+use proptest::prelude::*;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+/// Store files kept in a map rather than on disk.
+#[derive(Default)]
+struct MemFs(Mutex<HashMap<PathBuf, Vec<u8>>>);
+
+impl Fs for MemFs {
+    fn read(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.0.lock().unwrap().get(path).cloned())
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        self.0.lock().unwrap().insert(path.to_owned(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Point kv's config dir (the lock, machine overrides) at a temp dir, once for all tests.
+fn config_dir() -> PathBuf {
+    static INIT: Once = Once::new();
+    let dir = std::env::temp_dir().join(format!("kv-lib-props-{}", std::process::id()));
+    INIT.call_once(|| {
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("kv")).unwrap();
+        std::fs::write(dir.join("kv/config.json"), r#"{ "io.retry-for": "0s" }"#).unwrap();
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+    });
+    dir
+}
+
+/// A store on `fs` at a path of its own, which never exists on disk.
+fn open_on(fs: &Arc<MemFs>, name: &str) -> KVStore {
+    let path = config_dir().join(format!("{}.json", name));
+    KVStore::builder().path(path).fs(fs.clone()).build().unwrap()
+}
+
+fn unique_name(prefix: &str) -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!("{}-{}", prefix, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+fn contents(kvstore: &KVStore) -> BTreeMap<String, String> {
+    kvstore
+        .scan("")
+        .map(|entry| (entry.key.to_owned(), entry.value.to_owned()))
+        .collect()
+}
+
+/// A string mixing ascii, escapes JSON must handle and multi-byte characters.
+fn any_string() -> impl Strategy<Value = String> {
+    const CHARS: &[char] = &['a', 'z', '0', '.', '-', '/', ' ', '"', '\\', '\n', '\t', '\u{0}', 'é', '鍵', '🔑'];
+    prop::collection::vec(prop::sample::select(CHARS), 0..12).prop_map(String::from_iter)
+}
+
+fn any_store() -> impl Strategy<Value = Value> {
+    let meta = prop::option::of((0..u32::MAX as u64, 0..1000u64));
+    (
+        prop::collection::btree_map(any_string(), (any_string(), meta), 0..20),
+        prop::collection::btree_map(any_string(), any_string(), 0..5),
+    )
+        .prop_map(|(entries, cmds)| {
+            let mut kvs = Map::new();
+            let mut meta = Map::new();
+            for (key, (value, times)) in entries {
+                if let Some((created, later)) = times {
+                    meta.insert(key.clone(), json!({ "created": created, "modified": created + later }));
+                }
+                kvs.insert(key, Value::String(value));
+            }
+            let cmds: Map<String, Value> = cmds.into_iter().map(|(k, v)| (k, Value::String(v))).collect();
+            json!({ "kvs": kvs, "cmds": cmds, "hooks": [], "meta": meta, "locks": {} })
+        })
+}
+
+/// Few keys and values, so merges conflict and compare-and-set often matches.
+fn small_map() -> impl Strategy<Value = BTreeMap<String, String>> {
+    prop::collection::btree_map("[a-d]", "[xy]{0,2}", 0..5)
+}
+
+fn any_strategy() -> impl Strategy<Value = kv::Strategy> {
+    prop_oneof![Just(kv::Strategy::Keep), Just(kv::Strategy::Replace), Just(kv::Strategy::Fail)]
+}
+
+/// A compare-and-set: key, expected value, new value.
+type Swap = (String, Option<String>, Option<String>);
+
+fn any_swaps() -> impl Strategy<Value = Vec<Swap>> {
+    let value = || prop::option::of("[xy]");
+    prop::collection::vec(("[a-c]", value(), value()), 0..20)
+}
+
+/// What `merge` should leave: the local map with incoming keys added, conflicts settled by
+/// `strategy`. None if it should fail.
+fn expected_merge(
+    local: &BTreeMap<String, String>,
+    incoming: &BTreeMap<String, String>,
+    strategy: kv::Strategy,
+) -> Option<(BTreeMap<String, String>, Vec<String>)> {
+    let conflict = |key: &String, value: &String| local.get(key).is_some_and(|old| old != value);
+    if strategy == kv::Strategy::Fail && incoming.iter().any(|(key, value)| conflict(key, value)) {
+        return None;
+    }
+    let mut merged = local.clone();
+    let mut changed = vec![];
+    for (key, value) in incoming {
+        if !local.contains_key(key) || (strategy != kv::Strategy::Keep && conflict(key, value)) {
+            merged.insert(key.clone(), value.clone());
+            changed.push(key.clone());
+        }
+    }
+    Some((merged, changed))
+}
+
+/// Apply `swaps` to `kvstore` and to a plain map, checking each result against the map's.
+fn check_swaps(kvstore: &mut KVStore, swaps: &[Swap]) -> BTreeMap<String, String> {
+    let mut model = BTreeMap::new();
+    for (key, expected, value) in swaps {
+        let matches = model.get(key) == expected.as_ref();
+        let swapped = kvstore.compare_and_set(key, expected.as_deref(), value.as_deref()).unwrap();
+        assert_eq!(swapped, matches, "{} from {:?} to {:?}", key, expected, value);
+        if matches {
+            match value {
+                Some(value) => model.insert(key.clone(), value.clone()),
+                None => model.remove(key),
+            };
+        }
+        assert_eq!(contents(kvstore), model);
+    }
+    model
+}
+
+proptest! {
+    #[test]
+    fn store_json_round_trips(original in any_store()) {
+        let store: KVStore = serde_json::from_value(original.clone()).unwrap();
+        for (key, value) in original["kvs"].as_object().unwrap() {
+            prop_assert_eq!(store.get(key), value.as_str());
+        }
+        let saved = serde_json::to_string(&store).unwrap();
+        let reloaded: Value = serde_json::from_str(&saved).unwrap();
+        prop_assert_eq!(reloaded, original);
+    }
+
+    #[test]
+    fn merge_settles_conflicts_by_strategy(local in small_map(), incoming in small_map(), strategy in any_strategy()) {
+        let mut kvstore = KVStore::in_memory();
+        for (key, value) in &local {
+            kvstore.set(key, value).unwrap();
+        }
+        let result = kvstore.merge(incoming.clone(), strategy);
+        match expected_merge(&local, &incoming, strategy) {
+            Some((merged, changed)) => {
+                prop_assert_eq!(result.unwrap(), changed);
+                prop_assert_eq!(contents(&kvstore), merged);
+            }
+            None => {
+                prop_assert!(result.is_err());
+                prop_assert_eq!(contents(&kvstore), local);
+            }
+        }
+    }
+
+    #[test]
+    fn compare_and_set_in_memory(swaps in any_swaps()) {
+        check_swaps(&mut KVStore::in_memory(), &swaps);
+    }
+
+    #[test]
+    fn compare_and_set_in_a_file(swaps in any_swaps(), incoming in small_map()) {
+        let fs = Arc::new(MemFs::default());
+        let name = unique_name("cas");
+        let mut kvstore = open_on(&fs, &name);
+        let mut model = check_swaps(&mut kvstore, &swaps);
+        kvstore.merge(incoming.clone(), kv::Strategy::Replace).unwrap();
+        model.extend(incoming);
+        prop_assert_eq!(contents(&open_on(&fs, &name)), model);
+        let on_disk = config_dir().join(format!("{}.json", name));
+        prop_assert!(!on_disk.exists());
+    }
+}
+
+#[test]
+fn metadata_reads_the_injected_clock() {
+    static NOW: AtomicU64 = AtomicU64::new(1_000);
+    fn clock() -> u64 {
+        NOW.load(Ordering::Relaxed)
+    }
+    let fs = Arc::new(MemFs::default());
+    let path = config_dir().join("clock.json");
+    let mut kvstore = KVStore::builder().path(&path).fs(fs.clone()).clock(clock).build().unwrap();
+
+    kvstore.set("greeting", "hello").unwrap();
+    NOW.store(2_000, Ordering::Relaxed);
+    assert!(kvstore.compare_and_set("greeting", Some("hello"), Some("hi")).unwrap());
+    let meta = kvstore.entry("greeting").unwrap().meta.unwrap();
+    assert_eq!((meta.created, meta.modified), (1_000, 2_000));
+
+    let saved: Value = serde_json::from_slice(&fs.0.lock().unwrap()[&path]).unwrap();
+    assert_eq!(saved["kvs"]["greeting"], "hi");
+    assert_eq!(saved["meta"]["greeting"]["created"], 1_000);
+    assert!(!path.exists());
+}
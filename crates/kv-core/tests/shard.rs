@@ -0,0 +1,36 @@
+//! Sharded stores through the library: keys survive a save and load across shard files, and
+//! a shard that doesn't parse is an error rather than an empty shard.
+
+use kv::KVStore;
+
+#[test]
+fn shards_round_trip_and_corrupt_shards_fail() {
+    let dir = std::env::temp_dir().join(format!("kv-shard-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("kv")).unwrap();
+    std::fs::write(dir.join("kv/config.json"), r#"{ "io.retry-for": "0s" }"#).unwrap();
+    std::fs::write(dir.join("kv/kv.json"), r#"{ "kvs": {}, "cmds": {}, "hooks": [], "shards": 4 }"#).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+
+    let mut store = KVStore::open().unwrap();
+    let keys: Vec<String> = (0..50).map(|i| format!("key{}", i)).collect();
+    for key in &keys {
+        store.set(key, &format!("{}-value", key)).unwrap();
+    }
+    let shards = dir.join("kv/kv.shards");
+    assert_eq!(std::fs::read_dir(&shards).unwrap().count(), 4);
+    let main = std::fs::read_to_string(dir.join("kv/kv.json")).unwrap();
+    assert!(!main.contains("key1"), "{}", main);
+    let reopened = KVStore::open().unwrap();
+    for key in &keys {
+        assert_eq!(reopened.get(key), Some(&format!("{}-value", key)[..]));
+    }
+
+    let corrupt = shards.join("0002.json");
+    std::fs::write(&corrupt, "{ not json").unwrap();
+    let e = KVStore::open().err().unwrap();
+    assert!(e.to_string().starts_with("Cannot parse the shard"), "{}", e);
+    assert!(store.set("key1", "changed").is_err());
+    assert_eq!(std::fs::read_to_string(&corrupt).unwrap(), "{ not json");
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,30 @@
+//! The SQLite backend through the library: keys, metadata and expiry survive a reopen, and
+//! a JSON store next to a new database is imported.
+#![cfg(feature = "sqlite")]
+
+use kv::{Backend, KVStore};
+
+#[test]
+fn sqlite_store_opens_and_imports() {
+    let dir = std::env::temp_dir().join(format!("kv-lib-sqlite-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("kv")).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+    std::fs::write(dir.join("store.json"), r#"{"kvs": {"old": "kept"}, "cmds": {"hi": "echo hi"}, "hooks": []}"#)
+        .unwrap();
+    let path = dir.join("store.db");
+
+    let mut store = KVStore::builder().path(&path).backend(Backend::Sqlite).build().unwrap();
+    assert_eq!(store.path(), Some(path.as_path()));
+    assert_eq!(store.get("old"), Some("kept"));
+    assert_eq!(store.set("greeting", "hello").unwrap(), None);
+    assert_eq!(store.set("greeting", "hi").unwrap(), Some("hello".to_owned()));
+    assert_eq!(store.del("old").unwrap(), Some("kept".to_owned()));
+
+    let reopened = KVStore::open().unwrap();
+    assert_eq!(reopened.get("greeting"), Some("hi"));
+    assert_eq!(reopened.get("old"), None);
+    // The JSON store is only read, when the database is created.
+    let json = std::fs::read_to_string(dir.join("store.json")).unwrap();
+    assert!(json.contains("kept") && !json.contains("greeting"));
+}
@@ -0,0 +1,46 @@
+//! The library API: in-memory stores stay in memory, opened stores write their file, and
+//! failures come back as errors.
+
+use kv::KVStore;
+
+#[test]
+fn in_memory_and_opened_stores() {
+    let dir = std::env::temp_dir().join(format!("kv-lib-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("kv")).unwrap();
+    std::fs::write(dir.join("kv/config.json"), r#"{ "io.retry-for": "0s" }"#).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+    let path = dir.join("store.json");
+
+    // A directory where the store file should be can't be opened.
+    std::fs::create_dir(&path).unwrap();
+    let e = KVStore::builder().path(&path).build().err().unwrap();
+    assert!(e.to_string().starts_with("Cannot open"), "{}", e);
+    std::fs::remove_dir(&path).unwrap();
+
+    let mut memory = KVStore::in_memory();
+    assert_eq!(memory.path(), None);
+    assert_eq!(memory.set("k", "v").unwrap(), None);
+    assert_eq!(memory.get("k"), Some("v"));
+    assert_eq!(memory.del("k").unwrap(), Some("v".to_owned()));
+    assert!(memory.save().is_err());
+    assert!(!path.exists());
+    assert!(!dir.join("kv/kv.json").exists());
+
+    let mut store = KVStore::open().unwrap();
+    assert_eq!(store.path(), Some(path.as_path()));
+    assert_eq!(store.set("greeting", "hello").unwrap(), None);
+    assert_eq!(KVStore::open().unwrap().get("greeting"), Some("hello"));
+    assert_eq!(store.set("greeting", "hi").unwrap(), Some("hello".to_owned()));
+    assert_eq!(store.del("greeting").unwrap(), Some("hi".to_owned()));
+    assert_eq!(store.del("greeting").unwrap(), None);
+    assert_eq!(KVStore::open().unwrap().get("greeting"), None);
+    assert_eq!(store.set_serialized("ports", &[80, 443]).unwrap(), None);
+    let reopened = KVStore::open().unwrap();
+    assert_eq!(reopened.get_json::<Vec<u16>>("ports").unwrap().unwrap(), [80, 443]);
+    assert!(reopened.entry("ports").unwrap().meta.is_some());
+    assert!(!dir.join("kv/kv.json").exists());
+
+    let e = KVStore::subscribe("*").err().unwrap();
+    assert!(e.to_string().starts_with("The event log is off"), "{}", e);
+}